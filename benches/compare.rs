@@ -0,0 +1,276 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use kv_store::{db::Engine, options::Options};
+use parking_lot::RwLock;
+
+const KEY_NUM: usize = 100_000;
+const VALUE_SIZE: usize = 128;
+
+// 在不同的存储引擎上运行相同的负载，对比吞吐量
+// 运行方式：cargo bench --features compare-bench --bench compare
+//
+// 对比的引擎通过 KvAdapter 接入：本引擎、sled、rocksdb，以及内存中的 BTreeMap（作为上限参考）
+// 所有引擎都使用默认配置，写入时不 fsync，每个负载结束时调用 flush 持久化
+trait KvAdapter {
+    fn name(&self) -> &str;
+    fn put(&self, key: Bytes, value: Bytes);
+    fn get(&self, key: Bytes) -> Option<Bytes>;
+    fn delete(&self, key: Bytes);
+    // 持久化所有已经写入的数据
+    fn flush(&self);
+}
+
+struct KvStoreAdapter {
+    engine: Engine,
+    dir_path: PathBuf,
+}
+
+impl KvStoreAdapter {
+    fn open(dir_path: &str) -> Self {
+        let opts = Options {
+            dir_path: PathBuf::from(dir_path),
+            data_file_size: 64 * 1024 * 1024,
+            ..Default::default()
+        };
+        let _ = std::fs::remove_dir_all(&opts.dir_path);
+        Self {
+            dir_path: opts.dir_path.clone(),
+            engine: Engine::open(opts).expect("failed to open engine"),
+        }
+    }
+}
+
+impl KvAdapter for KvStoreAdapter {
+    fn name(&self) -> &str {
+        "kv_store"
+    }
+
+    fn put(&self, key: Bytes, value: Bytes) {
+        self.engine.put(key, value).unwrap();
+    }
+
+    fn get(&self, key: Bytes) -> Option<Bytes> {
+        self.engine.try_get(key).unwrap()
+    }
+
+    fn delete(&self, key: Bytes) {
+        self.engine.delete(key).unwrap();
+    }
+
+    fn flush(&self) {
+        self.engine.sync().unwrap();
+    }
+}
+
+impl Drop for KvStoreAdapter {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir_path);
+    }
+}
+
+struct SledAdapter {
+    db: sled::Db,
+    dir_path: PathBuf,
+}
+
+impl SledAdapter {
+    fn open(dir_path: &str) -> Self {
+        let dir_path = PathBuf::from(dir_path);
+        let _ = std::fs::remove_dir_all(&dir_path);
+        Self {
+            db: sled::open(&dir_path).expect("failed to open sled"),
+            dir_path,
+        }
+    }
+}
+
+impl KvAdapter for SledAdapter {
+    fn name(&self) -> &str {
+        "sled"
+    }
+
+    fn put(&self, key: Bytes, value: Bytes) {
+        self.db.insert(key.as_ref(), value.as_ref()).unwrap();
+    }
+
+    fn get(&self, key: Bytes) -> Option<Bytes> {
+        self.db
+            .get(key.as_ref())
+            .unwrap()
+            .map(|value| Bytes::copy_from_slice(&value))
+    }
+
+    fn delete(&self, key: Bytes) {
+        self.db.remove(key.as_ref()).unwrap();
+    }
+
+    fn flush(&self) {
+        self.db.flush().unwrap();
+    }
+}
+
+impl Drop for SledAdapter {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir_path);
+    }
+}
+
+struct RocksDbAdapter {
+    db: Option<rocksdb::DB>,
+    dir_path: PathBuf,
+}
+
+impl RocksDbAdapter {
+    fn open(dir_path: &str) -> Self {
+        let dir_path = PathBuf::from(dir_path);
+        let _ = std::fs::remove_dir_all(&dir_path);
+        Self {
+            db: Some(rocksdb::DB::open_default(&dir_path).expect("failed to open rocksdb")),
+            dir_path,
+        }
+    }
+
+    fn db(&self) -> &rocksdb::DB {
+        self.db.as_ref().unwrap()
+    }
+}
+
+impl KvAdapter for RocksDbAdapter {
+    fn name(&self) -> &str {
+        "rocksdb"
+    }
+
+    fn put(&self, key: Bytes, value: Bytes) {
+        self.db().put(key, value).unwrap();
+    }
+
+    fn get(&self, key: Bytes) -> Option<Bytes> {
+        self.db().get(key).unwrap().map(Bytes::from)
+    }
+
+    fn delete(&self, key: Bytes) {
+        self.db().delete(key).unwrap();
+    }
+
+    // 与其他引擎一样只持久化日志，不把内存表写入 SST 文件
+    fn flush(&self) {
+        self.db().flush_wal(true).unwrap();
+    }
+}
+
+impl Drop for RocksDbAdapter {
+    fn drop(&mut self) {
+        // 先关闭数据库再删除目录
+        self.db.take();
+        let _ = std::fs::remove_dir_all(&self.dir_path);
+    }
+}
+
+// 没有持久化的内存有序表，代表索引本身的开销
+#[derive(Default)]
+struct MemoryAdapter {
+    map: RwLock<BTreeMap<Bytes, Bytes>>,
+}
+
+impl KvAdapter for MemoryAdapter {
+    fn name(&self) -> &str {
+        "btreemap (memory)"
+    }
+
+    fn put(&self, key: Bytes, value: Bytes) {
+        self.map.write().insert(key, value);
+    }
+
+    fn get(&self, key: Bytes) -> Option<Bytes> {
+        self.map.read().get(&key).cloned()
+    }
+
+    fn delete(&self, key: Bytes) {
+        self.map.write().remove(&key);
+    }
+
+    fn flush(&self) {}
+}
+
+fn key(i: usize) -> Bytes {
+    Bytes::from(format!("bench-key-{:09}", i))
+}
+
+// 固定种子的随机序列，所有引擎读取相同顺序的 key
+fn shuffled(n: usize) -> Vec<usize> {
+    let mut state = 0x9e3779b97f4a7c15u64;
+    let mut order: Vec<usize> = (0..n).collect();
+    for i in (1..n).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        order.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+    order
+}
+
+fn report(adapter: &dyn KvAdapter, workload: &str, ops: usize, elapsed: Duration) {
+    println!(
+        "{:<20} {:<16} {:>12.0} ops/s",
+        adapter.name(),
+        workload,
+        ops as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn run_workloads(adapter: &dyn KvAdapter) {
+    let value = Bytes::from(vec![b'v'; VALUE_SIZE]);
+    let order = shuffled(KEY_NUM);
+
+    let start = Instant::now();
+    for i in 0..KEY_NUM {
+        adapter.put(key(i), value.clone());
+    }
+    adapter.flush();
+    report(adapter, "sequential put", KEY_NUM, start.elapsed());
+
+    let start = Instant::now();
+    for &i in order.iter() {
+        assert!(adapter.get(key(i)).is_some());
+    }
+    report(adapter, "random get", KEY_NUM, start.elapsed());
+
+    // 读写各占一半
+    let start = Instant::now();
+    for (n, &i) in order.iter().enumerate() {
+        match n % 2 {
+            0 => adapter.put(key(i), value.clone()),
+            _ => {
+                adapter.get(key(i));
+            }
+        }
+    }
+    adapter.flush();
+    report(adapter, "mixed 50/50", KEY_NUM, start.elapsed());
+
+    let start = Instant::now();
+    for &i in order.iter() {
+        adapter.delete(key(i));
+    }
+    adapter.flush();
+    report(adapter, "random delete", KEY_NUM, start.elapsed());
+}
+
+fn main() {
+    let adapters: Vec<Box<dyn KvAdapter>> = vec![
+        Box::new(KvStoreAdapter::open("/tmp/bitcask-rs-bench-compare")),
+        Box::new(SledAdapter::open("/tmp/bitcask-rs-bench-compare-sled")),
+        Box::new(RocksDbAdapter::open(
+            "/tmp/bitcask-rs-bench-compare-rocksdb",
+        )),
+        Box::new(MemoryAdapter::default()),
+    ];
+    for adapter in adapters.iter() {
+        run_workloads(adapter.as_ref());
+    }
+}
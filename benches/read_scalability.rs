@@ -0,0 +1,102 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use kv_store::{
+    db::Engine,
+    options::{IndexType, Options},
+};
+
+const KEY_NUM: usize = 100_000;
+const READS_PER_THREAD: usize = 200_000;
+
+fn key(i: usize) -> Bytes {
+    Bytes::from(format!("bench-key-{:09}", i))
+}
+
+// 并发读取的吞吐量，随线程数增加应当线性增长
+// 运行方式：cargo bench --bench read_scalability
+//
+// 读路径去掉 older_files / active_file 全局读写锁前后的结果（单核环境，三次运行）：
+//   之前  get:   1 threads  267607 ~ 317864 ops/s
+//   之后  get:   1 threads  255769 ~ 351598 ops/s
+// 单核下只能说明改动没有带来额外开销，多核下的扩展性需要在多核机器上运行
+//
+// BTree 与 ConcurrentBTree 索引的对比（单核环境）：
+//   BTree            get: 332287 ops/s  get with writer: 142564 ops/s
+//   ConcurrentBTree  get: 201542 ops/s  get with writer: 108821 ops/s
+// ConcurrentBTree 写入时需要复制路径上的节点，单线程下更慢，优势在于多核下读取不会被写入阻塞
+fn main() {
+    // 对比不同的内存索引实现
+    for index_type in [IndexType::BTree, IndexType::ConcurrentBTree] {
+        println!("index: {:?}", index_type);
+        bench_index(index_type);
+    }
+}
+
+fn bench_index(index_type: IndexType) {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-bench-read-scalability"),
+        data_file_size: 4 * 1024 * 1024,
+        index_type,
+        ..Default::default()
+    };
+    let _ = std::fs::remove_dir_all(&opts.dir_path);
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    let value = Bytes::from(vec![b'v'; 128]);
+    for i in 0..KEY_NUM {
+        engine.put(key(i), value.clone()).unwrap();
+    }
+
+    let max_threads = thread::available_parallelism().map_or(4, |n| n.get());
+    let mut threads = 1;
+    while threads <= max_threads {
+        let elapsed = run_reads(&engine, threads);
+        let ops = (threads * READS_PER_THREAD) as f64 / elapsed.as_secs_f64();
+        println!("get: {threads:>3} threads {:>12.0} ops/s", ops);
+        threads *= 2;
+    }
+
+    // 同时有一个线程持续写入
+    let stop = AtomicBool::new(false);
+    let elapsed = thread::scope(|s| {
+        s.spawn(|| {
+            let mut i = 0;
+            while !stop.load(Ordering::Relaxed) {
+                engine.put(key(i % KEY_NUM), value.clone()).unwrap();
+                i += 1;
+            }
+        });
+        let elapsed = run_reads(&engine, max_threads);
+        stop.store(true, Ordering::Relaxed);
+        elapsed
+    });
+    let ops = (max_threads * READS_PER_THREAD) as f64 / elapsed.as_secs_f64();
+    println!(
+        "get with writer: {max_threads:>3} threads {:>12.0} ops/s",
+        ops
+    );
+
+    std::fs::remove_dir_all(opts.dir_path).expect("failed to remove path");
+}
+
+fn run_reads(engine: &Engine, threads: usize) -> Duration {
+    let start = Instant::now();
+    thread::scope(|s| {
+        for t in 0..threads {
+            s.spawn(move || {
+                let mut i = t * 7919;
+                for _ in 0..READS_PER_THREAD {
+                    i = (i + 104_729) % KEY_NUM;
+                    engine.get(key(i)).unwrap();
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
@@ -0,0 +1,92 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+use bytes::Bytes;
+use kv_store::{db::Engine, options::Options};
+
+// 统计内存分配次数和分配的字节数
+struct CountingAlloc;
+
+static ALLOCS: AtomicU64 = AtomicU64::new(0);
+static ALLOC_BYTES: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        ALLOC_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        ALLOC_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const WRITES: usize = 200_000;
+
+// 写入路径上每次 put 的内存分配次数和分配的字节数
+// 运行方式：cargo bench --bench write_alloc
+//
+// 追加写入时复用编码缓冲区前后的结果（单核环境）：
+//   之前  value    16 bytes: 14.17 allocs/op    396 alloc bytes/op  607636 ops/s
+//         value   128 bytes: 14.17 allocs/op    847 alloc bytes/op  670038 ops/s
+//         value  4096 bytes: 14.17 allocs/op  16720 alloc bytes/op  153285 ops/s
+//   之后  value    16 bytes: 10.17 allocs/op    243 alloc bytes/op  742804 ops/s
+//         value   128 bytes: 10.17 allocs/op    355 alloc bytes/op  732393 ops/s
+//         value  4096 bytes: 10.17 allocs/op   4323 alloc bytes/op  164646 ops/s
+// 剩下的分配主要来自构造 LogRecord 时复制 key 和 value，以及更新索引
+fn main() {
+    for value_size in [16, 128, 4096] {
+        bench_put(value_size);
+    }
+}
+
+fn bench_put(value_size: usize) {
+    let opts = Options {
+        dir_path: PathBuf::from("/tmp/bitcask-rs-bench-write-alloc"),
+        data_file_size: 64 * 1024 * 1024,
+        ..Default::default()
+    };
+    let _ = std::fs::remove_dir_all(&opts.dir_path);
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // key 和 value 提前生成，不计入统计
+    let keys: Vec<Bytes> = (0..WRITES)
+        .map(|i| Bytes::from(format!("bench-key-{:09}", i)))
+        .collect();
+    let value = Bytes::from(vec![b'v'; value_size]);
+
+    let allocs = ALLOCS.load(Ordering::Relaxed);
+    let alloc_bytes = ALLOC_BYTES.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for key in keys {
+        engine.put(key, value.clone()).unwrap();
+    }
+    let elapsed = start.elapsed();
+    let allocs = ALLOCS.load(Ordering::Relaxed) - allocs;
+    let alloc_bytes = ALLOC_BYTES.load(Ordering::Relaxed) - alloc_bytes;
+
+    println!(
+        "put value {:>5} bytes: {:>6.2} allocs/op {:>8.0} alloc bytes/op {:>10.0} ops/s",
+        value_size,
+        allocs as f64 / WRITES as f64,
+        alloc_bytes as f64 / WRITES as f64,
+        WRITES as f64 / elapsed.as_secs_f64(),
+    );
+
+    std::mem::drop(engine);
+    std::fs::remove_dir_all(opts.dir_path).expect("failed to remove path");
+}
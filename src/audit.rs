@@ -0,0 +1,198 @@
+use std::{
+    fmt::Write as _,
+    fs::{File, OpenOptions},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::error;
+use parking_lot::Mutex;
+
+use crate::{
+    errors::{Errors, Result},
+    fio,
+};
+
+/// 审计日志中记录的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditOp {
+    Put,
+    Delete,
+}
+
+impl AuditOp {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AuditOp::Put => "put",
+            AuditOp::Delete => "delete",
+        }
+    }
+}
+
+// 审计日志，每个提交的写入追加一行 JSON（NDJSON），只追加不修改
+// 每行包含 ts（毫秒时间戳）、seq（事务序列号，非事务写入为 0）、op、key，写入还包含 value_len
+// key 是合法的 UTF-8 时记录在 key 中，否则以十六进制记录在 key_hex 中
+// 引擎内部的数据不会记录
+pub(crate) struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub(crate) fn open(path: &Path, mode: u32) -> Result<Self> {
+        let created = !path.exists();
+        let file = match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(mode)
+            .open(path)
+        {
+            Ok(file) => file,
+            Err(e) => {
+                error!("Failed to open audit log {:?}: {e}", path);
+                return Err(Errors::FailedToOpenAuditLog(e.into()));
+            }
+        };
+        if created {
+            fio::sync_parent_dir(path)?;
+        }
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    // 追加一条记录，写入失败只记录日志，数据已经提交，不影响写入的结果
+    pub(crate) fn record(&self, op: AuditOp, key: &[u8], seq: u64, value_len: Option<usize>) {
+        let line = audit_line(now_millis(), op, key, seq, value_len);
+        if let Err(e) = self.file.lock().write_all(line.as_bytes()) {
+            error!("Failed to write audit log: {e}");
+        }
+    }
+
+    pub(crate) fn sync(&self) -> Result<()> {
+        match self.file.lock().sync_all() {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                error!("Failed to sync audit log: {e}");
+                Err(Errors::FailedToSyncFile(e.into()))
+            }
+        }
+    }
+}
+
+fn audit_line(ts: u64, op: AuditOp, key: &[u8], seq: u64, value_len: Option<usize>) -> String {
+    let mut line = format!("{{\"ts\":{ts},\"seq\":{seq},\"op\":\"{}\",", op.as_str());
+    match std::str::from_utf8(key) {
+        Ok(key) => {
+            line.push_str("\"key\":\"");
+            for c in key.chars() {
+                match c {
+                    '"' => line.push_str("\\\""),
+                    '\\' => line.push_str("\\\\"),
+                    '\n' => line.push_str("\\n"),
+                    '\r' => line.push_str("\\r"),
+                    '\t' => line.push_str("\\t"),
+                    c if (c as u32) < 0x20 => {
+                        let _ = write!(line, "\\u{:04x}", c as u32);
+                    }
+                    c => line.push(c),
+                }
+            }
+            line.push('"');
+        }
+        Err(_) => {
+            line.push_str("\"key_hex\":\"");
+            for b in key {
+                let _ = write!(line, "{b:02x}");
+            }
+            line.push('"');
+        }
+    }
+    if let Some(value_len) = value_len {
+        let _ = write!(line, ",\"value_len\":{value_len}");
+    }
+    line.push_str("}\n");
+    line
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use crate::{db::Engine, options::Options};
+
+    use super::*;
+
+    #[test]
+    fn test_audit_log() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-audit-log");
+        let audit_path = PathBuf::from("/tmp/bitcask-rs-audit-log.ndjson");
+        opts.audit_log = Some(audit_path.clone());
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        assert!(engine.put(Bytes::from("a"), Bytes::from("value")).is_ok());
+        assert!(engine.delete(Bytes::from("a")).is_ok());
+        let wb = engine.new_write_batch(Default::default()).unwrap();
+        wb.put(Bytes::from("b"), Bytes::from("v")).unwrap();
+        wb.commit().unwrap();
+        // 幂等写入的请求 id 是内部数据，不会记录
+        let res = engine.put_idempotent(Bytes::from("req"), Bytes::from("c"), Bytes::from("v"));
+        assert!(res.unwrap());
+        assert!(engine.sync().is_ok());
+
+        let content = std::fs::read_to_string(&audit_path).unwrap();
+        let lines = content
+            .lines()
+            .map(|line| {
+                // 去掉时间戳
+                let (ts, rest) = line.split_once(",\"seq\"").unwrap();
+                assert!(ts.starts_with("{\"ts\":"));
+                rest.to_string()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                ":0,\"op\":\"put\",\"key\":\"a\",\"value_len\":5}",
+                ":0,\"op\":\"delete\",\"key\":\"a\"}",
+                ":1,\"op\":\"put\",\"key\":\"b\",\"value_len\":1}",
+                ":2,\"op\":\"put\",\"key\":\"c\",\"value_len\":1}",
+            ]
+        );
+
+        // 重新打开之后继续追加
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(engine.delete(Bytes::from("b")).is_ok());
+        let content = std::fs::read_to_string(&audit_path).unwrap();
+        assert_eq!(content.lines().count(), 5);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+        std::fs::remove_file(audit_path).expect("failed to remove file");
+    }
+
+    #[test]
+    fn test_audit_line() {
+        assert_eq!(
+            audit_line(1, AuditOp::Put, b"a\"b\\c\n\x01", 0, Some(3)),
+            "{\"ts\":1,\"seq\":0,\"op\":\"put\",\"key\":\"a\\\"b\\\\c\\n\\u0001\",\"value_len\":3}\n"
+        );
+        assert_eq!(
+            audit_line(2, AuditOp::Delete, &[0xff, 0x00], 7, None),
+            "{\"ts\":2,\"seq\":7,\"op\":\"delete\",\"key_hex\":\"ff00\"}\n"
+        );
+    }
+}
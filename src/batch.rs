@@ -1,33 +1,78 @@
 use std::{
     collections::HashMap,
     sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
 };
 
 use bytes::{BufMut, Bytes, BytesMut};
 use parking_lot::Mutex;
-use prost::{decode_length_delimiter, encode_length_delimiter};
+use prost::encoding::{decode_varint, encode_varint};
+
+pub(crate) mod pipeline;
 
 use crate::{
-    data::log_record::{LogRecord, LogRecordType},
+    audit::AuditOp,
+    data::log_record::{LogRecord, LogRecordType, MAX_LOG_RECORD_META_SIZE},
     db::Engine,
-    errors::{Errors, Result},
+    deadline,
+    errors::{invariant_violation, Errors, Result},
     options::WriteBatchOptions,
 };
 
-pub(crate) const NON_TRANSACTION_SEQ_NO: usize = 0;
+pub(crate) const NON_TRANSACTION_SEQ_NO: u64 = 0;
 const TXN_FINISH: &[u8] = "txn-fin".as_bytes();
 
 pub struct WriteBatch<'a> {
-    pending_writes: Arc<Mutex<HashMap<Vec<u8>, LogRecord>>>, // 暂存用户写入的数据
+    pending_writes: Arc<Mutex<PendingWrites>>, // 暂存用户写入的数据
     engine: &'a Engine,
 
     options: WriteBatchOptions,
 }
 
+// 暂存的数据，同时统计暂存的数据量
+#[derive(Default)]
+struct PendingWrites {
+    records: HashMap<Vec<u8>, LogRecord>,
+    // 暂存数据的 key + value + 元数据的长度
+    bytes: usize,
+}
+
+impl PendingWrites {
+    fn insert(&mut self, record: LogRecord) {
+        self.bytes += record_size(&record);
+        if let Some(old) = self.records.insert(record.key.clone(), record) {
+            self.bytes -= record_size(&old);
+        }
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        if let Some(old) = self.records.remove(key) {
+            self.bytes -= record_size(&old);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.records.clear();
+        self.bytes = 0;
+    }
+}
+
+impl std::ops::Deref for PendingWrites {
+    type Target = HashMap<Vec<u8>, LogRecord>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.records
+    }
+}
+
+fn record_size(record: &LogRecord) -> usize {
+    record.key.len() + record.value.len() + record.meta.len()
+}
+
 impl Engine {
-    pub fn new_write_batch(&self, options: WriteBatchOptions) -> Result<WriteBatch> {
+    pub fn new_write_batch(&self, options: WriteBatchOptions) -> Result<WriteBatch<'_>> {
         Ok(WriteBatch {
-            pending_writes: Arc::new(Mutex::new(HashMap::new())),
+            pending_writes: Arc::new(Mutex::new(PendingWrites::default())),
             engine: self,
             options,
         })
@@ -37,98 +82,249 @@ impl Engine {
 impl WriteBatch<'_> {
     // 批量操作写数据
     pub fn put(&self, key: Bytes, value: Bytes) -> Result<()> {
-        if key.is_empty() {
-            return Err(Errors::KeyIsEmpty);
+        self.engine.check_key(&key)?;
+        self.put_unchecked(key, value)
+    }
+
+    // 批量写数据并附带用户自定义的元数据，元数据最长 255 字节
+    pub fn put_with_meta(&self, key: Bytes, value: Bytes, meta: Bytes) -> Result<()> {
+        self.engine.check_key(&key)?;
+        if meta.len() > MAX_LOG_RECORD_META_SIZE {
+            return Err(Errors::MetaTooLarge);
         }
+        self.stage_put(key, value, meta)
+    }
 
+    // 写入数据并把过期时间设置为 ttl 之后，ttl 为 None 时清除原有的过期时间，不使用 default_ttl
+    pub(crate) fn put_with_ttl(
+        &self,
+        key: Bytes,
+        value: Bytes,
+        meta: Bytes,
+        ttl: Option<Duration>,
+    ) -> Result<()> {
+        self.engine.check_key(&key)?;
+        if meta.len() > MAX_LOG_RECORD_META_SIZE {
+            return Err(Errors::MetaTooLarge);
+        }
+        let record = LogRecord {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            rec_type: LogRecordType::NORMAL,
+            meta: meta.to_vec(),
+        };
+        let ttl_record = self.engine.key_ttl_record_with(&key, ttl);
+        let mut pending_writes = self.pending_writes.lock();
+        self.stage_with_ttl(&mut pending_writes, record, ttl_record)
+    }
+
+    // 不校验 key 直接暂存数据，用于写入内部数据
+    pub(crate) fn put_unchecked(&self, key: Bytes, value: Bytes) -> Result<()> {
+        self.stage_put(key, value, Bytes::new())
+    }
+
+    fn stage_put(&self, key: Bytes, value: Bytes, meta: Bytes) -> Result<()> {
         // 暂存数据
         let record = LogRecord {
             key: key.to_vec(),
             value: value.to_vec(),
             rec_type: LogRecordType::NORMAL,
+            meta: meta.to_vec(),
         };
 
+        let ttl_record = self.engine.key_ttl_record(&key, true);
         let mut pending_writes = self.pending_writes.lock();
-        pending_writes.insert(key.to_vec(), record);
-
-        Ok(())
+        self.stage_with_ttl(&mut pending_writes, record, ttl_record)
     }
 
     // 批量删除数据
     pub fn delete(&self, key: Bytes) -> Result<()> {
-        if key.is_empty() {
-            return Err(crate::errors::Errors::KeyIsEmpty);
-        }
+        self.engine.check_key(&key)?;
+        self.delete_unchecked(key)
+    }
 
+    // 不校验 key 直接暂存删除操作，用于删除内部数据
+    pub(crate) fn delete_unchecked(&self, key: Bytes) -> Result<()> {
+        let ttl_record = self.engine.key_ttl_record(&key, false);
         let mut pending_writes = self.pending_writes.lock();
-        // 如果数据不存在则直接返回
-        let index_pos = self.engine.index.get(key.to_vec());
-        if index_pos.is_none() {
-            if pending_writes.contains_key(&key.to_vec()) {
-                pending_writes.remove(&key.to_vec());
-            }
-        }
         // 暂存数据
         let record = LogRecord {
             key: key.to_vec(),
             value: Default::default(),
             rec_type: LogRecordType::DELETED,
+            meta: Default::default(),
+        };
+        self.check_limit(&pending_writes, &record)?;
+        // 如果数据不存在则直接返回
+        let index_pos = self.engine.index.get(key.to_vec());
+        if index_pos.is_none() && pending_writes.contains_key(&key.to_vec()) {
+            pending_writes.remove(&key);
+        }
+        self.stage_with_ttl(&mut pending_writes, record, ttl_record)
+    }
+
+    // 暂存记录以及同时需要写入的过期时间记录，超过事务的限制时两条记录都不会暂存
+    fn stage_with_ttl(
+        &self,
+        pending_writes: &mut PendingWrites,
+        record: LogRecord,
+        ttl_record: Option<LogRecord>,
+    ) -> Result<()> {
+        self.check_limit(pending_writes, &record)?;
+        let ttl_record = match ttl_record {
+            Some(ttl_record) => ttl_record,
+            None => {
+                pending_writes.insert(record);
+                return Ok(());
+            }
         };
-        pending_writes.insert(key.to_vec(), record);
+        let key = record.key.clone();
+        let old = pending_writes.get(&key).cloned();
+        pending_writes.insert(record);
+        if let Err(e) = self.check_limit(pending_writes, &ttl_record) {
+            match old {
+                Some(old) => pending_writes.insert(old),
+                None => pending_writes.remove(&key),
+            }
+            return Err(e);
+        }
+        pending_writes.insert(ttl_record);
+        Ok(())
+    }
+
+    // 暂存数据之前检查是否超过事务的最大数据条数和数据量
+    fn check_limit(&self, pending_writes: &PendingWrites, record: &LogRecord) -> Result<()> {
+        let old = pending_writes.get(&record.key);
+        if old.is_none() && pending_writes.len() >= self.options.max_batch_num {
+            return Err(Errors::ExceedMaxBatchNum);
+        }
+        let bytes = pending_writes.bytes + record_size(record) - old.map_or(0, record_size);
+        if bytes > self.options.max_batch_bytes {
+            return Err(Errors::ExceedMaxBatchBytes);
+        }
         Ok(())
     }
 
     // 提交数据，将数据写入到文件中，并更新内存索引
+    // 事务的所有数据和完成标识写入同一个数据文件，不会被切换活跃文件拆开
+    // 设置了 deadline 时等待内部锁超时返回 Errors::Timeout，暂存的数据保留，可以再次提交
     pub fn commit(&self) -> Result<()> {
+        match self.options.deadline {
+            Some(timeout) => deadline::with_deadline(timeout, || self.commit_pending()),
+            None => self.commit_pending(),
+        }
+    }
+
+    fn commit_pending(&self) -> Result<()> {
         let mut pending_writes = self.pending_writes.lock();
-        if pending_writes.len() == 0 {
+        if pending_writes.is_empty() {
             return Ok(());
         }
 
         if pending_writes.len() > self.options.max_batch_num {
-            return Err(Errors::ExceddMaxBatchNum);
+            return Err(Errors::ExceedMaxBatchNum);
         }
 
-        // 加锁保证事务提交串行化
-        let _lock = self.engine.batch_commit_lock.lock();
+        // 检查前缀配额
+        let writes = pending_writes
+            .values()
+            .map(|item| {
+                let value_size = match item.rec_type {
+                    LogRecordType::NORMAL => Some(item.value.len()),
+                    _ => None,
+                };
+                (item.key.as_slice(), value_size)
+            })
+            .collect::<Vec<_>>();
+        let quota_deltas = self.engine.check_quota(&writes)?;
+
+        // 加锁保证事务写入串行化
+        let append_start = Instant::now();
+        let lock = deadline::lock(&self.engine.batch_commit_lock)?;
         // 获取全局事务序列号
-        let seq_no = self.engine.seq_no.fetch_add(1, Ordering::SeqCst);
+        let seq_no = self.engine.seq.allocate()?;
 
-        let mut positions = HashMap::new();
-        // 开始写数据到数据文件中
-        for (_, item) in pending_writes.iter() {
-            let mut record = LogRecord {
+        // 所有数据和最后一条事务完成的数据一次写入到同一个数据文件中
+        let mut records = pending_writes
+            .values()
+            .map(|item| LogRecord {
                 key: log_record_key_with_seq(item.key.to_vec(), seq_no),
                 value: item.value.clone(),
                 rec_type: item.rec_type,
-            };
-            let pos = self.engine.append_log_record(&mut record)?;
-            positions.insert(item.key.clone(), pos);
-        }
-
-        // 写入最后一条事务完成的数据
-        let mut finish_record = LogRecord {
+                meta: item.meta.clone(),
+            })
+            .collect::<Vec<_>>();
+        records.push(LogRecord {
             key: log_record_key_with_seq(TXN_FINISH.to_vec(), seq_no),
             value: Default::default(),
             rec_type: LogRecordType::TXNFINISH,
+            meta: Default::default(),
+        });
+        let (positions, inflight) = self.engine.append_log_records(&records)?;
+        let positions = positions
+            .into_iter()
+            .zip(pending_writes.keys())
+            .map(|(pos, key)| (key.clone(), pos))
+            .collect::<HashMap<_, _>>();
+        let ticket = self.engine.commit_pipeline.issue();
+        drop(lock);
+        let append_latency = append_start.elapsed();
+
+        // 持久化，并发提交的多个事务合并为一次 fsync
+        // 索引更新之前一直持有 inflight，不能刷新内存写缓冲
+        let pipeline = &self.engine.commit_pipeline;
+        let fsync_start = Instant::now();
+        let sync_res = match self.options.sync_writes {
+            true => pipeline.sync(ticket, || self.engine.sync_files()),
+            false => Ok(()),
         };
-        self.engine.append_log_record(&mut finish_record)?;
-
-        // 持久化
-        if self.options.sync_writes {
-            self.engine.sync()?;
-        }
+        let fsync_latency = fsync_start.elapsed();
 
-        // 数据全部写完之后更新内存索引
-        for (_, item) in pending_writes.iter() {
-            let reord_pos = positions.get(&item.key).unwrap();
-            if item.rec_type == LogRecordType::NORMAL {
-                self.engine.index.put(item.key.clone(), *reord_pos);
+        // 数据全部写完之后按照提交顺序更新内存索引
+        let index_start = Instant::now();
+        pipeline.apply(ticket, || {
+            if sync_res.is_err() {
+                return;
             }
-            if item.rec_type == LogRecordType::DELETED {
-                self.engine.index.delete(item.key.clone());
+            for (_, item) in pending_writes.iter() {
+                let reord_pos = positions.get(&item.key).unwrap();
+                self.engine.mark_stale(&item.key);
+                if item.rec_type == LogRecordType::NORMAL {
+                    self.engine.index.put(item.key.clone(), *reord_pos);
+                    let value_len = Some(item.value.len());
+                    self.engine
+                        .record_mutation(AuditOp::Put, &item.key, seq_no, value_len);
+                }
+                if item.rec_type == LogRecordType::DELETED {
+                    self.engine.index.delete(item.key.clone());
+                    self.engine
+                        .record_mutation(AuditOp::Delete, &item.key, seq_no, None);
+                }
+                self.engine.apply_key_ttl_record(item);
             }
+        });
+        drop(inflight);
+        let index_latency = index_start.elapsed();
+        sync_res?;
+        self.engine.apply_quota(quota_deltas);
+        let user_bytes = pending_writes
+            .values()
+            .map(|item| (item.key.len() + item.value.len() + item.meta.len()) as u64)
+            .sum();
+        self.engine
+            .write_stats
+            .user_bytes
+            .fetch_add(user_bytes, Ordering::Relaxed);
+
+        // 记录事务提交的统计
+        let metrics = &self.engine.batch_metrics;
+        metrics.records.record(pending_writes.len() as u64);
+        metrics.bytes.record(user_bytes);
+        metrics.append_latency.record_duration(append_latency);
+        if self.options.sync_writes {
+            metrics.fsync_latency.record_duration(fsync_latency);
         }
+        metrics.index_latency.record_duration(index_latency);
         //清空暂存数据
         pending_writes.clear();
         Ok(())
@@ -136,28 +332,42 @@ impl WriteBatch<'_> {
 }
 
 // 编码seq no 和 key
-pub(crate) fn log_record_key_with_seq(key: Vec<u8>, seq_no: usize) -> Vec<u8> {
+pub(crate) fn log_record_key_with_seq(key: Vec<u8>, seq_no: u64) -> Vec<u8> {
     let mut enc_key = BytesMut::new();
-    encode_length_delimiter(seq_no, &mut enc_key).unwrap();
+    encode_varint(seq_no, &mut enc_key);
     enc_key.extend_from_slice(&key.to_vec());
     enc_key.to_vec()
 }
 
 // 解析LogRecord的key，拿到实际的key和seq no
-pub(crate) fn parse_log_record_key(key: Vec<u8>) -> (Vec<u8>, usize) {
+pub(crate) fn parse_log_record_key(key: Vec<u8>) -> (Vec<u8>, u64) {
+    try_parse_log_record_key(key).expect("invalid seq no in log record key")
+}
+
+// 解析 key 中的事务序列号，格式不正确时返回错误
+pub(crate) fn try_parse_log_record_key(key: Vec<u8>) -> Result<(Vec<u8>, u64)> {
     let mut buf = BytesMut::new();
     buf.put_slice(&key);
 
-    let seq_no = decode_length_delimiter(&mut buf).unwrap();
+    let seq_no = match decode_varint(&mut buf) {
+        Ok(seq_no) => seq_no,
+        Err(_) => {
+            return Err(invariant_violation(
+                Errors::DataFileCorrupted,
+                format_args!("Invalid seq no in log record key {:?}", key),
+            ))
+        }
+    };
 
-    (buf.to_vec(), seq_no)
+    Ok((buf.to_vec(), seq_no))
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{collections::btree_map::Keys, fs};
+    use std::fs;
 
     use crate::{
+        data::data_file::DataFile,
         options::Options,
         utils::{
             self,
@@ -195,9 +405,9 @@ mod tests {
         println!("{:?}", res1.is_ok());
 
         // 验证事务序列号
-        let seq_no = wb.engine.seq_no.load(Ordering::SeqCst);
+        let seq_no = wb.engine.current_seq();
         // println!("{}", seq_no);
-        assert_eq!(seq_no, 2);
+        assert_eq!(seq_no, 1);
 
         fs::remove_dir_all(opts.dir_path.clone()).unwrap();
     }
@@ -238,37 +448,226 @@ mod tests {
         assert!(keys.is_ok());
         let keys = keys.unwrap();
         // println!("{:?}", keys);
-        assert_eq!(3, keys.len());
+        assert_eq!(4, keys.len());
 
-        let seq_no = wb.engine.seq_no.load(Ordering::SeqCst);
+        let seq_no = wb.engine.current_seq();
         // println!("{}", seq_no);
-        assert_eq!(seq_no, 3);
+        assert_eq!(seq_no, 2);
+        // 重启之后序列号不会重复
+        assert_eq!(engine2.current_seq(), 2);
 
         fs::remove_dir_all(opts.dir_path.clone()).unwrap();
     }
 
-    
     #[test]
     fn test_write_batch_3() {
         let mut opts = Options::default();
         opts.dir_path = "/tmp/bitcask-rs-batch-3".parse().unwrap();
         opts.data_file_size = 64 * 1024 * 1024;
         let engine = Engine::open(opts.clone()).expect("Failed to open engine");
-        
+
         let keys = engine.list_keys();
         println!("{:?}", keys);
 
-
         // let mut wb_opts = WriteBatchOptions::default();
         // wb_opts.max_batch_num = 10000000;
         // let wb  = engine.new_write_batch(wb_opts).unwrap();
 
         // for i in 0..=1000000 {
-        //     let put_res = 
+        //     let put_res =
         //     wb.put(get_test_key(i), get_test_value(i));
         //     assert!(put_res.is_ok());
         // }
 
         // wb.commit();
     }
+
+    #[test]
+    fn test_write_batch_concurrent_sync_commit() {
+        let mut opts = Options::default();
+        opts.dir_path = "/tmp/bitcask-rs-batch-concurrent".parse().unwrap();
+        let engine = Arc::new(Engine::open(opts.clone()).expect("Failed to open engine"));
+
+        let handles = (0..4)
+            .map(|t| {
+                let engine = engine.clone();
+                std::thread::spawn(move || {
+                    for i in 0..25 {
+                        let wb = engine.new_write_batch(Default::default()).unwrap();
+                        wb.put(get_test_key(t * 100 + i), get_test_value(i))
+                            .unwrap();
+                        wb.put(get_test_key(10000 + t), get_test_value(i)).unwrap();
+                        assert!(wb.commit().is_ok());
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        // 每个事务都持久化，但 fsync 的次数不会超过事务数
+        assert!(engine.commit_pipeline.sync_count() <= 100);
+        let metrics = engine.batch_metrics();
+        assert_eq!(metrics.records.count, 100);
+        assert_eq!(metrics.records.max, 2);
+        assert_eq!(metrics.fsync_latency.count, 100);
+        assert!(metrics.bytes.sum > 0);
+        assert_eq!(metrics.index_latency.count, 100);
+        assert_eq!(engine.list_keys().unwrap().len(), 104);
+        for t in 0..4 {
+            assert_eq!(
+                engine.get(get_test_key(10000 + t)).unwrap(),
+                get_test_value(24)
+            );
+        }
+
+        // 重启之后数据完整
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("Failed to open engine");
+        assert_eq!(engine.list_keys().unwrap().len(), 104);
+
+        fs::remove_dir_all(opts.dir_path.clone()).unwrap();
+    }
+
+    #[test]
+    fn test_write_batch_single_file() {
+        let mut opts = Options::default();
+        opts.dir_path = "/tmp/bitcask-rs-batch-single-file".parse().unwrap();
+        opts.data_file_size = 1024;
+        let engine = Engine::open(opts.clone()).expect("Failed to open engine");
+        for i in 0..5 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+
+        // 事务的数据不会被拆分到多个数据文件中
+        let wb = engine.new_write_batch(Default::default()).unwrap();
+        for i in 100..120 {
+            wb.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        wb.commit().unwrap();
+        let file_ids = (100..120)
+            .map(|i| engine.index.get(get_test_key(i).to_vec()).unwrap().file_id)
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(file_ids.len(), 1);
+        assert!(*file_ids.iter().next().unwrap() > 0);
+
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("Failed to open engine");
+        assert_eq!(engine.list_keys().unwrap().len(), 25);
+
+        fs::remove_dir_all(opts.dir_path.clone()).unwrap();
+    }
+
+    #[test]
+    fn test_write_batch_tiny_data_file() {
+        let mut opts = Options::default();
+        opts.dir_path = "/tmp/bitcask-rs-batch-tiny-file".parse().unwrap();
+        // 一个事务的数据量超过数据文件大小
+        opts.data_file_size = 256;
+        let engine = Engine::open(opts.clone()).expect("Failed to open engine");
+        for i in 0..30 {
+            let wb = engine.new_write_batch(Default::default()).unwrap();
+            for j in 0..3 {
+                wb.put(get_test_key(i * 10 + j), get_test_value(j)).unwrap();
+            }
+            wb.commit().unwrap();
+
+            let file_ids = (0..3)
+                .map(|j| {
+                    let key = get_test_key(i * 10 + j).to_vec();
+                    engine.index.get(key).unwrap().file_id
+                })
+                .collect::<std::collections::HashSet<_>>();
+            assert_eq!(file_ids.len(), 1);
+        }
+        assert!(engine.stat().unwrap().data_file_num >= 30);
+
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("Failed to open engine");
+        assert_eq!(engine.list_keys().unwrap().len(), 90);
+
+        fs::remove_dir_all(opts.dir_path.clone()).unwrap();
+    }
+
+    #[test]
+    fn test_write_batch_txn_finish_in_next_file() {
+        let mut opts = Options::default();
+        opts.dir_path = "/tmp/bitcask-rs-batch-split-files".parse().unwrap();
+        fs::create_dir_all(&opts.dir_path).unwrap();
+
+        // 旧版本写入的事务可能跨越两个数据文件，事务完成标识在下一个文件中
+        let record = |key: &[u8], rec_type| LogRecord {
+            key: log_record_key_with_seq(key.to_vec(), 1),
+            value: "value".as_bytes().to_vec(),
+            rec_type,
+            meta: Default::default(),
+        };
+        let file0 = DataFile::new(opts.dir_path.clone(), 0, opts.io_type).unwrap();
+        file0
+            .write(&record(b"a", LogRecordType::NORMAL).encode())
+            .unwrap();
+        file0
+            .write(&record(b"b", LogRecordType::NORMAL).encode())
+            .unwrap();
+        file0.sync().unwrap();
+        let file1 = DataFile::new(opts.dir_path.clone(), 1, opts.io_type).unwrap();
+        file1
+            .write(&record(b"c", LogRecordType::NORMAL).encode())
+            .unwrap();
+        file1
+            .write(&record(TXN_FINISH, LogRecordType::TXNFINISH).encode())
+            .unwrap();
+        // 没有完成标识的事务不生效
+        let mut unfinished = record(b"d", LogRecordType::NORMAL);
+        unfinished.key = log_record_key_with_seq(b"d".to_vec(), 2);
+        file1.write(&unfinished.encode()).unwrap();
+        file1.sync().unwrap();
+        std::mem::drop((file0, file1));
+
+        let engine = Engine::open(opts.clone()).expect("Failed to open engine");
+        assert_eq!(engine.list_keys().unwrap().len(), 3);
+        assert_eq!(engine.get(Bytes::from("a")).unwrap(), Bytes::from("value"));
+        assert!(engine.get(Bytes::from("d")).is_err());
+        assert_eq!(engine.current_seq(), 2);
+
+        fs::remove_dir_all(opts.dir_path.clone()).unwrap();
+    }
+
+    #[test]
+    fn test_write_batch_stage_limit() {
+        let mut opts = Options::default();
+        opts.dir_path = "/tmp/bitcask-rs-batch-stage-limit".parse().unwrap();
+        let engine = Engine::open(opts.clone()).expect("Failed to open engine");
+
+        let wb = engine
+            .new_write_batch(WriteBatchOptions {
+                max_batch_num: 2,
+                max_batch_bytes: 20,
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(wb.put(Bytes::from("a"), Bytes::from("1")).is_ok());
+        assert!(wb.put(Bytes::from("b"), Bytes::from("1")).is_ok());
+        // 暂存时就检查数据条数，覆盖已经暂存的 key 不受影响
+        let res = wb.put(Bytes::from("c"), Bytes::from("1"));
+        assert_eq!(res.err().unwrap(), Errors::ExceedMaxBatchNum);
+        let res = wb.delete(Bytes::from("c"));
+        assert_eq!(res.err().unwrap(), Errors::ExceedMaxBatchNum);
+        assert!(wb.put(Bytes::from("a"), Bytes::from("12345")).is_ok());
+
+        // 检查数据量，替换的数据按差值计算
+        let res = wb.put(Bytes::from("b"), Bytes::from("0123456789abcdef"));
+        assert_eq!(res.err().unwrap(), Errors::ExceedMaxBatchBytes);
+        assert!(wb
+            .put(Bytes::from("b"), Bytes::from("0123456789ab"))
+            .is_ok());
+
+        assert!(wb.commit().is_ok());
+        assert_eq!(engine.get(Bytes::from("a")).unwrap(), Bytes::from("12345"));
+        // 提交之后重新计算
+        assert!(wb.put(Bytes::from("c"), Bytes::from("1")).is_ok());
+
+        fs::remove_dir_all(opts.dir_path.clone()).unwrap();
+    }
 }
@@ -0,0 +1,168 @@
+use parking_lot::{Condvar, Mutex};
+
+use crate::errors::Result;
+
+#[derive(Default)]
+struct PipelineState {
+    // 已经分配的最大序号
+    issued: u64,
+    // 已经持久化的最大序号
+    synced: u64,
+    // 是否有提交正在持久化
+    syncing: bool,
+    // 已经更新索引的最大序号
+    applied: u64,
+    // 持久化的次数
+    sync_count: u64,
+}
+
+/// 事务提交流水线
+/// 事务在提交锁内写入数据并按顺序领取序号，释放锁之后再持久化，
+/// 同时等待持久化的多个事务只由其中一个执行 fsync，之后按照序号顺序更新索引
+#[derive(Default)]
+pub(crate) struct CommitPipeline {
+    state: Mutex<PipelineState>,
+    cond: Condvar,
+}
+
+impl CommitPipeline {
+    // 领取序号，需要在提交锁内、数据写入完成之后调用
+    pub(crate) fn issue(&self) -> u64 {
+        let mut state = self.state.lock();
+        state.issued += 1;
+        state.issued
+    }
+
+    // 保证序号不超过 ticket 的事务都已经持久化
+    // 没有其他提交正在持久化时由当前提交执行 sync，一次覆盖之前领取序号的所有事务
+    pub(crate) fn sync<F>(&self, ticket: u64, sync: F) -> Result<()>
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        let mut state = self.state.lock();
+        loop {
+            if state.synced >= ticket {
+                return Ok(());
+            }
+            if !state.syncing {
+                break;
+            }
+            self.cond.wait(&mut state);
+        }
+
+        state.syncing = true;
+        let target = state.issued;
+        drop(state);
+        let res = sync();
+
+        let mut state = self.state.lock();
+        state.syncing = false;
+        state.sync_count += 1;
+        if res.is_ok() {
+            state.synced = std::cmp::max(state.synced, target);
+        }
+        self.cond.notify_all();
+        res
+    }
+
+    // 等待之前的事务都更新完索引之后再执行 apply，保证索引的更新顺序和写入顺序一致
+    // 持久化失败的提交也需要调用，否则之后的提交会一直等待
+    pub(crate) fn apply<F>(&self, ticket: u64, apply: F)
+    where
+        F: FnOnce(),
+    {
+        let mut state = self.state.lock();
+        while state.applied + 1 < ticket {
+            self.cond.wait(&mut state);
+        }
+        drop(state);
+
+        // apply 发生 panic 时也要推进序号
+        let _guard = AppliedGuard {
+            pipeline: self,
+            ticket,
+        };
+        apply();
+    }
+
+    #[cfg(test)]
+    pub(crate) fn sync_count(&self) -> u64 {
+        self.state.lock().sync_count
+    }
+}
+
+struct AppliedGuard<'a> {
+    pipeline: &'a CommitPipeline,
+    ticket: u64,
+}
+
+impl Drop for AppliedGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.pipeline.state.lock();
+        state.applied = std::cmp::max(state.applied, self.ticket);
+        self.pipeline.cond.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use crate::errors::Errors;
+
+    use super::*;
+
+    #[test]
+    fn test_commit_pipeline_coalesce_sync() {
+        let pipeline = CommitPipeline::default();
+        let syncs = AtomicU64::new(0);
+        let tickets = (0..3).map(|_| pipeline.issue()).collect::<Vec<_>>();
+        assert_eq!(tickets, vec![1, 2, 3]);
+
+        // 第一次 sync 覆盖了之前领取序号的所有事务
+        for ticket in tickets.iter() {
+            let res = pipeline.sync(*ticket, || {
+                syncs.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            });
+            assert!(res.is_ok());
+        }
+        assert_eq!(syncs.load(Ordering::SeqCst), 1);
+
+        // sync 失败之后的事务重新持久化
+        let ticket = pipeline.issue();
+        let err = || Errors::FailedToSyncFile(std::io::ErrorKind::Other.into());
+        let res = pipeline.sync(ticket, || Err(err()));
+        assert_eq!(res.err().unwrap(), err());
+        assert!(pipeline.sync(ticket, || Ok(())).is_ok());
+        assert_eq!(pipeline.sync_count(), 3);
+    }
+
+    #[test]
+    fn test_commit_pipeline_apply_in_order() {
+        let pipeline = Arc::new(CommitPipeline::default());
+        let tickets = (0..4).map(|_| pipeline.issue()).collect::<Vec<_>>();
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        // 逆序启动，仍然按照序号顺序更新
+        let handles = tickets
+            .into_iter()
+            .rev()
+            .map(|ticket| {
+                let pipeline = pipeline.clone();
+                let order = order.clone();
+                thread::spawn(move || pipeline.apply(ticket, || order.lock().push(ticket)))
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*order.lock(), vec![1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,564 @@
+//! 存储引擎的命令行工具
+//!
+//! kvctl fmt-dump <data file>  以带注释的十六进制格式显示数据文件中的记录
+//! kvctl fmt-doc               显示数据文件的格式说明
+//! kvctl import --format <jsonl|csv> <db dir> <file>    从文件批量导入数据，需要 json 特性
+//! kvctl export --format <jsonl|csv> <db dir> [file]    导出所有数据，不指定文件时输出到标准输出
+//! kvctl shell <db dir>        打开数据库并进入交互式命令行，输入 help 查看支持的命令
+
+use std::{path::Path, process::ExitCode};
+
+use kv_store::{data::data_file::DATA_FILE_NAME_SUFFIX, dump};
+
+const USAGE: &str = "usage:
+  kvctl fmt-dump <data file>
+  kvctl fmt-doc
+  kvctl import --format <jsonl|csv> <db dir> <file>
+  kvctl export --format <jsonl|csv> <db dir> [file]
+  kvctl shell <db dir>";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["fmt-dump", path] => fmt_dump(Path::new(path)),
+        ["fmt-doc"] => {
+            print!("{}", dump::format_doc());
+            ExitCode::SUCCESS
+        }
+        ["import", "--format", format, dir, file] => bulk::import(format, dir, file),
+        ["export", "--format", format, dir] => bulk::export(format, dir, None),
+        ["export", "--format", format, dir, file] => bulk::export(format, dir, Some(file)),
+        ["shell", dir] => shell::run(dir),
+        _ => {
+            eprintln!("{USAGE}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn fmt_dump(path: &Path) -> ExitCode {
+    // 数据文件的名称是文件 id 加上后缀
+    let file_id = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix(DATA_FILE_NAME_SUFFIX))
+        .and_then(|id| id.parse::<u32>().ok());
+    let file_id = match file_id {
+        Some(file_id) => file_id,
+        None => {
+            eprintln!("{:?} is not a data file", path);
+            return ExitCode::from(2);
+        }
+    };
+    let dir_path = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    match dump::dump_data_file(dir_path, file_id) {
+        Ok(dump) => {
+            print!("{dump}");
+            match dump.error {
+                None => ExitCode::SUCCESS,
+                Some(_) => ExitCode::FAILURE,
+            }
+        }
+        Err(e) => {
+            eprintln!("failed to read {:?}: {e}", path);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+mod shell {
+    use std::{
+        fmt::Write as _,
+        io::{self, BufRead, Write},
+        path::PathBuf,
+        process::ExitCode,
+    };
+
+    use bytes::Bytes;
+    use kv_store::{
+        db::Engine,
+        debug::KeyDisplay,
+        options::{IteratorOptions, Options},
+        Errors,
+    };
+
+    const HELP: &str = "commands:
+  get <key>              读取 key 的 value
+  put <key> <value>      写入数据
+  del <key>              删除 key
+  scan [prefix] [limit]  按顺序列出以 prefix 开头的数据，默认最多 100 条
+  stat                   显示统计信息
+  merge                  合并所有已封存的数据文件
+  mode <hex|utf8>        切换 key 和 value 的显示方式
+  history                显示执行过的命令，!<n> 重新执行第 n 条命令
+  help                   显示本帮助
+  quit                   退出
+参数中可以使用双引号包含空格，以 0x 开头的参数按十六进制解析";
+
+    // scan 默认显示的数量
+    const DEFAULT_SCAN_LIMIT: usize = 100;
+
+    // key 和 value 的显示方式
+    #[derive(Clone, Copy)]
+    enum Mode {
+        // 可读的 UTF-8 显示为字符串，否则显示为十六进制
+        Utf8,
+        // 总是显示为十六进制
+        Hex,
+    }
+
+    struct Shell {
+        engine: Engine,
+        mode: Mode,
+        history: Vec<String>,
+    }
+
+    pub fn run(dir: &str) -> ExitCode {
+        let opts = Options {
+            dir_path: PathBuf::from(dir),
+            ..Default::default()
+        };
+        let engine = match Engine::open(opts) {
+            Ok(engine) => engine,
+            Err(e) => {
+                eprintln!("failed to open database at {dir}: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+        let mut shell = Shell {
+            engine,
+            mode: Mode::Utf8,
+            history: Vec::new(),
+        };
+
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        loop {
+            print!("kv> ");
+            let _ = io::stdout().flush();
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(e)) => {
+                    eprintln!("failed to read input: {e}");
+                    break;
+                }
+                // 输入结束时退出
+                None => {
+                    println!();
+                    break;
+                }
+            };
+            if !shell.execute(line.trim()) {
+                break;
+            }
+        }
+
+        match shell.engine.close() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("failed to close database: {e}");
+                ExitCode::FAILURE
+            }
+        }
+    }
+
+    impl Shell {
+        // 执行一行命令，返回 false 时退出
+        fn execute(&mut self, line: &str) -> bool {
+            if line.is_empty() {
+                return true;
+            }
+            // !n 重新执行历史中的命令，重新执行的命令本身也会记录到历史中
+            let line = match line.strip_prefix('!') {
+                Some(n) => match n
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| self.history.get(n.checked_sub(1)?))
+                {
+                    Some(line) => {
+                        let line = line.clone();
+                        println!("{line}");
+                        line
+                    }
+                    None => {
+                        println!("error: no such command in history: {line}");
+                        return true;
+                    }
+                },
+                None => line.to_string(),
+            };
+            self.history.push(line.clone());
+
+            let args = match split_args(&line) {
+                Ok(args) => args,
+                Err(msg) => {
+                    println!("error: {msg}");
+                    return true;
+                }
+            };
+            let args = args.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            let res = match args.as_slice() {
+                [b"quit" | b"exit"] => return false,
+                [b"help"] => {
+                    println!("{HELP}");
+                    Ok(())
+                }
+                [b"get", key] => self.get(key),
+                [b"put", key, value] => self.put(key, value),
+                [b"del", key] => self.del(key),
+                [b"scan"] => self.scan(b"", DEFAULT_SCAN_LIMIT),
+                [b"scan", prefix] => self.scan(prefix, DEFAULT_SCAN_LIMIT),
+                [b"scan", prefix, limit] => {
+                    match std::str::from_utf8(limit).ok().and_then(|l| l.parse().ok()) {
+                        Some(limit) => self.scan(prefix, limit),
+                        None => {
+                            println!("error: invalid limit");
+                            Ok(())
+                        }
+                    }
+                }
+                [b"stat"] => self.stat(),
+                [b"merge"] => self.merge(),
+                [b"mode", b"hex"] => {
+                    self.mode = Mode::Hex;
+                    Ok(())
+                }
+                [b"mode", b"utf8"] => {
+                    self.mode = Mode::Utf8;
+                    Ok(())
+                }
+                [b"history"] => {
+                    for (i, line) in self.history.iter().enumerate() {
+                        println!("{:>4}  {line}", i + 1);
+                    }
+                    Ok(())
+                }
+                _ => {
+                    println!("error: unknown command, type help for usage");
+                    Ok(())
+                }
+            };
+            if let Err(e) = res {
+                println!("error: {e}");
+            }
+            true
+        }
+
+        fn get(&self, key: &[u8]) -> Result<(), Errors> {
+            match self.engine.try_get(Bytes::copy_from_slice(key))? {
+                Some(value) => println!("{}", self.display(&value)),
+                None => println!("(not found)"),
+            }
+            Ok(())
+        }
+
+        fn put(&self, key: &[u8], value: &[u8]) -> Result<(), Errors> {
+            self.engine
+                .put(Bytes::copy_from_slice(key), Bytes::copy_from_slice(value))?;
+            println!("OK");
+            Ok(())
+        }
+
+        fn del(&self, key: &[u8]) -> Result<(), Errors> {
+            self.engine.delete(Bytes::copy_from_slice(key))?;
+            println!("OK");
+            Ok(())
+        }
+
+        fn scan(&self, prefix: &[u8], limit: usize) -> Result<(), Errors> {
+            let iter = self.engine.try_iter(IteratorOptions {
+                prefix: prefix.to_vec(),
+                ..Default::default()
+            })?;
+            let mut count = 0;
+            while let Some((key, value)) = iter.next() {
+                if count == limit {
+                    println!("... (more than {limit} entries)");
+                    return Ok(());
+                }
+                println!("{} => {}", self.display(&key), self.display(&value));
+                count += 1;
+            }
+            println!("({count} entries)");
+            Ok(())
+        }
+
+        fn stat(&self) -> Result<(), Errors> {
+            let stat = self.engine.stat()?;
+            println!("keys: {}", stat.key_num);
+            println!("data files: {}", stat.data_file_num);
+            println!("disk size: {} bytes", stat.disk_size);
+            println!("user bytes written: {}", stat.user_bytes_written);
+            println!("data bytes written: {}", stat.data_bytes_written);
+            println!("merge bytes written: {}", stat.merge_bytes_written);
+            println!("write amplification: {:.2}", stat.write_amplification);
+            Ok(())
+        }
+
+        fn merge(&self) -> Result<(), Errors> {
+            let count = self.engine.merge()?;
+            println!("merged, {count} records rewritten");
+            Ok(())
+        }
+
+        fn display(&self, data: &[u8]) -> String {
+            match self.mode {
+                Mode::Utf8 => KeyDisplay(data).to_string(),
+                Mode::Hex => {
+                    let mut s = String::with_capacity(2 + data.len() * 2);
+                    s.push_str("0x");
+                    for b in data {
+                        let _ = write!(s, "{b:02x}");
+                    }
+                    s
+                }
+            }
+        }
+    }
+
+    // 按空白拆分参数，双引号中的内容作为一个参数，其中可以使用 \" 和 \\ 转义
+    // 以 0x 开头的参数按十六进制解析为字节
+    fn split_args(line: &str) -> Result<Vec<Vec<u8>>, &'static str> {
+        let mut args = Vec::new();
+        let mut chars = line.chars().peekable();
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if chars.peek().is_none() {
+                return Ok(args);
+            }
+            let mut arg = String::new();
+            if chars.next_if_eq(&'"').is_some() {
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => arg.push(c),
+                            _ => return Err("invalid escape in quoted argument"),
+                        },
+                        Some(c) => arg.push(c),
+                        None => return Err("unterminated quoted argument"),
+                    }
+                }
+                args.push(arg.into_bytes());
+                continue;
+            }
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.push(c);
+            }
+            match arg.strip_prefix("0x") {
+                Some(hex) => args.push(decode_hex(hex).ok_or("invalid hex argument")?),
+                None => args.push(arg.into_bytes()),
+            }
+        }
+    }
+
+    fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+        if !hex.len().is_multiple_of(2) {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+}
+
+#[cfg(feature = "json")]
+mod bulk {
+    use std::{
+        fs::File,
+        io::{self, BufRead, BufReader, BufWriter, Write},
+        path::PathBuf,
+        process::ExitCode,
+    };
+
+    use kv_store::{
+        bulk::BulkFormat, db::Engine, debug::KeyDisplay, options::Options, ErrorCategory, Errors,
+    };
+
+    // 每处理这么多条记录显示一次进度
+    const PROGRESS_INTERVAL: usize = 10_000;
+
+    pub fn import(format: &str, dir: &str, file: &str) -> ExitCode {
+        let format = match format.parse::<BulkFormat>() {
+            Ok(format) => format,
+            Err(e) => return usage_error(e),
+        };
+        let reader = match File::open(file) {
+            Ok(f) => BufReader::new(f),
+            Err(e) => {
+                eprintln!("failed to open {file}: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+        let engine = match open_engine(dir) {
+            Some(engine) => engine,
+            None => return ExitCode::FAILURE,
+        };
+        let mut loader = match engine.bulk_loader() {
+            Ok(loader) => loader,
+            Err(e) => return engine_error(&engine, e),
+        };
+
+        // 格式错误、key 不合法等问题只跳过这一行，读写失败时停止导入
+        let mut errors = 0;
+        for (i, line) in reader.lines().enumerate() {
+            let line_no = i + 1;
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("\nfailed to read {file} at line {line_no}: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            let line = line.strip_suffix('\r').unwrap_or(&line);
+            if line.is_empty() {
+                continue;
+            }
+            let res = format
+                .decode(line)
+                .and_then(|(key, value)| loader.put(key, value));
+            match res {
+                Ok(()) => {}
+                Err(e) if e.category() == ErrorCategory::Usage => {
+                    eprintln!("\nline {line_no}: {e}");
+                    errors += 1;
+                }
+                Err(e) => {
+                    eprintln!("\nline {line_no}: {e}");
+                    return engine_error(&engine, e);
+                }
+            }
+            if loader.loaded() % PROGRESS_INTERVAL == 0 && loader.loaded() > 0 {
+                eprint!("\rimported {} records", loader.loaded());
+            }
+        }
+
+        let imported = match loader.finish() {
+            Ok(imported) => imported,
+            Err(e) => return engine_error(&engine, e),
+        };
+        if let Err(e) = engine.close() {
+            return engine_error(&engine, e);
+        }
+        eprintln!("\rimported {imported} records, {errors} lines failed");
+        match errors {
+            0 => ExitCode::SUCCESS,
+            _ => ExitCode::FAILURE,
+        }
+    }
+
+    pub fn export(format: &str, dir: &str, file: Option<&str>) -> ExitCode {
+        let format = match format.parse::<BulkFormat>() {
+            Ok(format) => format,
+            Err(e) => return usage_error(e),
+        };
+        let mut writer: BufWriter<Box<dyn Write>> = match file {
+            Some(file) => match File::create(file) {
+                Ok(f) => BufWriter::new(Box::new(f)),
+                Err(e) => {
+                    eprintln!("failed to create {file}: {e}");
+                    return ExitCode::FAILURE;
+                }
+            },
+            None => BufWriter::new(Box::new(io::stdout())),
+        };
+        let engine = match open_engine(dir) {
+            Some(engine) => engine,
+            None => return ExitCode::FAILURE,
+        };
+
+        // 无法按行表示的记录跳过，并显示对应的 key
+        let (mut exported, mut skipped) = (0, 0);
+        let iter = match engine.try_iter(Default::default()) {
+            Ok(iter) => iter,
+            Err(e) => {
+                eprintln!("failed to read database: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+        while let Some((key, value)) = iter.next() {
+            let line = match format.encode(&key, &value) {
+                Some(line) => line,
+                None => {
+                    eprintln!(
+                        "\nskipped {}: not representable as a {format:?} line",
+                        KeyDisplay(&key)
+                    );
+                    skipped += 1;
+                    continue;
+                }
+            };
+            if let Err(e) = writeln!(writer, "{line}") {
+                eprintln!("\nfailed to write: {e}");
+                return ExitCode::FAILURE;
+            }
+            exported += 1;
+            if exported % PROGRESS_INTERVAL == 0 {
+                eprint!("\rexported {exported} records");
+            }
+        }
+        drop(iter);
+        if let Err(e) = writer.flush() {
+            eprintln!("failed to write: {e}");
+            return ExitCode::FAILURE;
+        }
+        if let Err(e) = engine.close() {
+            return engine_error(&engine, e);
+        }
+        eprintln!("\rexported {exported} records, {skipped} skipped");
+        match skipped {
+            0 => ExitCode::SUCCESS,
+            _ => ExitCode::FAILURE,
+        }
+    }
+
+    fn open_engine(dir: &str) -> Option<Engine> {
+        let opts = Options {
+            dir_path: PathBuf::from(dir),
+            ..Default::default()
+        };
+        match Engine::open(opts) {
+            Ok(engine) => Some(engine),
+            Err(e) => {
+                eprintln!("failed to open database at {dir}: {e}");
+                None
+            }
+        }
+    }
+
+    fn engine_error(engine: &Engine, e: Errors) -> ExitCode {
+        eprintln!("failed: {e}");
+        let _ = engine.close();
+        ExitCode::FAILURE
+    }
+
+    fn usage_error(e: Errors) -> ExitCode {
+        eprintln!("{e}");
+        ExitCode::from(2)
+    }
+}
+
+// 没有启用 json 特性时不支持导入导出
+#[cfg(not(feature = "json"))]
+mod bulk {
+    use std::process::ExitCode;
+
+    pub fn import(_format: &str, _dir: &str, _file: &str) -> ExitCode {
+        unsupported()
+    }
+
+    pub fn export(_format: &str, _dir: &str, _file: Option<&str>) -> ExitCode {
+        unsupported()
+    }
+
+    fn unsupported() -> ExitCode {
+        eprintln!("kvctl is built without the json feature, rebuild with --features json");
+        ExitCode::from(2)
+    }
+}
@@ -0,0 +1,172 @@
+use bytes::Bytes;
+
+use crate::{
+    db::{Engine, INTERNAL_KEY_PREFIX},
+    errors::{Errors, Result},
+    options::WriteBatchOptions,
+};
+
+// 内容记录在内部前缀下，key 为 BLAKE3 摘要
+const BLOB_PREFIX: &[u8] = b"blob/";
+// 引用计数，value 为 8 字节大端序整数
+const BLOB_REF_PREFIX: &[u8] = b"blob-ref/";
+
+// 摘要的长度
+const BLOB_KEY_SIZE: usize = 32;
+
+impl Engine {
+    /// 按内容寻址保存 value，返回 value 的 BLAKE3 摘要作为 key
+    /// 相同的内容只保存一次，每次保存引用计数加一，需要调用同样次数的 release_blob 才会删除
+    pub fn put_cas_blob(&self, value: Bytes) -> Result<Bytes> {
+        let hash = Bytes::copy_from_slice(blake3::hash(&value).as_bytes());
+        let _lock = self.blob_lock.lock();
+        let refs = self.blob_refs(&hash)?;
+
+        let wb = self.new_write_batch(WriteBatchOptions {
+            sync_writes: self.options.sync_write,
+            ..Default::default()
+        })?;
+        // 内容已经存在时只更新引用计数
+        if refs == 0 {
+            wb.put_unchecked(blob_key(BLOB_PREFIX, &hash), value)?;
+        }
+        wb.put_unchecked(
+            blob_key(BLOB_REF_PREFIX, &hash),
+            Bytes::copy_from_slice(&(refs + 1).to_be_bytes()),
+        )?;
+        wb.commit()?;
+        Ok(hash)
+    }
+
+    /// 读取 put_cas_blob 保存的内容
+    pub fn get_cas_blob(&self, key: Bytes) -> Result<Bytes> {
+        check_blob_key(&key)?;
+        self.get(blob_key(BLOB_PREFIX, &key))
+    }
+
+    /// 内容当前的引用计数，内容不存在时返回 0
+    pub fn blob_ref_count(&self, key: Bytes) -> Result<u64> {
+        check_blob_key(&key)?;
+        self.blob_refs(&key)
+    }
+
+    /// 释放一次引用，返回剩余的引用计数，计数为 0 时删除内容
+    /// 内容不存在时返回 Errors::KeyNotFound
+    pub fn release_blob(&self, key: Bytes) -> Result<u64> {
+        check_blob_key(&key)?;
+        let _lock = self.blob_lock.lock();
+        let refs = match self.blob_refs(&key)? {
+            0 => return Err(Errors::KeyNotFound),
+            refs => refs - 1,
+        };
+
+        let wb = self.new_write_batch(WriteBatchOptions {
+            sync_writes: self.options.sync_write,
+            ..Default::default()
+        })?;
+        if refs == 0 {
+            wb.delete_unchecked(blob_key(BLOB_PREFIX, &key))?;
+            wb.delete_unchecked(blob_key(BLOB_REF_PREFIX, &key))?;
+        } else {
+            wb.put_unchecked(
+                blob_key(BLOB_REF_PREFIX, &key),
+                Bytes::copy_from_slice(&refs.to_be_bytes()),
+            )?;
+        }
+        wb.commit()?;
+        Ok(refs)
+    }
+
+    fn blob_refs(&self, hash: &[u8]) -> Result<u64> {
+        match self.get(blob_key(BLOB_REF_PREFIX, hash)) {
+            Ok(value) => match <[u8; 8]>::try_from(value.as_ref()) {
+                Ok(bytes) => Ok(u64::from_be_bytes(bytes)),
+                Err(_) => Err(Errors::DataFileCorrupted),
+            },
+            Err(Errors::KeyNotFound) => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn check_blob_key(key: &[u8]) -> Result<()> {
+    match key.len() {
+        BLOB_KEY_SIZE => Ok(()),
+        0 => Err(Errors::KeyIsEmpty),
+        _ => Err(Errors::InvalidKey),
+    }
+}
+
+fn blob_key(prefix: &[u8], hash: &[u8]) -> Bytes {
+    let mut key = Vec::with_capacity(INTERNAL_KEY_PREFIX.len() + prefix.len() + hash.len());
+    key.extend_from_slice(INTERNAL_KEY_PREFIX);
+    key.extend_from_slice(prefix);
+    key.extend_from_slice(hash);
+    key.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{options::Options, utils::to_hex};
+
+    use super::*;
+
+    #[test]
+    fn test_cas_blob() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-cas-blob");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let key = engine.put_cas_blob(Bytes::from("abc")).unwrap();
+        assert_eq!(
+            to_hex(&key),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        // 相同的内容只保存一次
+        assert_eq!(engine.put_cas_blob(Bytes::from("abc")).unwrap(), key);
+        assert_eq!(engine.blob_ref_count(key.clone()).unwrap(), 2);
+        let other = engine.put_cas_blob(Bytes::from("def")).unwrap();
+        assert_ne!(other, key);
+        assert_eq!(
+            engine.get_cas_blob(key.clone()).unwrap(),
+            Bytes::from("abc")
+        );
+        // 内容对用户不可见
+        assert!(engine.list_keys().unwrap().is_empty());
+
+        // 引用计数在重启之后仍然有效
+        engine.close().expect("failed to close engine");
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(engine.release_blob(key.clone()).unwrap(), 1);
+        assert_eq!(
+            engine.get_cas_blob(key.clone()).unwrap(),
+            Bytes::from("abc")
+        );
+        assert_eq!(engine.release_blob(key.clone()).unwrap(), 0);
+        assert_eq!(
+            engine.get_cas_blob(key.clone()).err().unwrap(),
+            Errors::KeyNotFound
+        );
+        assert_eq!(
+            engine.release_blob(key.clone()).err().unwrap(),
+            Errors::KeyNotFound
+        );
+        assert_eq!(engine.blob_ref_count(key.clone()).unwrap(), 0);
+        assert_eq!(engine.get_cas_blob(other).unwrap(), Bytes::from("def"));
+
+        // 删除之后可以重新保存
+        assert_eq!(engine.put_cas_blob(Bytes::from("abc")).unwrap(), key);
+        assert_eq!(engine.blob_ref_count(key).unwrap(), 1);
+
+        assert_eq!(
+            engine.get_cas_blob(Bytes::from("abc")).err().unwrap(),
+            Errors::InvalidKey
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,102 @@
+use std::ops::{Deref, DerefMut};
+
+use parking_lot::Mutex;
+
+// 超过这个容量的缓冲区用完之后直接释放，避免一次很大的写入长期占用内存
+pub(crate) const MAX_POOLED_BUFFER_SIZE: usize = 1024 * 1024;
+
+// 最多保留的空闲缓冲区个数
+const MAX_POOLED_BUFFERS: usize = 4;
+
+/// 编码记录时使用的缓冲区池，写入时复用之前分配的内存
+#[derive(Default)]
+pub(crate) struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    // 取出一个空的缓冲区，没有空闲的缓冲区时新建一个
+    pub(crate) fn get(&self) -> PooledBuffer<'_> {
+        let buf = self.buffers.lock().pop().unwrap_or_default();
+        PooledBuffer { buf, pool: self }
+    }
+
+    fn put(&self, mut buf: Vec<u8>) {
+        if buf.capacity() > MAX_POOLED_BUFFER_SIZE {
+            return;
+        }
+        buf.clear();
+        let mut buffers = self.buffers.lock();
+        if buffers.len() < MAX_POOLED_BUFFERS {
+            buffers.push(buf);
+        }
+    }
+}
+
+/// 从缓冲区池中取出的缓冲区，释放时归还
+pub(crate) struct PooledBuffer<'a> {
+    buf: Vec<u8>,
+    pool: &'a BufferPool,
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        self.pool.put(std::mem::take(&mut self.buf));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_pool_reuse() {
+        let pool = BufferPool::default();
+        let ptr = {
+            let mut buf = pool.get();
+            buf.extend_from_slice(b"hello");
+            buf.as_ptr()
+        };
+
+        // 归还的缓冲区被清空之后复用
+        let buf = pool.get();
+        assert!(buf.is_empty());
+        assert!(buf.capacity() >= 5);
+        assert_eq!(buf.as_ptr(), ptr);
+
+        // 同时使用多个缓冲区
+        let buf2 = pool.get();
+        assert_ne!(buf2.as_ptr(), ptr);
+        std::mem::drop(buf);
+        std::mem::drop(buf2);
+        assert_eq!(pool.buffers.lock().len(), 2);
+    }
+
+    #[test]
+    fn test_buffer_pool_limits() {
+        let pool = BufferPool::default();
+        {
+            let mut buf = pool.get();
+            buf.reserve(MAX_POOLED_BUFFER_SIZE + 1);
+        }
+        assert!(pool.buffers.lock().is_empty());
+
+        let bufs: Vec<_> = (0..MAX_POOLED_BUFFERS + 2).map(|_| pool.get()).collect();
+        std::mem::drop(bufs);
+        assert_eq!(pool.buffers.lock().len(), MAX_POOLED_BUFFERS);
+    }
+}
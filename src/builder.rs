@@ -0,0 +1,165 @@
+use crate::{db::Engine, errors::Result, options::Options};
+
+/// 打开存储引擎过程中调用的回调，返回错误时打开失败，错误会返回给调用方
+pub type OpenHook = Box<dyn FnOnce(&Engine) -> Result<()> + Send>;
+
+/// 打开存储引擎的阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenPhase {
+    // 数据文件已经打开，索引还是空的
+    BeforeIndexLoad,
+
+    // 索引已经从数据文件中加载完成，后台任务还没有启动
+    AfterIndexLoad,
+
+    // 打开完成，即将返回给调用方
+    BeforeReturn,
+}
+
+// 按阶段保存的回调，同一阶段的回调按照注册的顺序调用
+#[derive(Default)]
+pub(crate) struct OpenHooks {
+    hooks: Vec<(OpenPhase, OpenHook)>,
+}
+
+impl OpenHooks {
+    pub(crate) fn run(&mut self, phase: OpenPhase, engine: &Engine) -> Result<()> {
+        let hooks = std::mem::take(&mut self.hooks);
+        let (current, rest): (Vec<_>, Vec<_>) = hooks.into_iter().partition(|(p, _)| *p == phase);
+        self.hooks = rest;
+        for (_, hook) in current {
+            hook(engine)?;
+        }
+        Ok(())
+    }
+}
+
+/// 存储引擎构造器，除了配置项之外还可以注册在打开的各个阶段调用的回调，
+/// 用于注册监控、执行数据迁移或者预热缓存
+pub struct EngineBuilder {
+    options: Options,
+    hooks: OpenHooks,
+}
+
+impl EngineBuilder {
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            hooks: OpenHooks::default(),
+        }
+    }
+
+    /// 注册在指定阶段调用的回调
+    pub fn hook<F>(mut self, phase: OpenPhase, f: F) -> Self
+    where
+        F: FnOnce(&Engine) -> Result<()> + Send + 'static,
+    {
+        self.hooks.hooks.push((phase, Box::new(f)));
+        self
+    }
+
+    /// 注册在加载索引之前调用的回调
+    pub fn before_index_load<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Engine) -> Result<()> + Send + 'static,
+    {
+        self.hook(OpenPhase::BeforeIndexLoad, f)
+    }
+
+    /// 注册在加载索引之后调用的回调
+    pub fn after_index_load<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Engine) -> Result<()> + Send + 'static,
+    {
+        self.hook(OpenPhase::AfterIndexLoad, f)
+    }
+
+    /// 注册在打开完成、返回之前调用的回调
+    pub fn before_return<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&Engine) -> Result<()> + Send + 'static,
+    {
+        self.hook(OpenPhase::BeforeReturn, f)
+    }
+
+    /// 打开存储引擎
+    pub fn open(self) -> Result<Engine> {
+        Engine::open_with_hooks(self.options, self.hooks)
+    }
+}
+
+impl Engine {
+    /// 使用构造器打开存储引擎
+    pub fn builder(options: Options) -> EngineBuilder {
+        EngineBuilder::new(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::{
+        errors::Errors,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_engine_builder_hooks() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-builder-hooks");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..10 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (e1, e2, e3) = (events.clone(), events.clone(), events.clone());
+        let engine = Engine::builder(opts.clone())
+            .before_return(move |engine| {
+                e3.lock()
+                    .unwrap()
+                    .push(("return", engine.list_keys()?.len()));
+                Ok(())
+            })
+            .after_index_load(move |engine| {
+                e2.lock()
+                    .unwrap()
+                    .push(("after", engine.list_keys()?.len()));
+                // 在加载之后执行迁移
+                engine.put(get_test_key(100), get_test_value(100))
+            })
+            .before_index_load(move |engine| {
+                e1.lock()
+                    .unwrap()
+                    .push(("before", engine.list_keys()?.len()));
+                Ok(())
+            })
+            .open()
+            .expect("failed to open engine");
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![("before", 0), ("after", 10), ("return", 11)]
+        );
+        assert_eq!(engine.get(get_test_key(100)).unwrap(), get_test_value(100));
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+
+        // 回调返回错误时打开失败
+        let res = EngineBuilder::new(opts.clone())
+            .after_index_load(|_| Err(Errors::InvalidKey))
+            .open();
+        assert_eq!(res.err().unwrap(), Errors::InvalidKey);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,282 @@
+//! 批量导入和导出数据，kvctl import / export 使用，需要 json 特性
+//! 每行一条记录，jsonl 格式为 {"key":"...","value":"..."}，csv 格式为 key,value 两个字段，
+//! 包含逗号、引号的字段使用双引号包围，引号写成两个引号；key 和 value 都需要是 UTF-8 文本
+
+use std::str::FromStr;
+
+use bytes::Bytes;
+
+use crate::{
+    batch::WriteBatch,
+    db::Engine,
+    errors::{Errors, Result},
+    json::JsonValue,
+    options::WriteBatchOptions,
+};
+
+/// 导入导出的文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BulkFormat {
+    Jsonl,
+    Csv,
+}
+
+impl FromStr for BulkFormat {
+    type Err = Errors;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jsonl" => Ok(BulkFormat::Jsonl),
+            "csv" => Ok(BulkFormat::Csv),
+            _ => Err(Errors::InvalidBulkRecord(
+                "unknown format, expected jsonl or csv",
+            )),
+        }
+    }
+}
+
+impl BulkFormat {
+    /// 解析一行记录，返回 key 和 value
+    pub fn decode(&self, line: &str) -> Result<(Bytes, Bytes)> {
+        match self {
+            BulkFormat::Jsonl => decode_jsonl(line),
+            BulkFormat::Csv => decode_csv(line),
+        }
+    }
+
+    /// 编码一条记录，不包含换行符；key 或者 value 不是 UTF-8 文本、
+    /// 或者 csv 格式下包含换行符时无法按行表示，返回 None
+    pub fn encode(&self, key: &[u8], value: &[u8]) -> Option<String> {
+        let key = std::str::from_utf8(key).ok()?;
+        let value = std::str::from_utf8(value).ok()?;
+        match self {
+            BulkFormat::Jsonl => {
+                Some(serde_json::json!({ "key": key, "value": value }).to_string())
+            }
+            BulkFormat::Csv => Some(format!(
+                "{},{}",
+                encode_csv_field(key)?,
+                encode_csv_field(value)?
+            )),
+        }
+    }
+}
+
+/// 批量写入数据，数据分批通过事务写入，写满一个事务时自动提交，结束时统一持久化
+/// 超过事务大小限制的 value 单独写入
+pub struct BulkLoader<'a> {
+    engine: &'a Engine,
+    batch: WriteBatch<'a>,
+    loaded: usize,
+}
+
+impl Engine {
+    pub fn bulk_loader(&self) -> Result<BulkLoader<'_>> {
+        Ok(BulkLoader {
+            engine: self,
+            batch: self.new_write_batch(bulk_batch_options())?,
+            loaded: 0,
+        })
+    }
+}
+
+impl<'a> BulkLoader<'a> {
+    pub fn put(&mut self, key: Bytes, value: Bytes) -> Result<()> {
+        match self.batch.put(key.clone(), value.clone()) {
+            Err(Errors::ExceedMaxBatchNum | Errors::ExceedMaxBatchBytes) => {
+                // 先提交已经暂存的数据，空的事务也放不下时单独写入
+                self.batch.commit()?;
+                self.batch = self.engine.new_write_batch(bulk_batch_options())?;
+                match self.batch.put(key.clone(), value.clone()) {
+                    Err(Errors::ExceedMaxBatchNum | Errors::ExceedMaxBatchBytes) => {
+                        self.engine.put(key, value)?
+                    }
+                    res => res?,
+                }
+            }
+            res => res?,
+        }
+        self.loaded += 1;
+        Ok(())
+    }
+
+    /// 已经写入（包括暂存在还没有提交的事务中）的记录数
+    pub fn loaded(&self) -> usize {
+        self.loaded
+    }
+
+    /// 提交最后一个事务并持久化，返回写入的记录数
+    pub fn finish(self) -> Result<usize> {
+        self.batch.commit()?;
+        self.engine.sync()?;
+        Ok(self.loaded)
+    }
+}
+
+fn bulk_batch_options() -> WriteBatchOptions {
+    // 导入结束时统一持久化
+    WriteBatchOptions {
+        sync_writes: false,
+        ..Default::default()
+    }
+}
+
+fn decode_jsonl(line: &str) -> Result<(Bytes, Bytes)> {
+    let fields = match serde_json::from_str::<JsonValue>(line) {
+        Ok(JsonValue::Object(fields)) => fields,
+        Ok(_) => return Err(Errors::InvalidBulkRecord("expected a JSON object")),
+        Err(_) => return Err(Errors::InvalidBulkRecord("invalid JSON")),
+    };
+    let field = |name: &str| match fields.get(name) {
+        Some(JsonValue::String(s)) => Ok(Bytes::from(s.clone())),
+        Some(_) => Err(Errors::InvalidBulkRecord("key and value must be strings")),
+        None => Err(Errors::InvalidBulkRecord("missing key or value")),
+    };
+    Ok((field("key")?, field("value")?))
+}
+
+fn decode_csv(line: &str) -> Result<(Bytes, Bytes)> {
+    let mut fields = Vec::with_capacity(2);
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err(Errors::InvalidBulkRecord("unterminated quoted field")),
+                }
+            }
+            if !matches!(chars.peek(), None | Some(',')) {
+                return Err(Errors::InvalidBulkRecord(
+                    "unexpected text after quoted field",
+                ));
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                if c == '"' {
+                    return Err(Errors::InvalidBulkRecord("quote in unquoted field"));
+                }
+                field.push(c);
+            }
+        }
+        fields.push(field);
+        if chars.next().is_none() {
+            break;
+        }
+    }
+    match <[String; 2]>::try_from(fields) {
+        Ok([key, value]) => Ok((key.into(), value.into())),
+        Err(_) => Err(Errors::InvalidBulkRecord("expected 2 fields")),
+    }
+}
+
+fn encode_csv_field(field: &str) -> Option<String> {
+    if field.contains(['\n', '\r']) {
+        return None;
+    }
+    if !field.contains([',', '"']) {
+        return Some(field.to_string());
+    }
+    Some(format!("\"{}\"", field.replace('"', "\"\"")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_bulk_format() {
+        for format in [BulkFormat::Jsonl, BulkFormat::Csv] {
+            for (key, value) in [
+                ("a", "1"),
+                ("with,comma", "with \"quote\""),
+                ("", "\t\\"),
+                ("k", ""),
+            ] {
+                let line = format.encode(key.as_bytes(), value.as_bytes()).unwrap();
+                assert_eq!(
+                    format.decode(&line).unwrap(),
+                    (Bytes::from(key), Bytes::from(value))
+                );
+            }
+            assert_eq!(format.encode(b"\xff", b"v"), None);
+        }
+        assert_eq!(BulkFormat::Csv.encode(b"k", b"two\nlines"), None);
+        assert!(BulkFormat::Jsonl.encode(b"k", b"two\nlines").is_some());
+
+        assert_eq!(
+            BulkFormat::Jsonl
+                .decode(r#"{"value":"v","key":"k","ttl":1}"#)
+                .unwrap(),
+            (Bytes::from("k"), Bytes::from("v"))
+        );
+        assert_eq!(
+            BulkFormat::Csv.decode("k,\"v,1\"").unwrap(),
+            (Bytes::from("k"), Bytes::from("v,1"))
+        );
+        for (format, line) in [
+            (BulkFormat::Jsonl, "[1]"),
+            (BulkFormat::Jsonl, "{\"key\":\"k\"}"),
+            (BulkFormat::Jsonl, "{\"key\":\"k\",\"value\":1}"),
+            (BulkFormat::Jsonl, "{"),
+            (BulkFormat::Csv, "k"),
+            (BulkFormat::Csv, "k,v,extra"),
+            (BulkFormat::Csv, "k,\"v"),
+            (BulkFormat::Csv, "k,\"v\"x"),
+            (BulkFormat::Csv, "k,v\"x"),
+        ] {
+            assert!(matches!(
+                format.decode(line),
+                Err(Errors::InvalidBulkRecord(_))
+            ));
+        }
+        assert_eq!("csv".parse::<BulkFormat>().unwrap(), BulkFormat::Csv);
+        assert!("xml".parse::<BulkFormat>().is_err());
+    }
+
+    #[test]
+    fn test_bulk_loader() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-bulk-loader");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 超过一个事务的记录数
+        let mut loader = engine.bulk_loader().unwrap();
+        let max_batch_num = WriteBatchOptions::default().max_batch_num;
+        for i in 0..max_batch_num + 10 {
+            loader.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        loader.put(get_test_key(0), get_test_value(100)).unwrap();
+        assert_eq!(
+            loader.put(Bytes::new(), get_test_value(1)).err().unwrap(),
+            Errors::KeyIsEmpty
+        );
+        assert_eq!(loader.loaded(), max_batch_num + 11);
+        assert_eq!(loader.finish().unwrap(), max_batch_num + 11);
+        assert!(!engine.has_unsynced_data());
+
+        assert_eq!(engine.list_keys().unwrap().len(), max_batch_num + 10);
+        assert_eq!(engine.get(get_test_key(0)).unwrap(), get_test_value(100));
+        assert_eq!(
+            engine.get(get_test_key(max_batch_num + 9)).unwrap(),
+            get_test_value(max_batch_num + 9)
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,158 @@
+//! 端到端的数据校验：写入时校验调用方提供的 value 校验值，读取时返回数据文件中存储的校验值以及重新计算的结果
+//! value 的校验值为 value 本身的 CRC32（IEEE），与数据文件中覆盖整条记录的校验值不同
+
+use bytes::Bytes;
+
+use crate::{
+    data::log_record::LogRecordType,
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+/// get_verified 读取到的 value 以及校验信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedValue {
+    pub value: Bytes,
+    // 数据文件中存储的记录校验值，覆盖记录头、元数据、key 和 value
+    pub stored_crc: u32,
+    // 重新计算的记录校验值是否与 stored_crc 一致
+    pub crc_valid: bool,
+    // value 的 CRC32，可以与写入时 put_with_checksum 提供的校验值比较
+    pub value_crc: u32,
+}
+
+/// 计算 value 的校验值，与 put_with_checksum 和 VerifiedValue::value_crc 使用相同的算法
+pub fn value_checksum(value: &[u8]) -> u32 {
+    crc32fast::hash(value)
+}
+
+impl Engine {
+    /// 写入数据之前校验调用方提供的 value 校验值，不一致时返回 Errors::ValueChecksumMismatch 并且不写入
+    pub fn put_with_checksum(&self, key: Bytes, value: Bytes, expected_crc: u32) -> Result<()> {
+        let actual = value_checksum(&value);
+        if actual != expected_crc {
+            return Err(Errors::ValueChecksumMismatch {
+                expected: expected_crc,
+                actual,
+            });
+        }
+        self.put(key, value)
+    }
+
+    /// 读取 key 对应的 value 以及校验信息，记录的校验值不一致时不返回错误，由调用方根据 crc_valid 决定如何处理
+    /// 分块存储的 value 只返回引用分块的记录的校验值，分块损坏时返回 Errors::InvalidLogRecordCrc
+    pub fn get_verified(&self, key: Bytes) -> Result<VerifiedValue> {
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+        if self.is_expired(&key) {
+            return Err(Errors::KeyNotFound);
+        }
+        self.flush_memtable()?;
+        loop {
+            let pos = self.index.get(key.to_vec()).ok_or(Errors::KeyNotFound)?;
+            let (record, crc) = self.files.read().read_log_record_with_crc(&pos)?;
+            // 读取期间记录被覆盖并回收时，使用新的位置重新读取
+            if (!crc.is_valid() || record.rec_type == LogRecordType::PADDING)
+                && self.index.get(key.to_vec()) != Some(pos)
+            {
+                continue;
+            }
+            match record.rec_type {
+                LogRecordType::DELETED => return Err(Errors::KeyNotFound),
+                LogRecordType::PADDING => {
+                    return Err(Errors::IndexDataMismatch {
+                        file_id: pos.file_id,
+                        offset: pos.offset,
+                    })
+                }
+                _ => {}
+            }
+            let value = match crc.is_valid() {
+                true => Bytes::from(self.resolve_chunks(record)?.value),
+                // 校验值不一致时记录头可能已经损坏，不再解析分块
+                false => Bytes::from(record.value),
+            };
+            return Ok(VerifiedValue {
+                value_crc: value_checksum(&value),
+                value,
+                stored_crc: crc.stored,
+                crc_valid: crc.is_valid(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, os::unix::fs::FileExt, path::PathBuf};
+
+    use crate::{
+        data::data_file::get_data_file_name,
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_value_checksum() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-value-checksum");
+        opts.value_chunk_size = Some(1024);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let value = get_test_value(1);
+        engine
+            .put_with_checksum(get_test_key(1), value.clone(), value_checksum(&value))
+            .unwrap();
+        let res = engine.put_with_checksum(get_test_key(2), value.clone(), 1);
+        assert!(matches!(
+            res,
+            Err(Errors::ValueChecksumMismatch { expected: 1, .. })
+        ));
+        assert!(engine.try_get(get_test_key(2)).unwrap().is_none());
+
+        let verified = engine.get_verified(get_test_key(1)).unwrap();
+        assert_eq!(verified.value, value);
+        assert!(verified.crc_valid);
+        assert_eq!(verified.value_crc, value_checksum(&value));
+
+        let big = Bytes::from(vec![7u8; 5000]);
+        engine.put(get_test_key(3), big.clone()).unwrap();
+        let verified = engine.get_verified(get_test_key(3)).unwrap();
+        assert_eq!(verified.value, big);
+        assert!(verified.crc_valid);
+        assert_eq!(
+            engine.get_verified(get_test_key(4)).err().unwrap(),
+            Errors::KeyNotFound
+        );
+        assert_eq!(
+            engine.get_verified(Bytes::new()).err().unwrap(),
+            Errors::KeyIsEmpty
+        );
+        engine.sync().unwrap();
+
+        // 修改数据文件中 value 的最后一个字节
+        let pos = engine.index.get(get_test_key(1).to_vec()).unwrap();
+        let file = OpenOptions::new()
+            .write(true)
+            .open(get_data_file_name(&opts.dir_path, pos.file_id))
+            .unwrap();
+        let offset = pos.offset + pos.size - 5;
+        file.write_all_at(&[!value[value.len() - 1]], offset)
+            .unwrap();
+        assert_eq!(
+            engine.get(get_test_key(1)).err().unwrap(),
+            Errors::InvalidLogRecordCrc
+        );
+        let verified = engine.get_verified(get_test_key(1)).unwrap();
+        assert!(!verified.crc_valid);
+        assert_ne!(verified.value, value);
+        assert_ne!(verified.value_crc, value_checksum(&value));
+        std::mem::drop(engine);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
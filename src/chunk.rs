@@ -0,0 +1,329 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    ops::Bound,
+    sync::atomic::Ordering,
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::{
+    audit::AuditOp,
+    batch::{log_record_key_with_seq, NON_TRANSACTION_SEQ_NO},
+    data::log_record::{LogRecord, LogRecordPos, LogRecordType},
+    db::{Engine, INTERNAL_KEY_PREFIX},
+    errors::{Errors, Result},
+    options::IteratorOptions,
+};
+
+// 分块记录在内部前缀下，key 为 分块前缀 + 分组 id + 分块序号
+const CHUNK_KEY_PREFIX: &[u8] = b"chunk/";
+
+// 头记录的 value：分组 id（8 字节）+ 分块数量（4 字节）+ value 总长度（8 字节）
+const CHUNK_HEAD_SIZE: usize = 20;
+
+/// 分块存储的 value 的头记录，记录在用户 key 下
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ChunkHead {
+    pub(crate) group: u64,
+    pub(crate) count: u32,
+    pub(crate) len: u64,
+}
+
+impl ChunkHead {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = BytesMut::with_capacity(CHUNK_HEAD_SIZE);
+        buf.put_u64(self.group);
+        buf.put_u32(self.count);
+        buf.put_u64(self.len);
+        buf.to_vec()
+    }
+
+    pub(crate) fn decode(mut buf: &[u8]) -> Result<Self> {
+        if buf.len() != CHUNK_HEAD_SIZE {
+            return Err(Errors::DataFileCorrupted);
+        }
+        Ok(Self {
+            group: buf.get_u64(),
+            count: buf.get_u32(),
+            len: buf.get_u64(),
+        })
+    }
+
+    // 所有分块的 key，按顺序排列
+    pub(crate) fn chunk_keys(&self) -> impl std::iter::Iterator<Item = Vec<u8>> + '_ {
+        (0..self.count).map(|i| chunk_key(self.group, i))
+    }
+}
+
+fn chunk_key_prefix() -> Vec<u8> {
+    let mut key = Vec::with_capacity(INTERNAL_KEY_PREFIX.len() + CHUNK_KEY_PREFIX.len());
+    key.extend_from_slice(INTERNAL_KEY_PREFIX);
+    key.extend_from_slice(CHUNK_KEY_PREFIX);
+    key
+}
+
+fn chunk_key(group: u64, idx: u32) -> Vec<u8> {
+    let mut key = chunk_key_prefix();
+    key.extend_from_slice(&group.to_be_bytes());
+    key.extend_from_slice(&idx.to_be_bytes());
+    key
+}
+
+impl Engine {
+    // 数据库中是否可能有分块存储的 value，没有时删除和覆盖不需要检查旧的记录
+    pub(crate) fn detect_chunks(&self) {
+        let prefix = chunk_key_prefix();
+        let options = IteratorOptions {
+            prefix: prefix.clone(),
+            ..Default::default()
+        };
+        let found = !self
+            .index
+            .scan(&options, Bound::Included(&prefix), 1)
+            .is_empty();
+        self.has_chunks.store(found, Ordering::Relaxed);
+    }
+
+    pub(crate) fn has_chunks(&self) -> bool {
+        self.has_chunks.load(Ordering::Relaxed)
+    }
+
+    // 将 value 拆分为多个分块写入，最后写入用户 key 下的头记录
+    // 头记录写入之前崩溃时，已经写入的分块不会被任何 key 引用
+    pub(crate) fn put_chunked(
+        &self,
+        key: Bytes,
+        value: Bytes,
+        meta: Bytes,
+        chunk_size: usize,
+    ) -> Result<()> {
+        let quota_deltas = self.check_quota(&[(&key, Some(value.len()))])?;
+        self.has_chunks.store(true, Ordering::Relaxed);
+
+        let group = self.new_chunk_group();
+        let chunks = value.chunks(chunk_size).collect::<Vec<_>>();
+        let head = ChunkHead {
+            group,
+            count: chunks.len() as u32,
+            len: value.len() as u64,
+        };
+        for (chunk_key, chunk) in head.chunk_keys().zip(chunks) {
+            let mut record = LogRecord {
+                key: log_record_key_with_seq(chunk_key.clone(), NON_TRANSACTION_SEQ_NO),
+                value: chunk.to_vec(),
+                rec_type: LogRecordType::NORMAL,
+                meta: Default::default(),
+            };
+            let (pos, _inflight) = self.append_log_record(&mut record)?;
+            if !self.index.put(chunk_key, pos) {
+                return Err(Errors::IndexUpdateFailed);
+            }
+        }
+
+        let record = LogRecord {
+            key: log_record_key_with_seq(key.to_vec(), NON_TRANSACTION_SEQ_NO),
+            value: head.encode(),
+            rec_type: LogRecordType::CHUNKED,
+            meta: meta.to_vec(),
+        };
+        let (old, pos, _inflight) = {
+            let _lock = self.append_lock.lock();
+            // 在写入锁内读取旧的记录，保证每个被覆盖的分块只清理一次
+            self.inflight.wait_idle();
+            let old = self.old_chunk_head(&key)?;
+            let pos = self.append_log_record_locked(&record, &self.write_stats.data_bytes)?;
+            (old, pos, self.inflight.begin())
+        };
+        self.mark_stale(&key);
+        if !self.index.put(key.to_vec(), pos) {
+            return Err(Errors::IndexUpdateFailed);
+        }
+        drop(_inflight);
+        if let Some(old) = old {
+            self.remove_chunks(&old)?;
+        }
+
+        self.apply_quota(quota_deltas);
+        self.write_stats.user_bytes.fetch_add(
+            (key.len() + value.len() + meta.len()) as u64,
+            Ordering::Relaxed,
+        );
+        self.record_mutation(
+            AuditOp::Put,
+            &key,
+            NON_TRANSACTION_SEQ_NO,
+            Some(value.len()),
+        );
+        Ok(())
+    }
+
+    // 删除 key，旧的 value 是分块存储时同时删除所有分块
+    pub(crate) fn delete_chunked(&self, key: &[u8], record: &mut LogRecord) -> Result<bool> {
+        let (old, _inflight) = {
+            let _lock = self.append_lock.lock();
+            self.inflight.wait_idle();
+            if self.index.get(key.to_vec()).is_none() {
+                return Ok(false);
+            }
+            let old = self.old_chunk_head(key)?;
+            self.append_log_record_locked(record, &self.write_stats.data_bytes)?;
+            (old, self.inflight.begin())
+        };
+        self.mark_stale(key);
+        let deleted = self.index.delete(key.to_vec());
+        drop(_inflight);
+        if let Some(old) = old {
+            self.remove_chunks(&old)?;
+        }
+        Ok(deleted)
+    }
+
+    // key 当前的记录是分块存储时返回头记录，需要持有 append_lock
+    pub(crate) fn old_chunk_head(&self, key: &[u8]) -> Result<Option<ChunkHead>> {
+        let pos = match self.index.get(key.to_vec()) {
+            Some(pos) => pos,
+            None => return Ok(None),
+        };
+        let record = self.read_log_record_at(&pos)?;
+        match record.rec_type {
+            LogRecordType::CHUNKED => Ok(Some(ChunkHead::decode(&record.value)?)),
+            _ => Ok(None),
+        }
+    }
+
+    // 为分块写入删除标记并从索引中移除
+    pub(crate) fn remove_chunks(&self, head: &ChunkHead) -> Result<()> {
+        let keys = head.chunk_keys().collect::<Vec<_>>();
+        let records = keys
+            .iter()
+            .map(|key| LogRecord {
+                key: log_record_key_with_seq(key.clone(), NON_TRANSACTION_SEQ_NO),
+                value: Default::default(),
+                rec_type: LogRecordType::DELETED,
+                meta: Default::default(),
+            })
+            .collect::<Vec<_>>();
+        let (_, _inflight) = self.append_log_records(&records)?;
+        for key in keys {
+            self.index.delete(key);
+        }
+        Ok(())
+    }
+
+    // 新的分块分组 id，与已有的分块不会冲突
+    fn new_chunk_group(&self) -> u64 {
+        loop {
+            let group = RandomState::new().build_hasher().finish();
+            if self.index.get(chunk_key(group, 0)).is_none() {
+                return group;
+            }
+        }
+    }
+
+    // 分块存储的记录读取所有分块拼接成完整的 value，其他记录原样返回
+    pub(crate) fn resolve_chunks(&self, mut record: LogRecord) -> Result<LogRecord> {
+        if record.rec_type != LogRecordType::CHUNKED {
+            return Ok(record);
+        }
+        let head = ChunkHead::decode(&record.value)?;
+        let mut value = Vec::with_capacity(head.len as usize);
+        for pos in self.chunk_positions(&head)? {
+            value.extend_from_slice(&self.read_log_record_at(&pos)?.value);
+        }
+        if value.len() as u64 != head.len {
+            return Err(Errors::DataFileCorrupted);
+        }
+        record.value = value;
+        record.rec_type = LogRecordType::NORMAL;
+        Ok(record)
+    }
+
+    // 所有分块在数据文件中的位置，分块缺失说明数据已经损坏
+    pub(crate) fn chunk_positions(&self, head: &ChunkHead) -> Result<Vec<LogRecordPos>> {
+        head.chunk_keys()
+            .map(|key| self.index.get(key).ok_or(Errors::DataFileCorrupted))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, path::PathBuf};
+
+    use crate::options::Options;
+
+    use super::*;
+
+    fn big_value(len: usize, seed: usize) -> Bytes {
+        (0..len)
+            .map(|i| ((i + seed) % 251) as u8)
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    #[test]
+    fn test_chunked_value() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-chunked-value");
+        opts.data_file_size = 64 * 1024;
+        opts.value_chunk_size = Some(16 * 1024);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // value 大于数据文件大小
+        let value = big_value(200 * 1024, 1);
+        let res = engine.put_with_meta(Bytes::from("big"), value.clone(), Bytes::from("meta"));
+        assert!(res.is_ok());
+        assert!(engine.put(Bytes::from("small"), Bytes::from("v")).is_ok());
+        assert_eq!(engine.get(Bytes::from("big")).unwrap(), value);
+        assert_eq!(
+            engine.get_with_meta(Bytes::from("big")).unwrap(),
+            (value.clone(), Bytes::from("meta"))
+        );
+        let mut reader = engine.get_reader(Bytes::from("big")).unwrap();
+        assert_eq!(reader.len(), value.len() as u64);
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(Bytes::from(buf), value);
+        // 分块对用户不可见
+        assert_eq!(engine.list_keys().unwrap().len(), 2);
+        let iter = engine.iter(Default::default());
+        assert_eq!(iter.next().unwrap(), (Bytes::from("big"), value.clone()));
+        assert!(engine.stat().unwrap().data_file_num > 3);
+
+        // 覆盖之后旧的分块被删除
+        let value2 = big_value(40 * 1024, 2);
+        assert!(engine.put(Bytes::from("big"), value2.clone()).is_ok());
+        assert_eq!(engine.get(Bytes::from("big")).unwrap(), value2);
+        assert_eq!(engine.index.list_keys(false, &[]).unwrap().len(), 2 + 3);
+
+        // 重启之后数据保持不变
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(engine.has_chunks());
+        assert_eq!(engine.get(Bytes::from("big")).unwrap(), value2);
+
+        // merge 之后分块仍然可以读取
+        assert!(engine
+            .merge_with(&crate::merge::OldestFirst { max_files: 100 })
+            .is_ok());
+        assert_eq!(engine.get(Bytes::from("big")).unwrap(), value2);
+
+        // 删除之后分块也被删除
+        assert!(engine.delete(Bytes::from("big")).is_ok());
+        assert_eq!(
+            engine.get(Bytes::from("big")).err().unwrap(),
+            Errors::KeyNotFound
+        );
+        assert_eq!(engine.index.list_keys(false, &[]).unwrap().len(), 1);
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(!engine.has_chunks());
+        assert_eq!(engine.index.list_keys(false, &[]).unwrap().len(), 1);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::db::Engine;
+
+/// 过期时间、租约、队列可见时间和事件时间戳使用的时钟，返回毫秒时间戳
+/// 通过 Options::clock 注入，测试中可以使用 ManualClock 控制时间
+pub trait Clock: Send + Sync {
+    fn now_millis(&self) -> u64;
+}
+
+/// 系统时钟，直接读取当前的墙上时间，系统时间被调整时过期时间会随之跳变
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        wall_millis()
+    }
+}
+
+/// 单调时钟，创建时读取一次墙上时间，之后按照单调时间递增
+/// 运行期间系统时间被调整不会影响过期判断，重启之后重新对齐墙上时间
+pub struct MonotonicClock {
+    base_millis: u64,
+    start: Instant,
+}
+
+impl MonotonicClock {
+    pub fn new() -> Self {
+        Self {
+            base_millis: wall_millis(),
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now_millis(&self) -> u64 {
+        self.base_millis
+            .saturating_add(self.start.elapsed().as_millis() as u64)
+    }
+}
+
+/// 手动控制的时钟，只有调用 set 或者 advance 时才会改变，用于测试过期逻辑
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(now_millis: u64) -> Self {
+        Self {
+            now: AtomicU64::new(now_millis),
+        }
+    }
+
+    pub fn set(&self, now_millis: u64) {
+        self.now.store(now_millis, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, d: Duration) {
+        self.now.fetch_add(d.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+impl Engine {
+    // 按照配置的时钟读取当前的毫秒时间戳
+    pub(crate) fn now_millis(&self) -> u64 {
+        self.clock.now_millis()
+    }
+}
+
+fn wall_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clocks() {
+        let system = SystemClock.now_millis();
+        let monotonic = MonotonicClock::new();
+        let first = monotonic.now_millis();
+        assert!(first >= system);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(monotonic.now_millis() >= first + 20);
+
+        let manual = ManualClock::new(1000);
+        assert_eq!(manual.now_millis(), 1000);
+        manual.advance(Duration::from_secs(2));
+        assert_eq!(manual.now_millis(), 3000);
+        manual.set(10);
+        assert_eq!(manual.now_millis(), 10);
+    }
+}
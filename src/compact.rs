@@ -0,0 +1,240 @@
+use bytes::Bytes;
+
+use crate::{
+    batch::{log_record_key_with_seq, NON_TRANSACTION_SEQ_NO},
+    data::log_record::LogRecord,
+    db::Engine,
+    errors::{Errors, Result},
+    fence::FILE_HEADER_SIZE,
+    index::IndexIter,
+    options::IteratorOptions,
+};
+
+impl Engine {
+    /// 将 key 的最新版本重新写入到活跃文件中，旧数据文件中该 key 的数据全部变为可回收的
+    /// 适用于少数频繁覆盖的大 key 占据了大部分可回收空间、但全量 merge 代价太大的情况
+    /// 返回是否发生了重写，key 不存在或者已经位于活跃文件中时不会重写
+    pub fn compact_key(&self, key: Bytes) -> Result<bool> {
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        // 持有写入锁，避免重写期间有新的数据写入导致覆盖顺序错乱
+        let _lock = self.append_lock.lock();
+        self.flush_memtable_locked()?;
+        let pos = match self.index.get(key.to_vec()) {
+            Some(pos) => pos,
+            None => return Ok(false),
+        };
+        if pos.file_id == self.files.read().active.get_file_id() {
+            return Ok(false);
+        }
+
+        let old_record = self.get_log_record_by_position(&pos)?;
+        let record = LogRecord {
+            key: log_record_key_with_seq(key.to_vec(), NON_TRANSACTION_SEQ_NO),
+            value: old_record.value,
+            rec_type: old_record.rec_type,
+            meta: old_record.meta,
+        };
+        let new_pos = self.append_log_record_locked(&record, &self.write_stats.merge_bytes)?;
+
+        // 只有索引仍然指向旧的位置时才更新，没有更新时新写入的记录是失效的
+        let updated = self.index.compare_and_put(key.to_vec(), pos, new_pos);
+        self.mark_stale_pos(match updated {
+            true => &pos,
+            false => &new_pos,
+        });
+        Ok(updated)
+    }
+
+    /// 重写前缀下所有 key 的最新版本，返回重写的 key 的数量
+    pub fn compact_prefix(&self, prefix: Bytes) -> Result<usize> {
+        self.flush_memtable()?;
+        let keys = IndexIter::from(self.index.iterator(IteratorOptions {
+            prefix: prefix.to_vec(),
+            ..Default::default()
+        }))
+        .map(|(key, _)| key)
+        .collect::<Vec<_>>();
+
+        let mut count = 0;
+        for key in keys {
+            if self.compact_key(key)? {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// 重写 [start, end) 范围内所有 key 的最新版本，end 为空时不限制上界，返回重写的 key 的数量
+    /// 重写之后不再包含有效数据的封存文件会被直接移除，适合在删除一段 key 之后回收空间，不需要全量 merge
+    pub fn compact_range(&self, start: Bytes, end: Bytes) -> Result<usize> {
+        if !end.is_empty() && start >= end {
+            return Ok(0);
+        }
+        self.flush_memtable()?;
+        let mut index_iter = self.index.iterator(Default::default());
+        index_iter.seek(start.to_vec());
+        let keys = IndexIter::from(index_iter)
+            .map(|(key, _)| key)
+            .take_while(|key| end.is_empty() || key < &end)
+            .collect::<Vec<_>>();
+
+        let mut count = 0;
+        for key in keys {
+            if self.compact_key(key)? {
+                count += 1;
+            }
+        }
+
+        // 只剩下文件头的封存文件，合并时不需要重写数据，只保留仍然需要的删除标记
+        let files = self.files.load();
+        let dead_files = self
+            .file_stats()
+            .into_iter()
+            .filter(|s| files.older.contains_key(&s.file_id) && s.live_bytes <= FILE_HEADER_SIZE)
+            .map(|s| s.file_id)
+            .collect::<Vec<_>>();
+        if !dead_files.is_empty() {
+            self.compact_files(&dead_files)?;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{options::Options, utils::rand_kv::get_test_value};
+
+    use super::*;
+
+    #[test]
+    fn test_compact_key() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-compact-key");
+        opts.data_file_size = 4 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let put_res =
+            engine.put_with_meta(Bytes::from("big:1"), get_test_value(1), Bytes::from("meta"));
+        assert!(put_res.is_ok());
+        let put_res = engine.put(Bytes::from("big:2"), get_test_value(2));
+        assert!(put_res.is_ok());
+        // 写满旧的数据文件
+        for i in 0..100 {
+            let put_res = engine.put(Bytes::from(format!("other:{i}")), get_test_value(i));
+            assert!(put_res.is_ok());
+        }
+        let pos = engine.index.get(b"big:1".to_vec()).unwrap();
+        assert_ne!(pos.file_id, engine.files.read().active.get_file_id());
+
+        // 不存在的 key
+        assert!(!engine.compact_key(Bytes::from("not-exist")).unwrap());
+
+        assert!(engine.compact_key(Bytes::from("big:1")).unwrap());
+        let pos = engine.index.get(b"big:1".to_vec()).unwrap();
+        assert_eq!(pos.file_id, engine.files.read().active.get_file_id());
+        // 已经在活跃文件中，不需要再次重写
+        assert!(!engine.compact_key(Bytes::from("big:1")).unwrap());
+        let stat = engine.stat().unwrap();
+        assert!(stat.merge_bytes_written > 0);
+
+        assert_eq!(engine.compact_prefix(Bytes::from("big:")).unwrap(), 1);
+
+        // 重启之后数据和元数据保持不变
+        engine.close().expect("failed to close");
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        let (value, meta) = engine2.get_with_meta(Bytes::from("big:1")).unwrap();
+        assert_eq!(value, get_test_value(1));
+        assert_eq!(meta, Bytes::from("meta"));
+        assert_eq!(
+            engine2.get(Bytes::from("big:2")).unwrap(),
+            get_test_value(2)
+        );
+        let pos = engine2.index.get(b"big:2".to_vec()).unwrap();
+        assert_eq!(pos.file_id, engine2.files.read().active.get_file_id());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_compact_range() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-compact-range");
+        opts.data_file_size = 4 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        for i in 0..100 {
+            let put_res = engine.put(Bytes::from(format!("a:{i:03}")), get_test_value(i));
+            assert!(put_res.is_ok());
+        }
+        for i in 0..20 {
+            let put_res = engine.put(Bytes::from(format!("b:{i:03}")), get_test_value(i));
+            assert!(put_res.is_ok());
+        }
+        let sealed = engine.files.load().older.len();
+        assert!(sealed > 1);
+
+        // 只重写范围内的 key
+        assert_eq!(
+            engine
+                .compact_range(Bytes::from("a:010"), Bytes::from("a:020"))
+                .unwrap(),
+            10
+        );
+        let active_id = engine.files.read().active.get_file_id();
+        let file_of = |key: &str| engine.index.get(key.as_bytes().to_vec()).unwrap().file_id;
+        assert_eq!(file_of("a:010"), active_id);
+        assert_eq!(file_of("a:019"), active_id);
+        assert_ne!(file_of("a:020"), active_id);
+        assert_ne!(file_of("a:009"), active_id);
+        assert_eq!(
+            engine
+                .compact_range(Bytes::from("b:"), Bytes::from("a:"))
+                .unwrap(),
+            0
+        );
+
+        // 删除前缀之后重写剩下的 key，不再包含有效数据的文件被移除
+        for i in 0..100 {
+            let del_res = engine.delete(Bytes::from(format!("a:{i:03}")));
+            assert!(del_res.is_ok());
+        }
+        let files_before = engine.file_stats().len();
+        let check_stats = |engine: &Engine| {
+            let report = engine.space_report().unwrap();
+            let stats = engine.file_stats();
+            for (stat, space) in stats.iter().zip(report.files.iter()) {
+                assert_eq!(stat.dead_bytes, space.dead_bytes);
+            }
+        };
+        check_stats(&engine);
+        assert!(
+            engine
+                .compact_range(Bytes::from("b:"), Bytes::new())
+                .unwrap()
+                > 0
+        );
+        assert!(engine.file_stats().len() < files_before);
+        check_stats(&engine);
+        for i in 0..20 {
+            assert_eq!(
+                engine.get(Bytes::from(format!("b:{i:03}"))).unwrap(),
+                get_test_value(i)
+            );
+        }
+
+        // 重启之后删除的 key 仍然不存在
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(engine.list_keys().unwrap().len(), 20);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
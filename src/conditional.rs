@@ -0,0 +1,210 @@
+use std::sync::atomic::Ordering;
+
+use bytes::Bytes;
+
+use crate::{
+    audit::AuditOp,
+    batch::{log_record_key_with_seq, NON_TRANSACTION_SEQ_NO},
+    data::log_record::{LogRecord, LogRecordPos, LogRecordType},
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+impl Engine {
+    /// 只有 key 不存在（或者已经被删除）时才写入，返回是否写入了数据
+    pub fn put_if_absent(&self, key: Bytes, value: Bytes) -> Result<bool> {
+        self.put_if(key, value, false)
+    }
+
+    /// 只有 key 已经存在时才写入，返回是否写入了数据
+    pub fn put_if_present(&self, key: Bytes, value: Bytes) -> Result<bool> {
+        self.put_if(key, value, true)
+    }
+
+    // 在持有 append_lock 时检查索引中 key 是否存在，与写入之间没有其他写入插入
+    fn put_if(&self, key: Bytes, value: Bytes, exists: bool) -> Result<bool> {
+        self.check_key(&key)?;
+        let quota_deltas = self.check_quota(&[(&key, Some(value.len()))])?;
+
+        let record = LogRecord {
+            key: log_record_key_with_seq(key.to_vec(), NON_TRANSACTION_SEQ_NO),
+            value: value.to_vec(),
+            rec_type: LogRecordType::NORMAL,
+            meta: Default::default(),
+        };
+        let (log_record_pos, _inflight) = {
+            let _lock = self.append_lock.lock();
+            // 等待已经写入的数据更新完索引，否则可能看不到刚刚写入的 key
+            self.inflight.wait_idle();
+            self.flush_memtable_locked()?;
+            if self.index.get(key.to_vec()).is_some() != exists {
+                return Ok(false);
+            }
+            let pos = self.append_log_record_locked(&record, &self.write_stats.data_bytes)?;
+            (pos, self.inflight.begin())
+        };
+
+        self.mark_stale(&key);
+        if !self.index.put(key.to_vec(), log_record_pos) {
+            return Err(Errors::IndexUpdateFailed);
+        }
+        self.apply_quota(quota_deltas);
+        self.write_stats
+            .user_bytes
+            .fetch_add((key.len() + value.len()) as u64, Ordering::Relaxed);
+        self.record_mutation(
+            AuditOp::Put,
+            &key,
+            NON_TRANSACTION_SEQ_NO,
+            Some(value.len()),
+        );
+        Ok(true)
+    }
+
+    // 只有 key 在索引中的位置仍然是 expected 时才写入，返回是否写入了数据
+    pub(crate) fn put_if_unchanged(
+        &self,
+        key: &Bytes,
+        expected: Option<LogRecordPos>,
+        value: Vec<u8>,
+    ) -> Result<bool> {
+        let quota_deltas = self.check_quota(&[(key, Some(value.len()))])?;
+        let value_len = value.len();
+        let record = LogRecord {
+            key: log_record_key_with_seq(key.to_vec(), NON_TRANSACTION_SEQ_NO),
+            value,
+            rec_type: LogRecordType::NORMAL,
+            meta: Default::default(),
+        };
+        let (old_chunks, pos, _inflight) = {
+            let _lock = self.append_lock.lock();
+            self.inflight.wait_idle();
+            self.flush_memtable_locked()?;
+            if self.index.get(key.to_vec()) != expected {
+                return Ok(false);
+            }
+            let old_chunks = self.old_chunk_head(key)?;
+            let pos = self.append_log_record_locked(&record, &self.write_stats.data_bytes)?;
+            (old_chunks, pos, self.inflight.begin())
+        };
+
+        self.mark_stale(key);
+        if !self.index.put(key.to_vec(), pos) {
+            return Err(Errors::IndexUpdateFailed);
+        }
+        drop(_inflight);
+        if let Some(old) = old_chunks {
+            self.remove_chunks(&old)?;
+        }
+        self.apply_quota(quota_deltas);
+        self.write_stats
+            .user_bytes
+            .fetch_add((key.len() + value_len) as u64, Ordering::Relaxed);
+        self.record_mutation(AuditOp::Put, key, NON_TRANSACTION_SEQ_NO, Some(value_len));
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::{
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_put_if_absent() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-put-if-absent");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        assert!(engine
+            .put_if_absent(get_test_key(1), get_test_value(1))
+            .unwrap());
+        assert!(!engine
+            .put_if_absent(get_test_key(1), get_test_value(2))
+            .unwrap());
+        assert_eq!(engine.get(get_test_key(1)).unwrap(), get_test_value(1));
+
+        // 被删除的 key 可以重新写入
+        assert!(engine.delete(get_test_key(1)).is_ok());
+        assert!(engine
+            .put_if_absent(get_test_key(1), get_test_value(3))
+            .unwrap());
+        assert_eq!(engine.get(get_test_key(1)).unwrap(), get_test_value(3));
+        assert_eq!(
+            engine
+                .put_if_absent(Bytes::new(), get_test_value(1))
+                .err()
+                .unwrap(),
+            Errors::KeyIsEmpty
+        );
+
+        // 并发写入同一个 key 只有一个成功
+        let inserted = AtomicUsize::new(0);
+        std::thread::scope(|s| {
+            for i in 0..4 {
+                let engine = &engine;
+                let inserted = &inserted;
+                s.spawn(move || {
+                    for j in 0..100 {
+                        if engine
+                            .put_if_absent(get_test_key(100 + j), get_test_value(i))
+                            .unwrap()
+                        {
+                            inserted.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(inserted.load(Ordering::SeqCst), 100);
+
+        // 重启之后数据保持不变
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(engine2.get(get_test_key(1)).unwrap(), get_test_value(3));
+        assert_eq!(engine2.list_keys().unwrap().len(), 101);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_put_if_present() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-put-if-present");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        assert!(!engine
+            .put_if_present(get_test_key(1), get_test_value(1))
+            .unwrap());
+        assert_eq!(
+            engine.get(get_test_key(1)).err().unwrap(),
+            Errors::KeyNotFound
+        );
+
+        assert!(engine.put(get_test_key(1), get_test_value(1)).is_ok());
+        assert!(engine
+            .put_if_present(get_test_key(1), get_test_value(2))
+            .unwrap());
+        assert_eq!(engine.get(get_test_key(1)).unwrap(), get_test_value(2));
+
+        assert!(engine.delete(get_test_key(1)).is_ok());
+        assert!(!engine
+            .put_if_present(get_test_key(1), get_test_value(3))
+            .unwrap());
+        assert!(engine.list_keys().unwrap().is_empty());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
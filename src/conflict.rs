@@ -0,0 +1,118 @@
+//! 跟随者回放复制数据时的冲突解决
+//! 跟随者已经有某个 key 的数据时，收到的同一个 key 的记录由 Options::conflict_resolver 决定是否生效
+
+use std::sync::Arc;
+
+use crate::{
+    batch::{try_parse_log_record_key, NON_TRANSACTION_SEQ_NO},
+    data::log_record::{LogRecordPos, LogRecordType},
+    db::DataFiles,
+    errors::Result,
+    index::Indexer,
+    options::Options,
+    utils::sharded_lock::ShardedLock,
+};
+
+/// 复制数据中一个 key 的一个版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordVersion {
+    /// 写入方的事务序列号，非事务写入为 0
+    pub seq_no: u64,
+    /// 记录所在的数据文件和偏移量，同一个写入方的记录按照写入顺序递增
+    pub file_id: u32,
+    pub offset: u64,
+    /// 是否是删除记录，跟随者本地的版本总是有效的数据
+    pub deleted: bool,
+}
+
+/// 冲突解决方式，同一个 key 必须总是得到相同的结果
+pub trait ConflictResolver: Send + Sync {
+    /// 跟随者已经有 key 的 local 版本时收到 incoming，返回 true 表示 incoming 生效
+    fn accept(&self, key: &[u8], local: &RecordVersion, incoming: &RecordVersion) -> bool;
+}
+
+/// 后写入的版本生效，默认的冲突解决方式
+/// 两个版本都有事务序列号时序列号较大的生效，否则按照数据文件中的写入顺序比较
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LastWriterWins;
+
+impl ConflictResolver for LastWriterWins {
+    fn accept(&self, _key: &[u8], local: &RecordVersion, incoming: &RecordVersion) -> bool {
+        if local.seq_no != NON_TRANSACTION_SEQ_NO && incoming.seq_no != NON_TRANSACTION_SEQ_NO {
+            return incoming.seq_no >= local.seq_no;
+        }
+        (incoming.file_id, incoming.offset) >= (local.file_id, local.offset)
+    }
+}
+
+// 跟随者回放时读取本地的版本并调用配置的冲突解决方式
+pub(crate) struct ReplicaConflicts {
+    resolver: Arc<dyn ConflictResolver>,
+    files: Arc<ShardedLock<DataFiles>>,
+}
+
+impl ReplicaConflicts {
+    // 配置的冲突解决方式，没有配置时使用 LastWriterWins
+    pub(crate) fn new(opts: &Options, files: Arc<ShardedLock<DataFiles>>) -> Self {
+        Self {
+            resolver: opts
+                .conflict_resolver
+                .clone()
+                .unwrap_or_else(|| Arc::new(LastWriterWins)),
+            files,
+        }
+    }
+
+    // 收到的记录是否生效，本地没有这个 key 时总是生效
+    pub(crate) fn accept(
+        &self,
+        index: &dyn Indexer,
+        key: &[u8],
+        seq_no: u64,
+        rec_type: LogRecordType,
+        pos: LogRecordPos,
+    ) -> Result<bool> {
+        let local_pos = match index.get(key.to_vec()) {
+            Some(local_pos) => local_pos,
+            None => return Ok(true),
+        };
+        let local_record = self.files.read().read_log_record_at(&local_pos)?;
+        let (_, local_seq_no) = try_parse_log_record_key(local_record.key)?;
+        let local = RecordVersion {
+            seq_no: local_seq_no,
+            file_id: local_pos.file_id,
+            offset: local_pos.offset,
+            deleted: false,
+        };
+        let incoming = RecordVersion {
+            seq_no,
+            file_id: pos.file_id,
+            offset: pos.offset,
+            deleted: rec_type == LogRecordType::DELETED,
+        };
+        Ok(self.resolver.accept(key, &local, &incoming))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_writer_wins() {
+        let version = |seq_no, file_id, offset| RecordVersion {
+            seq_no,
+            file_id,
+            offset,
+            deleted: false,
+        };
+        let lww = LastWriterWins;
+        // 事务序列号较大的生效
+        assert!(lww.accept(b"a", &version(1, 2, 100), &version(2, 1, 0)));
+        assert!(!lww.accept(b"a", &version(2, 1, 0), &version(1, 2, 100)));
+        // 非事务写入按照写入顺序比较
+        assert!(lww.accept(b"a", &version(5, 1, 100), &version(0, 1, 200)));
+        assert!(lww.accept(b"a", &version(0, 1, 100), &version(0, 2, 0)));
+        assert!(!lww.accept(b"a", &version(0, 2, 0), &version(3, 1, 100)));
+    }
+}
@@ -0,0 +1,214 @@
+use std::ops::{Bound, RangeBounds};
+
+use bytes::Bytes;
+
+use crate::{
+    db::{is_internal_key, Engine},
+    errors::Result,
+    options::{IteratorOptions, WriteBatchOptions},
+};
+
+// 每次从索引中读取的 key 的数量，每批数据作为一个事务写入目标数据库
+const COPY_SCAN_BATCH: usize = 1024;
+
+impl Engine {
+    /// 将 range 范围内的数据复制到另一个数据库中，返回复制的 key 的数量，用于重新分片和迁移部分数据
+    /// 数据分批通过目标数据库的事务写入，保留元数据和 key 剩余的过期时间，不使用目标数据库的 default_ttl；
+    /// 覆盖复制的 key 的前缀过期时间也会设置到目标数据库中
+    /// 引擎内部的数据（事件流、队列、内容寻址存储等）不会被复制
+    pub fn copy_range_to(&self, other: &Engine, range: impl RangeBounds<Bytes>) -> Result<usize> {
+        let prefix_ttls = self.active_prefix_ttls();
+        let mut used_ttls = vec![false; prefix_ttls.len()];
+        let max_batch_bytes = copy_batch_options(other).max_batch_bytes;
+        // 只扫描索引，内存写缓冲中的数据先写入数据文件
+        self.flush_memtable()?;
+
+        let mut copied = 0;
+        let mut cursor = range.start_bound().cloned();
+        loop {
+            let batch = self.index.scan(
+                &IteratorOptions::default(),
+                cursor.as_ref().map(|key| key.as_ref()),
+                COPY_SCAN_BATCH,
+            );
+            let mut done = batch.len() < COPY_SCAN_BATCH;
+            if let Some((key, _)) = batch.last() {
+                cursor = Bound::Excluded(key.clone());
+            }
+
+            let mut wb = other.new_write_batch(copy_batch_options(other))?;
+            let mut batch_bytes = 0;
+            for (key, _) in batch {
+                // key 按升序排列，第一个不在范围内的 key 之后都不在范围内
+                if !range.contains(&key) {
+                    done = true;
+                    break;
+                }
+                if is_internal_key(&key) {
+                    continue;
+                }
+                // 扫描之后被删除的 key
+                let Some((value, meta)) = self.try_get_with_meta(key.clone())? else {
+                    continue;
+                };
+                let ttl = self.ttl(&key);
+                for (i, (prefix, _)) in prefix_ttls.iter().enumerate() {
+                    used_ttls[i] |= key.starts_with(prefix);
+                }
+
+                // 过期时间记录与数据在同一个事务中写入
+                let ttl_size = other
+                    .key_ttl_record_with(&key, ttl)
+                    .map_or(0, |r| r.key.len() + r.value.len());
+                let size = key.len() + value.len() + meta.len() + ttl_size;
+                // 超过事务大小限制的 value 单独写入，由目标数据库决定是否分块存储
+                if size > max_batch_bytes {
+                    other.put_with_meta(key.clone(), value, meta)?;
+                    other.write_key_ttl_with(&key, ttl)?;
+                    copied += 1;
+                    continue;
+                }
+                if batch_bytes + size > max_batch_bytes {
+                    wb.commit()?;
+                    wb = other.new_write_batch(copy_batch_options(other))?;
+                    batch_bytes = 0;
+                }
+                wb.put_with_ttl(key, value, meta, ttl)?;
+                batch_bytes += size;
+                copied += 1;
+            }
+            wb.commit()?;
+            if done {
+                break;
+            }
+        }
+
+        for ((prefix, ttl), used) in prefix_ttls.into_iter().zip(used_ttls) {
+            if used {
+                other.expire_prefix(prefix, ttl)?;
+            }
+        }
+        Ok(copied)
+    }
+}
+
+fn copy_batch_options(other: &Engine) -> WriteBatchOptions {
+    WriteBatchOptions {
+        sync_writes: other.options.sync_write,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc, time::Duration};
+
+    use crate::{
+        clock::ManualClock,
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_copy_range_to() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-copy-range");
+        opts.value_chunk_size = Some(1024);
+        let mut other_opts = Options::default();
+        other_opts.dir_path = PathBuf::from("/tmp/bitcask-rs-copy-range-other");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let other = Engine::open(other_opts.clone()).expect("failed to open engine");
+
+        for i in 0..3000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine
+            .put_with_meta(get_test_key(10), get_test_value(10), Bytes::from("meta"))
+            .unwrap();
+        // 分块存储的大 value
+        let big = Bytes::from(vec![7u8; 5000]);
+        engine.put(get_test_key(20), big.clone()).unwrap();
+        engine.put_cas_blob(Bytes::from("internal")).unwrap();
+
+        let copied = engine
+            .copy_range_to(&other, get_test_key(0)..get_test_key(2500))
+            .unwrap();
+        assert_eq!(copied, 2500);
+        assert_eq!(other.list_keys().unwrap().len(), 2500);
+        assert_eq!(
+            other.get_with_meta(get_test_key(10)).unwrap(),
+            (get_test_value(10), Bytes::from("meta"))
+        );
+        assert_eq!(other.get(get_test_key(20)).unwrap(), big);
+        assert_eq!(other.get(get_test_key(2499)).unwrap(), get_test_value(2499));
+        assert!(other.try_get(get_test_key(2500)).unwrap().is_none());
+
+        // 复制剩下的部分，保留前缀的过期时间
+        engine
+            .put(Bytes::from("session/a"), Bytes::from("value"))
+            .unwrap();
+        engine
+            .expire_prefix(Bytes::from("session/"), Duration::from_secs(60))
+            .unwrap();
+        engine
+            .expire_prefix(Bytes::from("unused/"), Duration::from_secs(60))
+            .unwrap();
+        let copied = engine.copy_range_to(&other, get_test_key(2500)..).unwrap();
+        assert_eq!(copied, 501);
+        assert_eq!(other.list_keys().unwrap().len(), 3001);
+        assert!(other.prefix_ttl(b"session/").unwrap() > Duration::from_secs(50));
+        assert_eq!(other.prefix_ttl(b"unused/"), None);
+        assert_eq!(engine.copy_range_to(&other, ..).unwrap(), 3001);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(other_opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_copy_range_to_key_ttl() {
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-copy-range-ttl");
+        opts.default_ttl = Some(Duration::from_secs(60));
+        opts.value_chunk_size = Some(1024);
+        opts.clock = Some(clock.clone());
+        let mut other_opts = Options::default();
+        other_opts.dir_path = PathBuf::from("/tmp/bitcask-rs-copy-range-ttl-other");
+        other_opts.default_ttl = Some(Duration::from_secs(3600));
+        other_opts.clock = Some(clock.clone());
+        let mut engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let other = Engine::open(other_opts.clone()).expect("failed to open engine");
+
+        // 复制剩余的过期时间，没有过期时间的 key 在目标数据库中也没有过期时间
+        engine.put(get_test_key(1), get_test_value(1)).unwrap();
+        let big = Bytes::from(vec![7u8; 5000]);
+        engine.put(get_test_key(2), big.clone()).unwrap();
+        clock.advance(Duration::from_secs(20));
+        engine
+            .reopen(Options {
+                default_ttl: None,
+                ..opts.clone()
+            })
+            .unwrap();
+        engine.put(get_test_key(3), get_test_value(3)).unwrap();
+        other.put(get_test_key(3), get_test_value(30)).unwrap();
+        assert_eq!(engine.copy_range_to(&other, ..).unwrap(), 3);
+        assert_eq!(other.ttl(&get_test_key(1)), Some(Duration::from_secs(40)));
+        assert_eq!(other.ttl(&get_test_key(2)), Some(Duration::from_secs(40)));
+        assert_eq!(other.ttl(&get_test_key(3)), None);
+        assert_eq!(other.get(get_test_key(2)).unwrap(), big);
+
+        // 与源数据库同时过期
+        clock.advance(Duration::from_secs(40));
+        assert!(other.try_get(get_test_key(1)).unwrap().is_none());
+        assert!(other.try_get(get_test_key(2)).unwrap().is_none());
+        assert_eq!(other.get(get_test_key(3)).unwrap(), get_test_value(3));
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(other_opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -1,41 +1,150 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
 
-use bytes::{Buf, BytesMut};
+use bytes::{Buf, Bytes, BytesMut};
+use log::error;
 use parking_lot::RwLock;
 use prost::{decode_length_delimiter, length_delimiter_len};
 
-use crate::errors::Errors;
+use crate::errors::{invariant_violation, Errors};
 use crate::{
-    data::log_record::{max_log_record_header_size, LogRecord, LogRecordType},
+    data::log_record::{
+        max_log_record_header_size, LogRecord, LogRecordType, LOG_RECORD_META_FLAG,
+        LOG_RECORD_NO_SEQ_FLAG,
+    },
     errors::Result,
-    fio::{self, new_io_manager},
+    fio::{self, new_io_manager, new_read_only_io_manager},
+    options::IOType,
 };
 
-use super::log_record::ReadLogRecord;
+use super::{log_record::ReadLogRecord, read_stats::ReadStats};
+
+// 解析之后的记录头
+pub(crate) struct RecordHeader {
+    pub(crate) rec_type: LogRecordType,
+    pub(crate) key_size: usize,
+    pub(crate) value_size: usize,
+    pub(crate) meta_size: usize,
+    // 记录头在文件中的长度
+    pub(crate) header_size: usize,
+    // 记录头的原始数据，用于计算校验值
+    pub(crate) raw: Bytes,
+    // key 中省略了非事务写入的序列号前缀，key_size 为省略之后的长度
+    pub(crate) seq_omitted: bool,
+}
+
+// 记录中存储的校验值以及读取时重新计算的校验值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RecordCrc {
+    pub(crate) stored: u32,
+    pub(crate) computed: u32,
+}
+
+impl RecordCrc {
+    pub(crate) fn is_valid(&self) -> bool {
+        self.stored == self.computed
+    }
+}
 
 pub const DATA_FILE_NAME_SUFFIX: &str = ".data";
 
+// 创建数据文件时使用的临时文件后缀
+pub(crate) const TEMP_DATA_FILE_NAME_SUFFIX: &str = ".data.tmp";
+
+const UNSEALED: u64 = u64::MAX;
+
 pub struct DataFile {
-    // 数据文件id
-    pub(crate) file_id: Arc<RwLock<u32>>,
+    // 数据文件id，创建之后不会改变
+    pub(crate) file_id: u32,
     // 当前写便宜，记录文件写到什么位置
     pub(crate) write_off: Arc<RwLock<u64>>,
     // IO 管理
     pub(crate) io_manager: Box<dyn fio::IOManager>,
+    // 文件路径
+    path: PathBuf,
+    // 文件已经被淘汰（例如被 merge 替换），最后一个引用释放时删除磁盘上的文件
+    retired: AtomicBool,
+    // 文件中已经失效的数据量，包括被覆盖或删除的记录、删除标记、事务标识和对齐填充
+    stale_bytes: AtomicU64,
+    // 文件中的记录数，不包括对齐填充
+    record_count: AtomicU64,
+    // 已经持久化的位置
+    synced_off: AtomicU64,
+    // 封存之后文件的长度，不再变化，超出长度的读取不需要访问文件，没有封存时为 UNSEALED
+    sealed_len: AtomicU64,
+    // 按索引位置读取的统计
+    read_stats: ReadStats,
 }
 
 impl DataFile {
-    pub fn new(dir_path: PathBuf, file_id: u32) -> Result<Self> {
+    pub fn new(dir_path: PathBuf, file_id: u32, io_type: IOType) -> Result<Self> {
         // 根据path和id构造出完整的文件名称
         let file_name: PathBuf = get_data_file_name(&dir_path, file_id);
         // 初始化 io manager
-        let io_manager = new_io_manager(&file_name)?;
+        let io_manager = new_io_manager(&file_name, io_type)?;
+        Ok(Self::with_io_manager(file_name, file_id, io_manager))
+    }
 
-        Ok(DataFile {
-            file_id: Arc::new(RwLock::new(file_id)),
+    // 创建新的数据文件，先以临时文件名创建并持久化，再重命名为数据文件并持久化目录，
+    // 崩溃时不会留下只创建了一半的数据文件，残留的临时文件在下次打开时删除
+    pub fn create(dir_path: PathBuf, file_id: u32, io_type: IOType) -> Result<Self> {
+        Self::create_with_mode(dir_path, file_id, io_type, 0o666)
+    }
+
+    // 以 mode 权限创建新的数据文件
+    pub fn create_with_mode(
+        dir_path: PathBuf,
+        file_id: u32,
+        io_type: IOType,
+        mode: u32,
+    ) -> Result<Self> {
+        let file_name = get_data_file_name(&dir_path, file_id);
+        if file_name.exists() {
+            error!("Data file {:?} already exists", file_name);
+            return Err(Errors::DataDirectoryCorrupted);
+        }
+        let temp_name = get_temp_data_file_name(&dir_path, file_id);
+        let create_res = fio::create_file(&temp_name, mode)
+            .and_then(|file| file.sync_all())
+            .and_then(|_| fs::rename(&temp_name, &file_name));
+        if let Err(e) = create_res {
+            error!("Failed to create data file {:?}: {e}", file_name);
+            return Err(Errors::FailedToOpenDataFile(e.into()));
+        }
+        fio::sync_dir(&dir_path)?;
+        Self::new(dir_path, file_id, io_type)
+    }
+
+    // 以只读方式打开已经存在的数据文件
+    pub fn open_read_only(dir_path: PathBuf, file_id: u32) -> Result<Self> {
+        let file_name = get_data_file_name(&dir_path, file_id);
+        let io_manager = new_read_only_io_manager(&file_name)?;
+        Ok(Self::with_io_manager(file_name, file_id, io_manager))
+    }
+
+    pub(crate) fn with_io_manager(
+        file_name: PathBuf,
+        file_id: u32,
+        io_manager: Box<dyn fio::IOManager>,
+    ) -> Self {
+        DataFile {
+            file_id,
             write_off: Arc::new(RwLock::new(0)),
-            io_manager: Box::new(io_manager),
-        })
+            io_manager,
+            path: file_name,
+            retired: AtomicBool::new(false),
+            stale_bytes: AtomicU64::new(0),
+            record_count: AtomicU64::new(0),
+            synced_off: AtomicU64::new(0),
+            sealed_len: AtomicU64::new(UNSEALED),
+            read_stats: ReadStats::default(),
+        }
     }
 
     pub fn get_write_off(&self) -> u64 {
@@ -44,56 +153,169 @@ impl DataFile {
     }
 
     pub fn get_file_id(&self) -> u32 {
-        *self.file_id.read()
+        self.file_id
+    }
+
+    /// 文件中数据的长度，封存的文件为封存时的长度，否则为当前的写入位置
+    pub fn len(&self) -> u64 {
+        match self.sealed_len.load(Ordering::Acquire) {
+            UNSEALED => self.get_write_off(),
+            len => len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // 文件不会再写入，缓存当前的长度，之后超出长度的读取直接返回 EOF
+    pub(crate) fn seal(&self) {
+        self.sealed_len
+            .store(self.get_write_off(), Ordering::Release);
+    }
+
+    // 封存的文件中 offset 之后还可以读取的长度，没有封存时为 None
+    fn sealed_remaining(&self, offset: u64) -> Option<u64> {
+        match self.sealed_len.load(Ordering::Acquire) {
+            UNSEALED => None,
+            len => Some(len.saturating_sub(offset)),
+        }
     }
 
     /// 根据 offet 从数据文件中读取Logrecord
     pub fn read_log_record(&self, offset: u64) -> Result<ReadLogRecord> {
-        let mut header_buf = BytesMut::zeroed(max_log_record_header_size());
+        let (read_log_record, crc) = self.read_log_record_with_crc(offset)?;
+        if !crc.is_valid() {
+            return Err(Errors::InvalidLogRecordCrc);
+        }
+        Ok(read_log_record)
+    }
 
+    // 读取记录以及存储的和重新计算的校验值，校验值不一致时不返回错误
+    pub(crate) fn read_log_record_with_crc(
+        &self,
+        offset: u64,
+    ) -> Result<(ReadLogRecord, RecordCrc)> {
+        let RecordHeader {
+            rec_type,
+            key_size,
+            value_size,
+            meta_size,
+            header_size: actual_header_size,
+            raw,
+            seq_omitted,
+        } = self.read_record_header(offset)?;
+
+        let mut kv_buf = BytesMut::zeroed(meta_size + key_size + value_size + 4);
+        self.io_manager
+            .read(&mut kv_buf, offset + actual_header_size as u64)?;
+
+        // 校验值覆盖记录头和之后的数据，直接对读取到的字节计算，不需要重新编码记录
+        let body_size = meta_size + key_size + value_size;
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&raw);
+        hasher.update(&kv_buf[..body_size]);
+        let crc = RecordCrc {
+            stored: (&kv_buf[body_size..]).get_u32(),
+            computed: hasher.finalize(),
+        };
+
+        // 构造LogRecord
+        let mut key = Vec::with_capacity(key_size + 1);
+        if seq_omitted {
+            // 补回非事务写入的序列号，上层读取到的 key 与没有省略时相同
+            key.push(0);
+        }
+        key.extend_from_slice(&kv_buf[meta_size..meta_size + key_size]);
+        let log_record = LogRecord {
+            key,
+            value: kv_buf[meta_size + key_size..body_size].to_vec(),
+            rec_type,
+            meta: kv_buf[..meta_size].to_vec(),
+        };
+        // 构造结果并返回
+        let read_log_record = ReadLogRecord {
+            record: log_record,
+            size: actual_header_size + meta_size + key_size + value_size + 4,
+        };
+        Ok((read_log_record, crc))
+    }
+
+    // 读取并解析记录头，不包括元数据、key 和 value
+    pub(crate) fn read_record_header(&self, offset: u64) -> Result<RecordHeader> {
+        if self.sealed_remaining(offset) == Some(0) {
+            return Err(Errors::ReadDataFileEOF);
+        }
+        let mut header_buf = BytesMut::zeroed(max_log_record_header_size());
         self.io_manager.read(&mut header_buf, offset)?;
+        let raw = header_buf.clone();
 
         // 取出 type，在第一字节
         let rec_type = header_buf.get_u8();
         // 取出key和value的长度
-        let key_size = decode_length_delimiter(&mut header_buf).unwrap();
-        let value_size = decode_length_delimiter(header_buf).unwrap();
+        let corrupted = |what: &str| {
+            invariant_violation(
+                Errors::DataFileCorrupted,
+                format_args!(
+                    "Invalid {what} in data file {:?} at offset {offset}",
+                    self.path
+                ),
+            )
+        };
+        let key_size =
+            decode_length_delimiter(&mut header_buf).map_err(|_| corrupted("key size"))?;
+        let value_size =
+            decode_length_delimiter(&mut header_buf).map_err(|_| corrupted("value size"))?;
 
         // 如果key和value的长度都为0，则表示读取到文件末尾
         if key_size == 0 && value_size == 0 {
             return Err(Errors::ReadDataFileEOF);
         }
+        let record_type = match LogRecordType::try_from_u8(
+            rec_type & !(LOG_RECORD_META_FLAG | LOG_RECORD_NO_SEQ_FLAG),
+        ) {
+            Some(record_type) => record_type,
+            None => return Err(corrupted("record type")),
+        };
 
         // key 和value 有值，则读取header实际的长度,1为校验位的值
-        let actual_header_size =
-            length_delimiter_len(key_size) + length_delimiter_len(value_size) + 1;
-
-        let mut kv_buf = BytesMut::zeroed(key_size + value_size + 4);
-        self.io_manager
-            .read(&mut kv_buf, offset + actual_header_size as u64)?;
-
-        // 构造LogRecord
-        let mut log_record = LogRecord {
-            key: kv_buf.get(..key_size).unwrap().to_vec(),
-            value: kv_buf.get(key_size..kv_buf.len() - 4).unwrap().to_vec(),
-            rec_type: LogRecordType::from_u8(rec_type),
-        };
+        let mut header_size = length_delimiter_len(key_size) + length_delimiter_len(value_size) + 1;
 
-        // 向前移动到最后四个字节，就是crc值 拿到校验值
-        kv_buf.advance(key_size + value_size);
-        if kv_buf.get_u32() != log_record.get_crc() {
-            return Err(Errors::InvalidLogRecordCrc);
+        // 取出元数据的长度
+        let mut meta_size = 0;
+        if rec_type & LOG_RECORD_META_FLAG != 0 {
+            meta_size = header_buf.get_u8() as usize;
+            header_size += 1;
         }
-        // 构造结果并返回
-        Ok(ReadLogRecord {
-            record: log_record,
-            size: actual_header_size + key_size + value_size + 4,
+
+        Ok(RecordHeader {
+            rec_type: record_type,
+            key_size,
+            value_size,
+            meta_size,
+            header_size,
+            raw: raw.freeze().slice(..header_size),
+            seq_omitted: rec_type & LOG_RECORD_NO_SEQ_FLAG != 0,
         })
     }
 
-    pub fn set_write_off(&self, offset: u64) {
+    // 从给定位置读取数据，不足 buf 长度时返回读取到的长度
+    pub(crate) fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        let n = match self.sealed_remaining(offset) {
+            Some(0) => return Ok(0),
+            Some(remaining) => remaining.min(buf.len() as u64) as usize,
+            None => buf.len(),
+        };
+        self.io_manager.read(&mut buf[..n], offset)
+    }
+
+    pub fn set_write_off(&self, offset: u64) -> Result<()> {
         let mut write_guard = self.write_off.write();
+        self.io_manager.set_write_off(offset)?;
         *write_guard = offset;
+        // 已经存在于文件中的数据视为已经持久化
+        self.synced_off.store(offset, Ordering::SeqCst);
+        Ok(())
     }
 
     pub fn write(&self, buf: &[u8]) -> Result<usize> {
@@ -105,11 +327,65 @@ impl DataFile {
     }
 
     pub fn sync(&self) -> Result<()> {
-        self.io_manager.sync()
+        let write_off = self.get_write_off();
+        self.io_manager.sync()?;
+        self.synced_off.fetch_max(write_off, Ordering::SeqCst);
+        Ok(())
+    }
+
+    // 是否有写入之后还没有持久化的数据
+    pub(crate) fn has_unsynced_data(&self) -> bool {
+        self.get_write_off() > self.synced_off.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn read_stats(&self) -> &ReadStats {
+        &self.read_stats
+    }
+
+    pub(crate) fn add_stale_bytes(&self, n: u64) {
+        self.stale_bytes.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_stale_bytes(&self, n: u64) {
+        self.stale_bytes.store(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn get_stale_bytes(&self) -> u64 {
+        self.stale_bytes.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add_record_count(&self, n: u64) {
+        self.record_count.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn get_record_count(&self) -> u64 {
+        self.record_count.load(Ordering::Relaxed)
+    }
+
+    // 将文件标记为已淘汰，仍在使用该文件的迭代器等不受影响
+    pub(crate) fn retire(&self) {
+        self.retired.store(true, Ordering::SeqCst);
     }
 }
 
-pub fn get_data_file_name(dir_path: &PathBuf, file_id: u32) -> PathBuf {
+impl Drop for DataFile {
+    fn drop(&mut self) {
+        if self.retired.load(Ordering::SeqCst) {
+            if let Err(e) = fs::remove_file(&self.path) {
+                error!("Failed to remove retired data file {:?}: {e}", self.path);
+                return;
+            }
+            // 持久化删除操作，失败时只输出日志，重启之后最多重新加载一次已经淘汰的文件
+            let _ = fio::sync_parent_dir(&self.path);
+        }
+    }
+}
+
+pub(crate) fn get_temp_data_file_name(dir_path: &Path, file_id: u32) -> PathBuf {
+    dir_path.join(format!("{:09}{}", file_id, TEMP_DATA_FILE_NAME_SUFFIX))
+}
+
+pub fn get_data_file_name(dir_path: &Path, file_id: u32) -> PathBuf {
     PathBuf::from(format!(
         "{}/{:09}{}",
         dir_path.to_str().unwrap(),
@@ -120,45 +396,71 @@ pub fn get_data_file_name(dir_path: &PathBuf, file_id: u32) -> PathBuf {
 
 #[cfg(test)]
 mod tests {
-    use crate::data::log_record::{LogRecord, LogRecordType};
+    use std::{fs, path::PathBuf};
+
+    use crate::{
+        data::log_record::{LogRecord, LogRecordType},
+        errors::Errors,
+        options::IOType,
+    };
 
-    use super::DataFile;
+    use super::{get_data_file_name, DataFile};
 
     #[test]
     fn test_new_data_file() {
         let dir_path = std::env::temp_dir();
-        let data_file = DataFile::new(dir_path, 9090);
+        let data_file = DataFile::new(dir_path, 9090, IOType::StandardFIO);
         assert!(data_file.is_ok());
 
         let data_file: DataFile = data_file.unwrap();
         assert_eq!(data_file.get_file_id(), 9090);
     }
 
+    #[test]
+    fn test_create_data_file() {
+        let dir_path = std::env::temp_dir().join("bitcask-rs-create-data-file");
+        std::fs::create_dir_all(&dir_path).unwrap();
+        let data_file = DataFile::create(dir_path.clone(), 1, IOType::StandardFIO);
+        assert!(data_file.is_ok());
+        assert!(super::get_data_file_name(&dir_path, 1).is_file());
+        assert!(!super::get_temp_data_file_name(&dir_path, 1).exists());
+
+        // 不能覆盖已经存在的数据文件
+        let data_file = DataFile::create(dir_path.clone(), 1, IOType::StandardFIO);
+        assert_eq!(
+            data_file.err().unwrap(),
+            crate::errors::Errors::DataDirectoryCorrupted
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(dir_path).expect("failed to remove path");
+    }
+
     #[test]
     fn test_data_file_write() {
         let dir_path = std::env::temp_dir();
-        let data_file_res1 = DataFile::new(dir_path.clone(), 100);
+        let data_file_res1 = DataFile::new(dir_path.clone(), 100, IOType::StandardFIO);
         assert!(data_file_res1.is_ok());
         let data_file1 = data_file_res1.unwrap();
         assert_eq!(data_file1.get_file_id(), 100);
 
         let write_res1 = data_file1.write("aaa".as_bytes());
         assert!(write_res1.is_ok());
-        assert_eq!(write_res1.unwrap(), 3 as usize);
+        assert_eq!(write_res1.unwrap(), 3_usize);
 
         let write_res2 = data_file1.write("bbb".as_bytes());
         assert!(write_res2.is_ok());
-        assert_eq!(write_res2.unwrap(), 3 as usize);
+        assert_eq!(write_res2.unwrap(), 3_usize);
 
         let write_res3 = data_file1.write("ccc".as_bytes());
         assert!(write_res3.is_ok());
-        assert_eq!(write_res3.unwrap(), 3 as usize);
+        assert_eq!(write_res3.unwrap(), 3_usize);
     }
 
     #[test]
     fn test_data_file_sync() {
         let dir_path = std::env::temp_dir();
-        let data_file_res1 = DataFile::new(dir_path.clone(), 200);
+        let data_file_res1 = DataFile::new(dir_path.clone(), 200, IOType::StandardFIO);
         assert!(data_file_res1.is_ok());
         let data_file1 = data_file_res1.unwrap();
         assert_eq!(data_file1.get_file_id(), 200);
@@ -170,7 +472,7 @@ mod tests {
     #[test]
     fn test_data_file_read_log_record() {
         let dir_path = std::env::temp_dir();
-        let data_file_res1 = DataFile::new(dir_path.clone(), 700);
+        let data_file_res1 = DataFile::new(dir_path.clone(), 700, IOType::StandardFIO);
         assert!(data_file_res1.is_ok());
         let data_file1 = data_file_res1.unwrap();
         assert_eq!(data_file1.get_file_id(), 700);
@@ -179,6 +481,7 @@ mod tests {
             key: "name".as_bytes().to_vec(),
             value: "bitcask-rs-kv".as_bytes().to_vec(),
             rec_type: LogRecordType::NORMAL,
+            meta: Default::default(),
         };
         let write_res1 = data_file1.write(&enc1.encode());
         assert!(write_res1.is_ok());
@@ -196,6 +499,7 @@ mod tests {
             key: "name".as_bytes().to_vec(),
             value: "new-value".as_bytes().to_vec(),
             rec_type: LogRecordType::NORMAL,
+            meta: Default::default(),
         };
         let write_res2 = data_file1.write(&enc2.encode());
         assert!(write_res2.is_ok());
@@ -212,6 +516,7 @@ mod tests {
             key: "name".as_bytes().to_vec(),
             value: Default::default(),
             rec_type: LogRecordType::DELETED,
+            meta: Default::default(),
         };
         let write_res3 = data_file1.write(&enc3.encode());
         assert!(write_res3.is_ok());
@@ -223,4 +528,75 @@ mod tests {
         assert_eq!(enc3.value, read_enc3.value);
         assert_eq!(enc3.rec_type, read_enc3.rec_type);
     }
+
+    #[test]
+    fn test_data_file_read_log_record_crc() {
+        let dir_path = PathBuf::from("/tmp/bitcask-rs-data-file-crc");
+        fs::create_dir_all(&dir_path).unwrap();
+        let data_file = DataFile::new(dir_path.clone(), 0, IOType::StandardFIO).unwrap();
+        let record = LogRecord {
+            key: "name".as_bytes().to_vec(),
+            value: "bitcask-rs-kv".as_bytes().to_vec(),
+            rec_type: LogRecordType::NORMAL,
+            meta: "v1".as_bytes().to_vec(),
+        };
+        let enc = record.encode();
+        data_file.write(&enc).unwrap();
+        let read = data_file.read_log_record(0).unwrap();
+        assert_eq!(read.record, record);
+        assert_eq!(read.size, enc.len());
+        std::mem::drop(data_file);
+
+        // 修改记录头、元数据、value 中的任意一个字节都会导致校验失败
+        let path = get_data_file_name(&dir_path, 0);
+        for i in [0, 4, enc.len() - 6] {
+            let mut corrupted = enc.to_vec();
+            corrupted[i] ^= 0x04;
+            fs::write(&path, &corrupted).unwrap();
+            let data_file = DataFile::new(dir_path.clone(), 0, IOType::StandardFIO).unwrap();
+            assert_eq!(
+                data_file.read_log_record(0).err(),
+                Some(Errors::InvalidLogRecordCrc)
+            );
+        }
+
+        // 删除测试的文件夹
+        fs::remove_dir_all(dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_data_file_len() {
+        let dir_path = PathBuf::from("/tmp/bitcask-rs-data-file-len");
+        fs::create_dir_all(&dir_path).unwrap();
+        let data_file = DataFile::new(dir_path.clone(), 0, IOType::StandardFIO).unwrap();
+        assert!(data_file.is_empty());
+        let record = LogRecord {
+            key: "name".as_bytes().to_vec(),
+            value: "bitcask-rs-kv".as_bytes().to_vec(),
+            rec_type: LogRecordType::NORMAL,
+            meta: Default::default(),
+        };
+        let enc = record.encode();
+        data_file.write(&enc).unwrap();
+        assert_eq!(data_file.len(), enc.len() as u64);
+
+        // 封存之后即使文件变长，超出封存长度的部分也读取不到
+        data_file.seal();
+        let path = get_data_file_name(&dir_path, 0);
+        let mut content = fs::read(&path).unwrap();
+        content.extend_from_slice(&enc);
+        fs::write(&path, &content).unwrap();
+        assert_eq!(data_file.len(), enc.len() as u64);
+        assert_eq!(data_file.read_log_record(0).unwrap().record, record);
+        assert_eq!(
+            data_file.read_log_record(enc.len() as u64).err(),
+            Some(Errors::ReadDataFileEOF)
+        );
+        let mut buf = vec![0; enc.len() * 2];
+        assert_eq!(data_file.read_at(&mut buf, 4).unwrap(), enc.len() - 4);
+        assert_eq!(data_file.read_at(&mut buf, enc.len() as u64).unwrap(), 0);
+
+        // 删除测试的文件夹
+        fs::remove_dir_all(dir_path).expect("failed to remove path");
+    }
 }
@@ -1,13 +1,16 @@
 use core::panic;
 
-use bytes::{BufMut, BytesMut};
+use std::ops::Deref;
+
+use bytes::BufMut;
 use prost::{encode_length_delimiter, length_delimiter_len};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct LogRecordPos {
     pub(crate) file_id: u32,
     pub(crate) offset: u64,
-    // pub(crate) size: u64,
+    // 记录编码之后占用的字节数，用于统计数据文件中的有效和失效数据量
+    pub(crate) size: u64,
 }
 
 /// LogRecord写入数据文件的记录
@@ -17,8 +20,19 @@ pub struct LogRecord {
     pub(crate) key: Vec<u8>,
     pub(crate) value: Vec<u8>,
     pub(crate) rec_type: LogRecordType,
+    // 用户自定义的元数据，最长 255 字节
+    pub(crate) meta: Vec<u8>,
 }
 
+/// 元数据的最大长度
+pub const MAX_LOG_RECORD_META_SIZE: usize = u8::MAX as usize;
+
+// type 字节的最高位标识记录中是否带有元数据，不带元数据的记录格式保持不变
+pub(crate) const LOG_RECORD_META_FLAG: u8 = 0x80;
+
+// type 字节的次高位标识 key 中省略了非事务写入的序列号前缀，读取时补回，见 Options::omit_seq_prefix
+pub(crate) const LOG_RECORD_NO_SEQ_FLAG: u8 = 0x40;
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum LogRecordType {
     NORMAL = 1,
@@ -27,15 +41,35 @@ pub enum LogRecordType {
 
     //事务完成标识
     TXNFINISH = 3,
+
+    // 对齐填充，读取时直接跳过
+    PADDING = 4,
+
+    // 分块存储的 value 的头记录，value 中是分块的信息
+    CHUNKED = 5,
 }
 
 // LogRecordType::from_v8
 impl LogRecordType {
+    /// 解析记录类型，未知的类型返回 None
+    pub fn try_from_u8(v: u8) -> Option<Self> {
+        match v {
+            1 => Some(LogRecordType::NORMAL),
+            2 => Some(LogRecordType::DELETED),
+            3 => Some(LogRecordType::TXNFINISH),
+            4 => Some(LogRecordType::PADDING),
+            5 => Some(LogRecordType::CHUNKED),
+            _ => None,
+        }
+    }
+
     pub fn from_u8(v: u8) -> Self {
         match v {
             1 => LogRecordType::NORMAL,
             2 => LogRecordType::DELETED,
             3 => LogRecordType::TXNFINISH,
+            4 => LogRecordType::PADDING,
+            5 => LogRecordType::CHUNKED,
             _ => panic!("Unknown log record type!"),
         }
     }
@@ -48,53 +82,160 @@ pub struct TransactionRecord {
 }
 
 //
-// + -------- + --------- + --------- + --- + ----- + ------- +
-// | type 类型 | key size  | value size| key | value | CrC校验值 |
-// + -------- + --------- + --------- + --- + ----- + ------- +
-// |    1字节  | 变长（最大5）| 变长（最大5） | 变长 |  变长  |   4字节   |
-// + -------- + --------- + --------- + --- + ----- + ------- +
+// + -------- + --------- + --------- + --------- + ---- + --- + ----- + ------- +
+// | type 类型 | key size  | value size| meta size | meta | key | value | CrC校验值 |
+// + -------- + --------- + --------- + --------- + ---- + --- + ----- + ------- +
+// |    1字节  | 变长（最大5）| 变长（最大5） | 1字节（可选）| 变长 | 变长 |  变长  |   4字节   |
+// + -------- + --------- + --------- + --------- + ---- + --- + ----- + ------- +
+// 只有 type 带有 LOG_RECORD_META_FLAG 时才有 meta size 和 meta 部分
 impl LogRecord {
-    // encode 对logRecord 进行编码，，返回字节数组及其长度
-    pub fn encode(&self) -> Vec<u8> {
-        let (_, enc_buf) = self.encode_and_get_crc();
-        enc_buf
+    // encode 对logRecord 进行编码，同时计算出校验值
+    pub fn encode(&self) -> EncodedLogRecord {
+        let mut buf = Vec::with_capacity(self.encoded_length());
+        let crc = self.encode_to(&mut buf);
+        EncodedLogRecord {
+            buf,
+            header_size: self.header_size(self.key.len()),
+            crc,
+        }
     }
 
+    #[deprecated(note = "use LogRecord::encode().crc(), which does not encode the record twice")]
     pub fn get_crc(&mut self) -> u32 {
-        let (crc, _) = self.encode_and_get_crc();
-        crc
+        self.encode().crc()
     }
 
-    fn encode_and_get_crc(&self) -> (u32, Vec<u8>) {
-        let mut buf = BytesMut::with_capacity(self.encoded_length());
+    // 将编码后的数据追加到 buf 的末尾，返回校验值
+    pub(crate) fn encode_to(&self, buf: &mut Vec<u8>) -> u32 {
+        self.encode_to_with(buf, false)
+    }
+
+    // omit_seq_prefix 为 true 时，非事务写入的记录不写入 key 前的序列号，其他记录不受影响
+    pub(crate) fn encode_to_with(&self, buf: &mut Vec<u8>, omit_seq_prefix: bool) -> u32 {
+        let start = buf.len();
+        let omit = omit_seq_prefix && self.can_omit_seq();
+        let key = match omit {
+            true => &self.key[1..],
+            false => &self.key[..],
+        };
+        buf.reserve(self.encoded_length_with(omit));
 
         // 第一个字节存type类型
-        buf.put_u8(self.rec_type as u8);
+        let mut rec_type = self.rec_type as u8;
+        if !self.meta.is_empty() {
+            rec_type |= LOG_RECORD_META_FLAG;
+        }
+        if omit {
+            rec_type |= LOG_RECORD_NO_SEQ_FLAG;
+        }
+        buf.put_u8(rec_type);
         // 在存储key和value的长度
-        encode_length_delimiter(self.key.len(), &mut buf).unwrap();
-        encode_length_delimiter(self.value.len(), &mut buf).unwrap();
-        buf.extend_from_slice(&self.key);
+        encode_length_delimiter(key.len(), buf).unwrap();
+        encode_length_delimiter(self.value.len(), buf).unwrap();
+        // 存储元数据
+        if !self.meta.is_empty() {
+            buf.put_u8(self.meta.len() as u8);
+            buf.extend_from_slice(&self.meta);
+        }
+        buf.extend_from_slice(key);
         buf.extend_from_slice(&self.value);
 
         // 计算并存储CRC校验值
         let mut header = crc32fast::Hasher::new();
-        header.update(&buf);
+        header.update(&buf[start..]);
         let crc = header.finalize();
         buf.put_u32(crc);
 
         // println!("crc: {}", crc);
 
-        (crc, buf.to_vec())
+        crc
     }
 
     // 计算编码后长度
-    fn encoded_length(&self) -> usize {
+    pub(crate) fn encoded_length(&self) -> usize {
+        self.encoded_length_with(false)
+    }
+
+    // 按照 encode_to_with 的方式编码之后的长度
+    pub(crate) fn encoded_length_with(&self, omit_seq_prefix: bool) -> usize {
+        let key_len = match omit_seq_prefix && self.can_omit_seq() {
+            true => self.key.len() - 1,
+            false => self.key.len(),
+        };
+        self.header_size(key_len) + self.meta.len() + key_len + self.value.len() + 4
+    }
+
+    // key 以非事务的序列号（编码为一个 0 字节）开头，并且省略之后 key 不为空
+    fn can_omit_seq(&self) -> bool {
+        self.rec_type != LogRecordType::PADDING && self.key.len() > 1 && self.key[0] == 0
+    }
+
+    // 记录头的长度，包括 type、各部分的长度
+    fn header_size(&self, key_len: usize) -> usize {
+        let meta_size_len = match self.meta.is_empty() {
+            true => 0,
+            false => std::mem::size_of::<u8>(),
+        };
         std::mem::size_of::<u8>()
-            + length_delimiter_len(self.key.len())
+            + length_delimiter_len(key_len)
             + length_delimiter_len(self.value.len())
-            + self.key.len()
-            + self.value.len()
-            + 4
+            + meta_size_len
+    }
+}
+
+/// 编码之后的记录，可以直接写入数据文件，编码时已经计算出校验值
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedLogRecord {
+    buf: Vec<u8>,
+    header_size: usize,
+    crc: u32,
+}
+
+impl EncodedLogRecord {
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// 记录头，包括 type 和各部分的长度
+    pub fn header(&self) -> &[u8] {
+        &self.buf[..self.header_size]
+    }
+
+    /// 记录头之后的 meta、key 和 value，不包括校验值
+    pub fn payload(&self) -> &[u8] {
+        &self.buf[self.header_size..self.buf.len() - 4]
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+impl Deref for EncodedLogRecord {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+// 填充记录的最小长度，value 至少一个字节，避免被当作文件末尾
+pub(crate) const MIN_PADDING_SIZE: u64 = 8;
+
+/// 构造编码后长度恰好为 size 的填充记录，size 不能小于 MIN_PADDING_SIZE
+pub(crate) fn padding_record(size: u64) -> LogRecord {
+    let size = size as usize;
+    // type + key size + crc
+    let fixed = std::mem::size_of::<u8>() * 2 + 4;
+    let mut value_size = size - fixed - 1;
+    while value_size + length_delimiter_len(value_size) + fixed > size {
+        value_size -= 1;
+    }
+    LogRecord {
+        key: Default::default(),
+        value: vec![0; value_size],
+        rec_type: LogRecordType::PADDING,
+        meta: Default::default(),
     }
 }
 
@@ -107,7 +248,7 @@ pub struct ReadLogRecord {
 
 /// 获取Logrecord header部分的最大长度
 pub fn max_log_record_header_size() -> usize {
-    std::mem::size_of::<u8>() + length_delimiter_len(std::u32::MAX as usize) * 2
+    std::mem::size_of::<u8>() * 2 + length_delimiter_len(u32::MAX as usize) * 2
 }
 
 #[cfg(test)]
@@ -121,8 +262,10 @@ mod tests {
             key: "name".as_bytes().to_vec(),
             value: "bitcask-rs".as_bytes().to_vec(),
             rec_type: LogRecordType::NORMAL,
+            meta: Default::default(),
         };
-        let (crc1, enc1) = rec1.encode_and_get_crc();
+        let enc1 = rec1.encode();
+        let crc1 = enc1.crc();
         assert!(crc1 == 1020360578);
         assert!(enc1.len() == 21);
         // println!("{}, {:?}", crc1, enc1);
@@ -132,8 +275,10 @@ mod tests {
             key: "name1".as_bytes().to_vec(),
             value: Vec::default(),
             rec_type: LogRecordType::NORMAL,
+            meta: Default::default(),
         };
-        let (crc2, enc2) = rec2.encode_and_get_crc();
+        let enc2 = rec2.encode();
+        let crc2 = enc2.crc();
         // println!("{}, {:?}", crc2, enc2);
         assert!(crc2 == 1467182769);
         assert!(enc2.len() == 12);
@@ -143,10 +288,63 @@ mod tests {
             key: "name1".as_bytes().to_vec(),
             value: "bitcask-rs".as_bytes().to_vec(),
             rec_type: LogRecordType::DELETED,
+            meta: Default::default(),
         };
-        let (crc3, enc3) = rec3.encode_and_get_crc();
+        let enc3 = rec3.encode();
+        let crc3 = enc3.crc();
         // println!("{}, {:?}", crc3, enc3);
         assert!(crc3 == 243009088);
         assert!(enc3.len() == 22);
+
+        // 带有元数据
+        let rec4 = LogRecord {
+            key: "name".as_bytes().to_vec(),
+            value: "bitcask-rs".as_bytes().to_vec(),
+            rec_type: LogRecordType::NORMAL,
+            meta: "json".as_bytes().to_vec(),
+        };
+        let enc4 = rec4.encode();
+        assert!(enc4.len() == 26);
+        assert!(enc4[0] == LogRecordType::NORMAL as u8 | LOG_RECORD_META_FLAG);
+
+        // 记录头、数据和校验值可以分别取出
+        assert_eq!(enc4.header(), &enc4[..4]);
+        assert_eq!(enc4.payload(), b"jsonnamebitcask-rs");
+        assert_eq!(enc4[22..], enc4.crc().to_be_bytes());
+        assert_eq!(enc4.clone().into_vec(), enc4.to_vec());
+
+        // 省略非事务写入的序列号，事务中的记录不受影响
+        let mut rec5 = LogRecord {
+            key: b"\0name".to_vec(),
+            value: "bitcask-rs".as_bytes().to_vec(),
+            rec_type: LogRecordType::NORMAL,
+            meta: Default::default(),
+        };
+        let mut buf = Vec::new();
+        rec5.encode_to_with(&mut buf, true);
+        assert_eq!(buf.len(), rec5.encoded_length() - 1);
+        assert_eq!(buf.len(), rec5.encoded_length_with(true));
+        assert_eq!(buf[0], LogRecordType::NORMAL as u8 | LOG_RECORD_NO_SEQ_FLAG);
+        rec5.key[0] = 1;
+        assert_eq!(rec5.encoded_length_with(true), rec5.encoded_length());
+    }
+
+    #[test]
+    fn test_padding_record_size() {
+        for size in [
+            MIN_PADDING_SIZE,
+            9,
+            100,
+            133,
+            134,
+            4096,
+            16384,
+            16387,
+            100000,
+        ] {
+            let rec = padding_record(size);
+            assert_eq!(rec.encode().len() as u64, size);
+            assert_eq!(rec.rec_type, LogRecordType::PADDING);
+        }
     }
 }
@@ -1,2 +1,3 @@
 pub mod data_file;
 pub mod log_record;
+pub(crate) mod read_stats;
@@ -0,0 +1,104 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// 读取次数的半衰期，每经过一个周期读取次数减半，长期不再读取的文件计数会衰减到 0
+pub(crate) const READ_STATS_HALF_LIFE: Duration = Duration::from_secs(10 * 60);
+
+// 数据文件的读取统计，只使用原子操作，读取时的开销很小
+#[derive(Default)]
+pub(crate) struct ReadStats {
+    // 衰减之后的读取次数
+    reads: AtomicU64,
+    // 最近一次读取的时间（毫秒时间戳），0 表示没有读取过
+    last_read_ms: AtomicU64,
+    // 最近一次衰减所在的周期
+    period: AtomicU64,
+}
+
+impl ReadStats {
+    pub(crate) fn record(&self) {
+        let now = now_millis();
+        self.decay(now, READ_STATS_HALF_LIFE);
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.last_read_ms.fetch_max(now, Ordering::Relaxed);
+    }
+
+    // 返回 (衰减之后的读取次数, 最近一次读取的时间)
+    pub(crate) fn snapshot(&self) -> (u64, Option<SystemTime>) {
+        self.decay(now_millis(), READ_STATS_HALF_LIFE);
+        let last_read_at = match self.last_read_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(UNIX_EPOCH + Duration::from_millis(ms)),
+        };
+        (self.reads.load(Ordering::Relaxed), last_read_at)
+    }
+
+    // 按经过的周期数衰减读取次数，同一个周期内只有一个线程执行衰减
+    fn decay(&self, now_ms: u64, half_life: Duration) {
+        let period = now_ms / (half_life.as_millis() as u64).max(1);
+        let last = self.period.load(Ordering::Relaxed);
+        if period <= last {
+            return;
+        }
+        if self
+            .period
+            .compare_exchange(last, period, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        // 第一次读取之前没有需要衰减的计数
+        if last == 0 {
+            return;
+        }
+        let shift = (period - last).min(63) as u32;
+        let _ = self
+            .reads
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |r| Some(r >> shift));
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_stats_decay() {
+        let half_life = Duration::from_millis(100);
+        let stats = ReadStats::default();
+        stats.decay(1000, half_life);
+        for _ in 0..64 {
+            stats.reads.fetch_add(1, Ordering::Relaxed);
+        }
+
+        // 同一个周期内不会衰减
+        stats.decay(1099, half_life);
+        assert_eq!(stats.reads.load(Ordering::Relaxed), 64);
+        // 经过一个周期减半
+        stats.decay(1100, half_life);
+        assert_eq!(stats.reads.load(Ordering::Relaxed), 32);
+        // 经过三个周期
+        stats.decay(1400, half_life);
+        assert_eq!(stats.reads.load(Ordering::Relaxed), 4);
+        // 时间回退时不变
+        stats.decay(1000, half_life);
+        assert_eq!(stats.reads.load(Ordering::Relaxed), 4);
+        // 很久之后衰减到 0
+        stats.decay(u64::MAX, half_life);
+        assert_eq!(stats.reads.load(Ordering::Relaxed), 0);
+
+        stats.record();
+        let (reads, last_read_at) = stats.snapshot();
+        assert_eq!(reads, 1);
+        assert!(last_read_at.is_some());
+    }
+}
@@ -1,75 +1,544 @@
 use std::{
-    collections::HashMap,
+    collections::{hash_map::RandomState, HashMap},
     fs,
-    path::PathBuf,
+    hash::{BuildHasher, Hasher},
+    path::Path,
     sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Weak,
     },
+    time::{Duration, Instant, SystemTime},
 };
 
 use bytes::Bytes;
-use log::{error, warn};
+use log::{error, info, warn};
 use parking_lot::{Mutex, RwLock};
 
+#[cfg(feature = "drop-check")]
+use crate::options::UnsyncedDropAction;
 use crate::{
-    batch::{log_record_key_with_seq, parse_log_record_key, NON_TRANSACTION_SEQ_NO},
+    audit::{AuditLog, AuditOp},
+    batch::{
+        log_record_key_with_seq, pipeline::CommitPipeline, try_parse_log_record_key,
+        NON_TRANSACTION_SEQ_NO,
+    },
+    buffer_pool::BufferPool,
+    builder::{OpenHooks, OpenPhase},
+    clock::{Clock, SystemClock},
+    conflict::ReplicaConflicts,
     data::{
-        data_file::{DataFile, DATA_FILE_NAME_SUFFIX},
-        log_record::{LogRecord, LogRecordPos, LogRecordType, TransactionRecord},
+        data_file::{
+            get_data_file_name, DataFile, RecordCrc, DATA_FILE_NAME_SUFFIX,
+            TEMP_DATA_FILE_NAME_SUFFIX,
+        },
+        log_record::{
+            padding_record, LogRecord, LogRecordPos, LogRecordType, TransactionRecord,
+            MAX_LOG_RECORD_META_SIZE, MIN_PADDING_SIZE,
+        },
+    },
+    deadline,
+    errors::{invariant_violation, Errors, Result},
+    fence::{check_data_files, init_data_file, write_db_id, DbId, FILE_HEADER_SIZE},
+    fio,
+    follower::Follower,
+    hash::{self, KeyHasher},
+    index::{
+        self,
+        bloom::{BloomFilter, BloomFilterStats},
+        new_engine_indexer, Indexer,
     },
-    errors::{Errors, Result},
-    index::{self, new_indexer},
-    options::Options,
+    manifest::Manifest,
+    memtable::Memtable,
+    merge_dir::recover_merge_dir,
+    metadata::heal_metadata,
+    metrics::{BatchMetrics, OpStats},
+    mismatch::MismatchStats,
+    open_cost::OpenCost,
+    options::{IOType, IndexType, MetadataCheck, Options, RecordAlignment, SyncInterval},
+    prefix_ttl::PrefixTtl,
+    priority::check_priority,
+    punch::redo_punch_holes,
+    quota::QuotaEntry,
+    segment::{SealedSegment, SegmentSubscribers},
+    seq::SeqAllocator,
+    shutdown::ShutdownHandle,
+    stream::EventId,
+    trace::{check_sample_rate, OpTracer, TraceOp},
+    utils::sharded_lock::ShardedLock,
 };
 
 const INITAL_DILE_ID: u32 = 0;
 
-// #[derive(Clone)]
-pub struct Engine {
-    options: Arc<Options>,
+// 自动合并的后台任务检查失效数据比例的间隔
+const MERGE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 存储引擎的统计信息
+#[derive(Debug, Clone)]
+pub struct Stat {
+    // key 的数量
+    pub key_num: usize,
+    // 数据文件的数量
+    pub data_file_num: usize,
+    // 数据目录占用的磁盘空间
+    pub disk_size: u64,
+    // 以下写入统计从本次打开数据库开始计算
+    // 用户写入的数据量（key + value + 元数据）
+    pub user_bytes_written: u64,
+    // 用户写入时实际追加到数据文件的数据量（包含记录头、事务标识等）
+    pub data_bytes_written: u64,
+    // merge、compact 等回收空间的过程中写入的数据量
+    pub merge_bytes_written: u64,
+    // 写放大，实际写入磁盘的数据量与用户写入数据量的比值
+    pub write_amplification: f64,
+    // 每个数据文件的读取统计，按文件id升序
+    pub file_reads: Vec<FileReadStat>,
+}
+
+/// 数据文件的读取统计，从本次打开数据库开始计算
+/// 读取次数每 10 分钟减半，可以用来区分冷热数据文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileReadStat {
+    pub file_id: u32,
+    // 衰减之后的读取次数
+    pub reads: u64,
+    // 最近一次读取的时间，None 表示没有读取过
+    pub last_read_at: Option<SystemTime>,
+}
+
+// 写入数据量统计
+#[derive(Default)]
+pub(crate) struct WriteStats {
+    pub(crate) user_bytes: AtomicU64,
+    pub(crate) data_bytes: AtomicU64,
+    pub(crate) merge_bytes: AtomicU64,
+    // 最近一次合并的吞吐量（读取和重写的字节数 / 秒），没有合并过时为 0
+    pub(crate) merge_throughput: AtomicU64,
+}
+
+// 已经写入数据文件、但还没有更新内存索引的写入
+// 重写旧数据的过程在持有 append_lock 时等待这些写入完成，保证读取到的索引和数据文件一致，
+// 否则重写的旧数据可能追加在新数据之后，重启之后覆盖新数据
+#[derive(Default)]
+pub(crate) struct InflightWrites {
+    count: AtomicUsize,
+}
+
+impl InflightWrites {
+    // 需要在持有 append_lock 时调用
+    pub(crate) fn begin(&self) -> InflightGuard<'_> {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        InflightGuard(&self.count)
+    }
+
+    // 等待所有已经写入的数据更新完索引，需要在持有 append_lock 时调用
+    pub(crate) fn wait_idle(&self) {
+        while self.count.load(Ordering::SeqCst) > 0 {
+            std::thread::yield_now();
+        }
+    }
+
+    // 同 wait_idle，最多等待到 deadline，超时返回 false
+    pub(crate) fn wait_idle_until(&self, deadline: Instant) -> bool {
+        while self.count.load(Ordering::SeqCst) > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::yield_now();
+        }
+        true
+    }
+}
+
+pub(crate) struct InflightGuard<'a>(&'a AtomicUsize);
+
+impl Drop for InflightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// 某一时刻的数据文件集合，创建之后不再修改，切换活跃文件时整体替换
+pub(crate) struct DataFiles {
     // 当前活跃文件
-    active_file: Arc<RwLock<DataFile>>,
+    pub(crate) active: Arc<DataFile>,
     // 旧的数据文件
-    older_files: Arc<RwLock<HashMap<u32, DataFile>>>,
+    pub(crate) older: HashMap<u32, Arc<DataFile>>,
+    // 当前活跃文件开始使用的时间
+    pub(crate) active_since: Instant,
+}
+
+impl DataFiles {
+    pub(crate) fn get(&self, file_id: u32) -> Option<&DataFile> {
+        match self.active.get_file_id() == file_id {
+            true => Some(&self.active),
+            false => self.older.get(&file_id).map(|f| f.as_ref()),
+        }
+    }
+
+    // 获取数据文件的引用，文件被淘汰之后仍然可以继续读取
+    pub(crate) fn get_shared(&self, file_id: u32) -> Option<Arc<DataFile>> {
+        match self.active.get_file_id() == file_id {
+            true => Some(self.active.clone()),
+            false => self.older.get(&file_id).cloned(),
+        }
+    }
+
+    // 根据位置读取原始的 LogRecord
+    pub(crate) fn read_log_record_at(&self, log_record_pos: &LogRecordPos) -> Result<LogRecord> {
+        // 从对应的数据文件中获取对应的 Logrecord
+        let data_file = match self.get(log_record_pos.file_id) {
+            Some(data_file) => data_file,
+            // 找不到对应的数据文件，返回错误
+            None => {
+                return Err(Errors::FailedToOpenDataFile(
+                    std::io::ErrorKind::NotFound.into(),
+                ))
+            }
+        };
+        data_file.read_stats().record();
+        let read_log_record = data_file.read_log_record(log_record_pos.offset)?;
+        Ok(read_log_record.record)
+    }
+
+    // 根据位置读取记录以及校验值，校验值不一致时不返回错误
+    pub(crate) fn read_log_record_with_crc(
+        &self,
+        log_record_pos: &LogRecordPos,
+    ) -> Result<(LogRecord, RecordCrc)> {
+        let data_file = match self.get(log_record_pos.file_id) {
+            Some(data_file) => data_file,
+            None => {
+                return Err(Errors::FailedToOpenDataFile(
+                    std::io::ErrorKind::NotFound.into(),
+                ))
+            }
+        };
+        data_file.read_stats().record();
+        let (read_log_record, crc) = data_file.read_log_record_with_crc(log_record_pos.offset)?;
+        Ok((read_log_record.record, crc))
+    }
+}
+
+/// 存储引擎，内部状态保存在 EngineCore 中，后台合并任务持有内部状态的引用，在后台线程中合并数据文件
+pub struct Engine {
+    core: Arc<EngineCore>,
+}
+
+impl std::ops::Deref for Engine {
+    type Target = EngineCore;
+
+    fn deref(&self) -> &EngineCore {
+        &self.core
+    }
+}
+
+// 只在打开和 reopen 时修改内部状态，此时后台任务已经停止，没有其他引用
+impl std::ops::DerefMut for Engine {
+    fn deref_mut(&mut self) -> &mut EngineCore {
+        Arc::get_mut(&mut self.core).expect("engine core is shared with running background tasks")
+    }
+}
+
+// 后台任务持有的内部状态的弱引用，不会阻止存储引擎释放
+pub(crate) struct WeakEngine(Weak<EngineCore>);
+
+impl WeakEngine {
+    // 存储引擎已经释放时返回 None
+    pub(crate) fn upgrade(&self) -> Option<Engine> {
+        self.0.upgrade().map(|core| Engine { core })
+    }
+}
+
+impl EngineCore {
+    /// 当前活跃文件中是否有还没有持久化的数据，旧的数据文件在切换时已经持久化
+    pub fn has_unsynced_data(&self) -> bool {
+        self.follower.is_none()
+            && (self.files.read().active.has_unsynced_data()
+                || self.memtable.as_ref().is_some_and(|m| !m.is_empty()))
+    }
+}
+
+/// 存储引擎的内部状态，通过 Engine 访问
+pub struct EngineCore {
+    pub(crate) options: Arc<Options>,
+    // 数据文件，读取时只需要获取当前线程所属分片的锁
+    pub(crate) files: Arc<ShardedLock<DataFiles>>,
+    // 追加写入和切换活跃文件串行化
+    pub(crate) append_lock: Arc<Mutex<()>>,
     // 数据内存索引
-    pub index: Box<dyn index::Indexer>,
+    pub index: Arc<dyn index::Indexer>,
+    // 索引之前的布隆过滤器
+    pub(crate) bloom: Option<Arc<BloomFilter>>,
     //数据库启动时的文件id，只用于加载索引使用，
-    file_ids: Vec<u32>,
+    pub(crate) file_ids: Vec<u32>,
     // 事务提交保证串行化
     pub(crate) batch_commit_lock: Mutex<()>,
-    pub(crate) seq_no: Arc<AtomicUsize>,
+    // 事务提交流水线，合并并发提交的 fsync
+    pub(crate) commit_pipeline: CommitPipeline,
+    // 串行化幂等写入的检查和写入
+    pub(crate) idempotent_lock: Mutex<()>,
+    // 追加事件时串行分配事件 id，记录最近一次分配的 id
+    pub(crate) last_event_id: Mutex<EventId>,
+    // 队列的读写操作串行执行，保证同一个元素只会被取出一次
+    pub(crate) queue_lock: Mutex<()>,
+    // 设置了过期时间的前缀，读取时过滤掉已经过期的前缀下的 key
+    pub(crate) prefix_ttls: RwLock<Vec<PrefixTtl>>,
+    // 设置了过期时间的 key 及其过期时间（毫秒时间戳）
+    pub(crate) key_ttls: RwLock<HashMap<Vec<u8>, u64>>,
+    // 过期时间、租约等使用的时钟
+    pub(crate) clock: Arc<dyn Clock>,
+    // 计算 key 的哈希值和分片
+    pub(crate) key_hasher: Arc<dyn KeyHasher>,
+    // 修改前缀过期时间和清理过期前缀的操作串行执行
+    pub(crate) prefix_ttl_lock: Mutex<()>,
+    // 按内容寻址保存和释放的操作串行执行，保证引用计数正确
+    pub(crate) blob_lock: Mutex<()>,
+    // 按照 Options::op_trace_sample_rate 采样读写操作，采样率为 0 时为 None
+    pub(crate) op_tracer: Option<OpTracer>,
+    // 数据库 id，写入每个新建的数据文件的文件头
+    pub(crate) db_id: DbId,
+    // 事务序列号分配
+    pub(crate) seq: Arc<SeqAllocator>,
+    // 已封存数据文件的摘要清单
+    pub(crate) manifest: Arc<Manifest>,
+    // 按前缀注册的配额
+    pub(crate) quotas: Arc<RwLock<Vec<QuotaEntry>>>,
+    // 后台任务管理
+    pub(crate) background: ShutdownHandle,
+    // 写入数据量统计
+    pub(crate) write_stats: WriteStats,
+    // 还没有更新索引的写入
+    pub(crate) inflight: InflightWrites,
+    // 已封存数据文件的合并、打洞、复制和摘要计算串行执行
+    pub(crate) maintenance_lock: Arc<Mutex<()>>,
+    // 还没有释放的迭代器的数量
+    pub(crate) live_iterators: Arc<AtomicUsize>,
+    // 运行时索引与数据不一致的统计
+    pub(crate) mismatch_stats: MismatchStats,
+    // 后台定期持久化的次数
+    interval_syncs: Arc<AtomicU64>,
+    // 是否可能有分块存储的 value
+    pub(crate) has_chunks: AtomicBool,
+    // 写入时编码记录使用的缓冲区
+    encode_buffers: BufferPool,
+    // 审计日志
+    pub(crate) audit: Option<AuditLog>,
+    // 读写操作的次数统计
+    pub(crate) op_stats: OpStats,
+    // 事务提交的统计
+    pub(crate) batch_metrics: BatchMetrics,
+    // 数据文件封存事件的订阅者
+    pub(crate) segment_subscribers: Arc<SegmentSubscribers>,
+    // 跟随者模式下的状态，跟随者是只读的
+    pub(crate) follower: Option<Arc<Follower>>,
+    // 内存写缓冲，没有设置 Options::memtable_bytes 时为 None
+    pub(crate) memtable: Option<Memtable>,
+    // 本次打开时加载索引的代价，关闭时写入摘要
+    pub(crate) open_cost: OpenCost,
+    // 跟随者和历史版本是只读的，所有写入操作都会返回 Errors::ReadOnlyEngine
+    pub(crate) read_only: bool,
 }
 
 impl Engine {
     // 关闭数据库
+    // 先通知所有后台任务退出并等待，超时未退出的任务会在错误中返回
     pub fn close(&self) -> Result<()> {
-        let read_guard = self.active_file.read();
-        read_guard.sync()
+        let shutdown_res = self.background.shutdown(self.options.shutdown_timeout);
+        self.sync()?;
+        // 记录打开代价的摘要，下次打开之前可以估计加载时间
+        if !self.read_only && self.follower.is_none() {
+            self.write_open_summary()?;
+            if self.options.index_snapshot {
+                self.write_index_snapshot()?;
+            }
+        }
+        shutdown_res
+    }
+
+    /// 获取存储引擎的统计信息
+    pub fn stat(&self) -> Result<Stat> {
+        let keys = self.list_keys()?;
+        let data_file_num = self.files.read().older.len() + 1;
+        let disk_size = dir_disk_size(&self.options.dir_path)?;
+
+        let user_bytes_written = self.write_stats.user_bytes.load(Ordering::Relaxed);
+        let data_bytes_written = self.write_stats.data_bytes.load(Ordering::Relaxed);
+        let merge_bytes_written = self.write_stats.merge_bytes.load(Ordering::Relaxed);
+        let write_amplification = match user_bytes_written {
+            0 => 0.0,
+            n => (data_bytes_written + merge_bytes_written) as f64 / n as f64,
+        };
+
+        let file_reads = {
+            let files = self.files.read();
+            let mut file_reads = std::iter::once(&files.active)
+                .chain(files.older.values())
+                .map(|f| {
+                    let (reads, last_read_at) = f.read_stats().snapshot();
+                    FileReadStat {
+                        file_id: f.get_file_id(),
+                        reads,
+                        last_read_at,
+                    }
+                })
+                .collect::<Vec<_>>();
+            file_reads.sort_by_key(|s| s.file_id);
+            file_reads
+        };
+
+        Ok(Stat {
+            key_num: keys.len(),
+            data_file_num,
+            disk_size,
+            user_bytes_written,
+            data_bytes_written,
+            merge_bytes_written,
+            write_amplification,
+            file_reads,
+        })
+    }
+
+    /// 布隆过滤器的统计信息，没有启用布隆过滤器时返回 None
+    pub fn bloom_stats(&self) -> Option<BloomFilterStats> {
+        self.bloom.as_ref().map(|bloom| bloom.stats())
     }
 
-    /// 持久化当前活跃文件
+    /// 当前仍在运行的后台任务
+    pub fn background_tasks(&self) -> Vec<String> {
+        self.background.running_tasks()
+    }
+
+    /// 持久化当前活跃文件，启用内存写缓冲时先把其中的数据写入数据文件
     pub fn sync(&self) -> Result<()> {
-        let read_guard = self.active_file.read();
-        read_guard.sync()
+        self.flush_memtable()?;
+        self.sync_files()
+    }
+
+    // 持久化当前活跃文件和审计日志，不刷新内存写缓冲，
+    // 持有 InflightGuard 时只能调用这个方法，刷新内存写缓冲需要等待所有的 InflightGuard 释放
+    pub(crate) fn sync_files(&self) -> Result<()> {
+        self.files.read().active.sync()?;
+        if let Some(audit) = &self.audit {
+            audit.sync()?;
+        }
+        Ok(())
+    }
+
+    // 记录一次提交的写入，更新操作统计并写入审计日志，引擎内部的数据不会记录
+    pub(crate) fn record_mutation(
+        &self,
+        op: AuditOp,
+        key: &[u8],
+        seq: u64,
+        value_len: Option<usize>,
+    ) {
+        if is_internal_key(key) {
+            return;
+        }
+        match op {
+            AuditOp::Put => self.op_stats.puts.fetch_add(1, Ordering::Relaxed),
+            AuditOp::Delete => self.op_stats.deletes.fetch_add(1, Ordering::Relaxed),
+        };
+        if let Some(audit) = &self.audit {
+            audit.record(op, key, seq, value_len);
+        }
+    }
+
+    /// 将数据库克隆到另一个目录中，克隆出的目录可以作为独立的数据库打开和写入
+    /// 旧的数据文件不会再被修改，优先使用硬链接共享，活跃文件则完整复制；punch_holes 会跳过共享的文件
+    pub fn fork_to(&self, dir_path: impl AsRef<Path>) -> Result<()> {
+        let dir_path = dir_path.as_ref();
+        if dir_path.is_dir() {
+            match fs::read_dir(dir_path) {
+                Ok(mut entries) => {
+                    if entries.next().is_some() {
+                        return Err(Errors::TargetDirNotEmpty);
+                    }
+                }
+                Err(e) => return Err(Errors::FailedToReadDatabaseDir(e.into())),
+            }
+        } else if let Err(e) = fio::create_dir(dir_path, self.options.dir_mode, true) {
+            warn!("Failed to create fork Directory: {e}");
+            return Err(Errors::FailedToCreateDatabaseDir(e.into()));
+        } else {
+            fio::sync_parent_dir(dir_path)?;
+        }
+
+        // 持有写入锁，保证复制期间没有新的数据写入，内存写缓冲中的数据先写入数据文件
+        let _maintenance = self.maintenance_lock.lock();
+        let _lock = self.append_lock.lock();
+        self.flush_memtable_locked()?;
+        let files = self.files.load();
+        files.active.sync()?;
+
+        for file_id in files.older.keys() {
+            let src = get_data_file_name(&self.options.dir_path, *file_id);
+            let dst = get_data_file_name(dir_path, *file_id);
+            // 跨设备等无法硬链接的情况退化为复制
+            if fs::hard_link(&src, &dst).is_err() {
+                if let Err(e) = fs::copy(&src, &dst) {
+                    error!("Failed to copy data file {:?}: {e}", src);
+                    return Err(Errors::FailedToCopyDataFile(e.into()));
+                }
+            }
+        }
+
+        let active_fid = files.active.get_file_id();
+        let src = get_data_file_name(&self.options.dir_path, active_fid);
+        let dst = get_data_file_name(dir_path, active_fid);
+        if let Err(e) = fs::copy(&src, &dst) {
+            error!("Failed to copy active data file {:?}: {e}", src);
+            return Err(Errors::FailedToCopyDataFile(e.into()));
+        }
+
+        self.manifest.persist_to(dir_path)?;
+        self.seq.persist_to(dir_path)?;
+        // 复制的数据文件头中是当前数据库的 id，副本沿用这个 id
+        write_db_id(dir_path, &self.db_id, self.options.file_mode)?;
+        fio::sync_dir(dir_path)
     }
 
     // 打开 bitcask 存储引擎实例
     pub fn open(opts: Options) -> Result<Self> {
+        Self::open_with_hooks(opts, OpenHooks::default())
+    }
+
+    // 打开存储引擎，在打开的各个阶段调用对应的回调
+    pub(crate) fn open_with_hooks(opts: Options, mut hooks: OpenHooks) -> Result<Self> {
         if let Some(e) = check_options(&opts) {
             return Err(e);
         }
         let options = opts.clone();
-        // 判断数据目录是否存在，如果不存在则需要创建这个目录
         let dir_path = options.dir_path.clone();
+        // 完成或者丢弃上次中断的离线合并
+        recover_merge_dir(&dir_path)?;
+        // 判断数据目录是否存在，如果不存在则需要创建这个目录
         if !dir_path.is_dir() {
-            if let Err(e) = fs::create_dir(&dir_path) {
+            if let Err(e) = fio::create_dir(&dir_path, options.dir_mode, false) {
                 warn!("Failed to create database Directory: {e}");
-                return Err(Errors::FailedToCreateDatabaseDir);
+                return Err(Errors::FailedToCreateDatabaseDir(e.into()));
+            }
+            fio::sync_parent_dir(&dir_path)?;
+        }
+        // 检查元数据文件与数据文件是否一致
+        let mismatches = Self::check_metadata(&dir_path)?;
+        if !mismatches.is_empty() {
+            match options.metadata_check {
+                MetadataCheck::Strict => return Err(Errors::MetadataMismatch(mismatches)),
+                MetadataCheck::Heal => heal_metadata(&dir_path, &mismatches, options.file_mode)?,
             }
         }
+        // 重做上次没有完成的打洞
+        redo_punch_holes(&dir_path, options.file_mode)?;
         // 加载数据文件
-        let mut data_files = load_data_file(&dir_path)?;
+        let mut data_files = load_data_file(&dir_path, options.io_type)?;
+        // 拒绝混入的其他数据库的数据文件
+        let db_id = check_data_files(
+            &dir_path,
+            &data_files,
+            options.allow_foreign_data_files,
+            options.file_mode,
+        )?;
         // 设置 file id信息
         let mut file_ids = Vec::new();
         for v in data_files.iter() {
@@ -82,61 +551,195 @@ impl Engine {
         if data_files.len() > 1 {
             for _ in 0..=data_files.len() - 2 {
                 let file = data_files.pop().unwrap();
-                older_files.insert(file.get_file_id(), file);
+                older_files.insert(file.get_file_id(), Arc::new(file));
             }
         }
 
         let active_file = match data_files.pop() {
             Some(v) => v,
-            None => DataFile::new(dir_path.clone(), INITAL_DILE_ID)?,
+            None => {
+                let data_file = DataFile::create_with_mode(
+                    dir_path.clone(),
+                    INITAL_DILE_ID,
+                    options.io_type,
+                    options.file_mode,
+                )?;
+                init_data_file(&data_file, &db_id)?;
+                data_file
+            }
         };
 
         // 构造存储引擎实例
-        let engine = Self {
-            options: Arc::new(opts),
-            active_file: Arc::new(RwLock::new(active_file)),
-            older_files: Arc::new(RwLock::new(older_files)),
-            index: Box::new(new_indexer(options.index_type)),
+        let mut engine = Self::with_files(
+            opts,
+            DataFiles {
+                active: Arc::new(active_file),
+                older: older_files,
+                active_since: Instant::now(),
+            },
             file_ids,
-            batch_commit_lock: Mutex::new(()),
-            seq_no: Arc::new(AtomicUsize::new(1)),
-        };
+        );
+        engine.db_id = db_id;
 
         // 从数据文件中加载索引
-        let current_seq_no = engine.load_index_from_data_file()?;
+        hooks.run(OpenPhase::BeforeIndexLoad, &engine)?;
+        if let Ok(estimate) = Self::estimate_open_time(&dir_path) {
+            info!(
+                "Loading index from {} bytes of data files, expected to take {:?}",
+                estimate.data_bytes, estimate.estimated
+            );
+        }
+        let load_start = Instant::now();
+        let (current_seq_no, from_snapshot) = engine.load_index_from_data_file()?;
+        engine.open_cost = OpenCost {
+            // 使用索引快照时没有回放所有的数据文件，不测量加载速度
+            data_bytes: match from_snapshot {
+                true => 0,
+                false => engine.file_stats().iter().map(|s| s.total_bytes).sum(),
+            },
+            load_duration: load_start.elapsed(),
+        };
+        engine.load_replayed_state()?;
+        engine.init_stale_bytes()?;
+        hooks.run(OpenPhase::AfterIndexLoad, &engine)?;
 
-        // 更新当前事务序列号
-        if current_seq_no > 0 {
-            engine.seq_no.store(current_seq_no, Ordering::SeqCst);
+        // 从数据文件和持久化的序列号中恢复当前事务序列号
+        engine.seq = Arc::new(
+            SeqAllocator::open(&dir_path, current_seq_no)?.with_file_mode(options.file_mode),
+        );
+        engine.manifest = Arc::new(Manifest::load(&dir_path)?.with_file_mode(options.file_mode));
+        if let Some(path) = &engine.options.audit_log {
+            engine.audit = Some(AuditLog::open(path, options.file_mode)?);
         }
 
-        engine
-            .seq_no
-            .store(current_seq_no + 1, std::sync::atomic::Ordering::SeqCst);
+        engine.spawn_background_tasks()?;
 
+        hooks.run(OpenPhase::BeforeReturn, &engine)?;
         Ok(engine)
     }
 
+    // 使用已经打开的数据文件构造存储引擎实例，索引为空
+    pub(crate) fn with_files(opts: Options, files: DataFiles, file_ids: Vec<u32>) -> Self {
+        let (index, bloom) = new_engine_indexer(&opts);
+        let manifest = Arc::new(Manifest::empty(&opts.dir_path));
+        let op_tracer = OpTracer::new(opts.op_trace_sample_rate);
+        let background = ShutdownHandle::new(opts.background_priority.clone());
+        let clock = opts.clock.clone().unwrap_or_else(|| Arc::new(SystemClock));
+        let key_hasher = hash::key_hasher(&opts);
+        let memtable = opts.memtable_bytes.map(Memtable::new);
+        let core = EngineCore {
+            options: Arc::new(opts),
+            files: Arc::new(ShardedLock::new(files)),
+            append_lock: Arc::new(Mutex::new(())),
+            index: Arc::from(index),
+            bloom,
+            file_ids,
+            batch_commit_lock: Mutex::new(()),
+            commit_pipeline: CommitPipeline::default(),
+            idempotent_lock: Mutex::new(()),
+            last_event_id: Mutex::new(EventId::default()),
+            queue_lock: Mutex::new(()),
+            prefix_ttls: RwLock::new(Vec::new()),
+            key_ttls: RwLock::new(HashMap::new()),
+            clock,
+            key_hasher,
+            prefix_ttl_lock: Mutex::new(()),
+            blob_lock: Mutex::new(()),
+            op_tracer,
+            db_id: DbId::default(),
+            seq: Arc::new(SeqAllocator::read_only()),
+            manifest,
+            quotas: Arc::new(RwLock::new(Vec::new())),
+            background,
+            write_stats: WriteStats::default(),
+            inflight: InflightWrites::default(),
+            maintenance_lock: Arc::new(Mutex::new(())),
+            live_iterators: Arc::new(AtomicUsize::new(0)),
+            batch_metrics: BatchMetrics::default(),
+            mismatch_stats: MismatchStats::default(),
+            interval_syncs: Arc::new(AtomicU64::new(0)),
+            has_chunks: AtomicBool::new(false),
+            encode_buffers: BufferPool::default(),
+            audit: None,
+            op_stats: OpStats::default(),
+            segment_subscribers: Arc::new(SegmentSubscribers::default()),
+            follower: None,
+            memtable,
+            open_cost: OpenCost::default(),
+            read_only: false,
+        };
+        Self {
+            core: Arc::new(core),
+        }
+    }
+
     pub fn put(&self, key: Bytes, value: Bytes) -> Result<()> {
+        self.put_with_meta(key, value, Bytes::new())
+    }
+
+    /// 写入数据并附带用户自定义的元数据，元数据最长 255 字节
+    pub fn put_with_meta(&self, key: Bytes, value: Bytes, meta: Bytes) -> Result<()> {
         // 判断key的有效性
-        if key.is_empty() {
-            return Err(Errors::KeyIsEmpty);
+        self.check_key(&key)?;
+        if meta.len() > MAX_LOG_RECORD_META_SIZE {
+            return Err(Errors::MetaTooLarge);
+        }
+        if let Some(chunk_size) = self.options.value_chunk_size {
+            if value.len() > chunk_size {
+                self.put_chunked(key.clone(), value, meta, chunk_size)?;
+                return self.write_key_ttl(&key, true);
+            }
+        }
+        // 需要同时写入过期时间时通过事务写入
+        if self.key_ttl_record(&key, true).is_some() {
+            let wb = self.new_write_batch(self.key_ttl_batch_options())?;
+            wb.put_with_meta(key, value, meta)?;
+            return wb.commit();
+        }
+
+        // 启用内存写缓冲时先写入内存，之后批量写入数据文件
+        if self.memtable_put(&key, Some((value.clone(), meta.clone())))? {
+            self.record_mutation(
+                AuditOp::Put,
+                &key,
+                NON_TRANSACTION_SEQ_NO,
+                Some(value.len()),
+            );
+            return Ok(());
         }
 
+        let trace = self.trace_start();
+        // 检查前缀配额
+        let quota_deltas = self.check_quota(&[(&key, Some(value.len()))])?;
+
         // 构造logRecord结构体
         let mut record = LogRecord {
             key: log_record_key_with_seq(key.to_vec(), NON_TRANSACTION_SEQ_NO).to_vec(),
             value: value.to_vec(),
             rec_type: LogRecordType::NORMAL,
+            meta: meta.to_vec(),
         };
 
         // 追加写入到活跃文件中
-        let log_record_pos = self.append_log_record(&mut record)?;
+        let (log_record_pos, _inflight) = self.append_log_record(&mut record)?;
         // 更新内存索引
+        self.mark_stale(&key);
         let ok = self.index.put(key.to_vec(), log_record_pos);
         if !ok {
             return Err(Errors::IndexUpdateFailed);
         }
+        self.apply_quota(quota_deltas);
+        self.write_stats.user_bytes.fetch_add(
+            (key.len() + value.len() + meta.len()) as u64,
+            Ordering::Relaxed,
+        );
+        self.record_mutation(
+            AuditOp::Put,
+            &key,
+            NON_TRANSACTION_SEQ_NO,
+            Some(value.len()),
+        );
+        self.trace_finish(trace, TraceOp::Put, &key, Some(log_record_pos.file_id));
 
         Ok(())
     }
@@ -147,99 +750,329 @@ impl Engine {
             return Err(Errors::KeyIsEmpty);
         }
 
-        // 从内存索引中拿到对应的数据信息
-        let pos = self.index.get(key.to_vec());
-        // 不存在
-        if pos.is_none() {
+        if self.is_expired(&key) {
             return Err(Errors::KeyNotFound);
         }
+        // 从内存索引中拿到对应的数据
+        let log_record = self.get_indexed_log_record(&key)?;
+        Ok(log_record.value.into())
+    }
 
-        let log_record_pos = pos.unwrap();
-        self.get_value_by_position(&log_record_pos)
+    /// 根据key读取对应数据及其元数据
+    pub fn get_with_meta(&self, key: Bytes) -> Result<(Bytes, Bytes)> {
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        if self.is_expired(&key) {
+            return Err(Errors::KeyNotFound);
+        }
+        let log_record = self.get_indexed_log_record(&key)?;
+        Ok((log_record.value.into(), log_record.meta.into()))
     }
 
-    /// 根据key删除对应数据
-    pub fn delete(&self, key: Bytes) -> Result<()> {
-        // 判断key的有效性
+    /// 根据key读取对应数据，key 不存在时返回 None
+    pub fn try_get(&self, key: Bytes) -> Result<Option<Bytes>> {
+        Ok(self.try_get_with_meta(key)?.map(|(value, _)| value))
+    }
+
+    /// 根据key读取对应数据及其元数据，key 不存在时返回 None
+    pub fn try_get_with_meta(&self, key: Bytes) -> Result<Option<(Bytes, Bytes)>> {
         if key.is_empty() {
             return Err(Errors::KeyIsEmpty);
         }
+
+        if self.is_expired(&key) {
+            return Ok(None);
+        }
+        match self.get_indexed_log_record(&key) {
+            Ok(log_record) => Ok(Some((log_record.value.into(), log_record.meta.into()))),
+            Err(Errors::KeyNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 根据key删除对应数据
+    pub fn delete(&self, key: Bytes) -> Result<()> {
+        // 判断key的有效性
+        self.check_key(&key)?;
         // key 是够存在
         let pos = self.index.get(key.to_vec());
-        if pos.is_none() {
+        if pos.is_none() && !self.memtable_contains(&key) {
+            return self.write_key_ttl(&key, false);
+        }
+        // 需要同时删除过期时间时通过事务删除，可能有分块存储的 value 时在删除分块之后单独删除
+        if !self.has_chunks() && self.key_ttl_record(&key, false).is_some() {
+            let wb = self.new_write_batch(self.key_ttl_batch_options())?;
+            wb.delete(key)?;
+            return wb.commit();
+        }
+        if self.memtable_put(&key, None)? {
+            self.record_mutation(AuditOp::Delete, &key, NON_TRANSACTION_SEQ_NO, None);
             return Ok(());
         }
+        let trace = self.trace_start();
+        let quota_deltas = self.check_quota(&[(&key, None)])?;
         // 构造 LogRecord，标识其被删除
         let mut record = LogRecord {
             key: log_record_key_with_seq(key.to_vec(), NON_TRANSACTION_SEQ_NO).to_vec(),
             value: Default::default(),
             rec_type: LogRecordType::DELETED,
+            meta: Default::default(),
         };
 
+        // 可能有分块存储的 value 时需要同时删除旧的分块
+        if self.has_chunks() {
+            if self.delete_chunked(&key, &mut record)? {
+                self.apply_quota(quota_deltas);
+            }
+            self.write_stats
+                .user_bytes
+                .fetch_add(key.len() as u64, Ordering::Relaxed);
+            self.record_mutation(AuditOp::Delete, &key, NON_TRANSACTION_SEQ_NO, None);
+            self.trace_finish(trace, TraceOp::Delete, &key, None);
+            return self.write_key_ttl(&key, false);
+        }
+
         // 将数据追写入大数据文件中
-        self.append_log_record(&mut record)?;
+        let (tombstone_pos, _inflight) = self.append_log_record(&mut record)?;
         // 更新（删除）内存索引
-        let ok = self.index.delete(key.to_vec());
-        if !ok {
-            return Err(Errors::IndexUpdateFailed);
+        // 索引中的 key 可能已经被并发的删除移除，删除标记已经写入，同样视为删除成功
+        // 这时配额的用量已经由并发的删除更新过了
+        self.mark_stale(&key);
+        if self.index.delete(key.to_vec()) {
+            self.apply_quota(quota_deltas);
         }
+        self.write_stats
+            .user_bytes
+            .fetch_add(key.len() as u64, Ordering::Relaxed);
+        self.record_mutation(AuditOp::Delete, &key, NON_TRANSACTION_SEQ_NO, None);
+        self.trace_finish(trace, TraceOp::Delete, &key, Some(tombstone_pos.file_id));
 
         Ok(())
     }
 
-    pub(crate) fn get_value_by_position(&self, log_record_pos: &LogRecordPos) -> Result<Bytes> {
-        let active_file = self.active_file.read();
-        let older_file = self.older_files.read();
-        // 从对应的数据文件中获取对应的 Logrecord
-        let read_log_record = match active_file.get_file_id() == log_record_pos.file_id {
-            true => active_file.read_log_record(log_record_pos.offset)?,
-            false => {
-                let data_file = older_file.get(&log_record_pos.file_id);
-                if data_file.is_none() {
-                    // 找不到对应的数据文件，返回错误
-                    return Err(Errors::FailedToOpenDataFile);
-                }
-                data_file.unwrap().read_log_record(log_record_pos.offset)?
+    // 校验写入的key，key不能为空，并且需要通过用户配置的校验函数
+    pub(crate) fn check_key(&self, key: &[u8]) -> Result<()> {
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+        if is_internal_key(key) {
+            return Err(Errors::ReservedKeyPrefix);
+        }
+        if let Some(validator) = &self.options.key_validator {
+            if !validator(key) {
+                return Err(Errors::InvalidKey);
             }
-        };
-        let log_record = read_log_record.record;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn get_value_by_position(&self, log_record_pos: &LogRecordPos) -> Result<Bytes> {
+        let log_record = self.get_log_record_by_position(log_record_pos)?;
+        Ok(log_record.value.into())
+    }
+
+    // 根据索引位置读取有效的 LogRecord
+    pub(crate) fn get_log_record_by_position(
+        &self,
+        log_record_pos: &LogRecordPos,
+    ) -> Result<LogRecord> {
+        let log_record = self.read_log_record_at(log_record_pos)?;
 
         // 判断 log_record 的类型
         if log_record.rec_type == LogRecordType::DELETED {
             return Err(Errors::KeyNotFound);
         }
         // 否则返回有效数据
-        Ok(log_record.value.into())
+        Ok(log_record)
+    }
+
+    // 根据位置读取原始的 LogRecord，不区分记录类型
+    pub(crate) fn read_log_record_at(&self, log_record_pos: &LogRecordPos) -> Result<LogRecord> {
+        deadline::read(&self.files)?.read_log_record_at(log_record_pos)
+    }
+
+    // 将数据文件从当前的文件集合中移除，并在没有引用之后删除磁盘上的文件
+    // 调用方需要保证内存索引中已经没有指向这些文件的位置
+    pub(crate) fn retire_data_files(&self, file_ids: &[u32]) -> Result<()> {
+        let _lock = self.append_lock.lock();
+        // 删除的文件中可能带有最大的事务序列号，先持久化当前的序列号
+        self.seq.persist()?;
+        self.manifest.remove(file_ids)?;
+        self.files.update(|files| {
+            let mut older = files.older.clone();
+            for file_id in file_ids {
+                if let Some(data_file) = older.remove(file_id) {
+                    data_file.retire();
+                }
+            }
+            DataFiles {
+                active: files.active.clone(),
+                older,
+                active_since: files.active_since,
+            }
+        });
+        Ok(())
     }
+
+    // 在更新索引之前调用，将 key 当前所在的记录计入所在文件的失效数据量
+    pub(crate) fn mark_stale(&self, key: &[u8]) {
+        // 新写入的 key 通常不在过滤器中，不需要查找索引
+        if self
+            .bloom
+            .as_ref()
+            .is_some_and(|bloom| !bloom.may_contain(key))
+        {
+            return;
+        }
+        if let Some(pos) = self.index.get(key.to_vec()) {
+            self.mark_stale_pos(&pos);
+        }
+    }
+
+    // 将 pos 处的记录计入所在文件的失效数据量
+    pub(crate) fn mark_stale_pos(&self, pos: &LogRecordPos) {
+        if let Some(data_file) = self.files.read().get(pos.file_id) {
+            data_file.add_stale_bytes(pos.size);
+        }
+    }
+
+    // 根据索引计算每个数据文件中的失效数据量，加载完索引之后调用，之后在写入时累加
+    pub(crate) fn init_stale_bytes(&self) -> Result<()> {
+        let files = self.files.load();
+        let live_bytes = self.live_bytes_by_file(&files)?;
+        for data_file in files.older.values().chain(std::iter::once(&files.active)) {
+            let live = live_bytes.get(&data_file.get_file_id()).copied();
+            data_file.set_stale_bytes(data_file.len().saturating_sub(live.unwrap_or(0)));
+        }
+        Ok(())
+    }
+
+    // 按照文件id从小到大的顺序遍历所有数据文件中的记录，回调函数返回false时终止
+    // 回调中拿到的是原始的 LogRecord，key 中带有事务序列号
+    pub(crate) fn scan_log_records<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(LogRecordPos, LogRecord) -> bool,
+    {
+        let files = self.files.load();
+
+        let mut file_ids = files.older.keys().copied().collect::<Vec<_>>();
+        file_ids.sort();
+        file_ids.push(files.active.get_file_id());
+
+        for file_id in file_ids.iter() {
+            let data_file = files.get(*file_id).unwrap();
+            let mut offset = 0;
+            loop {
+                let (log_record, size) = match data_file.read_log_record(offset) {
+                    Ok(res) => (res.record, res.size),
+                    Err(Errors::ReadDataFileEOF) => break,
+                    Err(e) => return Err(e),
+                };
+                let pos = LogRecordPos {
+                    file_id: *file_id,
+                    offset,
+                    size: size as u64,
+                };
+                offset += size as u64;
+                if log_record.rec_type == LogRecordType::PADDING {
+                    continue;
+                }
+                if !f(pos, log_record) {
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     // 追加数据到当前活跃文件中
-    pub(crate) fn append_log_record(&self, record: &mut LogRecord) -> Result<LogRecordPos> {
-        let dir_path = self.options.dir_path.clone();
+    // 返回的 InflightGuard 需要在更新完索引之后再释放
+    pub(crate) fn append_log_record(
+        &self,
+        record: &mut LogRecord,
+    ) -> Result<(LogRecordPos, InflightGuard<'_>)> {
+        let _lock = deadline::lock(&self.append_lock)?;
+        let pos = self.append_log_record_locked(record, &self.write_stats.data_bytes)?;
+        Ok((pos, self.inflight.begin()))
+    }
 
-        let enc_record = record.encode();
-        let record_len = enc_record.len();
+    // 追加数据到当前活跃文件中，调用方需要持有 append_lock，写入的数据量累加到 written 中
+    pub(crate) fn append_log_record_locked(
+        &self,
+        record: &LogRecord,
+        written: &AtomicU64,
+    ) -> Result<LogRecordPos> {
+        let positions = self.append_log_records_locked(std::slice::from_ref(record), written)?;
+        Ok(positions[0])
+    }
 
-        // 当前活跃文件
-        let mut active_file = self.active_file.write();
-        // 判断当前写入文件是否达到阈值
-        //* */ 可否将持久化后的当前活跃文件加入到旧的文件中？
-        if active_file.get_write_off() + record_len as u64 > self.options.data_file_size {
-            // 将当前文件持久化
-            active_file.sync()?;
+    // 追加多条记录，所有记录编码到同一个缓冲区中一次写入
+    pub(crate) fn append_log_records(
+        &self,
+        records: &[LogRecord],
+    ) -> Result<(Vec<LogRecordPos>, InflightGuard<'_>)> {
+        let _lock = deadline::lock(&self.append_lock)?;
+        let positions = self.append_log_records_locked(records, &self.write_stats.data_bytes)?;
+        Ok((positions, self.inflight.begin()))
+    }
 
-            let current_fid = active_file.get_file_id();
-            // 将旧的数据文件存储到map中
-            let mut older_files = self.older_files.write();
-            let older_file = DataFile::new(dir_path.clone(), current_fid)?;
-            older_files.insert(current_fid, older_file);
+    // 追加多条记录到当前活跃文件中，调用方需要持有 append_lock
+    // 内存写缓冲中的数据先写入，保证之后的写入覆盖其中更早的写入
+    pub(crate) fn append_log_records_locked(
+        &self,
+        records: &[LogRecord],
+        written: &AtomicU64,
+    ) -> Result<Vec<LogRecordPos>> {
+        self.flush_memtable_locked()?;
+        self.write_log_records_locked(records, written)
+    }
 
-            // 打开新的数据文件
-            let new_file = DataFile::new(dir_path.clone(), current_fid + 1)?;
-            *active_file = new_file;
+    // 切换活跃文件只发生在写入之前，所有记录都写入同一个数据文件，
+    // 超过数据文件大小的一组记录也不会被拆分
+    pub(crate) fn write_log_records_locked(
+        &self,
+        records: &[LogRecord],
+        written: &AtomicU64,
+    ) -> Result<Vec<LogRecordPos>> {
+        // 只读模式下不能写入数据
+        if self.read_only {
+            return Err(Errors::ReadOnlyEngine);
         }
 
-        // 追加写数据到当前活跃文件中
+        // 当前活跃文件
+        let mut active_file = self.files.read().active.clone();
         let write_off = active_file.get_write_off();
-        active_file.write(&enc_record)?;
+        let mut buf = self.encode_buffers.get();
+        let mut offsets = self.encode_aligned(records, write_off, &mut buf);
+        // 判断当前写入文件是否达到阈值，或者其中的失效数据比例过高
+        if write_off > FILE_HEADER_SIZE
+            && (write_off + buf.len() as u64 > self.options.data_file_size
+                || self.stale_ratio_exceeded(&active_file))
+        {
+            active_file = rotate_active_file(
+                &self.files,
+                &self.options,
+                &self.segment_subscribers,
+                &self.db_id,
+            )?;
+            // 新文件从文件头之后开始，重新计算对齐
+            offsets = self.encode_aligned(records, active_file.get_write_off(), &mut buf);
+        }
+
+        // 追加写数据到当前活跃文件中
+        active_file.write(&buf)?;
+        written.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        // 删除标记、事务标识和对齐填充不会被索引引用，写入时直接计入失效数据
+        let live = records
+            .iter()
+            .filter(|r| matches!(r.rec_type, LogRecordType::NORMAL | LogRecordType::CHUNKED))
+            .map(|r| self.encoded_record_len(r))
+            .sum::<u64>();
+        active_file.add_stale_bytes(buf.len() as u64 - live);
+        active_file.add_record_count(records.len() as u64);
 
         // 根据配置项决定是否持久化
         if self.options.sync_write {
@@ -247,174 +1080,667 @@ impl Engine {
         }
 
         // 构造内存索引信息
-        Ok(LogRecordPos {
-            file_id: active_file.get_file_id(),
-            offset: write_off,
+        let file_id = active_file.get_file_id();
+        Ok(offsets
+            .into_iter()
+            .zip(records)
+            .map(|(offset, record)| LogRecordPos {
+                file_id,
+                offset,
+                size: self.encoded_record_len(record),
+            })
+            .collect())
+    }
+
+    // 按照 Options::omit_seq_prefix 编码之后记录的长度
+    fn encoded_record_len(&self, record: &LogRecord) -> u64 {
+        record.encoded_length_with(self.options.omit_seq_prefix) as u64
+    }
+
+    // 将记录按照对齐方式编码到 buf 中，返回每条记录在文件中的位置
+    fn encode_aligned(&self, records: &[LogRecord], start: u64, buf: &mut Vec<u8>) -> Vec<u64> {
+        buf.clear();
+        let mut offsets = Vec::with_capacity(records.len());
+        for record in records {
+            let offset = start + buf.len() as u64;
+            // 记录对齐需要填充的字节数
+            let padding = self
+                .options
+                .record_alignment
+                .padding(offset, self.encoded_record_len(record));
+            if padding > 0 {
+                padding_record(padding).encode_to(buf);
+            }
+            offsets.push(offset + padding);
+            record.encode_to_with(buf, self.options.omit_seq_prefix);
+        }
+        offsets
+    }
+
+    // 活跃文件中失效数据的比例是否达到切换的阈值
+    // 文件写入量太小时比例没有意义，至少写满数据文件大小的 1/16 才会判断
+    fn stale_ratio_exceeded(&self, active_file: &DataFile) -> bool {
+        let ratio = match self.options.rotate_stale_ratio {
+            Some(ratio) => ratio,
+            None => return false,
+        };
+        let write_off = active_file.get_write_off();
+        if write_off == 0 || write_off < self.options.data_file_size / 16 {
+            return false;
+        }
+        active_file.get_stale_bytes() as f64 / write_off as f64 >= ratio
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakEngine {
+        WeakEngine(Arc::downgrade(&self.core))
+    }
+
+    // 回放数据文件之后加载由已有数据决定的内存状态：前缀和 key 的过期时间、是否有分块存储的 value
+    pub(crate) fn load_replayed_state(&self) -> Result<()> {
+        self.detect_chunks();
+        self.load_prefix_ttls()?;
+        self.load_key_ttls()
+    }
+
+    // 按照配置项启动后台任务
+    pub(crate) fn spawn_background_tasks(&self) -> Result<()> {
+        // 按时间切换活跃文件
+        if let Some(interval) = self.options.rotate_interval {
+            self.spawn_rotate_task(interval)?;
+        }
+        // 后台定期持久化
+        if let Some(sync_interval) = self.options.sync_interval {
+            self.spawn_sync_task(sync_interval)?;
+        }
+        // 计算封存文件的摘要
+        if self.options.seal_digest {
+            self.spawn_manifest_task()?;
+        }
+        // 按失效数据的比例自动合并
+        if let Some(ratio) = self.options.merge_ratio {
+            if !self.read_only {
+                self.spawn_merge_task(ratio)?;
+            }
+        }
+        Ok(())
+    }
+
+    // 启动定期检查封存文件失效数据比例的后台任务，在后台线程中合并选出的文件
+    // 任务只持有内部状态的弱引用，存储引擎释放之后自动退出；合并只在每一批重写时短暂持有写入锁
+    // 合并失败不影响正常的读写，下一次检查时会重新选出这些文件
+    fn spawn_merge_task(&self, ratio: f64) -> Result<()> {
+        let weak = self.downgrade();
+        self.background.spawn("merge", move |signal| {
+            while !signal.wait_timeout(MERGE_CHECK_INTERVAL) {
+                let engine = match weak.upgrade() {
+                    Some(engine) => engine,
+                    None => break,
+                };
+                let files = engine.files.load();
+                let mut file_ids = files
+                    .older
+                    .values()
+                    .filter(|f| {
+                        let len = f.len();
+                        len > FILE_HEADER_SIZE && f.get_stale_bytes() as f64 / len as f64 >= ratio
+                    })
+                    .map(|f| f.get_file_id())
+                    .collect::<Vec<_>>();
+                drop(files);
+                if file_ids.is_empty() {
+                    continue;
+                }
+                file_ids.sort();
+                if let Err(e) = engine.compact_files(&file_ids) {
+                    error!("Failed to merge data files {file_ids:?}: {e}");
+                }
+            }
         })
     }
 
+    // 启动按时间切换活跃文件的后台任务，空的活跃文件不会被切换
+    fn spawn_rotate_task(&self, interval: Duration) -> Result<()> {
+        let files = self.files.clone();
+        let append_lock = self.append_lock.clone();
+        let options = self.options.clone();
+        let subscribers = self.segment_subscribers.clone();
+        let db_id = self.db_id;
+        self.background.spawn("rotate", move |signal| loop {
+            let wait = {
+                let files = files.read();
+                match files.active.get_write_off() <= FILE_HEADER_SIZE {
+                    true => interval,
+                    false => interval.saturating_sub(files.active_since.elapsed()),
+                }
+            };
+            if signal.wait_timeout(wait) {
+                break;
+            }
+
+            let _lock = append_lock.lock();
+            let (expired, empty) = {
+                let files = files.read();
+                (
+                    files.active_since.elapsed() >= interval,
+                    files.active.get_write_off() <= FILE_HEADER_SIZE,
+                )
+            };
+            if expired && !empty {
+                if let Err(e) = rotate_active_file(&files, &options, &subscribers, &db_id) {
+                    error!("Failed to rotate active data file: {e}");
+                }
+            }
+        })
+    }
+
+    // 启动定期持久化活跃文件的后台任务，上次持久化之后没有写入时跳过
+    fn spawn_sync_task(&self, sync_interval: SyncInterval) -> Result<()> {
+        let files = self.files.clone();
+        let syncs = self.interval_syncs.clone();
+        self.background.spawn("sync", move |signal| loop {
+            if signal.wait_timeout(jittered(sync_interval)) {
+                break;
+            }
+
+            let active = files.read().active.clone();
+            if !active.has_unsynced_data() {
+                continue;
+            }
+            match active.sync() {
+                Ok(()) => {
+                    syncs.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => error!("Failed to sync active data file: {e}"),
+            }
+        })
+    }
+
+    /// 后台定期持久化执行的次数，从本次打开数据库开始计算
+    pub fn interval_syncs(&self) -> u64 {
+        self.interval_syncs.load(Ordering::Relaxed)
+    }
+
     // 从数据文件中加载内存索引
     // 遍历数据文件中的内容，并依次处理其中的记录
-    fn load_index_from_data_file(&self) -> Result<usize> {
-        let mut current_seq_no = NON_TRANSACTION_SEQ_NO;
+    // 返回事务序列号以及是否使用了索引快照
+    fn load_index_from_data_file(&self) -> Result<(u64, bool)> {
+        let mut replayer = IndexReplayer::default();
 
         if self.file_ids.is_empty() {
-            return Ok(current_seq_no);
+            return Ok((replayer.current_seq_no, false));
         }
 
-        // 暂存事务相关的数据
-        let mut transaction_records = HashMap::new();
+        // 有可用的索引快照时只回放快照位置之后的数据
+        let mark = match self.options.index_snapshot {
+            true => self.load_index_snapshot()?,
+            false => None,
+        };
+        let (skip, start_offset) = match &mark {
+            Some(mark) => {
+                replayer.current_seq_no = mark.seq;
+                let skip = self.file_ids.iter().position(|id| *id == mark.file_id);
+                (skip.unwrap_or_default(), mark.offset)
+            }
+            None => (0, 0),
+        };
+        let file_ids = &self.file_ids[skip..];
 
-        let active_file = self.active_file.read();
-        let older_file = self.older_files.read();
+        let files = self.files.load();
+        let last = file_ids.len() - 1;
 
-        // 遍历每个文件id，去除对应的数据文件，并加载其中的数据
-        for (i, file_id) in self.file_ids.iter().enumerate() {
-            let mut offset = 0;
-            loop {
-                let log_record_res = match *file_id == active_file.get_file_id() {
-                    true => active_file.read_log_record(offset),
-                    false => {
-                        let data_file = older_file.get(file_id).unwrap();
-                        data_file.read_log_record(offset)
-                    }
-                };
-                let (mut log_record, size) = match log_record_res {
-                    Ok(res) => (res.record, res.size),
-                    Err(e) => {
-                        if e == Errors::ReadDataFileEOF {
-                            break;
-                        }
-                        return Err(e);
+        // 每次并行解码 load_threads 个数据文件，再按照文件 id 的顺序依次更新到内存索引
+        // 解码不依赖之前的文件，事务和删除的处理只在更新索引时按顺序进行
+        for (window, file_ids) in file_ids.chunks(self.options.load_threads).enumerate() {
+            let mut data_files = Vec::with_capacity(file_ids.len());
+            for file_id in file_ids {
+                match files.get(*file_id) {
+                    Some(data_file) => data_files.push(data_file),
+                    None => {
+                        return Err(invariant_violation(
+                            Errors::DataFileNotFound,
+                            format_args!("Data file {file_id} is not opened"),
+                        ))
                     }
-                };
+                }
+            }
+            // 崩溃时活跃文件末尾可能有没有写完整的记录，旧的数据文件在切换时已经持久化
+            let first = window * self.options.load_threads;
+            let decode = |i: usize, data_file: &DataFile| {
+                let offset = if first + i == 0 { start_offset } else { 0 };
+                decode_data_file(data_file, offset, first + i == last)
+            };
+            let decoded = match data_files.len() {
+                1 => vec![decode(0, data_files[0])],
+                _ => std::thread::scope(|scope| {
+                    let handles = data_files
+                        .iter()
+                        .enumerate()
+                        .map(|(i, data_file)| scope.spawn(move || decode(i, data_file)))
+                        .collect::<Vec<_>>();
+                    handles
+                        .into_iter()
+                        .map(|handle| handle.join().expect("data file decoder panicked"))
+                        .collect::<Vec<_>>()
+                }),
+            };
+            for (i, (data_file, decoded)) in data_files.into_iter().zip(decoded).enumerate() {
+                let decoded = decoded?;
+                if decoded.torn {
+                    warn!(
+                        "Discarding torn tail of data file {} after offset {}",
+                        data_file.get_file_id(),
+                        decoded.end
+                    );
+                }
+                let offset = replayer.apply(self.index.as_ref(), data_file, decoded)?;
+                // 如果当前文件时活跃文件，则需要设置活跃文件offset，供新数据写入，末尾不完整的记录被截断
+                // 旧的数据文件也需要记录长度，封存文件的摘要和 merge 的统计按照这个长度计算
+                if first + i == last {
+                    files.active.set_write_off(offset)?;
+                } else {
+                    data_file.set_write_off(offset)?;
+                    data_file.seal();
+                }
+            }
+        }
 
-                // 构建内存索引
-                let log_record_pos = LogRecordPos {
-                    file_id: *file_id,
-                    offset,
-                };
+        Ok((replayer.current_seq_no, mark.is_some()))
+    }
+}
 
-                // 解析key，拿到实际的key和se_no
-                let (real_key, seq_no) = parse_log_record_key(log_record.key.clone());
-                // 非事务提交的情况，直接更新到内存索引
-                if seq_no == NON_TRANSACTION_SEQ_NO {
-                    self.update_index(real_key, log_record.rec_type, log_record_pos);
-                } else {
-                    // 事务中的操作
-                    if log_record.rec_type == LogRecordType::TXNFINISH {
-                        // 更新到内存索引中
-                        self.update_index(real_key, log_record.rec_type, log_record_pos);
-                    } else {
-                        if log_record.rec_type == LogRecordType::TXNFINISH {
-                            let records: &Vec<TransactionRecord> =
-                                transaction_records.get(&seq_no).unwrap();
-                            for tnx_record in records.iter() {
-                                self.update_index(
-                                    tnx_record.record.key.clone(),
-                                    tnx_record.record.rec_type,
-                                    tnx_record.pos,
-                                );
-                            }
-                            transaction_records.remove(&seq_no);
-                        } else {
-                            // 正常提交，存起来
-                            log_record.key = real_key;
-                            transaction_records
-                                .entry(seq_no)
-                                .or_insert(Vec::new())
-                                .push(TransactionRecord {
-                                    record: log_record,
-                                    pos: log_record_pos,
-                                });
-                        }
-                    }
+// 释放时检查是否有没有持久化的数据，帮助在开发阶段发现遗漏的 sync 或 close 调用
+#[cfg(feature = "drop-check")]
+impl Drop for EngineCore {
+    fn drop(&mut self) {
+        if !self.has_unsynced_data() {
+            return;
+        }
+        let msg = format!(
+            "Engine at {:?} dropped with unsynced data, call sync() or close() before dropping",
+            self.options.dir_path
+        );
+        match self.options.unsynced_drop {
+            UnsyncedDropAction::Ignore => {}
+            UnsyncedDropAction::Log => error!("{msg}"),
+            // 已经在 panic 时不再 panic，避免进程直接退出
+            UnsyncedDropAction::Panic if !std::thread::panicking() => panic!("{msg}"),
+            UnsyncedDropAction::Panic => error!("{msg}"),
+        }
+    }
+}
+
+// 回放数据文件中的记录并更新内存索引，事务中的记录在读取到事务完成标识之后才生效
+// 未完成的事务记录会一直暂存，可以分多次回放同一个文件或者多个文件
+// 新写入的事务不会跨越数据文件，旧版本写入的事务完成标识可能在下一个文件中，按照序列号跨文件匹配
+#[derive(Default)]
+pub(crate) struct IndexReplayer {
+    // 暂存事务相关的数据
+    transaction_records: HashMap<u64, Vec<TransactionRecord>>,
+    // 读取到的最大事务序列号
+    pub(crate) current_seq_no: u64,
+    // 只回放序列号不超过 seq_limit 的事务，读取到更大的事务完成标识时停止回放
+    pub(crate) seq_limit: Option<u64>,
+    // 是否已经因为 seq_limit 停止回放
+    pub(crate) reached_limit: bool,
+    // 跟随者回放复制数据时的冲突解决，None 表示直接更新索引
+    pub(crate) conflicts: Option<ReplicaConflicts>,
+}
+
+impl IndexReplayer {
+    // 从 offset 开始回放数据文件，返回读取结束的位置
+    // allow_torn_tail 为 true 时文件可能正在被写入，末尾不完整的记录视为文件结束
+    pub(crate) fn replay(
+        &mut self,
+        index: &dyn Indexer,
+        data_file: &DataFile,
+        offset: u64,
+        allow_torn_tail: bool,
+    ) -> Result<u64> {
+        if self.reached_limit {
+            return Ok(offset);
+        }
+        let decoded = decode_data_file(data_file, offset, allow_torn_tail)?;
+        self.apply(index, data_file, decoded)
+    }
+
+    // 按照文件中的顺序把解码之后的记录更新到内存索引，返回回放结束的位置
+    fn apply(
+        &mut self,
+        index: &dyn Indexer,
+        data_file: &DataFile,
+        decoded: DecodedFile,
+    ) -> Result<u64> {
+        let mut updates = IndexUpdates::new(index);
+        for record in decoded.records {
+            if self.reached_limit {
+                return Ok(record.pos.offset);
+            }
+            data_file.add_record_count(1);
+
+            // 解析key，拿到实际的key和se_no
+            let (real_key, seq_no) = try_parse_log_record_key(record.key)?;
+            // 非事务提交的情况，直接更新到内存索引
+            if seq_no == NON_TRANSACTION_SEQ_NO {
+                self.update(&mut updates, real_key, seq_no, record.rec_type, record.pos)?;
+            } else if record.rec_type == LogRecordType::TXNFINISH
+                && self.seq_limit.is_some_and(|limit| seq_no > limit)
+            {
+                self.reached_limit = true;
+                return Ok(record.pos.offset);
+            } else if record.rec_type == LogRecordType::TXNFINISH {
+                // 事务完成，将暂存的数据更新到内存索引中
+                let records: Vec<TransactionRecord> =
+                    self.transaction_records.remove(&seq_no).unwrap_or_default();
+                for tnx_record in records.into_iter() {
+                    self.update(
+                        &mut updates,
+                        tnx_record.record.key,
+                        seq_no,
+                        tnx_record.record.rec_type,
+                        tnx_record.pos,
+                    )?;
                 }
+            } else {
+                // 事务中的操作，先暂存起来，更新索引只需要 key 和类型
+                self.transaction_records
+                    .entry(seq_no)
+                    .or_default()
+                    .push(TransactionRecord {
+                        record: LogRecord {
+                            key: real_key,
+                            value: Vec::new(),
+                            rec_type: record.rec_type,
+                            meta: Vec::new(),
+                        },
+                        pos: record.pos,
+                    });
+            }
 
-                // 更新当前事务序列号
-                current_seq_no = std::cmp::max(seq_no, current_seq_no);
+            // 更新当前事务序列号
+            self.current_seq_no = std::cmp::max(seq_no, self.current_seq_no);
+        }
+        Ok(decoded.end)
+    }
 
-                // 更新offset，下一次读取时候的开始位置
-                offset += size as u64;
+    // 更新内存索引，设置了冲突解决时只有被接受的记录才会生效
+    fn update(
+        &self,
+        updates: &mut IndexUpdates,
+        key: Vec<u8>,
+        seq_no: u64,
+        rec_type: LogRecordType,
+        pos: LogRecordPos,
+    ) -> Result<()> {
+        if let Some(conflicts) = &self.conflicts {
+            if matches!(
+                rec_type,
+                LogRecordType::NORMAL | LogRecordType::CHUNKED | LogRecordType::DELETED
+            ) {
+                // 暂存的更新写入索引之后才能读取到本地最新的版本
+                updates.flush();
+                if !conflicts.accept(updates.index, &key, seq_no, rec_type, pos)? {
+                    return Ok(());
+                }
             }
-            // 如果当前文件时活跃文件，则需要设置活跃文件offset，供新数据写入
-            if i == self.file_ids.len() {
-                active_file.set_write_off(offset);
+        }
+        updates.update(key, rec_type, pos);
+        Ok(())
+    }
+}
+
+// 解码之后的记录，不保留 value 和元数据
+struct DecodedRecord {
+    key: Vec<u8>,
+    rec_type: LogRecordType,
+    pos: LogRecordPos,
+}
+
+// 一个数据文件中从 offset 开始的所有记录，end 为读取结束的位置
+struct DecodedFile {
+    records: Vec<DecodedRecord>,
+    end: u64,
+    // 是否因为末尾不完整的记录而结束
+    torn: bool,
+}
+
+// 读取并解码数据文件中的记录，跳过对齐填充；不修改索引，可以在多个线程中同时解码不同的文件
+fn decode_data_file(
+    data_file: &DataFile,
+    mut offset: u64,
+    allow_torn_tail: bool,
+) -> Result<DecodedFile> {
+    let file_id = data_file.get_file_id();
+    let mut records = Vec::new();
+    let mut torn = false;
+    loop {
+        let (record, size) = match data_file.read_log_record(offset) {
+            Ok(res) => (res.record, res.size as u64),
+            Err(Errors::ReadDataFileEOF) => break,
+            Err(Errors::InvalidLogRecordCrc | Errors::DataFileCorrupted) if allow_torn_tail => {
+                torn = true;
+                break;
             }
+            Err(e) => return Err(e),
+        };
+        if record.rec_type != LogRecordType::PADDING {
+            records.push(DecodedRecord {
+                key: record.key,
+                rec_type: record.rec_type,
+                pos: LogRecordPos {
+                    file_id,
+                    offset,
+                    size,
+                },
+            });
         }
+        offset += size;
+    }
+    Ok(DecodedFile {
+        records,
+        end: offset,
+        torn,
+    })
+}
+
+// 引擎内部使用的 key 前缀，用户不能写入，遍历和列出 key 时也会跳过
+pub(crate) const INTERNAL_KEY_PREFIX: &[u8] = b"\x00__kv_internal__/";
+
+pub(crate) fn is_internal_key(key: &[u8]) -> bool {
+    key.starts_with(INTERNAL_KEY_PREFIX)
+}
+
+// 加载索引时每次更新的 key 的数量
+const INDEX_UPDATE_BATCH_SIZE: usize = 4096;
+
+// 加载索引时批量更新内存索引，减少获取索引写锁的次数
+// 删除之前先写入暂存的数据，保证更新的顺序不变，释放时写入剩余的数据
+struct IndexUpdates<'a> {
+    index: &'a dyn Indexer,
+    puts: Vec<(Vec<u8>, LogRecordPos)>,
+}
 
-        Ok(current_seq_no)
+impl<'a> IndexUpdates<'a> {
+    fn new(index: &'a dyn Indexer) -> Self {
+        Self {
+            index,
+            puts: Vec::new(),
+        }
     }
 
-    // 加载索引时更新内存数据
-    fn update_index(&self, key: Vec<u8>, rec_type: LogRecordType, pos: LogRecordPos) {
+    fn update(&mut self, key: Vec<u8>, rec_type: LogRecordType, pos: LogRecordPos) {
         match rec_type {
-            LogRecordType::NORMAL => {
-                self.index.put(key.clone(), pos);
+            LogRecordType::NORMAL | LogRecordType::CHUNKED => {
+                self.puts.push((key, pos));
+                if self.puts.len() >= INDEX_UPDATE_BATCH_SIZE {
+                    self.flush();
+                }
             }
             LogRecordType::DELETED => {
+                self.flush();
                 self.index.delete(key);
             }
             _ => {}
         }
+    }
 
-        // if *rec_type == LogRecordType::NORMAL {
-        //     self.index.put(key.clone(), pos);
-        // }
-
-        // if *rec_type == LogRecordType::DELETED {
-        //     self.index.delete(key);
-        // }
+    fn flush(&mut self) {
+        if !self.puts.is_empty() {
+            self.index.put_batch(std::mem::take(&mut self.puts));
+        }
     }
 }
 
-// 从数据目录中加载数据文件
-fn load_data_file(dir_path: &PathBuf) -> Result<Vec<DataFile>> {
-    let dir = fs::read_dir(dir_path.clone());
-    if dir.is_err() {
-        return Err(Errors::FailedToReadDatabaseDir);
+impl Drop for IndexUpdates<'_> {
+    fn drop(&mut self) {
+        self.flush();
     }
+}
 
-    let mut file_ids = Vec::<u32>::new();
-    let mut data_files = Vec::<DataFile>::new();
-    for file in dir.unwrap() {
-        if let Ok(entry) = file {
-            // 拿到文件名
-            let file_os_str = entry.file_name();
-            let file_name = file_os_str.to_str().unwrap();
-
-            // 判断文件名是否以 .data结尾
-            if file_name.ends_with(DATA_FILE_NAME_SUFFIX) {
-                // 000001.data
-                let split_name = file_name.split('.').collect::<Vec<_>>();
-                let file_id = match split_name[0].parse::<u32>() {
-                    Ok(fid) => fid,
-                    Err(_) => {
-                        error!("");
-                        return Err(Errors::DataDirectoryCorrupted);
-                    }
-                };
-                file_ids.push(file_id);
-            }
+// 持久化当前活跃文件，并打开新的活跃文件，旧的活跃文件加入到旧的数据文件中并通知订阅者
+// 调用方需要持有 append_lock
+fn rotate_active_file(
+    files: &ShardedLock<DataFiles>,
+    options: &Options,
+    subscribers: &SegmentSubscribers,
+    db_id: &DbId,
+) -> Result<Arc<DataFile>> {
+    let active_file = files.read().active.clone();
+    // 将当前文件持久化
+    active_file.sync()?;
+
+    let current_fid = active_file.get_file_id();
+    let new_file = Arc::new(DataFile::create_with_mode(
+        options.dir_path.clone(),
+        current_fid + 1,
+        options.io_type,
+        options.file_mode,
+    )?);
+    init_data_file(&new_file, db_id)?;
+    // 新的活跃文件创建成功之后，旧的活跃文件不会再写入
+    active_file.seal();
+    files.update(|files| {
+        let mut older = files.older.clone();
+        older.insert(current_fid, files.active.clone());
+        DataFiles {
+            active: new_file.clone(),
+            older,
+            active_since: Instant::now(),
         }
-    }
+    });
+    subscribers.notify(SealedSegment::new(&options.dir_path, &active_file));
+    Ok(new_file)
+}
+
+// 从数据目录中加载数据文件
+fn load_data_file(dir_path: &Path, io_type: IOType) -> Result<Vec<DataFile>> {
+    remove_temp_data_files(dir_path)?;
+    let mut data_files = Vec::<DataFile>::new();
+    let file_ids = data_file_ids(dir_path)?;
     // 如果没有数据文件，则直接返回
     if file_ids.is_empty() {
         return Ok(data_files);
     }
 
-    // 对文件排序，从小到大加载
-    file_ids.sort();
     // 遍历所有的文件id，依次打开对应的数据文件
     for file_id in file_ids.iter() {
-        let data_file = DataFile::new(dir_path.clone(), *file_id)?;
+        let data_file = DataFile::new(dir_path.to_path_buf(), *file_id, io_type)?;
         data_files.push(data_file);
     }
 
     Ok(data_files)
 }
 
-fn check_options(opts: &Options) -> Option<Errors> {
+// 删除创建数据文件时崩溃残留的临时文件
+fn remove_temp_data_files(dir_path: &Path) -> Result<()> {
+    let dir = match fs::read_dir(dir_path) {
+        Ok(dir) => dir,
+        Err(e) => return Err(Errors::FailedToReadDatabaseDir(e.into())),
+    };
+    let mut removed = false;
+    for entry in dir.flatten() {
+        let is_temp = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.ends_with(TEMP_DATA_FILE_NAME_SUFFIX));
+        if !is_temp {
+            continue;
+        }
+        if let Err(e) = fs::remove_file(entry.path()) {
+            warn!("Failed to remove temp data file {:?}: {e}", entry.path());
+            continue;
+        }
+        removed = true;
+    }
+    if removed {
+        fio::sync_dir(dir_path)?;
+    }
+    Ok(())
+}
+
+// 数据目录中所有数据文件的 id，从小到大排列
+pub(crate) fn data_file_ids(dir_path: &Path) -> Result<Vec<u32>> {
+    let dir = match fs::read_dir(dir_path) {
+        Ok(dir) => dir,
+        Err(e) => return Err(Errors::FailedToReadDatabaseDir(e.into())),
+    };
+
+    let mut file_ids = Vec::<u32>::new();
+    for entry in dir.flatten() {
+        // 拿到文件名，不是 UTF-8 的文件名不会是数据文件
+        let file_os_str = entry.file_name();
+        let file_name = match file_os_str.to_str() {
+            Some(file_name) => file_name,
+            None => continue,
+        };
+
+        // 判断文件名是否以 .data结尾
+        if file_name.ends_with(DATA_FILE_NAME_SUFFIX) {
+            // 000001.data
+            let split_name = file_name.split('.').collect::<Vec<_>>();
+            let file_id = match split_name[0].parse::<u32>() {
+                Ok(fid) => fid,
+                Err(_) => {
+                    error!("Invalid data file name {file_name}");
+                    return Err(Errors::DataDirectoryCorrupted);
+                }
+            };
+            file_ids.push(file_id);
+        }
+    }
+
+    // 对文件排序，从小到大加载
+    file_ids.sort();
+    Ok(file_ids)
+}
+
+// 计算数据目录中所有文件的大小
+fn dir_disk_size(dir_path: &Path) -> Result<u64> {
+    let dir = match fs::read_dir(dir_path) {
+        Ok(dir) => dir,
+        Err(e) => return Err(Errors::FailedToReadDatabaseDir(e.into())),
+    };
+    let mut size = 0;
+    for entry in dir.flatten() {
+        if let Ok(metadata) = entry.metadata() {
+            if metadata.is_file() {
+                size += metadata.len();
+            }
+        }
+    }
+    Ok(size)
+}
+
+// 在持久化间隔的基础上增加随机的抖动
+fn jittered(sync_interval: SyncInterval) -> Duration {
+    let jitter = sync_interval.jitter.as_nanos() as u64;
+    if jitter == 0 {
+        return sync_interval.interval;
+    }
+    let rand = RandomState::new().build_hasher().finish();
+    sync_interval.interval + Duration::from_nanos(rand % (jitter + 1))
+}
+
+pub(crate) fn check_options(opts: &Options) -> Option<Errors> {
     let dir_path = opts.dir_path.to_str();
     if dir_path.is_none() || dir_path.unwrap().is_empty() {
         return Some(Errors::DirPathIsEmpty);
@@ -424,5 +1750,77 @@ fn check_options(opts: &Options) -> Option<Errors> {
         return Some(Errors::DataFileSizeTooSmall);
     }
 
+    if let RecordAlignment::Record(size) | RecordAlignment::Block(size) = opts.record_alignment {
+        if size < MIN_PADDING_SIZE || !size.is_power_of_two() || size > opts.data_file_size {
+            return Some(Errors::InvalidRecordAlignment);
+        }
+    }
+
+    if opts.index_type == IndexType::Custom && opts.custom_index.is_none() {
+        return Some(Errors::CustomIndexNotSet);
+    }
+
+    if opts.bloom_filter.is_some_and(|b| {
+        b.expected_keys == 0 || !(b.false_positive_rate > 0.0 && b.false_positive_rate < 1.0)
+    }) {
+        return Some(Errors::InvalidBloomFilterOptions);
+    }
+
+    if opts.rotate_interval.is_some_and(|i| i.is_zero())
+        || opts
+            .rotate_stale_ratio
+            .is_some_and(|r| !(r > 0.0 && r <= 1.0))
+    {
+        return Some(Errors::InvalidRotateOptions);
+    }
+
+    if opts.merge_ratio.is_some_and(|r| !(r > 0.0 && r <= 1.0)) {
+        return Some(Errors::InvalidMergeRatio);
+    }
+
+    if opts.merge_rate_limit == Some(0) {
+        return Some(Errors::InvalidMergeRateLimit);
+    }
+
+    if opts.sync_interval.is_some_and(|s| s.interval.is_zero()) {
+        return Some(Errors::InvalidSyncInterval);
+    }
+
+    if opts.value_chunk_size == Some(0) {
+        return Some(Errors::InvalidValueChunkSize);
+    }
+
+    if let Some(e) = check_sample_rate(opts.op_trace_sample_rate) {
+        return Some(e);
+    }
+
+    if let Some(e) = check_priority(&opts.background_priority) {
+        return Some(e);
+    }
+
+    // 引擎需要读写自己创建的文件和目录
+    if opts.file_mode > 0o7777
+        || opts.file_mode & 0o600 != 0o600
+        || opts.dir_mode > 0o7777
+        || opts.dir_mode & 0o700 != 0o700
+    {
+        return Some(Errors::InvalidFileMode);
+    }
+
+    if opts.default_ttl.is_some_and(|ttl| ttl.is_zero()) {
+        return Some(Errors::InvalidDefaultTtl);
+    }
+
+    // 内存写缓冲不处理分块存储的 value
+    if opts.memtable_bytes == Some(0)
+        || (opts.memtable_bytes.is_some() && opts.value_chunk_size.is_some())
+    {
+        return Some(Errors::InvalidMemtableBytes);
+    }
+
+    if opts.load_threads == 0 {
+        return Some(Errors::InvalidLoadThreads);
+    }
+
     None
 }
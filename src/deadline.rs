@@ -0,0 +1,266 @@
+//! 阻塞操作的超时：get_with_deadline、put_with_deadline 以及设置了 WriteBatchOptions::deadline 的事务提交
+//! 在当前线程记录截止时间，等待写入锁、事务提交锁、索引和数据文件集合的读锁以及未完成的写入时最多等到截止时间，
+//! 超时返回 Errors::Timeout；受 merge_rate_limit 限速的休眠超过截止时间时同样超时
+//! 超时只发生在写入数据文件之前，数据已经写入之后的索引更新和 fsync 不会中途放弃，
+//! 分块存储的 value 写入分块之后同样不再超时
+
+use std::{
+    cell::Cell,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use parking_lot::{Mutex, MutexGuard, RwLockReadGuard};
+
+use crate::{
+    data::log_record::LogRecordPos,
+    db::{Engine, InflightWrites},
+    errors::{Errors, Result},
+    utils::sharded_lock::ShardedLock,
+};
+
+thread_local! {
+    // 当前线程正在执行的操作的截止时间
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+// 离开作用域时恢复之前的截止时间
+struct DeadlineGuard(Option<Instant>);
+
+impl Drop for DeadlineGuard {
+    fn drop(&mut self) {
+        DEADLINE.with(|d| d.set(self.0));
+    }
+}
+
+// 在截止时间内执行 f，嵌套调用时使用更早的截止时间
+pub(crate) fn with_deadline<R>(timeout: Duration, f: impl FnOnce() -> Result<R>) -> Result<R> {
+    let prev = DEADLINE.with(|d| d.get());
+    let deadline = match Instant::now().checked_add(timeout) {
+        Some(deadline) => prev.map_or(deadline, |p| p.min(deadline)),
+        None => return f(),
+    };
+    let _guard = DeadlineGuard(prev);
+    DEADLINE.with(|d| d.set(Some(deadline)));
+    f()
+}
+
+fn current() -> Option<Instant> {
+    DEADLINE.with(|d| d.get())
+}
+
+// 获取锁，设置了截止时间时最多等到截止时间
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>> {
+    match current() {
+        Some(deadline) => mutex.try_lock_until(deadline).ok_or(Errors::Timeout),
+        None => Ok(mutex.lock()),
+    }
+}
+
+// 获取读锁，设置了截止时间时最多等到截止时间
+pub(crate) fn read<T>(lock: &ShardedLock<T>) -> Result<RwLockReadGuard<'_, Arc<T>>> {
+    match current() {
+        Some(deadline) => lock.try_read_until(deadline).ok_or(Errors::Timeout),
+        None => Ok(lock.read()),
+    }
+}
+
+// 等待已经写入数据文件的写入更新完索引，设置了截止时间时最多等到截止时间
+pub(crate) fn wait_idle(inflight: &InflightWrites) -> Result<()> {
+    match current() {
+        Some(deadline) if !inflight.wait_idle_until(deadline) => Err(Errors::Timeout),
+        Some(_) => Ok(()),
+        None => {
+            inflight.wait_idle();
+            Ok(())
+        }
+    }
+}
+
+// 限速时休眠，休眠结束的时间超过截止时间时不休眠，直接返回 Errors::Timeout
+pub(crate) fn sleep(duration: Duration) -> Result<()> {
+    if let Some(deadline) = current() {
+        if Instant::now() + duration > deadline {
+            return Err(Errors::Timeout);
+        }
+    }
+    std::thread::sleep(duration);
+    Ok(())
+}
+
+impl Engine {
+    // 从索引中查找 key 的位置，设置了截止时间时最多等到截止时间
+    pub(crate) fn index_get(&self, key: &[u8]) -> Result<Option<LogRecordPos>> {
+        match current() {
+            Some(deadline) => self.index.get_until(key.to_vec(), deadline),
+            None => Ok(self.index.get(key.to_vec())),
+        }
+    }
+
+    /// 根据 key 读取数据，等待索引、数据文件集合的读锁超过 timeout 时返回 Errors::Timeout
+    pub fn get_with_deadline(&self, key: Bytes, timeout: Duration) -> Result<Bytes> {
+        with_deadline(timeout, || self.get(key))
+    }
+
+    /// 写入数据，等待内部锁超过 timeout 时返回 Errors::Timeout 并且不写入
+    pub fn put_with_deadline(&self, key: Bytes, value: Bytes, timeout: Duration) -> Result<()> {
+        with_deadline(timeout, || self.put(key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc, thread};
+
+    use crate::{
+        options::{Options, WriteBatchOptions},
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_deadline() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-deadline");
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let timeout = Duration::from_millis(50);
+        engine
+            .put_with_deadline(get_test_key(1), get_test_value(1), timeout)
+            .unwrap();
+
+        // 其他线程持有写入锁时超时，读取不需要等待写入锁
+        let lock = engine.append_lock.lock();
+        let start = Instant::now();
+        let res = engine.put_with_deadline(get_test_key(2), get_test_value(2), timeout);
+        assert_eq!(res.err().unwrap(), Errors::Timeout);
+        assert!(start.elapsed() >= timeout);
+        assert_eq!(
+            engine.get_with_deadline(get_test_key(1), timeout).unwrap(),
+            get_test_value(1)
+        );
+        let wb = engine
+            .new_write_batch(WriteBatchOptions {
+                deadline: Some(timeout),
+                ..Default::default()
+            })
+            .unwrap();
+        wb.put(get_test_key(3), get_test_value(3)).unwrap();
+        assert_eq!(wb.commit().err().unwrap(), Errors::Timeout);
+
+        // 超时之后截止时间恢复，锁释放之后正常写入
+        let handle = {
+            let engine = engine.clone();
+            thread::spawn(move || engine.put(get_test_key(4), get_test_value(4)))
+        };
+        thread::sleep(Duration::from_millis(20));
+        drop(lock);
+        handle.join().unwrap().unwrap();
+        assert!(current().is_none());
+        wb.commit().unwrap();
+        assert!(engine.try_get(get_test_key(2)).unwrap().is_none());
+        assert_eq!(engine.get(get_test_key(3)).unwrap(), get_test_value(3));
+        assert_eq!(engine.get(get_test_key(4)).unwrap(), get_test_value(4));
+        assert_eq!(
+            Errors::Timeout.category(),
+            crate::errors::ErrorCategory::Timeout
+        );
+        std::mem::drop(engine);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_deadline_read_and_flush() {
+        use parking_lot::RwLock;
+
+        use crate::{
+            index::{btree::BTree, Indexer, IndexerIterator},
+            options::{IndexType, IteratorOptions},
+        };
+
+        // 读取时需要等待 gate 的索引
+        struct GatedIndex {
+            inner: BTree,
+            gate: Arc<RwLock<()>>,
+        }
+
+        impl Indexer for GatedIndex {
+            fn put(&self, key: Vec<u8>, pos: LogRecordPos) -> bool {
+                self.inner.put(key, pos)
+            }
+            fn get(&self, key: Vec<u8>) -> Option<LogRecordPos> {
+                let _gate = self.gate.read();
+                self.inner.get(key)
+            }
+            fn get_until(&self, key: Vec<u8>, deadline: Instant) -> Result<Option<LogRecordPos>> {
+                let _gate = self.gate.try_read_until(deadline).ok_or(Errors::Timeout)?;
+                self.inner.get_until(key, deadline)
+            }
+            fn delete(&self, key: Vec<u8>) -> bool {
+                self.inner.delete(key)
+            }
+            fn compare_and_put(&self, key: Vec<u8>, old: LogRecordPos, new: LogRecordPos) -> bool {
+                self.inner.compare_and_put(key, old, new)
+            }
+            fn iterator(&self, option: IteratorOptions) -> Box<dyn IndexerIterator> {
+                self.inner.iterator(option)
+            }
+            fn list_keys(&self, reverse: bool, prefix: &[u8]) -> Result<Vec<Bytes>> {
+                self.inner.list_keys(reverse, prefix)
+            }
+        }
+
+        let gate = Arc::new(RwLock::new(()));
+        let factory_gate = gate.clone();
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-deadline-read");
+        opts.index_type = IndexType::Custom;
+        opts.custom_index = Some(Arc::new(move || {
+            Box::new(GatedIndex {
+                inner: BTree::new(),
+                gate: factory_gate.clone(),
+            })
+        }));
+        opts.memtable_bytes = Some(1024 * 1024);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let timeout = Duration::from_millis(50);
+        engine.put(get_test_key(1), get_test_value(1)).unwrap();
+        engine.flush_memtable().unwrap();
+
+        // 其他线程持有索引的锁时读取超时
+        let write_gate = gate.write();
+        let start = Instant::now();
+        let res = engine.get_with_deadline(get_test_key(1), timeout);
+        assert_eq!(res.err().unwrap(), Errors::Timeout);
+        assert!(start.elapsed() >= timeout);
+        drop(write_gate);
+        assert_eq!(
+            engine.get_with_deadline(get_test_key(1), timeout).unwrap(),
+            get_test_value(1)
+        );
+
+        // 刷新内存写缓冲需要等待未完成的写入，超时之后数据保留在内存中
+        engine.put(get_test_key(2), get_test_value(2)).unwrap();
+        let inflight = engine.inflight.begin();
+        let wb = engine
+            .new_write_batch(WriteBatchOptions {
+                deadline: Some(timeout),
+                ..Default::default()
+            })
+            .unwrap();
+        wb.put(get_test_key(3), get_test_value(3)).unwrap();
+        assert_eq!(wb.commit().err().unwrap(), Errors::Timeout);
+        assert_eq!(engine.get(get_test_key(2)).unwrap(), get_test_value(2));
+        drop(inflight);
+        wb.commit().unwrap();
+        assert_eq!(engine.get(get_test_key(2)).unwrap(), get_test_value(2));
+        assert_eq!(engine.get(get_test_key(3)).unwrap(), get_test_value(3));
+        std::mem::drop(engine);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
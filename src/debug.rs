@@ -0,0 +1,196 @@
+use std::fmt;
+
+use bytes::Bytes;
+
+use crate::{
+    batch::parse_log_record_key,
+    data::log_record::{LogRecord, LogRecordPos, LogRecordType},
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+/// 以可读的方式显示 key 或者 value
+/// 合法并且不含控制字符的 UTF-8 显示为带引号的字符串，否则显示为十六进制
+pub struct KeyDisplay<'a>(pub &'a [u8]);
+
+impl fmt::Display for KeyDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match std::str::from_utf8(self.0) {
+            Ok(s) if !s.chars().any(char::is_control) => write!(f, "{:?}", s),
+            _ => {
+                write!(f, "0x")?;
+                for b in self.0 {
+                    write!(f, "{:02x}", b)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Debug for KeyDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for LogRecordPos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "file {} offset {}", self.file_id, self.offset)
+    }
+}
+
+// 显示记录的类型、key 以及各部分的长度和校验值
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encoded = self.encode();
+        let (key, seq_no) = match self.rec_type {
+            LogRecordType::PADDING => (self.key.clone(), 0),
+            _ => parse_log_record_key(self.key.clone()),
+        };
+        write!(
+            f,
+            "{:?} key={} seq={} value_size={} meta_size={} size={} crc={:#010x}",
+            self.rec_type,
+            KeyDisplay(&key),
+            seq_no,
+            self.value.len(),
+            self.meta.len(),
+            encoded.len(),
+            encoded.crc(),
+        )
+    }
+}
+
+/// 一次读取的数据来源
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetExplain {
+    pub key: Bytes,
+    // 索引中记录的位置
+    pub file_id: u32,
+    pub offset: u64,
+    // 是否位于当前活跃文件中
+    pub in_active_file: bool,
+    // 记录的类型、写入时的事务序列号，非事务写入为 0
+    pub rec_type: LogRecordType,
+    pub seq_no: u64,
+    // 记录编码后的长度
+    pub size: usize,
+    pub value_size: usize,
+    pub meta_size: usize,
+    pub crc: u32,
+}
+
+impl fmt::Display for GetExplain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "key {} served from file {}{} offset {}: {:?} seq={} size={} value_size={} meta_size={} crc={:#010x}",
+            KeyDisplay(&self.key),
+            self.file_id,
+            if self.in_active_file { " (active)" } else { "" },
+            self.offset,
+            self.rec_type,
+            self.seq_no,
+            self.size,
+            self.value_size,
+            self.meta_size,
+            self.crc,
+        )
+    }
+}
+
+impl Engine {
+    /// 说明读取 key 时数据来自哪个文件的哪个位置，用于排查问题
+    /// key 不存在时返回 Errors::KeyNotFound
+    pub fn explain_get(&self, key: Bytes) -> Result<GetExplain> {
+        // 内存写缓冲中的数据没有位置，先写入数据文件
+        self.flush_memtable()?;
+        let pos = match self.index.get(key.to_vec()) {
+            Some(pos) => pos,
+            None => return Err(Errors::KeyNotFound),
+        };
+        let files = self.files.load();
+        let data_file = match files.get(pos.file_id) {
+            Some(data_file) => data_file,
+            None => return Err(Errors::DataFileNotFound),
+        };
+        let read = data_file.read_log_record(pos.offset)?;
+        let (_, seq_no) = parse_log_record_key(read.record.key.clone());
+        let record = read.record;
+        Ok(GetExplain {
+            key,
+            file_id: pos.file_id,
+            offset: pos.offset,
+            in_active_file: files.active.get_file_id() == pos.file_id,
+            rec_type: record.rec_type,
+            seq_no,
+            size: read.size,
+            value_size: record.value.len(),
+            meta_size: record.meta.len(),
+            crc: record.encode().crc(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{fence::FILE_HEADER_SIZE, options::Options};
+
+    use super::*;
+
+    #[test]
+    fn test_key_display() {
+        assert_eq!(KeyDisplay(b"user:1").to_string(), "\"user:1\"");
+        assert_eq!(KeyDisplay(b"a\nb").to_string(), "0x610a62");
+        assert_eq!(KeyDisplay(&[0xff, 0x00]).to_string(), "0xff00");
+        assert_eq!(format!("{:?}", KeyDisplay(b"")), "\"\"");
+
+        let record = LogRecord {
+            key: crate::batch::log_record_key_with_seq(b"name".to_vec(), 3),
+            value: b"bitcask-rs".to_vec(),
+            rec_type: LogRecordType::NORMAL,
+            meta: Default::default(),
+        };
+        let s = record.to_string();
+        assert!(s.starts_with("NORMAL key=\"name\" seq=3 value_size=10 meta_size=0 size=22"));
+    }
+
+    #[test]
+    fn test_explain_get() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-explain-get");
+        opts.data_file_size = 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let res = engine.put(Bytes::from("name"), Bytes::from("bitcask-rs"));
+        assert!(res.is_ok());
+
+        let explain = engine.explain_get(Bytes::from("name")).unwrap();
+        assert_eq!(explain.file_id, 0);
+        assert_eq!(explain.offset, FILE_HEADER_SIZE);
+        assert!(explain.in_active_file);
+        assert_eq!(explain.rec_type, LogRecordType::NORMAL);
+        assert_eq!(explain.seq_no, 0);
+        assert_eq!(explain.value_size, 10);
+        assert!(explain
+            .to_string()
+            .starts_with("key \"name\" served from file 0 (active) offset 31"));
+
+        // 切换文件之后位于旧的数据文件中
+        for i in 0..100 {
+            let res = engine.put(Bytes::from(format!("key-{i}")), Bytes::from("value"));
+            assert!(res.is_ok());
+        }
+        let explain = engine.explain_get(Bytes::from("name")).unwrap();
+        assert!(!explain.in_active_file);
+        assert_eq!(
+            engine.explain_get(Bytes::from("missing")).err().unwrap(),
+            Errors::KeyNotFound
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
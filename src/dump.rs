@@ -0,0 +1,433 @@
+//! 以带注释的十六进制格式显示数据文件的内容，用于说明磁盘格式和排查损坏的数据文件
+//! 字段的划分和解析使用与读写数据文件相同的编码代码
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use bytes::Bytes;
+use prost::{encoding::decode_varint, length_delimiter_len};
+
+use crate::{
+    data::{
+        data_file::{DataFile, RecordHeader},
+        log_record::{LogRecord, LogRecordType, LOG_RECORD_META_FLAG},
+    },
+    debug::KeyDisplay,
+    errors::{Errors, Result},
+    fio::IOManager,
+};
+
+// 每个字段最多显示的字节数，超过的部分省略
+const MAX_FIELD_HEX_BYTES: usize = 16;
+
+/// 记录中的一个字段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpField {
+    // 字段在文件中的位置
+    pub offset: u64,
+    pub name: &'static str,
+    pub bytes: Bytes,
+    // 字段解析之后的含义
+    pub desc: String,
+}
+
+/// 数据文件中的一条记录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordDump {
+    pub offset: u64,
+    pub rec_type: LogRecordType,
+    // 记录编码后的长度
+    pub size: usize,
+    pub crc_ok: bool,
+    pub fields: Vec<DumpField>,
+}
+
+/// 数据文件的全部记录
+#[derive(Debug, PartialEq, Eq)]
+pub struct DataFileDump {
+    pub file_id: u32,
+    pub records: Vec<RecordDump>,
+    // 最后一条记录结束的位置
+    pub end_offset: u64,
+    // 在 end_offset 处无法继续解析时的错误，正常读取到文件末尾时为 None
+    pub error: Option<Errors>,
+}
+
+/// 读取并解析数据文件中的所有记录，校验值不正确的记录也会返回，遇到无法解析的记录时停止
+pub fn dump_data_file(dir_path: &Path, file_id: u32) -> Result<DataFileDump> {
+    let data_file = DataFile::open_read_only(dir_path.to_path_buf(), file_id)?;
+    let mut records = Vec::new();
+    let mut offset = 0;
+    let error = loop {
+        match dump_record(&data_file, offset) {
+            Ok(record) => {
+                offset += record.size as u64;
+                records.push(record);
+            }
+            Err(Errors::ReadDataFileEOF) => break None,
+            Err(e) => break Some(e),
+        }
+    };
+    Ok(DataFileDump {
+        file_id,
+        records,
+        end_offset: offset,
+        error,
+    })
+}
+
+fn dump_record(data_file: &DataFile, offset: u64) -> Result<RecordDump> {
+    let RecordHeader {
+        rec_type,
+        key_size,
+        value_size,
+        meta_size,
+        header_size,
+        raw,
+        seq_omitted,
+    } = data_file.read_record_header(offset)?;
+
+    let body_size = meta_size + key_size + value_size + 4;
+    let mut body = vec![0u8; body_size];
+    if data_file.read_at(&mut body, offset + header_size as u64)? < body_size {
+        return Err(Errors::ReadDataFileEOF);
+    }
+    let body = Bytes::from(body);
+
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    let mut push = |name, bytes: Bytes, desc: String| {
+        fields.push(DumpField {
+            offset: offset + pos as u64,
+            name,
+            bytes: bytes.clone(),
+            desc,
+        });
+        pos += bytes.len();
+    };
+
+    let mut type_desc = format!("{:?}", rec_type);
+    if raw[0] & LOG_RECORD_META_FLAG != 0 {
+        type_desc.push_str(" | META");
+    }
+    if seq_omitted {
+        type_desc.push_str(" | NO_SEQ");
+    }
+    push("type", raw.slice(..1), type_desc);
+    let key_size_len = length_delimiter_len(key_size);
+    push(
+        "key size",
+        raw.slice(1..1 + key_size_len),
+        key_size.to_string(),
+    );
+    push(
+        "value size",
+        raw.slice(1 + key_size_len..1 + key_size_len + length_delimiter_len(value_size)),
+        value_size.to_string(),
+    );
+    if raw[0] & LOG_RECORD_META_FLAG != 0 {
+        push(
+            "meta size",
+            raw.slice(header_size - 1..header_size),
+            meta_size.to_string(),
+        );
+        push(
+            "meta",
+            body.slice(..meta_size),
+            KeyDisplay(&body[..meta_size]).to_string(),
+        );
+    }
+
+    // key 的开头是变长编码的事务序列号，填充记录以及省略了序列号的记录没有序列号
+    let key = body.slice(meta_size..meta_size + key_size);
+    let mut user_key = key.clone();
+    if seq_omitted {
+        push("seq no", Bytes::new(), "0 (omitted)".to_string());
+    } else if rec_type != LogRecordType::PADDING {
+        let seq_no = match decode_varint(&mut user_key) {
+            Ok(seq_no) => seq_no,
+            Err(_) => return Err(Errors::DataFileCorrupted),
+        };
+        let seq_len = key.len() - user_key.len();
+        push("seq no", key.slice(..seq_len), seq_no.to_string());
+    }
+    push("key", user_key.clone(), KeyDisplay(&user_key).to_string());
+
+    let value_start = meta_size + key_size;
+    let value = body.slice(value_start..value_start + value_size);
+    push("value", value.clone(), format!("{} bytes", value.len()));
+
+    // 使用与写入时相同的编码计算校验值
+    let mut full_key = Vec::with_capacity(key.len() + 1);
+    if seq_omitted {
+        full_key.push(0);
+    }
+    full_key.extend_from_slice(&key);
+    let record = LogRecord {
+        key: full_key,
+        value: value.to_vec(),
+        rec_type,
+        meta: body[..meta_size].to_vec(),
+    };
+    let expected = record.encode_to_with(&mut Vec::new(), seq_omitted);
+    let crc_bytes = body.slice(body_size - 4..);
+    let crc = u32::from_be_bytes([crc_bytes[0], crc_bytes[1], crc_bytes[2], crc_bytes[3]]);
+    let crc_ok = crc == expected;
+    let crc_desc = match crc_ok {
+        true => format!("{:#010x} ok", crc),
+        false => format!("{:#010x} mismatch, expected {:#010x}", crc, expected),
+    };
+    push("crc", crc_bytes, crc_desc);
+
+    Ok(RecordDump {
+        offset,
+        rec_type,
+        size: header_size + body_size,
+        crc_ok,
+        fields,
+    })
+}
+
+impl fmt::Display for DumpField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut hex = self
+            .bytes
+            .iter()
+            .take(MAX_FIELD_HEX_BYTES)
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if self.bytes.len() > MAX_FIELD_HEX_BYTES {
+            hex.push_str(" ..");
+        }
+        write!(
+            f,
+            "{:010}  {:<50}  {:<10}  {}",
+            self.offset, hex, self.name, self.desc
+        )
+    }
+}
+
+impl fmt::Display for RecordDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "record at offset {}: {:?}, {} bytes, crc {}",
+            self.offset,
+            self.rec_type,
+            self.size,
+            if self.crc_ok { "ok" } else { "MISMATCH" }
+        )?;
+        for field in self.fields.iter() {
+            writeln!(f, "  {}", field)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DataFileDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "data file {}: {} records",
+            self.file_id,
+            self.records.len()
+        )?;
+        for record in self.records.iter() {
+            write!(f, "{}", record)?;
+        }
+        match &self.error {
+            None => writeln!(f, "end of records at offset {}", self.end_offset),
+            Some(e) => writeln!(f, "can not parse record at offset {}: {e}", self.end_offset),
+        }
+    }
+}
+
+/// 生成数据文件格式的说明，包括每个字段的含义和一条编码示例
+pub fn format_doc() -> String {
+    let mut doc = String::new();
+    doc.push_str("Data file format\n\n");
+    doc.push_str("A data file is a sequence of records, the file ends at a record whose key size and value size are both 0.\n");
+    doc.push_str("Integers marked varint use protobuf varint encoding, the crc is a big endian crc32 of all preceding bytes of the record.\n\n");
+    for (name, size, desc) in [
+        (
+            "type",
+            "1",
+            "record type, the highest bit is set when the record has meta, the second highest bit is set when the seq no is omitted",
+        ),
+        ("key size", "varint", "length of seq no + key"),
+        ("value size", "varint", "length of value"),
+        (
+            "meta size",
+            "1",
+            "length of meta, only present with the meta bit",
+        ),
+        (
+            "meta",
+            "meta size",
+            "user metadata, only present with the meta bit",
+        ),
+        (
+            "seq no",
+            "varint",
+            "transaction seq no, 0 for non-transactional writes, absent in padding and with the omitted seq bit",
+        ),
+        ("key", "rest of key size", "user key"),
+        ("value", "value size", "user value"),
+        ("crc", "4", "checksum"),
+    ] {
+        doc.push_str(&format!("  {:<10}  {:<16}  {}\n", name, size, desc));
+    }
+    doc.push_str("\nRecord types:\n");
+    for v in 1..=u8::MAX {
+        match LogRecordType::try_from_u8(v) {
+            Some(rec_type) => doc.push_str(&format!("  {v} {:?}\n", rec_type)),
+            None => break,
+        }
+    }
+
+    // 使用写入数据文件时的编码生成示例
+    let record = LogRecord {
+        key: crate::batch::log_record_key_with_seq(b"name".to_vec(), 0),
+        value: b"bitcask-rs".to_vec(),
+        rec_type: LogRecordType::NORMAL,
+        meta: b"v1".to_vec(),
+    };
+    doc.push_str("\nExample:\n");
+    match dump_encoded(&record.encode()) {
+        Ok(dump) => doc.push_str(&dump.to_string()),
+        Err(e) => doc.push_str(&format!("failed to decode example: {e}\n")),
+    }
+    doc
+}
+
+// 解析内存中编码好的记录
+fn dump_encoded(encoded: &[u8]) -> Result<RecordDump> {
+    let io_manager = Box::new(BytesIO(encoded.to_vec()));
+    let data_file = DataFile::with_io_manager(PathBuf::new(), 0, io_manager);
+    dump_record(&data_file, 0)
+}
+
+// 只读的内存数据，用于解析示例记录
+struct BytesIO(Vec<u8>);
+
+impl IOManager for BytesIO {
+    fn read(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        let data = self.0.get(offset as usize..).unwrap_or_default();
+        let n = std::cmp::min(buf.len(), data.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    fn write(&self, _buf: &[u8]) -> Result<usize> {
+        Err(Errors::FailedToWriteToDataFile(
+            std::io::ErrorKind::Unsupported.into(),
+        ))
+    }
+
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_write_off(&self, _offset: u64) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{data::data_file::get_data_file_name, db::Engine, options::Options};
+
+    use super::*;
+
+    #[test]
+    fn test_dump_data_file() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-dump");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        engine
+            .put(Bytes::from("name"), Bytes::from("bitcask-rs"))
+            .unwrap();
+        engine
+            .put_with_meta(Bytes::from("a"), Bytes::from("1"), Bytes::from("v1"))
+            .unwrap();
+        engine.delete(Bytes::from("name")).unwrap();
+        std::mem::drop(engine);
+
+        let dump = dump_data_file(&opts.dir_path, 0).unwrap();
+        assert_eq!(dump.error, None);
+        let types: Vec<_> = dump.records.iter().map(|r| r.rec_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                LogRecordType::PADDING,
+                LogRecordType::NORMAL,
+                LogRecordType::NORMAL,
+                LogRecordType::DELETED
+            ]
+        );
+        assert!(dump.records.iter().all(|r| r.crc_ok));
+        let size = fs::metadata(get_data_file_name(&opts.dir_path, 0))
+            .unwrap()
+            .len();
+        assert_eq!(dump.end_offset, size);
+
+        // 字段首尾相连，覆盖整条记录
+        for record in dump.records.iter() {
+            let mut offset = record.offset;
+            for field in record.fields.iter() {
+                assert_eq!(field.offset, offset);
+                offset += field.bytes.len() as u64;
+            }
+            assert_eq!(offset, record.offset + record.size as u64);
+        }
+        let names: Vec<_> = dump.records[2].fields.iter().map(|f| f.name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "type",
+                "key size",
+                "value size",
+                "meta size",
+                "meta",
+                "seq no",
+                "key",
+                "value",
+                "crc"
+            ]
+        );
+        assert_eq!(dump.records[2].fields[6].desc, "\"a\"");
+        assert!(dump.to_string().contains("end of records"));
+
+        // 损坏的记录可以被显示出来
+        let path = get_data_file_name(&opts.dir_path, 0);
+        let mut content = fs::read(&path).unwrap();
+        let value = &dump.records[1].fields[5];
+        assert_eq!(value.name, "value");
+        content[value.offset as usize] ^= 0xff;
+        fs::write(&path, &content).unwrap();
+        let dump = dump_data_file(&opts.dir_path, 0).unwrap();
+        assert!(!dump.records[1].crc_ok);
+        assert!(dump.records[1].to_string().contains("MISMATCH"));
+        assert_eq!(dump.records.len(), 4);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_format_doc() {
+        let doc = format_doc();
+        assert!(doc.contains("value size"));
+        assert!(doc.contains("5 CHUNKED"));
+        assert!(doc
+            .lines()
+            .any(|line| line.contains("crc") && line.ends_with(" ok")));
+        assert!(doc.contains("NORMAL | META"));
+        assert!(!doc.contains("failed to decode example"));
+    }
+}
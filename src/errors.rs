@@ -1,22 +1,30 @@
-use std::result;
+use std::{error::Error, fmt, io, result};
 
 use thiserror::Error;
 
+use crate::metadata::MetadataMismatch;
+
 pub type Result<T> = result::Result<T, Errors>;
 
 #[derive(Error, Debug, PartialEq, Eq)]
 pub enum Errors {
     #[error("Failed to read from data file!")]
-    FailedToReadFromDataFile,
+    FailedToReadFromDataFile(#[source] IoError),
 
     #[error("Failed to write to data file!")]
-    FailedToWriteToDataFile,
+    FailedToWriteToDataFile(#[source] IoError),
 
     #[error("Failed to sync file!")]
-    FailedToSyncFile,
+    FailedToSyncFile(#[source] IoError),
+
+    #[error("Failed to sync dir!")]
+    FailedToSyncDir(#[source] IoError),
 
     #[error("Failed to open data file!")]
-    FailedToOpenDataFile,
+    FailedToOpenDataFile(#[source] IoError),
+
+    #[error("Failed to open audit log")]
+    FailedToOpenAuditLog(#[source] IoError),
 
     #[error("Empty key!")]
     KeyIsEmpty,
@@ -37,10 +45,10 @@ pub enum Errors {
     DataFileSizeTooSmall,
 
     #[error("Failed to create the databse directory")]
-    FailedToCreateDatabaseDir,
+    FailedToCreateDatabaseDir(#[source] IoError),
 
     #[error("Failed to read databse directory")]
-    FailedToReadDatabaseDir,
+    FailedToReadDatabaseDir(#[source] IoError),
 
     #[error("The databse directory maybe corrupted!")]
     DataDirectoryCorrupted,
@@ -52,5 +60,369 @@ pub enum Errors {
     InvalidLogRecordCrc,
 
     #[error("Exceed the max batch num")]
-    ExceddMaxBatchNum,
+    ExceedMaxBatchNum,
+
+    #[error("Exceed the max batch bytes")]
+    ExceedMaxBatchBytes,
+
+    #[error("Prefix quota exceeded")]
+    QuotaExceeded,
+
+    #[error("The target directory is not empty")]
+    TargetDirNotEmpty,
+
+    #[error("Failed to copy data file")]
+    FailedToCopyDataFile(#[source] IoError),
+
+    #[error("Record metadata can not be longer than 255 bytes")]
+    MetaTooLarge,
+
+    #[error("Key is rejected by the key validator")]
+    InvalidKey,
+
+    #[error("Failed to spawn background task")]
+    FailedToSpawnBackgroundTask(#[source] IoError),
+
+    #[error("Background tasks did not stop in time: {0:?}")]
+    BackgroundTasksStuck(Vec<String>),
+
+    #[error("Record alignment must be a power of two between 8 and data file size")]
+    InvalidRecordAlignment,
+
+    #[error("Rotate interval must be positive and stale ratio must be in (0, 1]")]
+    InvalidRotateOptions,
+
+    #[error("Merge ratio must be in (0, 1]")]
+    InvalidMergeRatio,
+
+    #[error("Merge rate limit must be positive")]
+    InvalidMergeRateLimit,
+
+    #[error("Sync interval must be positive")]
+    InvalidSyncInterval,
+
+    #[error("Value chunk size must be positive")]
+    InvalidValueChunkSize,
+
+    #[error("Options::custom_index must be set when index type is Custom")]
+    CustomIndexNotSet,
+
+    #[error(
+        "Bloom filter expected keys must be positive and false positive rate must be in (0, 1)"
+    )]
+    InvalidBloomFilterOptions,
+
+    #[error("The engine is opened in follower mode and is read only")]
+    ReadOnlyEngine,
+
+    #[error("Failed to watch database dir")]
+    FailedToWatchDatabaseDir(#[source] IoError),
+
+    #[error("Transaction seq no overflow")]
+    SeqNoOverflow,
+
+    #[error("Invalid seq no file")]
+    InvalidSeqNoFile,
+
+    #[error("Seq no {0} is beyond the latest seq no of the database")]
+    SeqNoOutOfRange(u64),
+
+    #[error("Key uses the prefix reserved for internal data")]
+    ReservedKeyPrefix,
+
+    #[error("Request id is empty")]
+    RequestIdIsEmpty,
+
+    #[error("Invalid manifest file")]
+    InvalidManifestFile,
+
+    #[error("Metadata files do not match data files: {0:?}")]
+    MetadataMismatch(Vec<MetadataMismatch>),
+
+    #[error("Data file is corrupted")]
+    DataFileCorrupted,
+
+    #[error("Index points to invalid data at file {file_id} offset {offset}")]
+    IndexDataMismatch { file_id: u32, offset: u64 },
+
+    #[error("Value is not a valid JSON document")]
+    InvalidJson,
+
+    #[error("Invalid JSON pointer or the pointer does not match the document")]
+    InvalidJsonPath,
+
+    #[error("JSON pointer is not found in the document")]
+    JsonPathNotFound,
+
+    #[error("Value is not a valid lease")]
+    InvalidLease,
+
+    #[error("Lease has been acquired by another holder")]
+    LeaseLost,
+
+    #[error("Operation trace sample rate must be between 0 and 1")]
+    InvalidOpTraceSampleRate,
+
+    #[error("Invalid database id file")]
+    InvalidDbIdFile,
+
+    #[error("Data file {0} belongs to a different database")]
+    ForeignDataFile(u32),
+
+    #[error("Option {0} can not be changed without closing the database")]
+    ImmutableOption(&'static str),
+
+    #[error("Background thread nice value must be in [-20, 19] and cpus must be valid")]
+    InvalidBackgroundPriority,
+
+    #[error("File mode and dir mode must be valid and allow the owner to access the files")]
+    InvalidFileMode,
+
+    #[error("Default ttl must be positive")]
+    InvalidDefaultTtl,
+
+    #[error("Memtable bytes must be positive and can not be used with value chunks")]
+    InvalidMemtableBytes,
+
+    #[error("Load threads must be positive")]
+    InvalidLoadThreads,
+
+    #[error("Failed to punch holes in data file")]
+    FailedToPunchHole(#[source] IoError),
+
+    #[error("Can not punch holes while iterators are in use")]
+    IteratorsInUse,
+
+    #[error("Invalid bulk import record: {0}")]
+    InvalidBulkRecord(&'static str),
+
+    #[error("Value checksum mismatch, expected {expected:#010x} but got {actual:#010x}")]
+    ValueChecksumMismatch { expected: u32, actual: u32 },
+
+    #[error("Operation timed out before the deadline")]
+    Timeout,
+}
+
+/// IO 类错误底层的 io::Error，可以通过 Errors::source 或者 Errors::io_error 获取
+/// 比较时只比较 io::ErrorKind，便于按照错误的类型判断是否重试
+/// 显示和 source 都直接透传 io::Error，错误链中不会重复出现同一条信息
+#[derive(Debug)]
+pub struct IoError(io::Error);
+
+impl IoError {
+    pub fn kind(&self) -> io::ErrorKind {
+        self.0.kind()
+    }
+
+    pub fn get_ref(&self) -> &io::Error {
+        &self.0
+    }
+}
+
+impl From<io::Error> for IoError {
+    fn from(e: io::Error) -> Self {
+        Self(e)
+    }
+}
+
+// 没有对应的 io::Error 时按照错误类型构造，例如数据文件不存在
+impl From<io::ErrorKind> for IoError {
+    fn from(kind: io::ErrorKind) -> Self {
+        Self(kind.into())
+    }
+}
+
+impl PartialEq for IoError {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind() == other.kind()
+    }
+}
+
+impl Eq for IoError {}
+
+impl fmt::Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for IoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.source()
+    }
+}
+
+// 数据文件中出现不符合格式的内容时调用，返回对应的错误
+// 启用 strict-invariants 特性时直接 panic，便于在测试环境中尽早发现问题
+pub(crate) fn invariant_violation(e: Errors, context: std::fmt::Arguments) -> Errors {
+    #[cfg(feature = "strict-invariants")]
+    panic!("{context}: {e}");
+    #[cfg(not(feature = "strict-invariants"))]
+    {
+        log::error!("{context}: {e}");
+        e
+    }
+}
+
+/// 错误的类别，用于决定重试、告警等处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    // 文件系统操作失败，通常可以重试
+    Io,
+
+    // 磁盘上的数据或者内存索引损坏，需要告警和人工处理
+    Corruption,
+
+    // 配置项不合法，修改配置之前重试不会成功
+    Config,
+
+    // 调用方式不正确或者数据不满足条件，例如 key 为空、key 不存在
+    Usage,
+
+    // 操作在截止时间之前没有完成，可以放宽期限后重试
+    Timeout,
+
+    // 后台任务没有按时停止，可能仍在访问数据目录
+    Background,
+}
+
+impl Errors {
+    /// 错误所属的类别
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Errors::FailedToReadFromDataFile(_)
+            | Errors::FailedToWriteToDataFile(_)
+            | Errors::FailedToSyncFile(_)
+            | Errors::FailedToSyncDir(_)
+            | Errors::FailedToOpenDataFile(_)
+            | Errors::FailedToOpenAuditLog(_)
+            | Errors::FailedToCreateDatabaseDir(_)
+            | Errors::FailedToReadDatabaseDir(_)
+            | Errors::FailedToCopyDataFile(_)
+            | Errors::FailedToSpawnBackgroundTask(_)
+            | Errors::FailedToWatchDatabaseDir(_)
+            | Errors::FailedToPunchHole(_) => ErrorCategory::Io,
+
+            Errors::Timeout => ErrorCategory::Timeout,
+
+            Errors::BackgroundTasksStuck(_) => ErrorCategory::Background,
+
+            Errors::IndexUpdateFailed
+            | Errors::DataFileNotFound
+            | Errors::DataDirectoryCorrupted
+            | Errors::ReadDataFileEOF
+            | Errors::InvalidLogRecordCrc
+            | Errors::InvalidSeqNoFile
+            | Errors::InvalidManifestFile
+            | Errors::MetadataMismatch(_)
+            | Errors::DataFileCorrupted
+            | Errors::IndexDataMismatch { .. }
+            | Errors::InvalidDbIdFile
+            | Errors::ForeignDataFile(_) => ErrorCategory::Corruption,
+
+            Errors::DirPathIsEmpty
+            | Errors::DataFileSizeTooSmall
+            | Errors::InvalidRecordAlignment
+            | Errors::InvalidRotateOptions
+            | Errors::InvalidMergeRatio
+            | Errors::InvalidMergeRateLimit
+            | Errors::InvalidSyncInterval
+            | Errors::InvalidValueChunkSize
+            | Errors::CustomIndexNotSet
+            | Errors::InvalidBloomFilterOptions
+            | Errors::InvalidOpTraceSampleRate
+            | Errors::ImmutableOption(_)
+            | Errors::InvalidBackgroundPriority
+            | Errors::InvalidFileMode
+            | Errors::InvalidDefaultTtl
+            | Errors::InvalidMemtableBytes
+            | Errors::InvalidLoadThreads => ErrorCategory::Config,
+
+            Errors::KeyIsEmpty
+            | Errors::KeyNotFound
+            | Errors::ExceedMaxBatchNum
+            | Errors::ExceedMaxBatchBytes
+            | Errors::QuotaExceeded
+            | Errors::TargetDirNotEmpty
+            | Errors::MetaTooLarge
+            | Errors::InvalidKey
+            | Errors::ReadOnlyEngine
+            | Errors::SeqNoOverflow
+            | Errors::SeqNoOutOfRange(_)
+            | Errors::ReservedKeyPrefix
+            | Errors::RequestIdIsEmpty
+            | Errors::InvalidJson
+            | Errors::InvalidJsonPath
+            | Errors::JsonPathNotFound
+            | Errors::InvalidLease
+            | Errors::LeaseLost
+            | Errors::IteratorsInUse
+            | Errors::InvalidBulkRecord(_)
+            | Errors::ValueChecksumMismatch { .. } => ErrorCategory::Usage,
+        }
+    }
+
+    /// IO 类错误底层的 io::Error，其他错误返回 None
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            Errors::FailedToReadFromDataFile(e)
+            | Errors::FailedToWriteToDataFile(e)
+            | Errors::FailedToSyncFile(e)
+            | Errors::FailedToSyncDir(e)
+            | Errors::FailedToOpenDataFile(e)
+            | Errors::FailedToOpenAuditLog(e)
+            | Errors::FailedToCreateDatabaseDir(e)
+            | Errors::FailedToReadDatabaseDir(e)
+            | Errors::FailedToCopyDataFile(e)
+            | Errors::FailedToSpawnBackgroundTask(e)
+            | Errors::FailedToWatchDatabaseDir(e)
+            | Errors::FailedToPunchHole(e) => Some(e.get_ref()),
+            _ => None,
+        }
+    }
+
+    #[deprecated(note = "use Errors::ExceedMaxBatchNum")]
+    #[allow(non_upper_case_globals)]
+    pub const ExceddMaxBatchNum: Errors = Errors::ExceedMaxBatchNum;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_category() {
+        let e = Errors::FailedToSyncDir(io::Error::other("disk failure").into());
+        assert_eq!(e.category(), ErrorCategory::Io);
+        // source 直接是底层的 io::Error，io::Error 的信息在错误链中只出现一次
+        let io_err = e.source().unwrap();
+        assert_eq!(io_err.to_string(), "disk failure");
+        assert!(io_err.source().is_none());
+        assert_eq!(e.io_error().unwrap().kind(), io::ErrorKind::Other);
+        assert_eq!(e, Errors::FailedToSyncDir(io::ErrorKind::Other.into()));
+        assert_ne!(e, Errors::FailedToSyncDir(io::ErrorKind::NotFound.into()));
+        assert!(Errors::KeyNotFound.source().is_none());
+        assert_eq!(
+            Errors::InvalidLogRecordCrc.category(),
+            ErrorCategory::Corruption
+        );
+        assert_eq!(
+            Errors::IndexDataMismatch {
+                file_id: 1,
+                offset: 0
+            }
+            .category(),
+            ErrorCategory::Corruption
+        );
+        assert_eq!(
+            Errors::DataFileSizeTooSmall.category(),
+            ErrorCategory::Config
+        );
+        assert_eq!(Errors::KeyNotFound.category(), ErrorCategory::Usage);
+        assert_eq!(Errors::Timeout.category(), ErrorCategory::Timeout);
+        assert_eq!(
+            Errors::BackgroundTasksStuck(vec!["merge".to_string()]).category(),
+            ErrorCategory::Background
+        );
+    }
 }
@@ -0,0 +1,225 @@
+use std::{
+    collections::hash_map::RandomState,
+    fs,
+    hash::{BuildHasher, Hasher},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{error, warn};
+
+use crate::{
+    data::{
+        data_file::DataFile,
+        log_record::{LogRecord, LogRecordType},
+    },
+    db::Engine,
+    errors::{Errors, Result},
+    utils::atomic_write,
+};
+
+/// 保存数据库 id 的文件，内容为 16 字节的随机 id
+pub const DB_ID_FILE_NAME: &str = "db-id";
+
+// 文件头填充记录的 value 以此开头，之后是数据库 id
+const FILE_HEADER_MAGIC: &[u8; 8] = b"kvdbid\x00\x01";
+
+// 文件头的长度：type + key size + value size + value + crc
+pub(crate) const FILE_HEADER_SIZE: u64 = 3 + (FILE_HEADER_MAGIC.len() + DB_ID_SIZE) as u64 + 4;
+
+const DB_ID_SIZE: usize = 16;
+
+/// 数据库 id，创建数据库时随机生成
+pub type DbId = [u8; DB_ID_SIZE];
+
+impl Engine {
+    /// 数据库 id，写入 db-id 文件以及每个数据文件的文件头
+    pub fn db_id(&self) -> DbId {
+        self.db_id
+    }
+}
+
+// 数据文件的文件头，使用填充记录保存，读取数据时和其他填充记录一样被跳过
+pub(crate) fn file_header(db_id: &DbId) -> Vec<u8> {
+    let mut value = FILE_HEADER_MAGIC.to_vec();
+    value.extend_from_slice(db_id);
+    LogRecord {
+        key: Default::default(),
+        value,
+        rec_type: LogRecordType::PADDING,
+        meta: Default::default(),
+    }
+    .encode()
+    .into_vec()
+}
+
+// 创建新的数据文件并写入文件头
+pub(crate) fn init_data_file(data_file: &DataFile, db_id: &DbId) -> Result<()> {
+    data_file.write(&file_header(db_id))?;
+    data_file.sync()
+}
+
+// 读取数据文件头中的数据库 id，之前的版本创建的文件没有文件头，返回 None
+pub(crate) fn read_file_header(data_file: &DataFile) -> Result<Option<DbId>> {
+    let record = match data_file.read_log_record(0) {
+        Ok(read) => read.record,
+        Err(Errors::ReadDataFileEOF) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if record.rec_type != LogRecordType::PADDING || !record.value.starts_with(FILE_HEADER_MAGIC) {
+        return Ok(None);
+    }
+    match DbId::try_from(&record.value[FILE_HEADER_MAGIC.len()..]) {
+        Ok(db_id) => Ok(Some(db_id)),
+        Err(_) => Err(Errors::DataFileCorrupted),
+    }
+}
+
+// 确定数据库 id 并检查所有的数据文件都属于这个数据库
+// 没有 db-id 文件时沿用数据文件头中的 id（升级之前的版本或者 db-id 文件丢失），都没有时生成新的 id
+pub(crate) fn check_data_files<'a>(
+    dir_path: &Path,
+    data_files: impl IntoIterator<Item = &'a DataFile>,
+    allow_foreign: bool,
+    file_mode: u32,
+) -> Result<DbId> {
+    let mut db_id = read_db_id(dir_path)?;
+    let persisted = db_id.is_some();
+    for data_file in data_files {
+        let file_db_id = match read_file_header(data_file)? {
+            Some(file_db_id) => file_db_id,
+            None => continue,
+        };
+        match db_id {
+            None => db_id = Some(file_db_id),
+            Some(id) if id == file_db_id => {}
+            Some(_) if allow_foreign => {
+                warn!(
+                    "Data file {} belongs to a different database",
+                    data_file.get_file_id()
+                );
+            }
+            Some(_) => return Err(Errors::ForeignDataFile(data_file.get_file_id())),
+        }
+    }
+
+    let db_id = db_id.unwrap_or_else(new_db_id);
+    if !persisted {
+        write_db_id(dir_path, &db_id, file_mode)?;
+    }
+    Ok(db_id)
+}
+
+pub(crate) fn read_db_id(dir_path: &Path) -> Result<Option<DbId>> {
+    let path = dir_path.join(DB_ID_FILE_NAME);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let buf = match fs::read(&path) {
+        Ok(buf) => buf,
+        Err(e) => {
+            error!("Failed to read db id file: {e}");
+            return Err(Errors::FailedToReadFromDataFile(e.into()));
+        }
+    };
+    match DbId::try_from(buf.as_slice()) {
+        Ok(db_id) => Ok(Some(db_id)),
+        Err(_) => Err(Errors::InvalidDbIdFile),
+    }
+}
+
+// 原子地写入，崩溃时不会留下写到一半的 id
+pub(crate) fn write_db_id(dir_path: &Path, db_id: &DbId, mode: u32) -> Result<()> {
+    atomic_write(&dir_path.join(DB_ID_FILE_NAME), db_id, mode)
+}
+
+// 使用随机的哈希种子、当前时间和进程 id 生成随机 id
+fn new_db_id() -> DbId {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    let mut db_id = [0; DB_ID_SIZE];
+    for (i, part) in db_id.chunks_mut(8).enumerate() {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(nanos);
+        hasher.write_u32(std::process::id());
+        hasher.write_usize(i);
+        part.copy_from_slice(&hasher.finish().to_be_bytes());
+    }
+    db_id
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use crate::{data::data_file::get_data_file_name, options::Options};
+
+    use super::*;
+
+    #[test]
+    fn test_file_header_size() {
+        assert_eq!(file_header(&new_db_id()).len() as u64, FILE_HEADER_SIZE);
+        assert_ne!(new_db_id(), new_db_id());
+    }
+
+    #[test]
+    fn test_reject_foreign_data_file() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-fence");
+        let mut other_opts = Options::default();
+        other_opts.dir_path = PathBuf::from("/tmp/bitcask-rs-fence-other");
+
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        engine.put(Bytes::from("a"), Bytes::from("1")).unwrap();
+        let db_id = engine.db_id();
+        engine.close().expect("failed to close engine");
+        std::mem::drop(engine);
+
+        // 重新打开同一个数据库，id 不变
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(engine.db_id(), db_id);
+        std::mem::drop(engine);
+
+        let other = Engine::open(other_opts.clone()).expect("failed to open engine");
+        other.put(Bytes::from("b"), Bytes::from("2")).unwrap();
+        assert_ne!(other.db_id(), db_id);
+        other.close().expect("failed to close engine");
+        std::mem::drop(other);
+
+        // 从其他数据库复制过来的文件
+        fs::copy(
+            get_data_file_name(&other_opts.dir_path, 0),
+            get_data_file_name(&opts.dir_path, 1),
+        )
+        .unwrap();
+        assert_eq!(
+            Engine::open(opts.clone()).err().unwrap(),
+            Errors::ForeignDataFile(1)
+        );
+
+        // 强制打开
+        opts.allow_foreign_data_files = true;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(engine.db_id(), db_id);
+        assert_eq!(engine.get(Bytes::from("a")).unwrap(), Bytes::from("1"));
+        assert_eq!(engine.get(Bytes::from("b")).unwrap(), Bytes::from("2"));
+        std::mem::drop(engine);
+
+        // db-id 文件丢失时沿用数据文件头中的 id
+        fs::remove_file(other_opts.dir_path.join(DB_ID_FILE_NAME)).unwrap();
+        let other = Engine::open(other_opts.clone()).expect("failed to open engine");
+        assert_eq!(
+            read_db_id(&other_opts.dir_path).unwrap(),
+            Some(other.db_id())
+        );
+        assert_ne!(other.db_id(), db_id);
+        std::mem::drop(other);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(other_opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,130 @@
+//! 所有 IOManager 实现都需要通过的测试，新增 IO 类型时在 fio::tests 中调用 check_io_manager 即可
+
+use std::{fs, path::PathBuf, sync::Arc, thread};
+
+use crate::errors::Result;
+
+use super::IOManager;
+
+type NewIOManager<'a> = &'a dyn Fn(&PathBuf) -> Result<Box<dyn IOManager>>;
+
+// 依次运行所有检查，name 用于区分不同实现的测试文件，new 打开或者创建给定路径的文件
+pub(crate) fn check_io_manager<F>(name: &str, new: F)
+where
+    F: Fn(&PathBuf) -> Result<Box<dyn IOManager>>,
+{
+    let run = |check: &str, f: fn(&PathBuf, NewIOManager)| {
+        let path = PathBuf::from(format!("/tmp/bitcask-rs-fio-{name}-{check}.data"));
+        let _ = fs::remove_file(&path);
+        f(&path, &new);
+        fs::remove_file(&path).expect("failed to remove file");
+    };
+    run("read-write", check_read_write);
+    run("short-read", check_short_read);
+    run("concurrent-write", check_concurrent_write);
+    run("reopen", check_reopen);
+    run("set-write-off", check_set_write_off);
+}
+
+fn read(io: &dyn IOManager, offset: u64, len: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; len];
+    let n = io.read(&mut buf, offset).unwrap();
+    buf.truncate(n);
+    buf
+}
+
+fn check_read_write(path: &PathBuf, new: NewIOManager) {
+    let io = new(path).unwrap();
+    assert_eq!(io.write(b"Hello World").unwrap(), 11);
+    assert_eq!(io.write(b"").unwrap(), 0);
+    assert_eq!(io.write(b"Hello KV-store").unwrap(), 14);
+
+    assert_eq!(read(io.as_ref(), 0, 11), b"Hello World");
+    assert_eq!(read(io.as_ref(), 11, 14), b"Hello KV-store");
+    assert_eq!(read(io.as_ref(), 6, 11), b"WorldHello ");
+
+    // 跨越多个 4KB 的块读写
+    let data: Vec<u8> = (0..10000).map(|i| (i % 251) as u8).collect();
+    assert_eq!(io.write(&data).unwrap(), data.len());
+    assert_eq!(read(io.as_ref(), 25, data.len()), data);
+    assert_eq!(read(io.as_ref(), 25 + 4090, 10), data[4090..4100]);
+    assert!(io.sync().is_ok());
+}
+
+fn check_short_read(path: &PathBuf, new: NewIOManager) {
+    let io = new(path).unwrap();
+    // 空文件读取不到数据
+    assert_eq!(io.read(&mut [0u8; 8], 0).unwrap(), 0);
+
+    io.write(b"Hello World").unwrap();
+    // 超过文件末尾的部分不会被读取，也不会修改 buf
+    let mut buf = [0xAAu8; 8];
+    assert_eq!(io.read(&mut buf, 6).unwrap(), 5);
+    assert_eq!(&buf[..5], b"World");
+    assert_eq!(&buf[5..], [0xAA; 3]);
+
+    assert_eq!(io.read(&mut [0u8; 8], 11).unwrap(), 0);
+    assert_eq!(io.read(&mut [0u8; 8], 4096).unwrap(), 0);
+}
+
+fn check_concurrent_write(path: &PathBuf, new: NewIOManager) {
+    const WRITERS: usize = 8;
+    const WRITES: usize = 200;
+    const RECORD: usize = 37;
+
+    let io: Arc<dyn IOManager> = Arc::from(new(path).unwrap());
+    let handles: Vec<_> = (0..WRITERS)
+        .map(|i| {
+            let io = io.clone();
+            thread::spawn(move || {
+                for _ in 0..WRITES {
+                    assert_eq!(io.write(&[i as u8; RECORD]).unwrap(), RECORD);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // 每次写入都是完整的，不会和其他线程的数据交错
+    let data = read(io.as_ref(), 0, WRITERS * WRITES * RECORD + 1);
+    assert_eq!(data.len(), WRITERS * WRITES * RECORD);
+    let mut counts = [0; WRITERS];
+    for record in data.chunks(RECORD) {
+        assert!(record.iter().all(|b| *b == record[0]));
+        counts[record[0] as usize] += 1;
+    }
+    assert_eq!(counts, [WRITES; WRITERS]);
+}
+
+fn check_reopen(path: &PathBuf, new: NewIOManager) {
+    let io = new(path).unwrap();
+    io.write(b"Hello World").unwrap();
+    assert!(io.sync().is_ok());
+    std::mem::drop(io);
+
+    // 重新打开之后设置写入位置，继续追加
+    let io = new(path).unwrap();
+    assert_eq!(read(io.as_ref(), 0, 11), b"Hello World");
+    io.set_write_off(11).unwrap();
+    io.write(b"!").unwrap();
+    assert!(io.sync().is_ok());
+    assert_eq!(read(io.as_ref(), 0, 100), b"Hello World!");
+}
+
+fn check_set_write_off(path: &PathBuf, new: NewIOManager) {
+    let io = new(path).unwrap();
+    io.write(b"Hello World").unwrap();
+
+    // 之后的数据被丢弃，从新的位置继续写入
+    io.set_write_off(5).unwrap();
+    assert_eq!(read(io.as_ref(), 0, 100), b"Hello");
+    io.write(b", KV").unwrap();
+    assert_eq!(read(io.as_ref(), 0, 100), b"Hello, KV");
+
+    io.set_write_off(0).unwrap();
+    assert_eq!(io.read(&mut [0u8; 8], 0).unwrap(), 0);
+    io.write(b"abc").unwrap();
+    assert_eq!(read(io.as_ref(), 0, 100), b"abc");
+}
@@ -0,0 +1,239 @@
+use std::{
+    fs::{File, OpenOptions},
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use log::error;
+use parking_lot::Mutex;
+
+use crate::errors::{Errors, Result};
+
+use super::IOManager;
+
+/// O_DIRECT 要求读写的偏移、长度和内存地址按块对齐
+pub(crate) const DIRECT_IO_BLOCK_SIZE: usize = 4096;
+
+/// DirectIO 使用 O_DIRECT 打开文件，绕过操作系统的页缓存
+/// 写入时最后一个不完整的块会补零写入磁盘，并在内存中保留，下次写入时重新写这个块
+pub struct DirectIO {
+    fd: File,
+    // 文件的逻辑长度，不包含补齐的零，读取时不需要加锁
+    len: AtomicU64,
+    // 最后一个不完整块中的数据，写入时加锁
+    tail: Mutex<Vec<u8>>,
+}
+
+impl DirectIO {
+    pub fn new(file_name: &PathBuf) -> Result<Self> {
+        let fd = match OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_DIRECT)
+            .open(file_name)
+        {
+            Ok(file) => file,
+            Err(e) => {
+                error!("Failed to open file with O_DIRECT: {e}");
+                return Err(Errors::FailedToOpenDataFile(e.into()));
+            }
+        };
+        let len = match fd.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(e) => {
+                error!("Failed to get file metadata: {e}");
+                return Err(Errors::FailedToOpenDataFile(e.into()));
+            }
+        };
+
+        let direct_io = Self {
+            fd,
+            len: AtomicU64::new(0),
+            tail: Mutex::new(Vec::new()),
+        };
+        direct_io.set_write_off(len)?;
+        Ok(direct_io)
+    }
+
+    // 读取 [offset, offset + len) 所在的对齐区域，offset 必须是块对齐的
+    fn read_aligned(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let mut buf = AlignedBuf::new(len);
+        let mut read = 0;
+        while read < len {
+            match self
+                .fd
+                .read_at(&mut buf.as_mut()[read..], offset + read as u64)
+            {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) => {
+                    error!("read from data file err: {}", e);
+                    return Err(Errors::FailedToReadFromDataFile(e.into()));
+                }
+            }
+            // O_DIRECT 下不完整的块只会出现在文件末尾
+            if read % DIRECT_IO_BLOCK_SIZE != 0 {
+                break;
+            }
+        }
+        Ok(buf.as_mut()[..read].to_vec())
+    }
+}
+
+impl IOManager for DirectIO {
+    fn read(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        let len = self.len.load(Ordering::Acquire);
+        if offset >= len {
+            return Ok(0);
+        }
+        let n = std::cmp::min(buf.len() as u64, len - offset) as usize;
+
+        let start = align_down(offset);
+        let end = align_up(offset + n as u64);
+        let data = self.read_aligned(start, (end - start) as usize)?;
+        let skip = (offset - start) as usize;
+        let n = std::cmp::min(n, data.len().saturating_sub(skip));
+        buf[..n].copy_from_slice(&data[skip..skip + n]);
+        Ok(n)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        let mut tail = self.tail.lock();
+        let len = self.len.load(Ordering::Acquire);
+        let block_start = len - tail.len() as u64;
+
+        let mut data = std::mem::take(&mut *tail);
+        data.extend_from_slice(buf);
+        let padded_len = align_up(data.len() as u64) as usize;
+        let mut aligned = AlignedBuf::new(padded_len);
+        aligned.as_mut()[..data.len()].copy_from_slice(&data);
+
+        if let Err(e) = self.fd.write_all_at(aligned.as_mut(), block_start) {
+            error!("Write to file err: {e}");
+            *tail = data[..data.len() - buf.len()].to_vec();
+            return Err(Errors::FailedToWriteToDataFile(e.into()));
+        }
+
+        self.len.store(len + buf.len() as u64, Ordering::Release);
+        let full_blocks = align_down(data.len() as u64) as usize;
+        data.drain(..full_blocks);
+        *tail = data;
+        Ok(buf.len())
+    }
+
+    fn sync(&self) -> Result<()> {
+        if let Err(e) = self.fd.sync_all() {
+            error!("Failed to sync data file: {}", e);
+            return Err(Errors::FailedToSyncFile(e.into()));
+        }
+        Ok(())
+    }
+
+    fn set_write_off(&self, offset: u64) -> Result<()> {
+        let mut tail = self.tail.lock();
+        let block_start = align_down(offset);
+        let tail_len = (offset - block_start) as usize;
+        let block = match tail_len {
+            0 => Vec::new(),
+            _ => self.read_aligned(block_start, DIRECT_IO_BLOCK_SIZE)?,
+        };
+        if block.len() < tail_len {
+            error!("Data file is shorter than the write offset {offset}");
+            return Err(Errors::FailedToReadFromDataFile(
+                std::io::ErrorKind::UnexpectedEof.into(),
+            ));
+        }
+        self.len.store(offset, Ordering::Release);
+        *tail = block[..tail_len].to_vec();
+        Ok(())
+    }
+}
+
+// 按块对齐的内存缓冲区
+struct AlignedBuf {
+    buf: Vec<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl AlignedBuf {
+    fn new(len: usize) -> Self {
+        let buf = vec![0u8; len + DIRECT_IO_BLOCK_SIZE];
+        let start = buf.as_ptr().align_offset(DIRECT_IO_BLOCK_SIZE);
+        Self { buf, start, len }
+    }
+
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.buf[self.start..self.start + self.len]
+    }
+}
+
+fn align_down(offset: u64) -> u64 {
+    offset / DIRECT_IO_BLOCK_SIZE as u64 * DIRECT_IO_BLOCK_SIZE as u64
+}
+
+fn align_up(offset: u64) -> u64 {
+    align_down(offset + DIRECT_IO_BLOCK_SIZE as u64 - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_direct_io_write_read() {
+        let path = PathBuf::from("/tmp/direct-io-a.data");
+        let _ = fs::remove_file(&path);
+        let fio = DirectIO::new(&path).unwrap();
+
+        let res1 = fio.write("Hello World".as_bytes());
+        assert_eq!(res1.ok().unwrap(), 11);
+        let data = vec![7u8; 5000];
+        let res2 = fio.write(&data);
+        assert_eq!(res2.ok().unwrap(), 5000);
+
+        let mut buf = [0u8; 11];
+        let res3 = fio.read(&mut buf, 0);
+        assert_eq!(res3.ok().unwrap(), 11);
+        assert_eq!(&buf, "Hello World".as_bytes());
+
+        // 跨越块边界读取
+        let mut buf = vec![0u8; 5000];
+        let res4 = fio.read(&mut buf, 11);
+        assert_eq!(res4.ok().unwrap(), 5000);
+        assert_eq!(buf, data);
+
+        // 读取到文件末尾
+        let mut buf = vec![0u8; 100];
+        let res5 = fio.read(&mut buf, 5000);
+        assert_eq!(res5.ok().unwrap(), 11);
+
+        assert!(fio.sync().is_ok());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_direct_io_reopen() {
+        let path = PathBuf::from("/tmp/direct-io-b.data");
+        let _ = fs::remove_file(&path);
+        let fio = DirectIO::new(&path).unwrap();
+        fio.write("Hello World".as_bytes()).unwrap();
+        std::mem::drop(fio);
+
+        // 文件被补齐到块大小，重新设置写入位置之后继续追加
+        let fio = DirectIO::new(&path).unwrap();
+        assert!(fio.set_write_off(11).is_ok());
+        fio.write(" Direct IO".as_bytes()).unwrap();
+
+        let mut buf = [0u8; 21];
+        let res = fio.read(&mut buf, 0);
+        assert_eq!(res.ok().unwrap(), 21);
+        assert_eq!(&buf, "Hello World Direct IO".as_bytes());
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -3,20 +3,21 @@ use std::{
     io::Write,
     os::unix::fs::FileExt,
     path::PathBuf,
-    sync::Arc,
 };
 
 use log::error;
-use parking_lot::RwLock;
 
 use crate::errors::{Errors, Result};
 
 use super::IOManager;
 
 /// FileIO 标准系统文件IO
+/// 读取使用 pread，写入以追加模式进行，都不需要加锁
 pub struct FileIO {
     /// 系统文件描述符
-    fd: Arc<RwLock<File>>,
+    fd: File,
+    /// 只读打开，不写入也不截断文件
+    read_only: bool,
 }
 
 impl FileIO {
@@ -48,14 +49,29 @@ impl FileIO {
             .append(true)
             .open(file_name)
         {
-            Ok(file) => {
-                return Ok(Self {
-                    fd: Arc::new(RwLock::new(file)),
-                })
+            Ok(file) => Ok(Self {
+                fd: file,
+                read_only: false,
+            }),
+            Err(e) => {
+                error!("Failed to open file: {e}");
+                Err(Errors::FailedToOpenDataFile(e.into()))
             }
+        }
+    }
+}
+
+impl FileIO {
+    // 以只读方式打开已经存在的文件，用于读取其他进程正在写入的数据文件
+    pub fn open_read_only(file_name: &PathBuf) -> Result<Self> {
+        match OpenOptions::new().read(true).open(file_name) {
+            Ok(file) => Ok(Self {
+                fd: file,
+                read_only: true,
+            }),
             Err(e) => {
                 error!("Failed to open file: {e}");
-                return Err(Errors::FailedToOpenDataFile);
+                Err(Errors::FailedToOpenDataFile(e.into()))
             }
         }
     }
@@ -63,34 +79,52 @@ impl FileIO {
 
 impl IOManager for FileIO {
     fn read(&self, buf: &mut [u8], offset: u64) -> crate::errors::Result<usize> {
-        let read_guard = self.fd.read();
-        match read_guard.read_at(buf, offset) {
-            Ok(n) => return Ok(n),
+        match self.fd.read_at(buf, offset) {
+            Ok(n) => Ok(n),
             Err(e) => {
                 error!("read from data file err: {}", e);
-                return Err(Errors::FailedToOpenDataFile);
+                Err(Errors::FailedToOpenDataFile(e.into()))
             }
-        };
+        }
     }
 
     fn sync(&self) -> crate::errors::Result<()> {
         // self.fd.
-        let read_guard = self.fd.read();
-        if let Err(e) = read_guard.sync_all() {
+        if let Err(e) = self.fd.sync_all() {
             error!("Failed to sync data file: {}", e);
-            return Err(Errors::FailedToSyncFile);
+            return Err(Errors::FailedToSyncFile(e.into()));
         }
 
         Ok(())
     }
 
+    fn set_write_off(&self, offset: u64) -> crate::errors::Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        // 文件以追加模式打开，末尾多余的数据（例如 DirectIO 补齐的零）需要截断
+        let len = match self.fd.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(e) => {
+                error!("Failed to get file metadata: {e}");
+                return Err(Errors::FailedToReadFromDataFile(e.into()));
+            }
+        };
+        if len > offset {
+            if let Err(e) = self.fd.set_len(offset) {
+                error!("Failed to truncate data file: {e}");
+                return Err(Errors::FailedToWriteToDataFile(e.into()));
+            }
+        }
+        Ok(())
+    }
+
     fn write(&self, buf: &[u8]) -> crate::errors::Result<usize> {
-        let mut write_guard = self.fd.write();
-        match write_guard.write(buf) {
-            Ok(n) => return Ok(n),
+        match (&self.fd).write(buf) {
+            Ok(n) => Ok(n),
             Err(e) => {
                 error!("Write to file err: {e}");
-                return Err(Errors::FailedToReadFromDataFile);
+                Err(Errors::FailedToReadFromDataFile(e.into()))
             }
         }
     }
@@ -1,10 +1,25 @@
+#[cfg(test)]
+mod conformance;
+#[cfg(target_os = "linux")]
+mod direct_io;
 mod file_io;
+#[cfg(test)]
+pub(crate) mod torn;
 
-use std::path::PathBuf;
+use std::{
+    fs::{DirBuilder, File, OpenOptions},
+    io,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+};
 
 use file_io::FileIO;
+use log::{error, warn};
 
-use crate::errors::Result;
+use crate::{
+    errors::{Errors, Result},
+    options::IOType,
+};
 
 pub trait IOManager: Sync + Send {
     // 从文件给定位置读取数据
@@ -15,9 +30,111 @@ pub trait IOManager: Sync + Send {
 
     /// 持久化数据
     fn sync(&self) -> Result<()>;
+
+    /// 设置下一次写入的位置，之后的数据都会被丢弃
+    fn set_write_off(&self, offset: u64) -> Result<()>;
+}
+
+// 以只读方式打开已经存在的文件
+pub fn new_read_only_io_manager(file_name: &PathBuf) -> Result<Box<dyn IOManager>> {
+    Ok(Box::new(FileIO::open_read_only(file_name)?))
 }
 
 // 根据文件名称初始化 IOManger
-pub fn new_io_manager(file_name: &PathBuf) -> Result<impl IOManager> {
-    FileIO::new(file_name)
+// 不支持 DirectIO 的平台或者文件系统会退化为标准文件IO
+pub fn new_io_manager(file_name: &PathBuf, io_type: IOType) -> Result<Box<dyn IOManager>> {
+    let io_manager = open_io_manager(file_name, io_type)?;
+    // 测试中注册了断电模拟的目录
+    #[cfg(test)]
+    let io_manager = torn::wrap(file_name, io_manager);
+    Ok(io_manager)
+}
+
+fn open_io_manager(file_name: &PathBuf, io_type: IOType) -> Result<Box<dyn IOManager>> {
+    match io_type {
+        IOType::StandardFIO => Ok(Box::new(FileIO::new(file_name)?)),
+        IOType::DirectIO => {
+            #[cfg(target_os = "linux")]
+            match direct_io::DirectIO::new(file_name) {
+                Ok(direct_io) => return Ok(Box::new(direct_io)),
+                Err(e) => warn!("DirectIO is not supported, fall back to standard file io: {e}"),
+            }
+            #[cfg(not(target_os = "linux"))]
+            warn!("DirectIO is not supported on this platform, fall back to standard file io");
+            Ok(Box::new(FileIO::new(file_name)?))
+        }
+    }
+}
+
+/// 以 mode 权限创建文件，文件已经存在时截断，实际的权限还会受到 umask 的限制
+pub fn create_file(path: &Path, mode: u32) -> io::Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(mode)
+        .open(path)
+}
+
+/// 以 mode 权限创建目录，recursive 为 true 时同时创建不存在的父目录
+pub fn create_dir(path: &Path, mode: u32, recursive: bool) -> io::Result<()> {
+    DirBuilder::new()
+        .recursive(recursive)
+        .mode(mode)
+        .create(path)
+}
+
+/// 持久化目录，使目录中文件的创建、删除和重命名在崩溃之后仍然有效
+pub fn sync_dir(dir_path: &Path) -> Result<()> {
+    match File::open(dir_path).and_then(|dir| dir.sync_all()) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            error!("Failed to sync dir {:?}: {e}", dir_path);
+            Err(Errors::FailedToSyncDir(e.into()))
+        }
+    }
+}
+
+/// 持久化文件或者目录所在的目录，用于创建目录或者删除文件之后
+pub fn sync_parent_dir(path: &Path) -> Result<()> {
+    match path.parent() {
+        Some(parent) if parent.as_os_str().is_empty() => sync_dir(Path::new(".")),
+        Some(parent) => sync_dir(parent),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_dir() {
+        let dir_path = std::env::temp_dir().join("bitcask-rs-sync-dir");
+        std::fs::create_dir_all(&dir_path).unwrap();
+        assert!(sync_dir(&dir_path).is_ok());
+        assert!(sync_parent_dir(&dir_path.join("a.data")).is_ok());
+        assert!(sync_parent_dir(Path::new("a.data")).is_ok());
+        assert!(sync_parent_dir(Path::new("/")).is_ok());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(&dir_path).expect("failed to remove path");
+        assert_eq!(
+            sync_dir(&dir_path).err().unwrap(),
+            Errors::FailedToSyncDir(std::io::ErrorKind::NotFound.into())
+        );
+    }
+
+    #[test]
+    fn test_file_io_conformance() {
+        conformance::check_io_manager("file-io", |path| new_io_manager(path, IOType::StandardFIO));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_direct_io_conformance() {
+        conformance::check_io_manager("direct-io", |path| {
+            Ok(Box::new(direct_io::DirectIO::new(path)?))
+        });
+    }
 }
@@ -0,0 +1,211 @@
+//! 测试使用的断电模拟：注册目录中打开的文件由 TornWriteIO 包装，记录每个文件已经持久化的长度
+//! crash 时按照随机的方式丢弃没有持久化的数据：只保留随机长度的前缀，前缀中没有持久化的部分还可能有块没有写入，
+//! 模拟页缓存乱序写回；crash 之前打开的文件之后的写入都会被忽略，模拟进程已经退出
+
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use parking_lot::{const_mutex, Mutex};
+
+use crate::errors::Result;
+
+use super::IOManager;
+
+// 模拟乱序写回时的块大小
+const TORN_BLOCK_SIZE: u64 = 512;
+
+static SIMULATORS: Mutex<Vec<Arc<CrashSimulator>>> = const_mutex(Vec::new());
+
+pub(crate) struct CrashSimulator {
+    dir_path: PathBuf,
+    // 每次 crash 之后递增，之前打开的文件不再写入
+    epoch: AtomicU64,
+    rng: Mutex<u64>,
+    // 文件已经持久化的长度，文件关闭之后仍然保留，crash 时同样会丢弃没有持久化的数据
+    durable: Mutex<HashMap<PathBuf, u64>>,
+}
+
+impl CrashSimulator {
+    // 之后在 dir_path 中打开的文件都会被包装，seed 决定每次 crash 丢弃的数据
+    pub(crate) fn install(dir_path: &Path, seed: u64) -> Arc<Self> {
+        let sim = Arc::new(Self {
+            dir_path: dir_path.to_path_buf(),
+            epoch: AtomicU64::new(0),
+            rng: Mutex::new(seed | 1),
+            durable: Mutex::new(HashMap::new()),
+        });
+        let mut simulators = SIMULATORS.lock();
+        simulators.retain(|s| s.dir_path != dir_path);
+        simulators.push(sim.clone());
+        sim
+    }
+
+    pub(crate) fn uninstall(&self) {
+        SIMULATORS.lock().retain(|s| s.dir_path != self.dir_path);
+    }
+
+    // 模拟断电，返回丢弃的字节数
+    pub(crate) fn crash(&self) -> u64 {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        let mut durable = self.durable.lock();
+        let mut dropped = 0;
+        for (path, durable_len) in durable.iter_mut() {
+            let file = match OpenOptions::new().write(true).open(path) {
+                Ok(file) => file,
+                // 已经删除或者重命名的文件
+                Err(_) => continue,
+            };
+            let len = file.metadata().unwrap().len();
+            if len <= *durable_len {
+                *durable_len = len;
+                continue;
+            }
+            let keep = *durable_len + self.next_u64() % (len - *durable_len + 1);
+            file.set_len(keep).unwrap();
+            // 保留的部分中随机有一个块没有写回
+            let blocks = (keep - *durable_len).div_ceil(TORN_BLOCK_SIZE);
+            if blocks > 0 && self.next_u64().is_multiple_of(2) {
+                let start = *durable_len + self.next_u64() % blocks * TORN_BLOCK_SIZE;
+                let end = (start + TORN_BLOCK_SIZE).min(keep);
+                file.write_all_at(&vec![0u8; (end - start) as usize], start)
+                    .unwrap();
+            }
+            file.sync_all().unwrap();
+            dropped += len - keep;
+            *durable_len = keep;
+        }
+        dropped
+    }
+
+    fn next_u64(&self) -> u64 {
+        // xorshift64
+        let mut rng = self.rng.lock();
+        *rng ^= *rng << 13;
+        *rng ^= *rng >> 7;
+        *rng ^= *rng << 17;
+        *rng
+    }
+}
+
+// 文件所在的目录注册了断电模拟时包装 io_manager
+pub(crate) fn wrap(path: &Path, io_manager: Box<dyn IOManager>) -> Box<dyn IOManager> {
+    let sim = match SIMULATORS
+        .lock()
+        .iter()
+        .find(|s| path.parent() == Some(s.dir_path.as_path()))
+    {
+        Some(sim) => sim.clone(),
+        None => return io_manager,
+    };
+    let len = std::fs::metadata(path).map_or(0, |m| m.len());
+    // 同一个文件再次打开时沿用之前记录的长度，没有持久化的数据仍然可能丢失
+    sim.durable.lock().entry(path.to_path_buf()).or_insert(len);
+    Box::new(TornWriteIO {
+        inner: io_manager,
+        path: path.to_path_buf(),
+        epoch: sim.epoch.load(Ordering::SeqCst),
+        sim,
+    })
+}
+
+// 写入直接写到文件（相当于页缓存），sync 之后才记录为已经持久化
+pub(crate) struct TornWriteIO {
+    inner: Box<dyn IOManager>,
+    path: PathBuf,
+    epoch: u64,
+    sim: Arc<CrashSimulator>,
+}
+
+impl TornWriteIO {
+    fn crashed(&self) -> bool {
+        self.sim.epoch.load(Ordering::SeqCst) != self.epoch
+    }
+}
+
+impl IOManager for TornWriteIO {
+    fn read(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        self.inner.read(buf, offset)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        if self.crashed() {
+            return Ok(buf.len());
+        }
+        self.inner.write(buf)
+    }
+
+    fn sync(&self) -> Result<()> {
+        if self.crashed() {
+            return Ok(());
+        }
+        // 持有锁，避免与 crash 同时进行
+        let mut durable = self.sim.durable.lock();
+        self.inner.sync()?;
+        let len = std::fs::metadata(&self.path).map_or(0, |m| m.len());
+        durable.insert(self.path.clone(), len);
+        Ok(())
+    }
+
+    fn set_write_off(&self, offset: u64) -> Result<()> {
+        if self.crashed() {
+            return Ok(());
+        }
+        self.inner.set_write_off(offset)?;
+        // 截断之后的长度不一定已经持久化，按照较短的长度计算
+        if let Some(len) = self.sim.durable.lock().get_mut(&self.path) {
+            *len = (*len).min(offset);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{fio::new_io_manager, options::IOType};
+
+    use super::*;
+
+    #[test]
+    fn test_torn_write_io() {
+        let dir_path = PathBuf::from("/tmp/bitcask-rs-torn-write");
+        fs::create_dir_all(&dir_path).unwrap();
+        let path = dir_path.join("a.data");
+        let sim = CrashSimulator::install(&dir_path, 42);
+
+        let io = new_io_manager(&path, IOType::StandardFIO).unwrap();
+        io.write(&[1u8; 1000]).unwrap();
+        io.sync().unwrap();
+        io.write(&[2u8; 3000]).unwrap();
+        let dropped = sim.crash();
+
+        // 持久化的数据保留，没有持久化的数据只保留前缀，crash 之后的写入被忽略
+        let content = fs::read(&path).unwrap();
+        assert_eq!(content.len() as u64 + dropped, 4000);
+        assert!(content[..1000].iter().all(|b| *b == 1));
+        assert!(content[1000..].iter().all(|b| *b == 2 || *b == 0));
+        io.write(&[3u8; 10]).unwrap();
+        io.sync().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), content);
+
+        // 重新打开之后正常写入
+        let io = new_io_manager(&path, IOType::StandardFIO).unwrap();
+        io.write(&[4u8; 10]).unwrap();
+        io.sync().unwrap();
+        assert_eq!(sim.crash(), 0);
+        assert_eq!(fs::read(&path).unwrap().len(), content.len() + 10);
+        sim.uninstall();
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(&dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,508 @@
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use log::error;
+use parking_lot::Mutex;
+
+#[cfg(feature = "watch")]
+mod watch;
+
+use crate::{
+    conflict::ReplicaConflicts,
+    data::data_file::DataFile,
+    db::{check_options, data_file_ids, DataFiles, Engine, IndexReplayer},
+    errors::{Errors, Result},
+    index::Indexer,
+    manifest::Manifest,
+    options::Options,
+    seq::SeqAllocator,
+    utils::sharded_lock::ShardedLock,
+};
+
+// 跟随者模式下的状态
+// 跟随者只读取其他进程正在写入的数据目录，不会创建、写入或者截断任何文件
+// 回放时已经有同一个 key 的数据，由 Options::conflict_resolver 决定收到的记录是否生效
+pub(crate) struct Follower {
+    dir_path: PathBuf,
+    files: Arc<ShardedLock<DataFiles>>,
+    index: Arc<dyn Indexer>,
+    seq: Arc<SeqAllocator>,
+    // 回放进度，活跃文件的读取位置保存在活跃文件的 write_off 中
+    replayer: Mutex<IndexReplayer>,
+}
+
+impl Follower {
+    // 读取上次之后新写入的数据并更新索引，返回读取的数据量
+    fn catch_up(&self) -> Result<u64> {
+        let mut replayer = self.replayer.lock();
+        let mut active_file = self.files.read().active.clone();
+
+        // 先列出数据文件，再读取当前的活跃文件
+        // 写入方创建新文件之前已经持久化了旧的活跃文件，存在更新的文件时当前文件一定是完整的
+        let new_file_ids = data_file_ids(&self.dir_path)?
+            .into_iter()
+            .filter(|id| *id > active_file.get_file_id())
+            .collect::<Vec<_>>();
+
+        let start = active_file.get_write_off();
+        let offset = replayer.replay(
+            self.index.as_ref(),
+            &active_file,
+            start,
+            new_file_ids.is_empty(),
+        )?;
+        active_file.set_write_off(offset)?;
+        if !new_file_ids.is_empty() {
+            active_file.seal();
+        }
+        let mut read_bytes = offset - start;
+
+        for (i, file_id) in new_file_ids.iter().enumerate() {
+            let data_file = Arc::new(DataFile::open_read_only(self.dir_path.clone(), *file_id)?);
+            // 先加入到文件集合中再更新索引，保证索引中的位置都能读取到
+            self.files.update(|files| {
+                let mut older = files.older.clone();
+                older.insert(active_file.get_file_id(), files.active.clone());
+                DataFiles {
+                    active: data_file.clone(),
+                    older,
+                    active_since: Instant::now(),
+                }
+            });
+            let is_last = i == new_file_ids.len() - 1;
+            let offset = replayer.replay(self.index.as_ref(), &data_file, 0, is_last)?;
+            data_file.set_write_off(offset)?;
+            if !is_last {
+                data_file.seal();
+            }
+            read_bytes += offset;
+            active_file = data_file;
+        }
+
+        self.seq.observe(replayer.current_seq_no);
+        Ok(read_bytes)
+    }
+}
+
+impl Engine {
+    /// 以只读的跟随者模式打开另一个进程正在写入的数据目录，作为本地的只读副本
+    /// 跟随者按照 follower_poll_interval 定期读取新封存的文件和活跃文件中新写入的数据，
+    /// 增量更新内存索引，所有写入操作都会返回 Errors::ReadOnlyEngine
+    /// 启用 watch 特性时通过 notify 监听数据目录，新数据写入后立即更新
+    pub fn open_follower(opts: Options) -> Result<Self> {
+        if let Some(e) = check_options(&opts) {
+            return Err(e);
+        }
+        let dir_path = opts.dir_path.clone();
+        if !dir_path.is_dir() {
+            return Err(Errors::FailedToReadDatabaseDir(
+                std::io::ErrorKind::NotFound.into(),
+            ));
+        }
+
+        // 从最旧的数据文件开始，之后的文件在 catch_up 中依次打开
+        let first_file_id = match data_file_ids(&dir_path)?.first() {
+            Some(file_id) => *file_id,
+            None => return Err(Errors::DataFileNotFound),
+        };
+        let active_file = DataFile::open_read_only(dir_path.clone(), first_file_id)?;
+
+        let poll_interval = opts.follower_poll_interval;
+        let mut engine = Engine::with_files(
+            opts,
+            DataFiles {
+                active: Arc::new(active_file),
+                older: Default::default(),
+                active_since: Instant::now(),
+            },
+            Vec::new(),
+        );
+        engine.manifest = Arc::new(Manifest::load(&dir_path)?);
+        let mut replayer = IndexReplayer::default();
+        replayer.conflicts = Some(ReplicaConflicts::new(&engine.options, engine.files.clone()));
+        let follower = Arc::new(Follower {
+            dir_path,
+            files: engine.files.clone(),
+            index: engine.index.clone(),
+            seq: engine.seq.clone(),
+            replayer: Mutex::new(replayer),
+        });
+        follower.catch_up()?;
+        engine.follower = Some(follower);
+        engine.read_only = true;
+        engine.load_replayed_state()?;
+
+        if let Some(interval) = poll_interval {
+            engine.spawn_follower_task(interval)?;
+        }
+        Ok(engine)
+    }
+
+    /// 跟随者模式下立即读取新写入的数据并更新索引，返回读取的数据量
+    /// 读取到新数据时重新加载过期时间，非跟随者模式下直接返回 0
+    pub fn catch_up(&self) -> Result<u64> {
+        let follower = match &self.follower {
+            Some(follower) => follower,
+            None => return Ok(0),
+        };
+        let read_bytes = follower.catch_up()?;
+        if read_bytes > 0 {
+            self.load_replayed_state()?;
+        }
+        Ok(read_bytes)
+    }
+
+    /// 是否以跟随者模式打开
+    pub fn is_follower(&self) -> bool {
+        self.follower.is_some()
+    }
+
+    /// 是否是只读的，跟随者和历史版本都是只读的
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    #[cfg(not(feature = "watch"))]
+    fn spawn_follower_task(&self, interval: Duration) -> Result<()> {
+        self.spawn_follower_poll_task(interval)
+    }
+
+    // 监听数据目录的变化，有新数据写入或者新建文件时立即更新
+    // 同时仍然按照 interval 定期检查，避免遗漏事件，无法监听时退化为定时轮询
+    #[cfg(feature = "watch")]
+    fn spawn_follower_task(&self, interval: Duration) -> Result<()> {
+        // 检查停止信号的间隔
+        const WATCH_TICK: Duration = Duration::from_millis(50);
+
+        let watcher = match watch::DirWatcher::new(&self.options.dir_path) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                log::warn!("Failed to watch database dir, fall back to polling: {e}");
+                return self.spawn_follower_poll_task(interval);
+            }
+        };
+        let weak = self.downgrade();
+        self.background.spawn("follower", move |signal| {
+            let mut last_catch_up = Instant::now();
+            while !signal.is_shutdown() {
+                let changed = watcher.wait(std::cmp::min(WATCH_TICK, interval));
+                if changed || last_catch_up.elapsed() >= interval {
+                    last_catch_up = Instant::now();
+                    let engine = match weak.upgrade() {
+                        Some(engine) => engine,
+                        None => break,
+                    };
+                    if let Err(e) = engine.catch_up() {
+                        error!("Follower failed to catch up: {e}");
+                    }
+                }
+            }
+        })
+    }
+
+    fn spawn_follower_poll_task(&self, interval: Duration) -> Result<()> {
+        let weak = self.downgrade();
+        self.background.spawn("follower", move |signal| {
+            while !signal.wait_timeout(interval) {
+                let engine = match weak.upgrade() {
+                    Some(engine) => engine,
+                    None => break,
+                };
+                if let Err(e) = engine.catch_up() {
+                    error!("Follower failed to catch up: {e}");
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, io::Write};
+
+    use bytes::Bytes;
+
+    use crate::{
+        clock::ManualClock,
+        conflict::{ConflictResolver, RecordVersion},
+        data::{
+            data_file::get_data_file_name,
+            log_record::{LogRecord, LogRecordType},
+        },
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_follower_catch_up() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-follower");
+        opts.data_file_size = 4 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..10 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+
+        let mut follower_opts = opts.clone();
+        follower_opts.follower_poll_interval = None;
+        let follower = Engine::open_follower(follower_opts).expect("failed to open follower");
+        assert!(follower.is_follower());
+        assert_eq!(follower.list_keys().unwrap().len(), 10);
+        assert_eq!(follower.get(get_test_key(9)).unwrap(), get_test_value(9));
+
+        // 写入方继续写入并切换文件，跟随者读取新的数据
+        for i in 10..300 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+        let res = engine.delete(get_test_key(0));
+        assert!(res.is_ok());
+        let wb = engine.new_write_batch(Default::default()).unwrap();
+        wb.put(get_test_key(1000), get_test_value(1000)).unwrap();
+        wb.commit().unwrap();
+
+        assert!(follower.get(get_test_key(299)).is_err());
+        assert!(follower.catch_up().unwrap() > 0);
+        assert_eq!(follower.list_keys().unwrap().len(), 300);
+        assert!(follower.get(get_test_key(0)).is_err());
+        assert_eq!(
+            follower.get(get_test_key(299)).unwrap(),
+            get_test_value(299)
+        );
+        assert!(follower.get(get_test_key(1000)).is_ok());
+        assert_eq!(
+            follower.stat().unwrap().data_file_num,
+            engine.stat().unwrap().data_file_num
+        );
+        // 没有新数据
+        assert_eq!(follower.catch_up().unwrap(), 0);
+
+        // 跟随者不能写入
+        let res = follower.put(get_test_key(1), get_test_value(1));
+        assert_eq!(res.err().unwrap(), Errors::ReadOnlyEngine);
+        let res = follower.delete(get_test_key(1));
+        assert_eq!(res.err().unwrap(), Errors::ReadOnlyEngine);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    // 不接受删除，其他记录按照写入顺序生效
+    struct IgnoreDeletes;
+
+    impl ConflictResolver for IgnoreDeletes {
+        fn accept(&self, _key: &[u8], _local: &RecordVersion, incoming: &RecordVersion) -> bool {
+            !incoming.deleted
+        }
+    }
+
+    #[test]
+    fn test_follower_conflict_resolver() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-follower-conflict");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        engine.put(get_test_key(1), get_test_value(1)).unwrap();
+        engine.put(get_test_key(2), get_test_value(2)).unwrap();
+
+        let mut follower_opts = opts.clone();
+        follower_opts.follower_poll_interval = None;
+        follower_opts.conflict_resolver = Some(Arc::new(IgnoreDeletes));
+        let follower = Engine::open_follower(follower_opts).expect("failed to open follower");
+
+        // 本地已有的 key 不接受删除，覆盖写入和事务中的写入仍然生效
+        engine.delete(get_test_key(1)).unwrap();
+        engine.put(get_test_key(2), get_test_value(20)).unwrap();
+        let wb = engine.new_write_batch(Default::default()).unwrap();
+        wb.put(get_test_key(3), get_test_value(3)).unwrap();
+        wb.delete(get_test_key(2)).unwrap();
+        wb.commit().unwrap();
+        assert!(follower.catch_up().unwrap() > 0);
+        assert_eq!(follower.get(get_test_key(1)).unwrap(), get_test_value(1));
+        assert_eq!(follower.get(get_test_key(2)).unwrap(), get_test_value(20));
+        assert_eq!(follower.get(get_test_key(3)).unwrap(), get_test_value(3));
+
+        // 默认后写入的记录生效，与写入方一致
+        let mut default_opts = opts.clone();
+        default_opts.follower_poll_interval = None;
+        let follower = Engine::open_follower(default_opts).expect("failed to open follower");
+        assert!(follower.get(get_test_key(1)).is_err());
+        assert!(follower.get(get_test_key(2)).is_err());
+        assert_eq!(follower.get(get_test_key(3)).unwrap(), get_test_value(3));
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_follower_key_ttl() {
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-follower-key-ttl");
+        opts.default_ttl = Some(Duration::from_secs(60));
+        opts.value_chunk_size = Some(1024);
+        opts.clock = Some(clock.clone());
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        engine.put(get_test_key(1), get_test_value(1)).unwrap();
+        let big = Bytes::from(vec![7u8; 5000]);
+        engine.put(get_test_key(2), big.clone()).unwrap();
+
+        let mut follower_opts = opts.clone();
+        follower_opts.follower_poll_interval = None;
+        let follower = Engine::open_follower(follower_opts).expect("failed to open follower");
+        assert!(follower.has_chunks());
+        assert_eq!(follower.get(get_test_key(2)).unwrap(), big);
+        assert_eq!(
+            follower.ttl(&get_test_key(1)),
+            Some(Duration::from_secs(60))
+        );
+
+        // 跟随者读取新写入的过期时间
+        clock.advance(Duration::from_secs(30));
+        engine.put(get_test_key(3), get_test_value(3)).unwrap();
+        assert!(follower.catch_up().unwrap() > 0);
+        clock.advance(Duration::from_secs(31));
+        assert_eq!(
+            follower.get(get_test_key(1)).err().unwrap(),
+            Errors::KeyNotFound
+        );
+        assert_eq!(
+            follower.get(get_test_key(2)).err().unwrap(),
+            Errors::KeyNotFound
+        );
+        assert_eq!(follower.get(get_test_key(3)).unwrap(), get_test_value(3));
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(
+            follower.get(get_test_key(3)).err().unwrap(),
+            Errors::KeyNotFound
+        );
+        engine.close().expect("failed to close");
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_follower_prefix_ttl() {
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-follower-prefix-ttl");
+        opts.clock = Some(clock.clone());
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        engine
+            .put(Bytes::from("session:1"), get_test_value(1))
+            .unwrap();
+        engine
+            .put(Bytes::from("session:2"), get_test_value(2))
+            .unwrap();
+        engine
+            .put(Bytes::from("user:1"), get_test_value(3))
+            .unwrap();
+        engine
+            .expire_prefix(Bytes::from("session:"), Duration::from_secs(60))
+            .unwrap();
+
+        let mut follower_opts = opts.clone();
+        follower_opts.follower_poll_interval = None;
+        let follower = Engine::open_follower(follower_opts).expect("failed to open follower");
+        assert_eq!(
+            follower.prefix_ttl(b"session:"),
+            Some(Duration::from_secs(60))
+        );
+
+        // catch_up 读取新设置的前缀过期时间
+        engine
+            .expire_prefix(Bytes::from("user:"), Duration::from_secs(120))
+            .unwrap();
+        assert!(follower.catch_up().unwrap() > 0);
+        clock.advance(Duration::from_secs(61));
+        assert_eq!(
+            follower.get(Bytes::from("session:1")).err().unwrap(),
+            Errors::KeyNotFound
+        );
+        assert!(follower.get(Bytes::from("session:2")).is_err());
+        assert_eq!(
+            follower.get(Bytes::from("user:1")).unwrap(),
+            get_test_value(3)
+        );
+        clock.advance(Duration::from_secs(60));
+        assert!(follower.get(Bytes::from("user:1")).is_err());
+        engine.close().expect("failed to close");
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[cfg(feature = "watch")]
+    #[test]
+    fn test_follower_watch() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-follower-watch");
+        opts.data_file_size = 4 * 1024;
+        opts.follower_poll_interval = Some(Duration::from_secs(60));
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let follower = Engine::open_follower(opts.clone()).expect("failed to open follower");
+
+        // 轮询间隔很长，数据通过目录监听及时同步
+        for i in 0..200 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+        std::thread::sleep(Duration::from_millis(300));
+        assert_eq!(follower.list_keys().unwrap().len(), 200);
+        assert!(follower.stat().unwrap().data_file_num > 1);
+        assert!(follower.close().is_ok());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_follower_torn_tail() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-follower-torn-tail");
+        opts.follower_poll_interval = Some(Duration::from_millis(20));
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let res = engine.put(get_test_key(1), get_test_value(1));
+        assert!(res.is_ok());
+        std::mem::drop(engine);
+
+        let follower = Engine::open_follower(opts.clone()).expect("failed to open follower");
+        assert_eq!(follower.background_tasks(), vec!["follower".to_string()]);
+
+        // 模拟写入方只写了一半的记录
+        let record = LogRecord {
+            key: crate::batch::log_record_key_with_seq(b"torn".to_vec(), 0),
+            value: b"value".to_vec(),
+            rec_type: LogRecordType::NORMAL,
+            meta: Default::default(),
+        };
+        let enc = record.encode();
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(get_data_file_name(&opts.dir_path, 0))
+            .unwrap();
+        file.write_all(&enc[..enc.len() / 2]).unwrap();
+        file.sync_all().unwrap();
+
+        assert_eq!(follower.catch_up().unwrap(), 0);
+        assert!(follower.get(Bytes::from("torn")).is_err());
+
+        // 写完剩余的部分之后，后台任务读取到完整的记录
+        file.write_all(&enc[enc.len() / 2..]).unwrap();
+        file.sync_all().unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(
+            follower.get(Bytes::from("torn")).unwrap(),
+            Bytes::from("value")
+        );
+        assert!(follower.close().is_ok());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
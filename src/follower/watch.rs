@@ -0,0 +1,67 @@
+use std::{
+    io,
+    path::Path,
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+
+use log::error;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::errors::{Errors, Result};
+
+/// 基于 notify 监听数据目录中的文件变化，各个平台使用系统提供的机制
+pub(crate) struct DirWatcher {
+    // 释放时停止监听
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl DirWatcher {
+    pub(crate) fn new(dir_path: &Path) -> Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(tx) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                error!("Failed to create dir watcher: {e}");
+                return Err(Errors::FailedToWatchDatabaseDir(to_io_error(e).into()));
+            }
+        };
+        if let Err(e) = watcher.watch(dir_path, RecursiveMode::NonRecursive) {
+            error!("Failed to watch database dir: {e}");
+            return Err(Errors::FailedToWatchDatabaseDir(to_io_error(e).into()));
+        }
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    // 等待目录中的文件发生变化，超时返回 false
+    // 返回之前会读取掉所有已经到达的事件，多次修改只会唤醒一次
+    pub(crate) fn wait(&self, timeout: Duration) -> bool {
+        let mut changed = match self.events.recv_timeout(timeout) {
+            Ok(event) => is_change(&event),
+            Err(_) => return false,
+        };
+        while let Ok(event) = self.events.try_recv() {
+            changed |= is_change(&event);
+        }
+        changed
+    }
+}
+
+// 文件内容被修改、新建文件、文件被移动到目录中，监听出错时也按照有变化处理
+fn is_change(event: &notify::Result<Event>) -> bool {
+    match event {
+        Ok(event) => matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)),
+        Err(_) => true,
+    }
+}
+
+fn to_io_error(e: notify::Error) -> io::Error {
+    match e.kind {
+        notify::ErrorKind::Io(e) => e,
+        _ => io::Error::other(e),
+    }
+}
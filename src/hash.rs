@@ -0,0 +1,115 @@
+//! key 的哈希函数，布隆过滤器、操作追踪中的 key 哈希以及按 key 分片的组件都通过 Options::key_hasher 使用同一个哈希函数
+//! 内置的实现按照公开的算法计算，结果不随版本变化，可以与外部的分片路由保持一致
+
+use std::{hash::Hasher, sync::Arc};
+
+use fnv::FnvHasher;
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+use crate::{db::Engine, options::Options};
+
+/// key 的哈希函数，同一个 key 必须总是得到相同的哈希值
+pub trait KeyHasher: Send + Sync {
+    fn hash(&self, key: &[u8]) -> u64;
+
+    /// key 所在的分片，默认为哈希值对分片数取余，shards 不能为 0
+    fn shard(&self, key: &[u8], shards: usize) -> usize {
+        (self.hash(key) % shards as u64) as usize
+    }
+}
+
+/// 64 位的 XXH3，默认使用的哈希函数，由 xxhash-rust 实现
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Xxh3 {
+    pub seed: u64,
+}
+
+impl Xxh3 {
+    pub fn with_seed(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+impl KeyHasher for Xxh3 {
+    fn hash(&self, key: &[u8]) -> u64 {
+        xxh3_64_with_seed(key, self.seed)
+    }
+}
+
+/// 64 位的 FNV-1a，适合与只支持 FNV 的外部路由配合使用，由 fnv 实现
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fnv1a;
+
+impl KeyHasher for Fnv1a {
+    fn hash(&self, key: &[u8]) -> u64 {
+        let mut hasher = FnvHasher::default();
+        hasher.write(key);
+        hasher.finish()
+    }
+}
+
+// 配置的哈希函数，没有配置时使用 Xxh3
+pub(crate) fn key_hasher(opts: &Options) -> Arc<dyn KeyHasher> {
+    opts.key_hasher
+        .clone()
+        .unwrap_or_else(|| Arc::new(Xxh3::default()))
+}
+
+impl Engine {
+    /// 使用 Options::key_hasher 计算 key 所在的分片，shards 不能为 0
+    pub fn key_shard(&self, key: &[u8], shards: usize) -> usize {
+        self.key_hasher.shard(key, shards)
+    }
+
+    // 使用配置的哈希函数计算 key 的哈希值
+    pub(crate) fn hash_key(&self, key: &[u8]) -> u64 {
+        self.key_hasher.hash(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::Errors;
+
+    use super::*;
+
+    #[test]
+    fn test_key_hashers() {
+        let xxh = Xxh3::default();
+        assert_eq!(xxh.hash(b""), 0x2D06_8005_38D3_94C2);
+        let long = (0..100u8).collect::<Vec<_>>();
+        assert_ne!(Xxh3::with_seed(1).hash(&long), xxh.hash(&long));
+
+        assert_eq!(Fnv1a.hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(Fnv1a.hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(Fnv1a.hash(b"foobar"), 0x8594_4171_f739_67e8);
+
+        for key in [&b"a"[..], b"abc", &long] {
+            assert!(xxh.shard(key, 7) < 7);
+            assert_eq!(Fnv1a.shard(key, 16), (Fnv1a.hash(key) % 16) as usize);
+        }
+    }
+
+    #[test]
+    fn test_engine_key_hasher() {
+        let mut opts = Options::default();
+        opts.dir_path = std::path::PathBuf::from("/tmp/bitcask-rs-key-hasher");
+        opts.key_hasher = Some(Arc::new(Fnv1a));
+        let mut engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for key in [&b"a"[..], b"user/1", b"user/2"] {
+            assert_eq!(engine.key_shard(key, 8), Fnv1a.shard(key, 8));
+        }
+
+        // 重新打开时不能修改哈希函数
+        let mut new_opts = opts.clone();
+        new_opts.key_hasher = None;
+        assert_eq!(
+            engine.reopen(new_opts).err().unwrap(),
+            Errors::ImmutableOption("key_hasher")
+        );
+        engine.close().expect("failed to close");
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,167 @@
+use std::{collections::HashMap, path::Path, sync::Arc, time::Instant};
+
+use crate::{
+    data::data_file::DataFile,
+    db::{check_options, data_file_ids, DataFiles, Engine, IndexReplayer},
+    errors::{Errors, Result},
+    index::new_engine_indexer,
+    options::Options,
+    seq::{read_seq_no, SEQ_NO_FILE_NAME},
+};
+
+impl Engine {
+    /// 以只读方式打开数据目录在事务序列号 seq_no 提交之后的历史版本
+    /// 按照写入顺序回放数据文件，读取到序列号大于 seq_no 的事务完成标识时停止，
+    /// 之后写入的所有数据都不可见，之前的非事务写入都可见
+    /// merge、compact 会把旧的数据重写到文件末尾，历史版本只对没有合并过的目录准确，
+    /// 通常在 fork_to 创建的检查点上使用
+    pub fn open_at(dir_path: impl AsRef<Path>, seq_no: u64) -> Result<Self> {
+        let opts = Options {
+            dir_path: dir_path.as_ref().to_path_buf(),
+            ..Default::default()
+        };
+        Self::open_at_with(opts, seq_no)
+    }
+
+    /// 同 open_at，使用指定的配置打开
+    pub fn open_at_with(opts: Options, seq_no: u64) -> Result<Self> {
+        if let Some(e) = check_options(&opts) {
+            return Err(e);
+        }
+        let dir_path = opts.dir_path.clone();
+        if !dir_path.is_dir() {
+            return Err(Errors::FailedToReadDatabaseDir(
+                std::io::ErrorKind::NotFound.into(),
+            ));
+        }
+
+        let file_ids = data_file_ids(&dir_path)?;
+        let mut data_files = Vec::with_capacity(file_ids.len());
+        for file_id in file_ids.iter() {
+            data_files.push(Arc::new(DataFile::open_read_only(
+                dir_path.clone(),
+                *file_id,
+            )?));
+        }
+
+        let mut replayer = IndexReplayer::default();
+        replayer.seq_limit = Some(seq_no);
+        let (index, bloom) = new_engine_indexer(&opts);
+        let mut visible = 0;
+        for data_file in data_files.iter() {
+            if replayer.reached_limit {
+                break;
+            }
+            let offset = replayer.replay(index.as_ref(), data_file, 0, false)?;
+            data_file.set_write_off(offset)?;
+            visible += 1;
+        }
+
+        // 检查点记录的序列号之后没有数据，不能打开
+        let latest = std::cmp::max(
+            replayer.current_seq_no,
+            read_seq_no(&dir_path.join(SEQ_NO_FILE_NAME))?,
+        );
+        if !replayer.reached_limit && seq_no > latest {
+            return Err(Errors::SeqNoOutOfRange(seq_no));
+        }
+
+        // 停止位置之后的文件不可见
+        data_files.truncate(visible);
+        let active = match data_files.pop() {
+            Some(data_file) => data_file,
+            None => return Err(Errors::DataFileNotFound),
+        };
+        let older = data_files
+            .into_iter()
+            .map(|f| (f.get_file_id(), f))
+            .collect::<HashMap<_, _>>();
+
+        let mut engine = Engine::with_files(
+            opts,
+            DataFiles {
+                active,
+                older,
+                active_since: Instant::now(),
+            },
+            Vec::new(),
+        );
+        engine.index = Arc::from(index);
+        engine.bloom = bloom;
+        engine
+            .seq
+            .observe(std::cmp::min(replayer.current_seq_no, seq_no));
+        engine.read_only = true;
+        engine.load_replayed_state()?;
+        Ok(engine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use crate::utils::rand_kv::{get_test_key, get_test_value};
+
+    use super::*;
+
+    #[test]
+    fn test_open_at() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-open-at");
+        opts.data_file_size = 4 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 每个事务之前写入一部分非事务数据，数据跨越多个文件
+        for seq in 1..=3 {
+            for i in 0..50 {
+                let res = engine.put(get_test_key(seq * 100 + i), get_test_value(i));
+                assert!(res.is_ok());
+            }
+            let wb = engine.new_write_batch(Default::default()).unwrap();
+            wb.put(Bytes::from("seq"), Bytes::from(seq.to_string()))
+                .unwrap();
+            wb.delete(get_test_key((seq - 1) * 100)).unwrap();
+            wb.commit().unwrap();
+        }
+        assert_eq!(engine.current_seq(), 3);
+
+        let checkpoint = PathBuf::from("/tmp/bitcask-rs-open-at-checkpoint");
+        engine.fork_to(&checkpoint).unwrap();
+
+        let history = Engine::open_at(&checkpoint, 1).expect("failed to open history");
+        assert!(history.is_read_only());
+        assert_eq!(history.current_seq(), 1);
+        assert_eq!(history.get(Bytes::from("seq")).unwrap(), Bytes::from("1"));
+        // 序列号 2 的事务之前的非事务写入可见，之后的不可见
+        assert!(history.get(get_test_key(249)).is_ok());
+        assert!(history.get(get_test_key(300)).is_err());
+        assert!(history.get(get_test_key(100)).is_ok());
+        assert_eq!(history.list_keys().unwrap().len(), 100 + 1);
+        let res = history.put(Bytes::from("seq"), Bytes::from("x"));
+        assert_eq!(res.err().unwrap(), Errors::ReadOnlyEngine);
+        assert!(history.stat().unwrap().data_file_num < engine.stat().unwrap().data_file_num);
+
+        // 0 只包含第一个事务之前的数据
+        let history = Engine::open_at(&checkpoint, 0).expect("failed to open history");
+        assert!(history.get(Bytes::from("seq")).is_err());
+        assert_eq!(history.list_keys().unwrap().len(), 50);
+
+        // 最新的版本与检查点一致
+        let history = Engine::open_at(&checkpoint, 3).expect("failed to open history");
+        assert_eq!(history.get(Bytes::from("seq")).unwrap(), Bytes::from("3"));
+        assert_eq!(
+            history.list_keys().unwrap().len(),
+            engine.list_keys().unwrap().len()
+        );
+
+        let res = Engine::open_at(&checkpoint, 4);
+        assert_eq!(res.err().unwrap(), Errors::SeqNoOutOfRange(4));
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(checkpoint).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,146 @@
+use bytes::Bytes;
+
+use crate::{
+    db::{Engine, INTERNAL_KEY_PREFIX},
+    errors::{Errors, Result},
+    options::{IteratorOptions, WriteBatchOptions},
+};
+
+// 请求 id 记录在内部前缀下，value 为过期时间（毫秒时间戳）
+const REQUEST_ID_PREFIX: &[u8] = b"request-id/";
+
+impl Engine {
+    /// 幂等写入，相同的 request_id 在 idempotency_ttl 内只会写入一次
+    /// 请求 id 和数据在同一个事务中提交，重启之后仍然有效
+    /// 返回 true 表示写入了数据，false 表示请求 id 已经存在，跳过了这次写入
+    pub fn put_idempotent(&self, request_id: Bytes, key: Bytes, value: Bytes) -> Result<bool> {
+        if request_id.is_empty() {
+            return Err(Errors::RequestIdIsEmpty);
+        }
+        self.check_key(&key)?;
+
+        let marker = request_id_key(&request_id);
+        let now = self.now_millis();
+        let _lock = self.idempotent_lock.lock();
+        if let Some(expire_at) = self.request_id_expire_at(&marker)? {
+            if expire_at > now {
+                return Ok(false);
+            }
+        }
+
+        let expire_at = now.saturating_add(self.options.idempotency_ttl.as_millis() as u64);
+        let wb = self.new_write_batch(WriteBatchOptions {
+            sync_writes: self.options.sync_write,
+            ..Default::default()
+        })?;
+        wb.put(key, value)?;
+        wb.put_unchecked(marker, Bytes::copy_from_slice(&expire_at.to_be_bytes()))?;
+        wb.commit()?;
+        Ok(true)
+    }
+
+    /// 清理已经过期的请求 id 记录，返回清理的数量
+    pub fn purge_expired_request_ids(&self) -> Result<usize> {
+        let now = self.now_millis();
+        let _lock = self.idempotent_lock.lock();
+        let mut expired = Vec::new();
+        let mut index_iter = self.index.iterator(IteratorOptions {
+            prefix: request_id_key(&[]).to_vec(),
+            ..Default::default()
+        });
+        while let Some((key, _)) = index_iter.next() {
+            if let Some(expire_at) = self.request_id_expire_at(&key)? {
+                if expire_at <= now {
+                    expired.push(key);
+                }
+            }
+        }
+
+        let max_batch_num = WriteBatchOptions::default().max_batch_num;
+        for chunk in expired.chunks(max_batch_num) {
+            let wb = self.new_write_batch(Default::default())?;
+            for key in chunk {
+                wb.delete_unchecked(key.clone())?;
+            }
+            wb.commit()?;
+        }
+        Ok(expired.len())
+    }
+
+    fn request_id_expire_at(&self, marker: &Bytes) -> Result<Option<u64>> {
+        match self.get(marker.clone()) {
+            Ok(value) => match <[u8; 8]>::try_from(value.as_ref()) {
+                Ok(bytes) => Ok(Some(u64::from_be_bytes(bytes))),
+                Err(_) => Ok(None),
+            },
+            Err(Errors::KeyNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn request_id_key(request_id: &[u8]) -> Bytes {
+    let mut key = Vec::with_capacity(INTERNAL_KEY_PREFIX.len() + REQUEST_ID_PREFIX.len());
+    key.extend_from_slice(INTERNAL_KEY_PREFIX);
+    key.extend_from_slice(REQUEST_ID_PREFIX);
+    key.extend_from_slice(request_id);
+    key.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use crate::{
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_put_idempotent() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-put-idempotent");
+        opts.idempotency_ttl = Duration::from_millis(200);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let id = Bytes::from("req-1");
+        let res = engine.put_idempotent(id.clone(), get_test_key(1), get_test_value(1));
+        assert!(res.unwrap());
+        // 重复的请求被跳过
+        let res = engine.put_idempotent(id.clone(), get_test_key(1), get_test_value(2));
+        assert!(!res.unwrap());
+        assert_eq!(engine.get(get_test_key(1)).unwrap(), get_test_value(1));
+        let res = engine.put_idempotent(Bytes::from("req-2"), get_test_key(2), get_test_value(2));
+        assert!(res.unwrap());
+
+        // 内部记录对用户不可见，也不能直接写入
+        assert_eq!(engine.list_keys().unwrap().len(), 2);
+        let iter = engine.iter(Default::default());
+        let mut count = 0;
+        while iter.next().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 2);
+        let res = engine.put(request_id_key(b"req-3"), get_test_value(3));
+        assert_eq!(res.err().unwrap(), Errors::ReservedKeyPrefix);
+
+        // 重启之后请求 id 仍然有效
+        engine.close().expect("failed to close engine");
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let res = engine.put_idempotent(id.clone(), get_test_key(1), get_test_value(2));
+        assert!(!res.unwrap());
+
+        // 过期之后可以再次写入
+        std::thread::sleep(Duration::from_millis(250));
+        assert_eq!(engine.purge_expired_request_ids().unwrap(), 2);
+        let res = engine.put_idempotent(id, get_test_key(1), get_test_value(2));
+        assert!(res.unwrap());
+        assert_eq!(engine.get(get_test_key(1)).unwrap(), get_test_value(2));
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    ops::Bound,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use bytes::Bytes;
+
+use super::{IndexStats, Indexer, IndexerIterator};
+use crate::{
+    data::log_record::LogRecordPos, errors::Result, hash::KeyHasher, options::BloomFilterOptions,
+    options::IteratorOptions,
+};
+
+/// 布隆过滤器的统计信息
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BloomFilterStats {
+    // 查找的次数
+    pub lookups: u64,
+    // 过滤器判断 key 不存在，跳过索引查找的次数
+    pub negatives: u64,
+    // 过滤器判断 key 可能存在，但实际不存在的次数
+    pub false_positives: u64,
+    // 过滤器的位数和哈希函数个数
+    pub bits: u64,
+    pub hashes: u32,
+}
+
+impl BloomFilterStats {
+    /// 实际观测到的误判率，即不存在的 key 中没有被过滤掉的比例
+    pub fn false_positive_rate(&self) -> f64 {
+        match self.negatives + self.false_positives {
+            0 => 0.0,
+            n => self.false_positives as f64 / n as f64,
+        }
+    }
+}
+
+// 并发的布隆过滤器，删除 key 时不会清除对应的位，误判率会随着删除的 key 增加而升高
+pub(crate) struct BloomFilter {
+    bits: Vec<AtomicU64>,
+    hashes: u32,
+    hasher: Arc<dyn KeyHasher>,
+    lookups: AtomicU64,
+    negatives: AtomicU64,
+    false_positives: AtomicU64,
+}
+
+impl BloomFilter {
+    // 根据预期的 key 数量和误判率计算位数和哈希函数个数
+    pub(crate) fn new(opts: &BloomFilterOptions, hasher: Arc<dyn KeyHasher>) -> Self {
+        let n = std::cmp::max(opts.expected_keys, 1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-n * opts.false_positive_rate.ln() / (ln2 * ln2)).ceil() as u64;
+        let words = std::cmp::max(bits.div_ceil(64), 1);
+        let hashes = ((words * 64) as f64 / n * ln2).round().clamp(1.0, 30.0) as u32;
+        Self {
+            bits: (0..words).map(|_| AtomicU64::new(0)).collect(),
+            hashes,
+            hasher,
+            lookups: AtomicU64::new(0),
+            negatives: AtomicU64::new(0),
+            false_positives: AtomicU64::new(0),
+        }
+    }
+
+    // 双重哈希生成 hashes 个位置
+    fn positions(&self, key: &[u8]) -> impl Iterator<Item = u64> {
+        let hash = self.hasher.hash(key);
+        let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        let bits = self.bits.len() as u64 * 64;
+        (0..self.hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % bits)
+    }
+
+    pub(crate) fn insert(&self, key: &[u8]) {
+        for pos in self.positions(key) {
+            self.bits[(pos / 64) as usize].fetch_or(1 << (pos % 64), Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn may_contain(&self, key: &[u8]) -> bool {
+        self.positions(key).all(|pos| {
+            self.bits[(pos / 64) as usize].load(Ordering::Relaxed) & (1 << (pos % 64)) != 0
+        })
+    }
+
+    pub(crate) fn stats(&self) -> BloomFilterStats {
+        BloomFilterStats {
+            lookups: self.lookups.load(Ordering::Relaxed),
+            negatives: self.negatives.load(Ordering::Relaxed),
+            false_positives: self.false_positives.load(Ordering::Relaxed),
+            bits: self.bits.len() as u64 * 64,
+            hashes: self.hashes,
+        }
+    }
+}
+
+// 在索引之前使用布隆过滤器判断 key 是否存在，不存在的 key 不需要查找索引
+pub(crate) struct BloomIndex {
+    inner: Box<dyn Indexer>,
+    filter: Arc<BloomFilter>,
+}
+
+impl BloomIndex {
+    pub(crate) fn new(inner: Box<dyn Indexer>, filter: Arc<BloomFilter>) -> Self {
+        Self { inner, filter }
+    }
+}
+
+impl Indexer for BloomIndex {
+    fn put(&self, key: Vec<u8>, pos: LogRecordPos) -> bool {
+        self.filter.insert(&key);
+        self.inner.put(key, pos)
+    }
+
+    fn put_batch(&self, items: Vec<(Vec<u8>, LogRecordPos)>) {
+        for (key, _) in items.iter() {
+            self.filter.insert(key);
+        }
+        self.inner.put_batch(items)
+    }
+
+    fn get(&self, key: Vec<u8>) -> Option<LogRecordPos> {
+        self.filter.lookups.fetch_add(1, Ordering::Relaxed);
+        if !self.filter.may_contain(&key) {
+            self.filter.negatives.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        let pos = self.inner.get(key);
+        if pos.is_none() {
+            self.filter.false_positives.fetch_add(1, Ordering::Relaxed);
+        }
+        pos
+    }
+
+    fn get_until(&self, key: Vec<u8>, deadline: Instant) -> Result<Option<LogRecordPos>> {
+        self.filter.lookups.fetch_add(1, Ordering::Relaxed);
+        if !self.filter.may_contain(&key) {
+            self.filter.negatives.fetch_add(1, Ordering::Relaxed);
+            return Ok(None);
+        }
+        let pos = self.inner.get_until(key, deadline)?;
+        if pos.is_none() {
+            self.filter.false_positives.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(pos)
+    }
+
+    fn delete(&self, key: Vec<u8>) -> bool {
+        self.inner.delete(key)
+    }
+
+    fn compare_and_put(&self, key: Vec<u8>, old: LogRecordPos, new: LogRecordPos) -> bool {
+        self.inner.compare_and_put(key, old, new)
+    }
+
+    fn iterator(&self, option: IteratorOptions) -> Box<dyn IndexerIterator> {
+        self.inner.iterator(option)
+    }
+
+    fn scan(
+        &self,
+        options: &IteratorOptions,
+        start: Bound<&[u8]>,
+        limit: usize,
+    ) -> Vec<(Bytes, LogRecordPos)> {
+        self.inner.scan(options, start, limit)
+    }
+
+    fn list_keys(&self, reverse: bool, prefix: &[u8]) -> Result<Vec<Bytes>> {
+        self.inner.list_keys(reverse, prefix)
+    }
+
+    fn stats(&self) -> Option<IndexStats> {
+        self.inner.stats()
+    }
+
+    fn shrink(&self) {
+        self.inner.shrink()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash::Xxh3, index::btree::BTree};
+
+    #[test]
+    fn test_bloom_filter() {
+        let filter = BloomFilter::new(
+            &BloomFilterOptions {
+                expected_keys: 1000,
+                false_positive_rate: 0.01,
+            },
+            Arc::new(Xxh3::default()),
+        );
+        let stats = filter.stats();
+        assert!(stats.bits >= 9585);
+        assert_eq!(stats.hashes, 7);
+
+        for i in 0..1000 {
+            filter.insert(format!("key-{i}").as_bytes());
+        }
+        for i in 0..1000 {
+            assert!(filter.may_contain(format!("key-{i}").as_bytes()));
+        }
+        let false_positives = (0..10000)
+            .filter(|i| filter.may_contain(format!("other-{i}").as_bytes()))
+            .count();
+        assert!(false_positives < 300);
+    }
+
+    #[test]
+    fn test_bloom_index() {
+        let filter = Arc::new(BloomFilter::new(
+            &BloomFilterOptions {
+                expected_keys: 100,
+                false_positive_rate: 0.01,
+            },
+            Arc::new(Xxh3::default()),
+        ));
+        let index = BloomIndex::new(Box::new(BTree::new()), filter.clone());
+        let pos = LogRecordPos {
+            file_id: 1,
+            offset: 10,
+            size: 11,
+        };
+        assert!(index.put(b"a".to_vec(), pos));
+        assert_eq!(index.get(b"a".to_vec()), Some(pos));
+        for i in 0..100 {
+            assert!(index.get(format!("miss-{i}").into_bytes()).is_none());
+        }
+        let stats = filter.stats();
+        assert_eq!(stats.lookups, 101);
+        assert_eq!(stats.negatives + stats.false_positives, 100);
+        assert!(stats.negatives > 90);
+
+        // 删除之后过滤器仍然认为 key 可能存在
+        assert!(index.delete(b"a".to_vec()));
+        assert!(index.get(b"a".to_vec()).is_none());
+        assert_eq!(filter.stats().false_positives, stats.false_positives + 1);
+        assert_eq!(index.list_keys(false, &[]).unwrap().len(), 0);
+    }
+}
@@ -1,21 +1,36 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    ops::Bound,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
 use bytes::Bytes;
 use parking_lot::RwLock;
 
-use crate::{data::log_record::LogRecordPos, errors::Result, options::IteratorOptions};
+use crate::{
+    data::log_record::LogRecordPos,
+    errors::{Errors, Result},
+    options::IteratorOptions,
+};
 
-use super::{Indexer, IndexerIterator};
+use super::{IndexStats, Indexer, IndexerIterator};
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct BTree {
     tree: Arc<RwLock<BTreeMap<Vec<u8>, LogRecordPos>>>,
+    // 创建或者上次整理之后 key 数量的峰值，持有写锁时更新
+    peak_keys: Arc<AtomicUsize>,
 }
 
 impl BTree {
     pub fn new() -> Self {
         Self {
             tree: Arc::new(RwLock::new(BTreeMap::new())),
+            peak_keys: Arc::new(AtomicUsize::new(0)),
         }
     }
 }
@@ -28,64 +43,156 @@ impl Indexer for BTree {
             .entry(key)
             .and_modify(|v| *v = pos)
             .or_insert(pos);
+        self.peak_keys
+            .fetch_max(write_guard.len(), Ordering::Relaxed);
         true
     }
 
+    fn put_batch(&self, items: Vec<(Vec<u8>, LogRecordPos)>) {
+        let mut write_guard = self.tree.write();
+        for (key, pos) in items {
+            write_guard.insert(key, pos);
+        }
+        self.peak_keys
+            .fetch_max(write_guard.len(), Ordering::Relaxed);
+    }
+
     fn delete(&self, key: Vec<u8>) -> bool {
         let mut write_guard = self.tree.write();
         let remove_res = write_guard.remove(&key);
         remove_res.is_some()
     }
 
+    fn compare_and_put(&self, key: Vec<u8>, old: LogRecordPos, new: LogRecordPos) -> bool {
+        let mut write_guard = self.tree.write();
+        match write_guard.get_mut(&key) {
+            Some(pos) if *pos == old => {
+                *pos = new;
+                true
+            }
+            _ => false,
+        }
+    }
+
     fn get(&self, key: Vec<u8>) -> Option<LogRecordPos> {
         let read_grard = self.tree.read();
         read_grard.get(&key).copied()
     }
 
+    fn get_until(&self, key: Vec<u8>, deadline: Instant) -> Result<Option<LogRecordPos>> {
+        let read_guard = self.tree.try_read_until(deadline).ok_or(Errors::Timeout)?;
+        Ok(read_guard.get(&key).copied())
+    }
+
     fn iterator(&self, option: IteratorOptions) -> Box<dyn IndexerIterator> {
         let read_guard = self.tree.read();
-        let mut items = read_guard
+        let items = read_guard
             .iter()
-            .map(|(a, b)| (a.clone(), b.clone()))
+            .map(|(a, b)| (a.clone(), *b))
             .collect::<Vec<_>>();
-        if option.reverse {
-            items.reverse();
+        Box::new(BTreeIterator::new(items, option))
+    }
+
+    fn scan(
+        &self,
+        options: &IteratorOptions,
+        start: Bound<&[u8]>,
+        limit: usize,
+    ) -> Vec<(Bytes, LogRecordPos)> {
+        let prefix = &options.prefix;
+        // 正序遍历时直接从前缀开始
+        let start = match start {
+            Bound::Unbounded if !options.reverse && !prefix.is_empty() => {
+                Bound::Included(prefix.as_slice())
+            }
+            start => start,
+        };
+        let read_guard = self.tree.read();
+        let range: Box<dyn Iterator<Item = (&Vec<u8>, &LogRecordPos)>> = match options.reverse {
+            true => Box::new(read_guard.range::<[u8], _>((Bound::Unbounded, start)).rev()),
+            false => Box::new(read_guard.range::<[u8], _>((start, Bound::Unbounded))),
+        };
+        // 前缀相同的 key 是连续的
+        range
+            .skip_while(|(key, _)| !key.starts_with(prefix))
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .take(limit)
+            .map(|(key, pos)| (Bytes::copy_from_slice(key), *pos))
+            .collect()
+    }
+
+    fn list_keys(&self, reverse: bool, prefix: &[u8]) -> Result<Vec<bytes::Bytes>> {
+        let read_guard = self.tree.read();
+        // 前缀相同的 key 是连续的，从前缀开始遍历
+        let mut keys: Vec<Bytes> = read_guard
+            .range::<[u8], _>((Bound::Included(prefix), Bound::Unbounded))
+            .map(|(key, _)| key)
+            .take_while(|key| key.starts_with(prefix))
+            .map(|key| Bytes::copy_from_slice(key))
+            .collect();
+        if reverse {
+            keys.reverse();
         }
-        Box::new(BTreeIterator {
-            items,
-            curr_index: 0,
-            options: option,
-        })
+        Ok(keys)
     }
 
-    fn list_keys(&self) -> Result<Vec<bytes::Bytes>> {
+    fn stats(&self) -> Option<IndexStats> {
         let read_guard = self.tree.read();
-        let keys = read_guard
-            .keys()
+        Some(IndexStats {
+            keys: read_guard.len(),
+            peak_keys: self.peak_keys.load(Ordering::Relaxed),
+        })
+    }
+
+    fn shrink(&self) {
+        let mut write_guard = self.tree.write();
+        // 从有序的数据批量构建，节点都是填满的，key 也只保留需要的容量
+        let tree = std::mem::take(&mut *write_guard);
+        *write_guard = tree
             .into_iter()
-            .map(|a| Bytes::copy_from_slice(a))
+            .map(|(mut key, pos)| {
+                key.shrink_to_fit();
+                (key, pos)
+            })
             .collect();
-        Ok(keys)
+        self.peak_keys.store(write_guard.len(), Ordering::Relaxed);
     }
 }
 
 pub struct BTreeIterator {
     // 存储Key + 索引
-    items: Vec<(Vec<u8>, LogRecordPos)>,
+    items: Vec<(Bytes, LogRecordPos)>,
     // 当前遍历的位置的下标
     curr_index: usize,
     // 配置项
     options: IteratorOptions,
 }
 
+impl BTreeIterator {
+    // 根据按 key 升序排列的数据构造迭代器
+    pub(crate) fn new(mut items: Vec<(Vec<u8>, LogRecordPos)>, options: IteratorOptions) -> Self {
+        if options.reverse {
+            items.reverse();
+        }
+        Self {
+            items: items
+                .into_iter()
+                .map(|(key, pos)| (Bytes::from(key), pos))
+                .collect(),
+            curr_index: 0,
+            options,
+        }
+    }
+}
+
 impl IndexerIterator for BTreeIterator {
     fn seek(&mut self, key: Vec<u8>) {
         // 二分查找
         self.curr_index = match self.items.binary_search_by(|(x, _)| {
             if self.options.reverse {
-                x.cmp(&key).reverse()
+                x.as_ref().cmp(key.as_slice()).reverse()
             } else {
-                x.cmp(&key)
+                x.as_ref().cmp(key.as_slice())
             }
         }) {
             Ok(equal_val) => equal_val,
@@ -97,20 +204,35 @@ impl IndexerIterator for BTreeIterator {
         self.curr_index = 0
     }
 
-    fn next(&mut self) -> Option<(&Vec<u8>, &LogRecordPos)> {
+    fn next(&mut self) -> Option<(Bytes, LogRecordPos)> {
         if self.curr_index >= self.items.len() {
             return None;
         }
         while let Some(item) = self.items.get(self.curr_index) {
             self.curr_index += 1;
             let prefix = &self.options.prefix;
-            if prefix.is_empty() || item.0.starts_with(&prefix) {
-                return Some((&item.0, &item.1));
+            if prefix.is_empty() || item.0.starts_with(prefix) {
+                return Some((item.0.clone(), item.1));
             }
         }
 
         None
     }
+
+    fn next_n(&mut self, n: usize) -> Vec<(Bytes, LogRecordPos)> {
+        let prefix = &self.options.prefix;
+        let mut items = Vec::with_capacity(n.min(self.items.len().saturating_sub(self.curr_index)));
+        while items.len() < n {
+            let Some(item) = self.items.get(self.curr_index) else {
+                break;
+            };
+            self.curr_index += 1;
+            if prefix.is_empty() || item.0.starts_with(prefix) {
+                items.push((item.0.clone(), item.1));
+            }
+        }
+        items
+    }
 }
 
 #[cfg(test)]
@@ -125,20 +247,20 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
-                // size: 11,
+                size: 11,
             },
         );
-        assert!(res1 == true);
+        assert!(res1);
 
         let res2 = bt.put(
             "aa".as_bytes().to_vec(),
             LogRecordPos {
                 file_id: 11,
                 offset: 22,
-                // size: 11,
+                size: 11,
             },
         );
-        assert!(res2 == true);
+        assert!(res2);
 
         // let res3 = bt.put(
         //     "aa".as_bytes().to_vec(),
@@ -162,19 +284,19 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
-                // size: 11,
+                size: 11,
             },
         );
-        assert!(res1 == true);
+        assert!(res1);
         let res2 = bt.put(
             "aa".as_bytes().to_vec(),
             LogRecordPos {
                 file_id: 11,
                 offset: 22,
-                // size: 11,
+                size: 11,
             },
         );
-        assert!(res2 == true);
+        assert!(res2);
 
         let pos1 = bt.get("".as_bytes().to_vec());
         assert!(pos1.is_some());
@@ -187,6 +309,52 @@ mod tests {
         assert_eq!(pos2.unwrap().offset, 22);
     }
 
+    #[test]
+    fn test_btree_put_batch() {
+        let bt = BTree::new();
+        let pos = |offset| LogRecordPos {
+            file_id: 1,
+            offset,
+            size: 11,
+        };
+        bt.put_batch(vec![
+            ("aa".as_bytes().to_vec(), pos(1)),
+            ("bb".as_bytes().to_vec(), pos(2)),
+            ("aa".as_bytes().to_vec(), pos(3)),
+        ]);
+
+        // 相同的 key 以最后一个为准
+        assert_eq!(bt.get("aa".as_bytes().to_vec()).unwrap().offset, 3);
+        assert_eq!(bt.get("bb".as_bytes().to_vec()).unwrap().offset, 2);
+        assert_eq!(bt.list_keys(false, &[]).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_btree_shrink() {
+        let bt = BTree::new();
+        let pos = LogRecordPos {
+            file_id: 1,
+            offset: 10,
+            size: 11,
+        };
+        for i in 0..1000u32 {
+            bt.put(i.to_be_bytes().to_vec(), pos);
+        }
+        for i in 0..900u32 {
+            bt.delete(i.to_be_bytes().to_vec());
+        }
+        let stats = bt.stats().unwrap();
+        assert_eq!((stats.keys, stats.peak_keys), (100, 1000));
+        assert!((stats.fragmentation() - 0.9).abs() < 1e-9);
+
+        bt.shrink();
+        let stats = bt.stats().unwrap();
+        assert_eq!((stats.keys, stats.peak_keys), (100, 100));
+        assert_eq!(stats.fragmentation(), 0.0);
+        assert_eq!(bt.get(950u32.to_be_bytes().to_vec()), Some(pos));
+        assert_eq!(bt.list_keys(false, &[]).unwrap().len(), 100);
+    }
+
     // #[test]
     // fn test_btree_delete() {
     //     let bt = BTree::new();
@@ -241,6 +409,7 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                size: 11,
             },
         );
         let mut iter2 = bt.iterator(Default::default());
@@ -256,6 +425,7 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                size: 11,
             },
         );
         bt.put(
@@ -263,6 +433,7 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                size: 11,
             },
         );
         bt.put(
@@ -270,6 +441,7 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                size: 11,
             },
         );
         bt.put(
@@ -277,6 +449,7 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                size: 11,
             },
         );
         bt.put(
@@ -284,6 +457,7 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                size: 11,
             },
         );
 
@@ -330,6 +504,7 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                size: 11,
             },
         );
         let mut iter_opt1 = IteratorOptions::default();
@@ -343,6 +518,7 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                size: 11,
             },
         );
         bt.put(
@@ -350,6 +526,7 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                size: 11,
             },
         );
         bt.put(
@@ -357,6 +534,7 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                size: 11,
             },
         );
         bt.put(
@@ -364,6 +542,7 @@ mod tests {
             LogRecordPos {
                 file_id: 1,
                 offset: 10,
+                size: 11,
             },
         );
 
@@ -372,7 +551,7 @@ mod tests {
         let mut iter3 = bt.iterator(iter_opt2);
         while let Some(item) = iter3.next() {
             // println!("{:?}", String::from_utf8(item.0.to_vec()));
-            assert!(item.0.len() > 0);
+            assert!(!item.0.is_empty());
         }
 
         // 有前缀的情况
@@ -383,4 +562,39 @@ mod tests {
             println!("{:?}", String::from_utf8(item.0.to_vec()));
         }
     }
+
+    #[test]
+    fn test_btree_iterator_owned_items() {
+        use crate::index::IndexIter;
+
+        let bt = BTree::new();
+        for key in ["aa", "bb", "cc"] {
+            bt.put(
+                key.as_bytes().to_vec(),
+                LogRecordPos {
+                    file_id: 1,
+                    offset: 10,
+                    size: 11,
+                },
+            );
+        }
+
+        // 返回的 key 可以在迭代器释放之后继续使用
+        let mut iter = bt.iterator(Default::default());
+        let first = iter.next().unwrap();
+        let second = iter.next().unwrap();
+        std::mem::drop(iter);
+        assert_eq!(first.0, Bytes::from("aa"));
+        assert_eq!(second.0, Bytes::from("bb"));
+
+        // 标准库迭代器适配器
+        let keys = IndexIter::from(bt.iterator(IteratorOptions {
+            reverse: true,
+            ..Default::default()
+        }))
+        .map(|(key, _)| key)
+        .take(2)
+        .collect::<Vec<_>>();
+        assert_eq!(keys, vec![Bytes::from("cc"), Bytes::from("bb")]);
+    }
 }
@@ -0,0 +1,472 @@
+use std::{cmp::Ordering, ops::Bound, sync::Arc, time::Instant};
+
+use bytes::Bytes;
+
+use crate::{
+    data::log_record::LogRecordPos,
+    errors::{Errors, Result},
+    options::IteratorOptions,
+    utils::sharded_lock::ShardedLock,
+};
+
+use super::{btree::BTreeIterator, Indexer, IndexerIterator};
+
+type Link = Option<Arc<Node>>;
+
+// 持久化平衡树（AVL）的节点，创建之后不再修改
+struct Node {
+    key: Vec<u8>,
+    pos: LogRecordPos,
+    height: u32,
+    left: Link,
+    right: Link,
+}
+
+/// 并发有序索引
+/// 每个版本的树都是不可变的，写入时只复制从根到目标节点路径上的节点生成新版本，再替换根节点
+/// 读取和遍历在某个版本上进行，不会因为写入而阻塞，旧版本在没有读者引用之后自动释放
+pub struct ConcurrentBTree {
+    root: ShardedLock<Link>,
+}
+
+impl ConcurrentBTree {
+    pub fn new() -> Self {
+        Self {
+            root: ShardedLock::new(None),
+        }
+    }
+}
+
+impl Default for ConcurrentBTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Indexer for ConcurrentBTree {
+    fn put(&self, key: Vec<u8>, pos: LogRecordPos) -> bool {
+        self.root.update(|root| Some(insert(root, &key, pos)));
+        true
+    }
+
+    fn put_batch(&self, items: Vec<(Vec<u8>, LogRecordPos)>) {
+        // 只替换一次根节点
+        self.root.update(|root| {
+            let mut root = root.clone();
+            for (key, pos) in items {
+                root = Some(insert(&root, &key, pos));
+            }
+            root
+        });
+    }
+
+    fn get(&self, key: Vec<u8>) -> Option<LogRecordPos> {
+        lookup(&self.root.read(), &key)
+    }
+
+    fn get_until(&self, key: Vec<u8>, deadline: Instant) -> Result<Option<LogRecordPos>> {
+        let root = self.root.try_read_until(deadline).ok_or(Errors::Timeout)?;
+        Ok(lookup(&root, &key))
+    }
+
+    fn delete(&self, key: Vec<u8>) -> bool {
+        let mut found = false;
+        self.root.update(|root| match remove(root, &key) {
+            Some(new_root) => {
+                found = true;
+                new_root
+            }
+            None => root.clone(),
+        });
+        found
+    }
+
+    fn compare_and_put(&self, key: Vec<u8>, old: LogRecordPos, new: LogRecordPos) -> bool {
+        let mut swapped = false;
+        self.root.update(|root| {
+            if lookup(root, &key) != Some(old) {
+                return root.clone();
+            }
+            swapped = true;
+            Some(insert(root, &key, new))
+        });
+        swapped
+    }
+
+    fn iterator(&self, option: IteratorOptions) -> Box<dyn IndexerIterator> {
+        let root = self.root.load();
+        let mut items = Vec::new();
+        walk(&root, &mut |node| items.push((node.key.clone(), node.pos)));
+        Box::new(BTreeIterator::new(items, option))
+    }
+
+    fn scan(
+        &self,
+        options: &IteratorOptions,
+        start: Bound<&[u8]>,
+        limit: usize,
+    ) -> Vec<(Bytes, LogRecordPos)> {
+        let prefix = &options.prefix;
+        // 正序遍历时直接从前缀开始
+        let start = match start {
+            Bound::Unbounded if !options.reverse && !prefix.is_empty() => {
+                Bound::Included(prefix.as_slice())
+            }
+            start => start,
+        };
+        let root = self.root.load();
+        let mut items = Vec::new();
+        walk_from(&root, start, options.reverse, &mut |node| {
+            // 前缀相同的 key 是连续的
+            if !node.key.starts_with(prefix) {
+                return items.is_empty();
+            }
+            items.push((Bytes::copy_from_slice(&node.key), node.pos));
+            items.len() < limit
+        });
+        items
+    }
+
+    fn list_keys(&self, reverse: bool, prefix: &[u8]) -> Result<Vec<Bytes>> {
+        let options = IteratorOptions {
+            prefix: prefix.to_vec(),
+            reverse,
+            ..Default::default()
+        };
+        let keys = self
+            .scan(&options, Bound::Unbounded, usize::MAX)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        Ok(keys)
+    }
+}
+
+fn lookup(root: &Link, key: &[u8]) -> Option<LogRecordPos> {
+    let mut link = root;
+    while let Some(node) = link {
+        link = match key.cmp(&node.key) {
+            Ordering::Less => &node.left,
+            Ordering::Greater => &node.right,
+            Ordering::Equal => return Some(node.pos),
+        };
+    }
+    None
+}
+
+// 按顺序遍历从 start 开始的节点，f 返回 false 时停止，返回是否遍历完成
+fn walk_from(
+    link: &Link,
+    start: Bound<&[u8]>,
+    reverse: bool,
+    f: &mut dyn FnMut(&Node) -> bool,
+) -> bool {
+    let node = match link {
+        Some(node) => node,
+        None => return true,
+    };
+    let key = node.key.as_slice();
+    let in_range = match (start, reverse) {
+        (Bound::Unbounded, _) => true,
+        (Bound::Included(start), false) => key >= start,
+        (Bound::Excluded(start), false) => key > start,
+        (Bound::Included(start), true) => key <= start,
+        (Bound::Excluded(start), true) => key < start,
+    };
+    let (first, second) = match reverse {
+        true => (&node.right, &node.left),
+        false => (&node.left, &node.right),
+    };
+    // 不在范围内时，先遍历的子树也都不在范围内
+    if in_range && !(walk_from(first, start, reverse, f) && f(node)) {
+        return false;
+    }
+    walk_from(second, start, reverse, f)
+}
+
+fn height(link: &Link) -> u32 {
+    link.as_ref().map_or(0, |node| node.height)
+}
+
+fn make(key: Vec<u8>, pos: LogRecordPos, left: Link, right: Link) -> Arc<Node> {
+    Arc::new(Node {
+        key,
+        pos,
+        height: std::cmp::max(height(&left), height(&right)) + 1,
+        left,
+        right,
+    })
+}
+
+// 构造新节点，左右子树高度差超过 1 时进行旋转
+fn balance(key: Vec<u8>, pos: LogRecordPos, left: Link, right: Link) -> Arc<Node> {
+    let (hl, hr) = (height(&left), height(&right));
+    if hl > hr + 1 {
+        let l = left.unwrap();
+        if height(&l.left) >= height(&l.right) {
+            // 右旋
+            let r = make(key, pos, l.right.clone(), right);
+            return make(l.key.clone(), l.pos, l.left.clone(), Some(r));
+        }
+        // 先左旋再右旋
+        let lr = l.right.as_ref().unwrap();
+        let new_l = make(l.key.clone(), l.pos, l.left.clone(), lr.left.clone());
+        let new_r = make(key, pos, lr.right.clone(), right);
+        return make(lr.key.clone(), lr.pos, Some(new_l), Some(new_r));
+    }
+    if hr > hl + 1 {
+        let r = right.unwrap();
+        if height(&r.right) >= height(&r.left) {
+            // 左旋
+            let l = make(key, pos, left, r.left.clone());
+            return make(r.key.clone(), r.pos, Some(l), r.right.clone());
+        }
+        // 先右旋再左旋
+        let rl = r.left.as_ref().unwrap();
+        let new_l = make(key, pos, left, rl.left.clone());
+        let new_r = make(r.key.clone(), r.pos, rl.right.clone(), r.right.clone());
+        return make(rl.key.clone(), rl.pos, Some(new_l), Some(new_r));
+    }
+    make(key, pos, left, right)
+}
+
+fn insert(link: &Link, key: &[u8], pos: LogRecordPos) -> Arc<Node> {
+    let node = match link {
+        Some(node) => node,
+        None => return make(key.to_vec(), pos, None, None),
+    };
+    match key.cmp(&node.key) {
+        Ordering::Less => balance(
+            node.key.clone(),
+            node.pos,
+            Some(insert(&node.left, key, pos)),
+            node.right.clone(),
+        ),
+        Ordering::Greater => balance(
+            node.key.clone(),
+            node.pos,
+            node.left.clone(),
+            Some(insert(&node.right, key, pos)),
+        ),
+        Ordering::Equal => make(node.key.clone(), pos, node.left.clone(), node.right.clone()),
+    }
+}
+
+// 删除 key 之后的新树，key 不存在时返回 None
+fn remove(link: &Link, key: &[u8]) -> Option<Link> {
+    let node = link.as_ref()?;
+    match key.cmp(&node.key) {
+        Ordering::Less => {
+            let left = remove(&node.left, key)?;
+            Some(Some(balance(
+                node.key.clone(),
+                node.pos,
+                left,
+                node.right.clone(),
+            )))
+        }
+        Ordering::Greater => {
+            let right = remove(&node.right, key)?;
+            Some(Some(balance(
+                node.key.clone(),
+                node.pos,
+                node.left.clone(),
+                right,
+            )))
+        }
+        Ordering::Equal => match (&node.left, &node.right) {
+            (None, right) => Some(right.clone()),
+            (left, None) => Some(left.clone()),
+            (left, Some(right)) => {
+                // 用右子树中最小的节点替换被删除的节点
+                let (min_key, min_pos, right) = remove_min(right);
+                Some(Some(balance(min_key, min_pos, left.clone(), right)))
+            }
+        },
+    }
+}
+
+fn remove_min(node: &Arc<Node>) -> (Vec<u8>, LogRecordPos, Link) {
+    match &node.left {
+        None => (node.key.clone(), node.pos, node.right.clone()),
+        Some(left) => {
+            let (min_key, min_pos, left) = remove_min(left);
+            let new_node = balance(node.key.clone(), node.pos, left, node.right.clone());
+            (min_key, min_pos, Some(new_node))
+        }
+    }
+}
+
+// 按 key 升序遍历所有节点
+fn walk<F>(root: &Link, f: &mut F)
+where
+    F: FnMut(&Node),
+{
+    let mut stack = Vec::new();
+    let mut link = root.as_ref();
+    loop {
+        while let Some(node) = link {
+            stack.push(node);
+            link = node.left.as_ref();
+        }
+        match stack.pop() {
+            Some(node) => {
+                f(node);
+                link = node.right.as_ref();
+            }
+            None => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn pos(offset: u64) -> LogRecordPos {
+        LogRecordPos {
+            file_id: 1,
+            offset,
+            size: 11,
+        }
+    }
+
+    #[test]
+    fn test_concurrent_btree_put_get_delete() {
+        let bt = ConcurrentBTree::new();
+        for i in 0..1000u64 {
+            assert!(bt.put(format!("key-{:04}", i).into_bytes(), pos(i)));
+        }
+        // 覆盖已有的 key
+        assert!(bt.put("key-0010".as_bytes().to_vec(), pos(10010)));
+
+        assert_eq!(
+            bt.get("key-0010".as_bytes().to_vec()).unwrap().offset,
+            10010
+        );
+        assert_eq!(bt.get("key-0999".as_bytes().to_vec()).unwrap().offset, 999);
+        assert!(bt.get("key-1000".as_bytes().to_vec()).is_none());
+
+        for i in (0..1000u64).step_by(2) {
+            assert!(bt.delete(format!("key-{:04}", i).into_bytes()));
+        }
+        assert!(!bt.delete("key-0000".as_bytes().to_vec()));
+        assert!(bt.get("key-0000".as_bytes().to_vec()).is_none());
+        assert_eq!(bt.get("key-0001".as_bytes().to_vec()).unwrap().offset, 1);
+
+        // 删除之后仍然保持平衡并且有序
+        let root = bt.root.load();
+        assert!(height(&root) <= 13);
+        let keys = bt.list_keys(false, &[]).unwrap();
+        assert_eq!(keys.len(), 500);
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_concurrent_btree_put_batch() {
+        let bt = ConcurrentBTree::new();
+        bt.put("key-0000".as_bytes().to_vec(), pos(1));
+        let items = (0..1000u64)
+            .map(|i| (format!("key-{:04}", i % 500).into_bytes(), pos(i)))
+            .collect();
+        bt.put_batch(items);
+
+        // 相同的 key 以最后一个为准
+        assert_eq!(bt.get("key-0000".as_bytes().to_vec()).unwrap().offset, 500);
+        assert_eq!(bt.get("key-0499".as_bytes().to_vec()).unwrap().offset, 999);
+        assert_eq!(bt.list_keys(false, &[]).unwrap().len(), 500);
+        assert!(height(&bt.root.load()) <= 12);
+        bt.put_batch(Vec::new());
+        assert_eq!(bt.list_keys(false, &[]).unwrap().len(), 500);
+    }
+
+    #[test]
+    fn test_concurrent_btree_scan() {
+        let bt = ConcurrentBTree::new();
+        let expected = crate::index::btree::BTree::new();
+        for i in (0..200u64).step_by(3) {
+            bt.put(format!("key-{:03}", i).into_bytes(), pos(i));
+            expected.put(format!("key-{:03}", i).into_bytes(), pos(i));
+        }
+
+        // 与 BTree 的结果一致
+        let starts = ["key-000", "key-050", "key-051", "key-199", "a", "z"];
+        for reverse in [false, true] {
+            for prefix in ["", "key-1", "key-05", "x"] {
+                let options = IteratorOptions {
+                    prefix: prefix.as_bytes().to_vec(),
+                    reverse,
+                    ..Default::default()
+                };
+                for limit in [1, 5, 1000] {
+                    let check = |start: Bound<&[u8]>| {
+                        assert_eq!(
+                            bt.scan(&options, start, limit),
+                            expected.scan(&options, start, limit)
+                        );
+                    };
+                    check(Bound::Unbounded);
+                    for start in starts {
+                        check(Bound::Included(start.as_bytes()));
+                        check(Bound::Excluded(start.as_bytes()));
+                    }
+                }
+            }
+        }
+
+        let items = bt.scan(&Default::default(), Bound::Excluded(b"key-048"), 2);
+        assert_eq!(items[0].0, Bytes::from("key-051"));
+        assert_eq!(items[1].0, Bytes::from("key-054"));
+    }
+
+    #[test]
+    fn test_concurrent_btree_iterator() {
+        let bt = ConcurrentBTree::new();
+        for key in ["ccde", "ccdf", "bcde", "acde", "ccae", "cfde"] {
+            bt.put(key.as_bytes().to_vec(), pos(10));
+        }
+
+        let mut iter = bt.iterator(Default::default());
+        // 创建迭代器之后的写入不影响迭代器
+        bt.put("zzzz".as_bytes().to_vec(), pos(10));
+        iter.seek("ca".as_bytes().to_vec());
+        let mut keys = Vec::new();
+        while let Some((key, _)) = iter.next() {
+            keys.push(String::from_utf8(key.to_vec()).unwrap());
+        }
+        assert_eq!(keys, vec!["ccae", "ccde", "ccdf", "cfde"]);
+
+        // 反向迭代并指定前缀
+        let iter_opts = IteratorOptions {
+            prefix: "cc".as_bytes().to_vec(),
+            reverse: true,
+            ..Default::default()
+        };
+        let mut iter = bt.iterator(iter_opts);
+        let first = iter.next();
+        assert_eq!(*first.unwrap().0, "ccdf".as_bytes().to_vec());
+    }
+
+    #[test]
+    fn test_concurrent_btree_parallel() {
+        let bt = Arc::new(ConcurrentBTree::new());
+        let handles = (0..4u64)
+            .map(|t| {
+                let bt = bt.clone();
+                thread::spawn(move || {
+                    for i in 0..500u64 {
+                        let key = format!("{t}-{:04}", i).into_bytes();
+                        bt.put(key.clone(), pos(i));
+                        assert_eq!(bt.get(key).unwrap().offset, i);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(bt.list_keys(false, &[]).unwrap().len(), 2000);
+    }
+}
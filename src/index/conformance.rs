@@ -0,0 +1,236 @@
+//! 所有 Indexer 实现都需要通过的测试，新增索引类型时在 index::tests 中调用 check_indexer 即可
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+
+use crate::{data::log_record::LogRecordPos, options::IteratorOptions};
+
+use super::{Indexer, IndexerIterator};
+
+// 依次运行所有检查，每个检查使用 new 创建一个新的空索引
+pub(crate) fn check_indexer<F>(new: F)
+where
+    F: Fn() -> Box<dyn Indexer>,
+{
+    check_put_get(new().as_ref());
+    check_put_batch(new().as_ref());
+    check_delete(new().as_ref());
+    check_compare_and_put(new().as_ref());
+    check_iterator(new().as_ref());
+    check_iterator_seek(new().as_ref());
+    check_iterator_prefix(new().as_ref());
+    check_scan(new().as_ref());
+    check_list_keys(new().as_ref());
+}
+
+fn pos(offset: u64) -> LogRecordPos {
+    LogRecordPos {
+        file_id: 1,
+        offset,
+        size: 11,
+    }
+}
+
+// 乱序写入的测试数据，offset 为 key 在数组中的下标
+const KEYS: [&[u8]; 8] = [b"bb", b"a", b"ab", b"c", b"b", b"ba", b"\xff", b"aa"];
+
+fn fill(index: &dyn Indexer) {
+    for (i, key) in KEYS.iter().enumerate() {
+        index.put(key.to_vec(), pos(i as u64));
+    }
+}
+
+fn sorted_keys(reverse: bool, prefix: &[u8]) -> Vec<Bytes> {
+    let mut keys: Vec<Bytes> = KEYS
+        .iter()
+        .filter(|key| key.starts_with(prefix))
+        .map(|key| Bytes::copy_from_slice(key))
+        .collect();
+    keys.sort();
+    if reverse {
+        keys.reverse();
+    }
+    keys
+}
+
+fn collect(iter: &mut dyn IndexerIterator) -> Vec<Bytes> {
+    let mut keys = Vec::new();
+    while let Some((key, _)) = iter.next() {
+        keys.push(key);
+    }
+    keys
+}
+
+fn options(reverse: bool, prefix: &[u8]) -> IteratorOptions {
+    IteratorOptions {
+        prefix: prefix.to_vec(),
+        reverse,
+        ..Default::default()
+    }
+}
+
+fn check_put_get(index: &dyn Indexer) {
+    assert!(index.get(b"a".to_vec()).is_none());
+    assert!(index.put(b"a".to_vec(), pos(1)));
+    assert_eq!(index.get(b"a".to_vec()), Some(pos(1)));
+
+    // 覆盖已经存在的 key
+    assert!(index.put(b"a".to_vec(), pos(2)));
+    assert_eq!(index.get(b"a".to_vec()), Some(pos(2)));
+
+    // 空 key 也是合法的索引项
+    assert!(index.put(Vec::new(), pos(3)));
+    assert_eq!(index.get(Vec::new()), Some(pos(3)));
+    assert_eq!(index.get(b"a".to_vec()), Some(pos(2)));
+}
+
+fn check_put_batch(index: &dyn Indexer) {
+    index.put_batch(vec![
+        (b"a".to_vec(), pos(1)),
+        (b"b".to_vec(), pos(2)),
+        (b"a".to_vec(), pos(3)),
+    ]);
+    assert_eq!(index.get(b"a".to_vec()), Some(pos(3)));
+    assert_eq!(index.get(b"b".to_vec()), Some(pos(2)));
+    assert_eq!(index.list_keys(false, &[]).unwrap().len(), 2);
+}
+
+fn check_delete(index: &dyn Indexer) {
+    assert!(!index.delete(b"a".to_vec()));
+    fill(index);
+    assert!(index.delete(b"a".to_vec()));
+    assert!(index.get(b"a".to_vec()).is_none());
+    assert!(!index.delete(b"a".to_vec()));
+
+    // 删除之后可以重新写入
+    assert!(index.put(b"a".to_vec(), pos(10)));
+    assert_eq!(index.get(b"a".to_vec()), Some(pos(10)));
+
+    for key in KEYS {
+        assert!(index.delete(key.to_vec()));
+    }
+    assert!(index.list_keys(false, &[]).unwrap().is_empty());
+    assert!(index.iterator(IteratorOptions::default()).next().is_none());
+}
+
+fn check_compare_and_put(index: &dyn Indexer) {
+    // key 不存在时不会写入
+    assert!(!index.compare_and_put(b"a".to_vec(), pos(1), pos(2)));
+    assert!(index.get(b"a".to_vec()).is_none());
+
+    index.put(b"a".to_vec(), pos(1));
+    assert!(!index.compare_and_put(b"a".to_vec(), pos(5), pos(2)));
+    assert_eq!(index.get(b"a".to_vec()), Some(pos(1)));
+    assert!(index.compare_and_put(b"a".to_vec(), pos(1), pos(2)));
+    assert_eq!(index.get(b"a".to_vec()), Some(pos(2)));
+}
+
+fn check_iterator(index: &dyn Indexer) {
+    assert!(index.iterator(IteratorOptions::default()).next().is_none());
+    fill(index);
+
+    for reverse in [false, true] {
+        let mut iter = index.iterator(options(reverse, &[]));
+        assert_eq!(collect(iter.as_mut()), sorted_keys(reverse, &[]));
+        assert!(iter.next().is_none());
+
+        // 重新回到起点
+        iter.rewind();
+        assert_eq!(collect(iter.as_mut()), sorted_keys(reverse, &[]));
+    }
+
+    // 迭代器返回 key 对应的位置
+    let mut iter = index.iterator(IteratorOptions::default());
+    while let Some((key, pos)) = iter.next() {
+        assert_eq!(index.get(key.to_vec()), Some(pos));
+    }
+}
+
+fn check_iterator_seek(index: &dyn Indexer) {
+    fill(index);
+
+    // 正序时定位到第一个大于等于目标的 key
+    let mut iter = index.iterator(options(false, &[]));
+    iter.seek(b"b".to_vec());
+    assert_eq!(iter.next().unwrap().0, Bytes::from("b"));
+    iter.seek(b"ac".to_vec());
+    assert_eq!(iter.next().unwrap().0, Bytes::from("b"));
+    iter.seek(Vec::new());
+    assert_eq!(iter.next().unwrap().0, Bytes::from("a"));
+    iter.seek(b"\xff\xff".to_vec());
+    assert!(iter.next().is_none());
+
+    // 倒序时定位到第一个小于等于目标的 key
+    let mut iter = index.iterator(options(true, &[]));
+    iter.seek(b"b".to_vec());
+    assert_eq!(iter.next().unwrap().0, Bytes::from("b"));
+    iter.seek(b"ac".to_vec());
+    assert_eq!(iter.next().unwrap().0, Bytes::from("ab"));
+    iter.seek(b"\xff\xff".to_vec());
+    assert_eq!(iter.next().unwrap().0, Bytes::from_static(b"\xff"));
+    iter.seek(Vec::new());
+    assert!(iter.next().is_none());
+}
+
+fn check_iterator_prefix(index: &dyn Indexer) {
+    fill(index);
+    for reverse in [false, true] {
+        for prefix in [&b"a"[..], b"b", b"ba", b"d"] {
+            let mut iter = index.iterator(options(reverse, prefix));
+            assert_eq!(collect(iter.as_mut()), sorted_keys(reverse, prefix));
+        }
+    }
+}
+
+fn check_scan(index: &dyn Indexer) {
+    fill(index);
+    let keys = |items: Vec<(Bytes, LogRecordPos)>| -> Vec<Bytes> {
+        items.into_iter().map(|(key, _)| key).collect()
+    };
+
+    for reverse in [false, true] {
+        for prefix in [&b""[..], b"a", b"b", b"d"] {
+            let opts = options(reverse, prefix);
+            let all = index.scan(&opts, Bound::Unbounded, usize::MAX);
+            assert_eq!(keys(all), sorted_keys(reverse, prefix));
+            assert_eq!(
+                keys(index.scan(&opts, Bound::Unbounded, 2)),
+                sorted_keys(reverse, prefix)
+                    .into_iter()
+                    .take(2)
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    // 从给定位置继续扫描
+    let opts = options(false, &[]);
+    assert_eq!(
+        keys(index.scan(&opts, Bound::Included(b"b"), 2)),
+        vec![Bytes::from("b"), Bytes::from("ba")]
+    );
+    assert_eq!(
+        keys(index.scan(&opts, Bound::Excluded(b"b"), 2)),
+        vec![Bytes::from("ba"), Bytes::from("bb")]
+    );
+    let opts = options(true, b"b");
+    assert_eq!(
+        keys(index.scan(&opts, Bound::Excluded(b"bb"), 10)),
+        vec![Bytes::from("ba"), Bytes::from("b")]
+    );
+    assert!(index.scan(&opts, Bound::Excluded(b"b"), 10).is_empty());
+}
+
+fn check_list_keys(index: &dyn Indexer) {
+    assert!(index.list_keys(false, &[]).unwrap().is_empty());
+    fill(index);
+    for reverse in [false, true] {
+        for prefix in [&b""[..], b"a", b"b", b"ac", b"d", b"\xff"] {
+            assert_eq!(
+                index.list_keys(reverse, prefix).unwrap(),
+                sorted_keys(reverse, prefix)
+            );
+        }
+    }
+}
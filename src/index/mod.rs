@@ -1,30 +1,128 @@
+pub mod bloom;
 pub mod btree;
+pub mod concurrent_btree;
+#[cfg(test)]
+pub(crate) mod conformance;
+
+use std::{ops::Bound, sync::Arc, time::Instant};
 
 use bytes::Bytes;
 
 use crate::{
     data::log_record::LogRecordPos,
     errors::Result,
-    options::{IndexType, IteratorOptions},
+    hash::key_hasher,
+    options::{IndexType, IteratorOptions, Options},
 };
 
 pub trait Indexer: Sync + Send {
     fn put(&self, key: Vec<u8>, pos: LogRecordPos) -> bool;
 
+    // 按顺序写入多个 key，相同的 key 以最后一个为准，只需要获取一次写锁
+    fn put_batch(&self, items: Vec<(Vec<u8>, LogRecordPos)>) {
+        for (key, pos) in items {
+            self.put(key, pos);
+        }
+    }
+
     fn get(&self, key: Vec<u8>) -> Option<LogRecordPos>;
 
+    // 同 get，等待锁最多到 deadline，超时返回 Errors::Timeout，用于 Engine::get_with_deadline
+    // 默认直接调用 get，读取需要等待锁的索引应该实现这个方法
+    fn get_until(&self, key: Vec<u8>, deadline: Instant) -> Result<Option<LogRecordPos>> {
+        let _ = deadline;
+        Ok(self.get(key))
+    }
+
     fn delete(&self, key: Vec<u8>) -> bool;
 
+    // 只有当 key 当前的位置等于 old 时才更新为 new，返回是否更新成功
+    fn compare_and_put(&self, key: Vec<u8>, old: LogRecordPos, new: LogRecordPos) -> bool;
+
     fn iterator(&self, option: IteratorOptions) -> Box<dyn IndexerIterator>;
 
-    fn list_keys(&self) -> Result<Vec<Bytes>>;
+    // 按照 options 中的顺序和前缀，返回从 start 开始的最多 limit 个数据
+    // 默认通过 iterator 实现，每次调用都会复制整个索引，有序索引应该实现更高效的版本
+    fn scan(
+        &self,
+        options: &IteratorOptions,
+        start: Bound<&[u8]>,
+        limit: usize,
+    ) -> Vec<(Bytes, LogRecordPos)> {
+        let mut iter = self.iterator(options.clone());
+        if let Bound::Included(key) | Bound::Excluded(key) = start {
+            iter.seek(key.to_vec());
+        }
+        let mut items = Vec::new();
+        while items.len() < limit {
+            match iter.next() {
+                Some((key, _)) if start == Bound::Excluded(key.as_ref()) => continue,
+                Some(item) => items.push(item),
+                None => break,
+            }
+        }
+        items
+    }
+
+    // 返回以 prefix 开头的所有 key，按 key 的字节序升序排列，reverse 为 true 时降序排列
+    // 所有索引实现都必须保证这个顺序，Engine::list_keys 依赖于此
+    fn list_keys(&self, reverse: bool, prefix: &[u8]) -> Result<Vec<Bytes>>;
+
+    // 当前的 key 数量以及上次整理之后的峰值，默认返回 None 表示不统计
+    fn stats(&self) -> Option<IndexStats> {
+        None
+    }
+
+    // 只保留有效的数据重建内部结构，大量删除之后释放不再使用的内存
+    // 删除时已经释放内存的索引不需要实现
+    fn shrink(&self) {}
+}
+
+/// 内存索引的统计信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexStats {
+    pub keys: usize,
+    // 创建或者上次整理之后 key 数量的峰值，删除 key 之后峰值占用的内存不一定会归还
+    pub peak_keys: usize,
+}
+
+impl IndexStats {
+    /// 已经删除的 key 占峰值的比例，比例较高时可以调用 Engine::shrink_index
+    pub fn fragmentation(&self) -> f64 {
+        match self.peak_keys {
+            0 => 0.0,
+            peak => 1.0 - self.keys as f64 / peak as f64,
+        }
+    }
 }
 
 // 根据类型创建内存索引
-pub fn new_indexer(index_type: IndexType) -> impl Indexer {
+pub fn new_indexer(index_type: IndexType) -> Box<dyn Indexer> {
     match index_type {
-        IndexType::BTree => btree::BTree::new(),
+        IndexType::BTree => Box::new(btree::BTree::new()),
+        IndexType::ConcurrentBTree => Box::new(concurrent_btree::ConcurrentBTree::new()),
         IndexType::SkipList => todo!(),
+        IndexType::Custom => panic!("IndexType::Custom requires Options::custom_index"),
+    }
+}
+
+// 根据配置创建存储引擎使用的索引，启用布隆过滤器时同时返回过滤器
+pub(crate) fn new_engine_indexer(
+    opts: &Options,
+) -> (Box<dyn Indexer>, Option<Arc<bloom::BloomFilter>>) {
+    let index = match (&opts.index_type, &opts.custom_index) {
+        (IndexType::Custom, Some(factory)) => factory(),
+        (index_type, _) => new_indexer(index_type.clone()),
+    };
+    match &opts.bloom_filter {
+        Some(bloom_opts) => {
+            let filter = Arc::new(bloom::BloomFilter::new(bloom_opts, key_hasher(opts)));
+            (
+                Box::new(bloom::BloomIndex::new(index, filter.clone())),
+                Some(filter),
+            )
+        }
+        None => (index, None),
     }
 }
 
@@ -36,5 +134,68 @@ pub trait IndexerIterator: Sync + Send {
     fn seek(&mut self, key: Vec<u8>);
 
     // Next 跳转到下一个key，返回None则说明迭代完毕
-    fn next(&mut self) -> Option<(&Vec<u8>, &LogRecordPos)>;
+    // 返回的 key 与迭代器的生命周期无关，可以在迭代过程中保存或者传递给其他组件
+    fn next(&mut self) -> Option<(Bytes, LogRecordPos)>;
+
+    // NextN 一次读取最多 n 个 key，返回的数量小于 n 说明迭代完毕
+    // 默认逐个调用 next，可以一次访问索引的实现应当覆盖该方法
+    fn next_n(&mut self, n: usize) -> Vec<(Bytes, LogRecordPos)> {
+        let mut items = Vec::with_capacity(n.min(1024));
+        while items.len() < n {
+            match self.next() {
+                Some(item) => items.push(item),
+                None => break,
+            }
+        }
+        items
+    }
+}
+
+/// 将索引迭代器包装为标准库的迭代器，可以使用 map、filter、take 等适配器
+pub struct IndexIter(Box<dyn IndexerIterator>);
+
+impl From<Box<dyn IndexerIterator>> for IndexIter {
+    fn from(iter: Box<dyn IndexerIterator>) -> Self {
+        Self(iter)
+    }
+}
+
+impl Iterator for IndexIter {
+    type Item = (Bytes, LogRecordPos);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash::Xxh3, options::BloomFilterOptions};
+
+    fn new_bloom_index() -> Box<dyn Indexer> {
+        let filter = Arc::new(bloom::BloomFilter::new(
+            &BloomFilterOptions::default(),
+            Arc::new(Xxh3::default()),
+        ));
+        Box::new(bloom::BloomIndex::new(
+            new_indexer(IndexType::BTree),
+            filter,
+        ))
+    }
+
+    #[test]
+    fn test_btree_conformance() {
+        conformance::check_indexer(|| new_indexer(IndexType::BTree));
+    }
+
+    #[test]
+    fn test_concurrent_btree_conformance() {
+        conformance::check_indexer(|| new_indexer(IndexType::ConcurrentBTree));
+    }
+
+    #[test]
+    fn test_bloom_index_conformance() {
+        conformance::check_indexer(new_bloom_index);
+    }
 }
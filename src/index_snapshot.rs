@@ -0,0 +1,418 @@
+//! 索引快照：关闭时把内存索引写入 INDEX.snapshot，下次打开时直接加载，只回放快照位置之后写入的数据
+//! 快照中记录了当时每个数据文件的长度，快照位置之前的数据文件被修改或者删除（例如合并）时快照失效，
+//! 退化为回放所有的数据文件；快照文件损坏时同样忽略
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufReader, Read, Write},
+    path::Path,
+};
+
+use log::{info, warn};
+
+use crate::{
+    data::{data_file::get_data_file_name, log_record::LogRecordPos},
+    db::Engine,
+    errors::{Errors, Result},
+    index::IndexIter,
+    utils::atomic_write_with,
+};
+
+/// 关闭时写入的索引快照的文件名
+pub const INDEX_SNAPSHOT_FILE_NAME: &str = "INDEX.snapshot";
+
+const INDEX_SNAPSHOT_MAGIC: &[u8; 8] = b"KVIDXSN1";
+
+// key 长度为该值时表示索引项结束
+const END_OF_ENTRIES: u32 = u32::MAX;
+
+// 加载快照时每次更新的 key 的数量
+const LOAD_BATCH_SIZE: usize = 4096;
+
+// 快照中记录的数据文件
+struct SnapshotFile {
+    file_id: u32,
+    // 文件中数据的长度
+    len: u64,
+    // 写入快照时文件在磁盘上的大小
+    disk_len: u64,
+    record_count: u64,
+}
+
+// 加载快照之后从 file_id 的 offset 处继续回放
+pub(crate) struct SnapshotMark {
+    pub(crate) file_id: u32,
+    pub(crate) offset: u64,
+    pub(crate) seq: u64,
+}
+
+impl Engine {
+    // 关闭时写入索引快照，调用之前已经持久化了所有的数据
+    pub(crate) fn write_index_snapshot(&self) -> Result<()> {
+        let dir_path = &self.options.dir_path;
+        let path = dir_path.join(INDEX_SNAPSHOT_FILE_NAME);
+        let _lock = self.append_lock.lock();
+        // 等待已经写入数据文件的记录更新完索引，快照中的索引与快照位置一致
+        self.inflight.wait_idle();
+        let files = self.files.load();
+        files.active.sync()?;
+
+        let mut data_files = files
+            .older
+            .values()
+            .chain(std::iter::once(&files.active))
+            .collect::<Vec<_>>();
+        data_files.sort_by_key(|f| f.get_file_id());
+        let mut snapshot_files = Vec::with_capacity(data_files.len());
+        for data_file in data_files {
+            let file_id = data_file.get_file_id();
+            snapshot_files.push(SnapshotFile {
+                file_id,
+                len: data_file.len(),
+                disk_len: disk_len(dir_path, file_id)?,
+                record_count: data_file.get_record_count(),
+            });
+        }
+
+        let write = |w: &mut dyn Write| -> io::Result<()> {
+            let mut w = CrcWriter::new(w);
+            w.write_all(INDEX_SNAPSHOT_MAGIC)?;
+            w.write_all(&self.seq.current().to_le_bytes())?;
+            w.write_all(&files.active.get_file_id().to_le_bytes())?;
+            w.write_all(&files.active.get_write_off().to_le_bytes())?;
+            w.write_all(&(snapshot_files.len() as u32).to_le_bytes())?;
+            for f in snapshot_files.iter() {
+                w.write_all(&f.file_id.to_le_bytes())?;
+                w.write_all(&f.len.to_le_bytes())?;
+                w.write_all(&f.disk_len.to_le_bytes())?;
+                w.write_all(&f.record_count.to_le_bytes())?;
+            }
+            for (key, pos) in IndexIter::from(self.index.iterator(Default::default())) {
+                w.write_all(&(key.len() as u32).to_le_bytes())?;
+                w.write_all(&key)?;
+                w.write_all(&pos.file_id.to_le_bytes())?;
+                w.write_all(&pos.offset.to_le_bytes())?;
+                w.write_all(&pos.size.to_le_bytes())?;
+            }
+            w.write_all(&END_OF_ENTRIES.to_le_bytes())?;
+            let crc = w.hasher.clone().finalize();
+            w.inner.write_all(&crc.to_le_bytes())
+        };
+        atomic_write_with(&path, self.options.file_mode, |w| write(w))
+    }
+
+    // 加载索引快照，快照不存在或者已经失效时返回 None，此时索引没有被修改
+    pub(crate) fn load_index_snapshot(&self) -> Result<Option<SnapshotMark>> {
+        let dir_path = &self.options.dir_path;
+        let path = dir_path.join(INDEX_SNAPSHOT_FILE_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        // 先校验整个文件，校验通过之后才修改索引
+        match verify_checksum(&path) {
+            Ok(true) => {}
+            Ok(false) => {
+                warn!("Ignoring corrupted index snapshot");
+                return Ok(None);
+            }
+            Err(e) => {
+                warn!("Ignoring unreadable index snapshot: {e}");
+                return Ok(None);
+            }
+        }
+
+        let mut reader = match File::open(&path) {
+            Ok(file) => BufReader::new(file),
+            Err(e) => {
+                warn!("Ignoring unreadable index snapshot: {e}");
+                return Ok(None);
+            }
+        };
+        let (mark, snapshot_files) = read_header(&mut reader).map_err(snapshot_corrupted)?;
+        if let Some(reason) = self.check_snapshot_files(&mark, &snapshot_files)? {
+            info!("Index snapshot is stale, replaying all data files: {reason}");
+            return Ok(None);
+        }
+
+        let mut batch = Vec::with_capacity(LOAD_BATCH_SIZE);
+        while let Some(entry) = read_entry(&mut reader).map_err(snapshot_corrupted)? {
+            batch.push(entry);
+            if batch.len() >= LOAD_BATCH_SIZE {
+                self.index.put_batch(std::mem::take(&mut batch));
+            }
+        }
+        if !batch.is_empty() {
+            self.index.put_batch(batch);
+        }
+
+        // 快照位置之前的数据文件不再回放，直接使用快照中记录的长度
+        let files = self.files.load();
+        for f in snapshot_files {
+            let data_file = match files.get(f.file_id) {
+                Some(data_file) => data_file,
+                None => continue,
+            };
+            data_file.add_record_count(f.record_count);
+            if f.file_id < mark.file_id {
+                data_file.set_write_off(f.len)?;
+                data_file.seal();
+            }
+        }
+        Ok(Some(mark))
+    }
+
+    // 检查快照位置之前的数据文件是否与写入快照时一致，不一致时返回原因
+    fn check_snapshot_files(
+        &self,
+        mark: &SnapshotMark,
+        snapshot_files: &[SnapshotFile],
+    ) -> Result<Option<String>> {
+        let dir_path = &self.options.dir_path;
+        let recorded = snapshot_files
+            .iter()
+            .map(|f| (f.file_id, f))
+            .collect::<HashMap<_, _>>();
+        if recorded.get(&mark.file_id).map(|f| f.len) != Some(mark.offset) {
+            return Ok(Some("invalid snapshot position".to_string()));
+        }
+        for file_id in self.file_ids.iter().filter(|id| **id <= mark.file_id) {
+            if !recorded.contains_key(file_id) {
+                return Ok(Some(format!("data file {file_id} is not in the snapshot")));
+            }
+        }
+        for f in snapshot_files {
+            if !self.file_ids.contains(&f.file_id) {
+                return Ok(Some(format!("data file {} is missing", f.file_id)));
+            }
+            let len = disk_len(dir_path, f.file_id)?;
+            // 快照所在的文件之后可能继续写入，之前的文件不会再修改
+            let unchanged = match f.file_id == mark.file_id {
+                true => len >= f.len,
+                false => len == f.disk_len,
+            };
+            if !unchanged {
+                return Ok(Some(format!("data file {} has changed", f.file_id)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn disk_len(dir_path: &Path, file_id: u32) -> Result<u64> {
+    match fs::metadata(get_data_file_name(dir_path, file_id)) {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(e) => {
+            warn!("Failed to get metadata of data file {file_id}: {e}");
+            Err(Errors::FailedToReadFromDataFile(e.into()))
+        }
+    }
+}
+
+// 校验值已经通过时仍然无法解析，说明是其他版本写入的文件
+fn snapshot_corrupted(e: io::Error) -> Errors {
+    warn!("Failed to parse index snapshot: {e}");
+    Errors::DataFileCorrupted
+}
+
+// 最后 4 字节为之前所有内容的 CRC32
+fn verify_checksum(path: &Path) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if len < 4 {
+        return Ok(false);
+    }
+    let mut w = CrcWriter::new(io::sink());
+    io::copy(&mut (&mut file).take(len - 4), &mut w)?;
+    let mut crc = [0u8; 4];
+    file.read_exact(&mut crc)?;
+    Ok(w.hasher.finalize() == u32::from_le_bytes(crc))
+}
+
+fn read_header(r: &mut impl Read) -> io::Result<(SnapshotMark, Vec<SnapshotFile>)> {
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != INDEX_SNAPSHOT_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid magic"));
+    }
+    let seq = read_u64(r)?;
+    let file_id = read_u32(r)?;
+    let offset = read_u64(r)?;
+    let count = read_u32(r)?;
+    let mut files = Vec::new();
+    for _ in 0..count {
+        files.push(SnapshotFile {
+            file_id: read_u32(r)?,
+            len: read_u64(r)?,
+            disk_len: read_u64(r)?,
+            record_count: read_u64(r)?,
+        });
+    }
+    let mark = SnapshotMark {
+        file_id,
+        offset,
+        seq,
+    };
+    Ok((mark, files))
+}
+
+fn read_entry(r: &mut impl Read) -> io::Result<Option<(Vec<u8>, LogRecordPos)>> {
+    let key_len = read_u32(r)?;
+    if key_len == END_OF_ENTRIES {
+        return Ok(None);
+    }
+    let mut key = vec![0u8; key_len as usize];
+    r.read_exact(&mut key)?;
+    let pos = LogRecordPos {
+        file_id: read_u32(r)?,
+        offset: read_u64(r)?,
+        size: read_u64(r)?,
+    };
+    Ok(Some((key, pos)))
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+// 写入的同时计算校验值
+struct CrcWriter<W: Write> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+}
+
+impl<W: Write> CrcWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for CrcWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    // 索引中所有的 key 和位置
+    fn index_entries(engine: &Engine) -> Vec<(bytes::Bytes, LogRecordPos)> {
+        IndexIter::from(engine.index.iterator(Default::default())).collect()
+    }
+
+    // 使用索引快照打开时不测量加载的数据量
+    fn loaded_from_snapshot(engine: &Engine) -> bool {
+        engine.open_cost.data_bytes == 0
+    }
+
+    #[test]
+    fn test_index_snapshot() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-index-snapshot");
+        opts.data_file_size = 32 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..2000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        for i in 0..500 {
+            engine.delete(get_test_key(i)).unwrap();
+        }
+        let wb = engine.new_write_batch(Default::default()).unwrap();
+        wb.put(get_test_key(5000), get_test_value(5000)).unwrap();
+        wb.commit().unwrap();
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+        assert!(opts.dir_path.join(INDEX_SNAPSHOT_FILE_NAME).is_file());
+
+        // 加载快照得到的索引和统计与回放所有数据文件相同
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let entries = index_entries(&engine);
+        let stats = engine.file_stats();
+        let seq = engine.current_seq();
+        assert_eq!(
+            engine.get(get_test_key(1000)).unwrap(),
+            get_test_value(1000)
+        );
+        assert!(engine.try_get(get_test_key(100)).unwrap().is_none());
+        assert!(loaded_from_snapshot(&engine));
+        std::mem::drop(engine);
+        let mut full_opts = opts.clone();
+        full_opts.index_snapshot = false;
+        let engine = Engine::open(full_opts.clone()).expect("failed to open engine");
+        assert_eq!(index_entries(&engine), entries);
+        assert_eq!(engine.file_stats(), stats);
+        assert_eq!(engine.current_seq(), seq);
+        std::mem::drop(engine);
+
+        // 快照之后继续写入并切换数据文件，没有关闭时只回放快照之后的数据
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 2000..3000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine.delete(get_test_key(1000)).unwrap();
+        engine.sync().unwrap();
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(loaded_from_snapshot(&engine));
+        assert!(engine.try_get(get_test_key(1000)).unwrap().is_none());
+        assert_eq!(
+            engine.get(get_test_key(2500)).unwrap(),
+            get_test_value(2500)
+        );
+        assert_eq!(engine.list_keys().unwrap().len(), 2500);
+
+        // 合并删除了快照之前的数据文件，快照失效
+        engine.merge().unwrap();
+        engine.sync().unwrap();
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(!loaded_from_snapshot(&engine));
+        assert_eq!(engine.list_keys().unwrap().len(), 2500);
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+
+        // 损坏的快照被忽略
+        let path = opts.dir_path.join(INDEX_SNAPSHOT_FILE_NAME);
+        let mut content = fs::read(&path).unwrap();
+        let n = content.len();
+        content[n / 2] ^= 0xff;
+        fs::write(&path, &content).unwrap();
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(!loaded_from_snapshot(&engine));
+        assert_eq!(engine.list_keys().unwrap().len(), 2500);
+        assert_eq!(
+            engine.get(get_test_key(2999)).unwrap(),
+            get_test_value(2999)
+        );
+        engine.close().expect("failed to close");
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
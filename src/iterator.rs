@@ -1,28 +1,155 @@
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Bound,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 use bytes::Bytes;
+use log::error;
 use parking_lot::RwLock;
 
-use crate::{db::Engine, errors::Result, index::IndexerIterator, options::IteratorOptions};
+use crate::{
+    batch::{try_parse_log_record_key, NON_TRANSACTION_SEQ_NO},
+    data::log_record::{LogRecordPos, LogRecordType},
+    db::{is_internal_key, DataFiles, Engine},
+    errors::Result,
+    index::{btree::BTreeIterator, Indexer, IndexerIterator},
+    options::{IteratorConsistency, IteratorOptions},
+};
 
 // 迭代器接口
+// 迭代器创建时持有当时的数据文件集合，之后即使 merge 淘汰了旧的数据文件，
+// 这些文件也会在迭代器释放之后才被删除，迭代过程中读取到的仍然是创建时的数据
 pub struct Iterator<'a> {
     // 索引迭代器
     index_iter: Arc<RwLock<Box<dyn IndexerIterator>>>,
+    // 创建迭代器时的数据文件集合
+    files: Arc<DataFiles>,
     engine: &'a Engine,
+    _live: LiveIterator,
 }
 
 impl Engine {
-    pub fn iter(&self, options: IteratorOptions) -> Iterator {
-        Iterator {
-            index_iter: Arc::new(RwLock::new(self.index.iterator(options))),
+    /// 创建迭代器，启用内存写缓冲时其中的数据写入失败只记录日志，迭代器中不包含这部分数据；
+    /// 扫描删除标记失败时同样只记录日志，迭代器中只包含有效数据。需要感知错误时使用 try_iter
+    pub fn iter(&self, options: IteratorOptions) -> Iterator<'_> {
+        if let Err(e) = self.flush_memtable() {
+            error!("Failed to flush memtable before iterating: {e}");
+        }
+        match self.new_iterator(options.clone()) {
+            Ok(iter) => iter,
+            Err(e) => {
+                error!("Failed to scan tombstones from data files: {e}");
+                let options = IteratorOptions {
+                    include_tombstones: false,
+                    ..options
+                };
+                self.new_iterator(options)
+                    .expect("iterator without tombstones never fails")
+            }
+        }
+    }
+
+    /// 创建迭代器，内存写缓冲中的数据写入数据文件失败、或者扫描删除标记失败时返回错误
+    pub fn try_iter(&self, options: IteratorOptions) -> Result<Iterator<'_>> {
+        // 迭代器只遍历索引，先把内存写缓冲中的数据写入数据文件
+        self.flush_memtable()?;
+        self.new_iterator(options)
+    }
+
+    fn new_iterator(&self, options: IteratorOptions) -> Result<Iterator<'_>> {
+        // 在获取索引之前计数，打洞时不会回收迭代器持有的位置
+        let live = LiveIterator::new(&self.live_iterators);
+        // 先获取数据文件集合再获取索引，索引中的位置只可能指向该集合中的文件或者之后新建的文件
+        let files = self.files.load();
+        let index_iter = match (options.include_tombstones, options.consistency) {
+            (true, _) => self.tombstone_iterator(options)?,
+            (false, IteratorConsistency::Snapshot) => self.index.iterator(options),
+            (false, IteratorConsistency::ReadCommitted) => {
+                Box::new(ReadCommittedIterator::new(self.index.clone(), options))
+            }
+        };
+        Ok(Iterator {
+            index_iter: Arc::new(RwLock::new(index_iter)),
+            files,
             engine: self,
+            _live: live,
+        })
+    }
+
+    // 构造同时包含有效数据和删除标记的迭代器
+    // 删除标记从数据文件中扫描得到，只保留最后一次操作是删除的 key，扫描失败时返回错误
+    fn tombstone_iterator(&self, options: IteratorOptions) -> Result<Box<dyn IndexerIterator>> {
+        let mut items = BTreeMap::new();
+        let mut index_iter = self.index.iterator(IteratorOptions {
+            prefix: options.prefix.clone(),
+            ..Default::default()
+        });
+        while let Some((key, pos)) = index_iter.next() {
+            items.insert(key.to_vec(), pos);
+        }
+
+        // 每个 key 最后一条已提交的记录
+        let mut latest = HashMap::new();
+        let mut transaction_records = HashMap::new();
+        let mut parse_err = None;
+        self.scan_log_records(|pos, record| {
+            let (real_key, seq_no) = match try_parse_log_record_key(record.key) {
+                Ok(res) => res,
+                Err(e) => {
+                    parse_err = Some(e);
+                    return false;
+                }
+            };
+            if seq_no == NON_TRANSACTION_SEQ_NO {
+                latest.insert(real_key, (record.rec_type, pos));
+            } else if record.rec_type == LogRecordType::TXNFINISH {
+                let records: Vec<(Vec<u8>, LogRecordType, LogRecordPos)> =
+                    transaction_records.remove(&seq_no).unwrap_or_default();
+                for (key, rec_type, pos) in records.into_iter() {
+                    latest.insert(key, (rec_type, pos));
+                }
+            } else {
+                transaction_records
+                    .entry(seq_no)
+                    .or_insert(Vec::new())
+                    .push((real_key, record.rec_type, pos));
+            }
+            true
+        })?;
+        if let Some(e) = parse_err {
+            return Err(e);
         }
+
+        for (key, (rec_type, pos)) in latest.into_iter() {
+            if rec_type == LogRecordType::DELETED
+                && key.starts_with(&options.prefix)
+                && !items.contains_key(&key)
+            {
+                items.insert(key, pos);
+            }
+        }
+
+        Ok(Box::new(BTreeIterator::new(
+            items.into_iter().collect(),
+            options,
+        )))
     }
 
-    // 返回数据库中所有的key
+    // 返回数据库中所有的key，按 key 的字节序升序排列
     pub fn list_keys(&self) -> Result<Vec<Bytes>> {
-        self.index.list_keys()
+        self.list_keys_with(false, &[])
+    }
+
+    // 返回以 prefix 开头的所有 key，reverse 为 true 时按降序排列
+    pub fn list_keys_with(&self, reverse: bool, prefix: &[u8]) -> Result<Vec<Bytes>> {
+        self.flush_memtable()?;
+        let mut keys = self.index.list_keys(reverse, prefix)?;
+        keys.retain(|key| !is_internal_key(key) && !self.is_expired(key));
+        Ok(keys)
     }
 
     // 对数据库中所有数据进行操作。
@@ -33,7 +160,7 @@ impl Engine {
         Self: Sized,
         F: Fn(Bytes, Bytes) -> bool,
     {
-        let iter = self.iter(Default::default());
+        let iter = self.try_iter(Default::default())?;
         while let Some((key, value)) = iter.next() {
             if !f(key, value) {
                 break;
@@ -43,40 +170,171 @@ impl Engine {
     }
 }
 
-#[allow(unused)]
+// 迭代器释放时减少 Engine::live_iterators 的计数
+// 不借用 Engine，迭代器最后一次使用之后就可以关闭数据库
+pub(crate) struct LiveIterator(Arc<AtomicUsize>);
+
+impl LiveIterator {
+    pub(crate) fn new(count: &Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::SeqCst);
+        Self(count.clone())
+    }
+}
+
+impl Drop for LiveIterator {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 impl Iterator<'_> {
     // Rewind 从新回到迭代器的起点，即第一个数据
-    fn rewind(&self) {
+    pub fn rewind(&self) {
         let mut index_iter = self.index_iter.write();
         index_iter.rewind();
     }
 
     // Seek 根据传入的key 查找第一恶大于或小于等于的目标key，从这个key开始遍历
-    fn seek(&self, key: Vec<u8>) {
+    pub fn seek(&self, key: Vec<u8>) {
         let mut index_iter = self.index_iter.write();
         index_iter.seek(key);
     }
 
     // Next 跳转到下一个key，返回None则说明迭代完毕
-    fn next(&self) -> Option<(Bytes, Bytes)> {
+    // 遍历删除标记时，被删除的 key 对应的 value 为空
+    pub fn next(&self) -> Option<(Bytes, Bytes)> {
+        self.next_record().map(|(key, value, _)| (key, value))
+    }
+
+    // NextRecord 跳转到下一个key，同时返回记录的类型，用于区分有效数据和删除标记
+    pub fn next_record(&self) -> Option<(Bytes, Bytes, LogRecordType)> {
         let mut index_iter = self.index_iter.write();
-        if let Some(item) = index_iter.next() {
-            let value = self
-                .engine
-                .get_value_by_position(item.1)
-                .expect("failed to get value from data file");
-            return Some((Bytes::from(item.0.to_vec()), value));
+        while let Some(item) = index_iter.next() {
+            // 跳过引擎内部的数据和已经过期的前缀下的数据
+            if is_internal_key(&item.0) || self.engine.is_expired(&item.0) {
+                continue;
+            }
+            // 创建迭代器之后新建的文件不在集合中，从当前的数据文件中读取
+            let record = match self.files.get(item.1.file_id) {
+                Some(_) => self.files.read_log_record_at(&item.1),
+                None => self.engine.read_log_record_at(&item.1),
+            }
+            .and_then(|record| self.engine.resolve_chunks(record))
+            .expect("failed to get value from data file");
+            return Some((item.0, record.value.into(), record.rec_type));
         }
 
         None
     }
+
+    // CollectN 一次读取最多 n 条数据，返回的数量小于 n 说明迭代完毕
+    // 一次从索引中取出多个 key，再按照数据文件和偏移量的顺序读取 value，比逐个调用 next 的吞吐更高
+    pub fn collect_n(&self, n: usize) -> Result<Vec<(Bytes, Bytes)>> {
+        let mut index_iter = self.index_iter.write();
+        let mut items = Vec::with_capacity(n.min(1024));
+        while items.len() < n {
+            let batch = index_iter.next_n(n - items.len());
+            let exhausted = batch.len() < n - items.len();
+            items.extend(
+                batch
+                    .into_iter()
+                    .filter(|(key, _)| !is_internal_key(key) && !self.engine.is_expired(key)),
+            );
+            if exhausted {
+                break;
+            }
+        }
+        drop(index_iter);
+
+        // 按照位置排序之后读取，同一个文件中的读取是顺序的
+        let mut order: Vec<usize> = (0..items.len()).collect();
+        order.sort_by_key(|&i| (items[i].1.file_id, items[i].1.offset));
+        let current = self.engine.files.load();
+        let mut values = vec![Bytes::new(); items.len()];
+        for i in order {
+            let pos = &items[i].1;
+            // 创建迭代器之后新建的文件不在集合中，从当前的数据文件中读取
+            let record = match self.files.get(pos.file_id) {
+                Some(_) => self.files.read_log_record_at(pos),
+                None => current.read_log_record_at(pos),
+            }
+            .and_then(|record| self.engine.resolve_chunks(record))?;
+            values[i] = record.value.into();
+        }
+        Ok(items.into_iter().map(|(key, _)| key).zip(values).collect())
+    }
+}
+
+// 读已提交模式下的索引迭代器，不复制索引，每次从索引中读取上一个 key 之后的第一个 key
+struct ReadCommittedIterator {
+    index: Arc<dyn Indexer>,
+    options: IteratorOptions,
+    // 下一次从索引中读取的起点
+    cursor: Bound<Vec<u8>>,
+}
+
+impl ReadCommittedIterator {
+    fn new(index: Arc<dyn Indexer>, options: IteratorOptions) -> Self {
+        Self {
+            index,
+            options,
+            cursor: Bound::Unbounded,
+        }
+    }
+}
+
+impl IndexerIterator for ReadCommittedIterator {
+    fn rewind(&mut self) {
+        self.cursor = Bound::Unbounded;
+    }
+
+    fn seek(&mut self, key: Vec<u8>) {
+        self.cursor = Bound::Included(key);
+    }
+
+    fn next(&mut self) -> Option<(Bytes, LogRecordPos)> {
+        let (key, pos) = self
+            .index
+            .scan(&self.options, self.cursor.as_ref().map(Vec::as_slice), 1)
+            .pop()?;
+        self.cursor = Bound::Excluded(key.to_vec());
+        Some((key, pos))
+    }
+
+    // 一次扫描读取多个 key，只访问一次索引
+    fn next_n(&mut self, n: usize) -> Vec<(Bytes, LogRecordPos)> {
+        if n == 0 {
+            return Vec::new();
+        }
+        let items = self
+            .index
+            .scan(&self.options, self.cursor.as_ref().map(Vec::as_slice), n);
+        if let Some((key, _)) = items.last() {
+            self.cursor = Bound::Excluded(key.to_vec());
+        }
+        items
+    }
+}
+
+// 标准库的迭代器适配器，可以使用 map、filter、take 等适配器，返回的数据与 next 相同
+impl std::iter::Iterator for Iterator<'_> {
+    type Item = (Bytes, Bytes);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Iterator::next(self)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
 
-    use crate::{options::Options, utils};
+    use crate::{
+        data::{data_file::get_data_file_name, log_record::LogRecord},
+        errors::Errors,
+        options::Options,
+        utils,
+    };
 
     use super::*;
 
@@ -101,6 +359,30 @@ mod tests {
         let keys2 = engine.list_keys();
         assert_eq!(keys2.ok().unwrap().len(), 4);
 
+        // key 按字节序排列，可以指定前缀和顺序
+        assert_eq!(
+            engine.list_keys().unwrap(),
+            vec![
+                Bytes::from("aacc"),
+                Bytes::from("bbac"),
+                Bytes::from("ccde"),
+                Bytes::from("eecc")
+            ]
+        );
+        assert_eq!(
+            engine.list_keys_with(true, &[]).unwrap(),
+            vec![
+                Bytes::from("eecc"),
+                Bytes::from("ccde"),
+                Bytes::from("bbac"),
+                Bytes::from("aacc")
+            ]
+        );
+        assert_eq!(
+            engine.list_keys_with(true, b"cc").unwrap(),
+            vec![Bytes::from("ccde")]
+        );
+
         // 删除测试的文件夹
         std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
     }
@@ -122,9 +404,9 @@ mod tests {
 
         engine
             .fold(|key, value| {
-                assert!(key.len() > 0);
-                assert!(value.len() > 0);
-                return true;
+                assert!(!key.is_empty());
+                assert!(!value.is_empty());
+                true
             })
             .unwrap();
 
@@ -193,7 +475,7 @@ mod tests {
         iter_opts1.reverse = true;
         let iter2 = engine.iter(iter_opts1);
         while let Some(item) = iter2.next() {
-            assert!(item.0.len() > 0);
+            assert!(!item.0.is_empty());
         }
 
         // 删除测试的文件夹
@@ -221,7 +503,323 @@ mod tests {
         iter_opt1.prefix = "dd".as_bytes().to_vec();
         let iter1 = engine.iter(iter_opt1);
         while let Some(item) = iter1.next() {
-            assert!(item.0.len() > 0);
+            assert!(!item.0.is_empty());
+        }
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_iterator_include_tombstones() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-iter-tombstones");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let put_res1 = engine.put(Bytes::from("aacc"), utils::rand_kv::get_test_value(10));
+        assert!(put_res1.is_ok());
+        let put_res2 = engine.put(Bytes::from("bbcc"), utils::rand_kv::get_test_value(10));
+        assert!(put_res2.is_ok());
+        let put_res3 = engine.put(Bytes::from("ccde"), utils::rand_kv::get_test_value(10));
+        assert!(put_res3.is_ok());
+        let del_res1 = engine.delete(Bytes::from("bbcc"));
+        assert!(del_res1.is_ok());
+        // 删除之后重新写入的 key 不是删除标记
+        let del_res2 = engine.delete(Bytes::from("ccde"));
+        assert!(del_res2.is_ok());
+        let put_res4 = engine.put(Bytes::from("ccde"), utils::rand_kv::get_test_value(11));
+        assert!(put_res4.is_ok());
+
+        // 事务中的删除
+        let wb = engine.new_write_batch(Default::default()).unwrap();
+        wb.delete(Bytes::from("aacc")).unwrap();
+        wb.commit().unwrap();
+
+        let mut iter_opts = IteratorOptions::default();
+        iter_opts.include_tombstones = true;
+        let iter1 = engine.iter(iter_opts.clone());
+        let mut items = Vec::new();
+        while let Some((key, value, rec_type)) = iter1.next_record() {
+            items.push((key, value, rec_type));
+        }
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].0, Bytes::from("aacc"));
+        assert_eq!(items[0].2, LogRecordType::DELETED);
+        assert_eq!(items[1].0, Bytes::from("bbcc"));
+        assert_eq!(items[1].2, LogRecordType::DELETED);
+        assert!(items[1].1.is_empty());
+        assert_eq!(items[2].0, Bytes::from("ccde"));
+        assert_eq!(items[2].1, utils::rand_kv::get_test_value(11));
+        assert_eq!(items[2].2, LogRecordType::NORMAL);
+
+        // 默认不包含删除标记
+        let iter2 = engine.iter(IteratorOptions::default());
+        assert_eq!(iter2.next().unwrap().0, Bytes::from("ccde"));
+        assert!(iter2.next().is_none());
+
+        // 前缀和反向遍历
+        iter_opts.prefix = "bb".as_bytes().to_vec();
+        iter_opts.reverse = true;
+        let iter3 = engine.iter(iter_opts);
+        assert_eq!(iter3.next().unwrap().0, Bytes::from("bbcc"));
+        assert!(iter3.next().is_none());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    // 格式不正确的记录会触发 strict-invariants 的检查，只在没有启用该特性时运行
+    #[test]
+    #[cfg(not(feature = "strict-invariants"))]
+    fn test_iterator_tombstones_corrupted_key() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-iter-tombstones-corrupted");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let put_res = engine.put(Bytes::from("aacc"), utils::rand_kv::get_test_value(10));
+        assert!(put_res.is_ok());
+
+        // 数据文件中 key 的序列号格式不正确时 try_iter 返回错误，iter 只包含有效数据
+        let mut record = LogRecord {
+            key: vec![0xff; 11],
+            value: Default::default(),
+            rec_type: LogRecordType::DELETED,
+            meta: Default::default(),
+        };
+        let _ = engine.append_log_record(&mut record).unwrap();
+        let mut iter_opts = IteratorOptions::default();
+        iter_opts.include_tombstones = true;
+        assert_eq!(
+            engine.try_iter(iter_opts.clone()).err().unwrap(),
+            Errors::DataFileCorrupted
+        );
+        let iter = engine.iter(iter_opts);
+        assert_eq!(iter.next().unwrap().0, Bytes::from("aacc"));
+        assert!(iter.next().is_none());
+        std::mem::drop(engine);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_iterator_stable_after_files_retired() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-iter-retired");
+        opts.data_file_size = 4 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        for i in 0..200 {
+            let put_res = engine.put(
+                utils::rand_kv::get_test_key(i),
+                utils::rand_kv::get_test_value(i),
+            );
+            assert!(put_res.is_ok());
+        }
+        let older_ids = engine
+            .files
+            .load()
+            .older
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        assert!(!older_ids.is_empty());
+
+        let iter = engine.iter(IteratorOptions::default());
+        assert!(iter.next().is_some());
+
+        // 模拟 merge 淘汰旧的数据文件，迭代器仍然可以读取到所有数据
+        engine.retire_data_files(&older_ids).unwrap();
+        let path = get_data_file_name(&opts.dir_path, older_ids[0]);
+        assert!(path.is_file());
+        let mut count = 1;
+        while let Some((_, value)) = iter.next() {
+            assert!(!value.is_empty());
+            count += 1;
+        }
+        assert_eq!(count, 200);
+
+        // 迭代器释放之后文件被删除
+        std::mem::drop(iter);
+        assert!(!path.exists());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_iterator_std_adapter() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-iter-std-adapter");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..10 {
+            let put_res = engine.put(
+                utils::rand_kv::get_test_key(i),
+                utils::rand_kv::get_test_value(i),
+            );
+            assert!(put_res.is_ok());
+        }
+
+        let iter = engine.iter(IteratorOptions::default());
+        let values = iter
+            .filter(|(key, _)| key.ends_with(b"1") || key.ends_with(b"2"))
+            .map(|(_, value)| value)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![
+                utils::rand_kv::get_test_value(1),
+                utils::rand_kv::get_test_value(2)
+            ]
+        );
+        assert_eq!(engine.iter(IteratorOptions::default()).count(), 10);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_iterator_read_committed() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-iter-read-committed");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..300 {
+            let put_res = engine.put(
+                utils::rand_kv::get_test_key(i * 2),
+                utils::rand_kv::get_test_value(i),
+            );
+            assert!(put_res.is_ok());
+        }
+
+        let snapshot = engine.iter(IteratorOptions::default());
+        let read_committed = engine.iter(IteratorOptions {
+            consistency: IteratorConsistency::ReadCommitted,
+            ..Default::default()
+        });
+        assert!(snapshot.next().is_some());
+        assert!(read_committed.next().is_some());
+
+        // 创建之后写入新的 key、覆盖和删除已有的 key
+        for i in 0..300 {
+            let put_res = engine.put(
+                utils::rand_kv::get_test_key(i * 2 + 1),
+                utils::rand_kv::get_test_value(i),
+            );
+            assert!(put_res.is_ok());
+        }
+        let put_res = engine.put(utils::rand_kv::get_test_key(500), Bytes::from("new"));
+        assert!(put_res.is_ok());
+        let del_res = engine.delete(utils::rand_kv::get_test_key(400));
+        assert!(del_res.is_ok());
+
+        // 快照只能看到创建时的数据
+        let items = snapshot.collect::<HashMap<_, _>>();
+        assert_eq!(items.len(), 299);
+        assert_eq!(
+            items.get(&utils::rand_kv::get_test_key(500)).unwrap(),
+            &utils::rand_kv::get_test_value(250)
+        );
+        assert!(items.contains_key(&utils::rand_kv::get_test_key(400)));
+
+        // 读已提交可以看到新写入的数据，看不到已删除的数据
+        let items = read_committed.collect::<HashMap<_, _>>();
+        assert_eq!(items.len(), 598);
+        assert_eq!(
+            items.get(&utils::rand_kv::get_test_key(500)).unwrap(),
+            &Bytes::from("new")
+        );
+        assert!(!items.contains_key(&utils::rand_kv::get_test_key(400)));
+
+        // seek、rewind、前缀和反向遍历
+        let read_committed = engine.iter(IteratorOptions {
+            reverse: true,
+            consistency: IteratorConsistency::ReadCommitted,
+            ..Default::default()
+        });
+        assert_eq!(read_committed.count(), 599);
+        let read_committed = engine.iter(IteratorOptions {
+            reverse: true,
+            consistency: IteratorConsistency::ReadCommitted,
+            ..Default::default()
+        });
+        read_committed.seek(utils::rand_kv::get_test_key(10).to_vec());
+        assert_eq!(
+            read_committed.next().unwrap().0,
+            utils::rand_kv::get_test_key(10)
+        );
+        assert_eq!(read_committed.count(), 10);
+        let read_committed = engine.iter(IteratorOptions {
+            prefix: b"bitcask-rs-key-00000001".to_vec(),
+            consistency: IteratorConsistency::ReadCommitted,
+            ..Default::default()
+        });
+        assert_eq!(
+            read_committed.next().unwrap().0,
+            utils::rand_kv::get_test_key(10)
+        );
+        read_committed.rewind();
+        assert_eq!(read_committed.count(), 10);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_iterator_collect_n() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-iter-collect-n");
+        opts.data_file_size = 4 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 数据分布在多个数据文件中，写入顺序与 key 的顺序不同
+        for i in (0..300).rev() {
+            let key = Bytes::from(format!("key-{:03}", i));
+            engine.put(key, utils::rand_kv::get_test_value(i)).unwrap();
+        }
+        engine.put_cas_blob(Bytes::from("internal")).unwrap();
+        assert!(engine.files.read().older.len() > 1);
+
+        for consistency in [
+            IteratorConsistency::Snapshot,
+            IteratorConsistency::ReadCommitted,
+        ] {
+            let iter = engine.iter(IteratorOptions {
+                consistency,
+                ..Default::default()
+            });
+            let mut all = Vec::new();
+            loop {
+                let batch = iter.collect_n(64).unwrap();
+                let done = batch.len() < 64;
+                all.extend(batch);
+                if done {
+                    break;
+                }
+            }
+            let expected: Vec<_> = engine.iter(Default::default()).collect();
+            assert_eq!(all.len(), 300);
+            assert_eq!(all, expected);
+            assert!(iter.collect_n(64).unwrap().is_empty());
+
+            // 与 seek、prefix、reverse 一起使用
+            let iter = engine.iter(IteratorOptions {
+                prefix: b"key-1".to_vec(),
+                reverse: true,
+                consistency,
+                ..Default::default()
+            });
+            iter.seek(b"key-150".to_vec());
+            let batch = iter.collect_n(3).unwrap();
+            let keys: Vec<_> = batch.iter().map(|(k, _)| k.clone()).collect();
+            assert_eq!(
+                keys,
+                vec![
+                    Bytes::from("key-150"),
+                    Bytes::from("key-149"),
+                    Bytes::from("key-148")
+                ]
+            );
+            assert_eq!(batch[0].1, utils::rand_kv::get_test_value(150));
+            assert_eq!(iter.next().unwrap().0, Bytes::from("key-147"));
         }
 
         // 删除测试的文件夹
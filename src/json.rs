@@ -0,0 +1,322 @@
+//! 以 JSON 文档存储的 value，通过 JSON Pointer（RFC 6901）读取或者修改文档中的一部分
+//! 客户端不需要每次读取和写回整个文档，适合配置存储之类的场景
+//! 文档的解析和序列化由 serde_json 完成
+
+use bytes::Bytes;
+use serde_json::Map;
+
+use crate::{
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+/// JSON 值，对象中的字段保持写入时的顺序，数字保存原始的文本，不会丢失精度
+pub type JsonValue = serde_json::Value;
+
+/// 根据 JSON Pointer 获取文档中的值，空字符串表示整个文档
+pub fn pointer<'a>(document: &'a JsonValue, path: &str) -> Result<Option<&'a JsonValue>> {
+    let mut value = document;
+    for token in parse_pointer(path)? {
+        let next = match value {
+            JsonValue::Object(fields) => fields.get(&token),
+            JsonValue::Array(items) => array_index(&token).and_then(|i| items.get(i)),
+            _ => None,
+        };
+        value = match next {
+            Some(next) => next,
+            None => return Ok(None),
+        };
+    }
+    Ok(Some(value))
+}
+
+/// 将 JSON Pointer 指向的位置设置为 value
+/// 不存在的中间对象会被创建，数组可以使用 "-" 或者数组长度作为下标在末尾追加
+pub fn set_pointer(document: &mut JsonValue, path: &str, value: JsonValue) -> Result<()> {
+    let tokens = parse_pointer(path)?;
+    let mut target = document;
+    for token in tokens {
+        target = match target {
+            JsonValue::Object(fields) => fields
+                .entry(token)
+                .or_insert_with(|| JsonValue::Object(Map::new())),
+            JsonValue::Array(items) => {
+                let i = match token.as_str() {
+                    "-" => items.len(),
+                    _ => array_index(&token).ok_or(Errors::InvalidJsonPath)?,
+                };
+                if i > items.len() {
+                    return Err(Errors::InvalidJsonPath);
+                }
+                if i == items.len() {
+                    items.push(JsonValue::Object(Map::new()));
+                }
+                &mut items[i]
+            }
+            // 不能在数字、字符串等值下面添加字段
+            _ => return Err(Errors::InvalidJsonPath),
+        };
+    }
+    *target = value;
+    Ok(())
+}
+
+// 解析 JSON Pointer，返回反转义之后的每一段
+fn parse_pointer(path: &str) -> Result<Vec<String>> {
+    if path.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rest = match path.strip_prefix('/') {
+        Some(rest) => rest,
+        None => return Err(Errors::InvalidJsonPath),
+    };
+    rest.split('/')
+        .map(|token| {
+            let mut unescaped = String::with_capacity(token.len());
+            let mut chars = token.chars();
+            while let Some(c) = chars.next() {
+                if c != '~' {
+                    unescaped.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('0') => unescaped.push('~'),
+                    Some('1') => unescaped.push('/'),
+                    _ => return Err(Errors::InvalidJsonPath),
+                }
+            }
+            Ok(unescaped)
+        })
+        .collect()
+}
+
+// 数组下标不能有前导零
+fn array_index(token: &str) -> Option<usize> {
+    if token.is_empty() || (token.len() > 1 && token.starts_with('0')) {
+        return None;
+    }
+    if !token.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+// 解析存储的 value
+fn parse_document(value: &[u8]) -> Result<JsonValue> {
+    serde_json::from_slice(value).map_err(|_| Errors::InvalidJson)
+}
+
+impl Engine {
+    /// 读取 key 对应的 JSON 文档中 path 指向的值，path 为空字符串时返回整个文档
+    /// key 不存在时返回 Errors::KeyNotFound，path 不存在时返回 Errors::JsonPathNotFound
+    pub fn json_get(&self, key: Bytes, path: &str) -> Result<JsonValue> {
+        let document = parse_document(&self.get(key)?)?;
+        match pointer(&document, path)? {
+            Some(value) => Ok(value.clone()),
+            None => Err(Errors::JsonPathNotFound),
+        }
+    }
+
+    /// 将 key 对应的 JSON 文档中 path 指向的位置设置为 value 并写回，key 不存在时从空对象开始
+    /// 读取和写回之间有其他写入时重新读取，不会覆盖并发的修改
+    /// 文档总是作为一条记录写入，不会按照 Options::value_chunk_size 分块
+    pub fn json_set(&self, key: Bytes, path: &str, value: JsonValue) -> Result<()> {
+        self.check_key(&key)?;
+        loop {
+            let pos = self.index.get(key.to_vec());
+            let mut document = match pos {
+                Some(_) => match self.get_indexed_log_record(&key) {
+                    Ok(record) => parse_document(&record.value)?,
+                    // 读取期间被并发删除
+                    Err(Errors::KeyNotFound) => continue,
+                    Err(e) => return Err(e),
+                },
+                None => JsonValue::Object(Map::new()),
+            };
+            set_pointer(&mut document, path, value.clone())?;
+            let encoded = document.to_string();
+            if self.put_if_unchanged(&key, pos, encoded.into_bytes())? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc, thread};
+
+    use crate::options::Options;
+
+    use super::*;
+
+    fn json(s: &str) -> JsonValue {
+        parse_document(s.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_json_parse_and_display() {
+        let text = r#"{"a":{"b":[1,-2.5e+3,true,null]},"s":"q\"\\\n\u00e9\ud83d\ude00","e":{}}"#;
+        let value = json(text);
+        assert_eq!(
+            pointer(&value, "/s").unwrap(),
+            Some(&JsonValue::from("q\"\\\né😀"))
+        );
+        assert_eq!(json(&value.to_string()), value);
+        assert_eq!(
+            json(" [ 1 , { \"a\" : 2 } ] ").to_string(),
+            r#"[1,{"a":2}]"#
+        );
+        // 重复的字段以最后一个为准
+        assert_eq!(json(r#"{"a":1,"a":2}"#).to_string(), r#"{"a":2}"#);
+
+        for invalid in [
+            "",
+            "{",
+            "[1,]",
+            "01",
+            "1.",
+            "-",
+            "tru",
+            "\"a",
+            "{\"a\" 1}",
+            "1 2",
+            "\"\\x\"",
+            "\"\\ud800\"",
+        ] {
+            assert_eq!(
+                parse_document(invalid.as_bytes()),
+                Err(Errors::InvalidJson),
+                "{invalid}"
+            );
+        }
+        // 嵌套过深的输入不会导致栈溢出
+        let deep = "[".repeat(200) + &"]".repeat(200);
+        assert_eq!(parse_document(deep.as_bytes()), Err(Errors::InvalidJson));
+    }
+
+    #[test]
+    fn test_json_pointer() {
+        let mut value = json(r#"{"a":{"b":[1,2]},"c/d":3,"e~f":4}"#);
+        assert_eq!(pointer(&value, "").unwrap(), Some(&value.clone()));
+        assert_eq!(pointer(&value, "/a/b/1").unwrap(), Some(&json("2")));
+        assert_eq!(pointer(&value, "/c~1d").unwrap(), Some(&json("3")));
+        assert_eq!(pointer(&value, "/e~0f").unwrap(), Some(&json("4")));
+        assert_eq!(pointer(&value, "/a/b/01").unwrap(), None);
+        assert_eq!(pointer(&value, "/a/x").unwrap(), None);
+        assert_eq!(pointer(&value, "a"), Err(Errors::InvalidJsonPath));
+        assert_eq!(pointer(&value, "/~2"), Err(Errors::InvalidJsonPath));
+
+        set_pointer(&mut value, "/a/b/0", json("10")).unwrap();
+        set_pointer(&mut value, "/a/b/-", json("3")).unwrap();
+        set_pointer(&mut value, "/a/b/3", json("4")).unwrap();
+        set_pointer(&mut value, "/x/y/z", json("true")).unwrap();
+        assert_eq!(
+            value.to_string(),
+            r#"{"a":{"b":[10,2,3,4]},"c/d":3,"e~f":4,"x":{"y":{"z":true}}}"#
+        );
+        assert_eq!(
+            set_pointer(&mut value, "/a/b/9", json("1")),
+            Err(Errors::InvalidJsonPath)
+        );
+        assert_eq!(
+            set_pointer(&mut value, "/c~1d/x", json("1")),
+            Err(Errors::InvalidJsonPath)
+        );
+        set_pointer(&mut value, "", json("[]")).unwrap();
+        assert_eq!(value, json("[]"));
+    }
+
+    #[test]
+    fn test_engine_json_set_get() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-json");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let key = Bytes::from("config");
+
+        assert_eq!(engine.json_get(key.clone(), ""), Err(Errors::KeyNotFound));
+        engine
+            .json_set(key.clone(), "/server/port", JsonValue::from(8080))
+            .unwrap();
+        engine
+            .json_set(key.clone(), "/server/host", JsonValue::from("localhost"))
+            .unwrap();
+        assert_eq!(
+            engine.get(key.clone()).unwrap(),
+            Bytes::from(r#"{"server":{"port":8080,"host":"localhost"}}"#)
+        );
+        assert_eq!(
+            engine.json_get(key.clone(), "/server/port").unwrap(),
+            JsonValue::from(8080)
+        );
+        assert_eq!(
+            engine.json_get(key.clone(), "/server/tls"),
+            Err(Errors::JsonPathNotFound)
+        );
+
+        // 不是 JSON 的 value
+        engine
+            .put(Bytes::from("raw"), Bytes::from("{oops"))
+            .unwrap();
+        assert_eq!(
+            engine.json_get(Bytes::from("raw"), ""),
+            Err(Errors::InvalidJson)
+        );
+        assert_eq!(
+            engine.json_set(Bytes::from("raw"), "/a", JsonValue::Null),
+            Err(Errors::InvalidJson)
+        );
+        assert_eq!(
+            engine.get(Bytes::from("raw")).unwrap(),
+            Bytes::from("{oops")
+        );
+
+        // 重启之后数据仍然存在
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(
+            engine.json_get(key.clone(), "/server/host").unwrap(),
+            JsonValue::from("localhost")
+        );
+
+        // 删除测试的文件夹
+        std::mem::drop(engine);
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_engine_json_set_concurrent() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-json-concurrent");
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+        let key = Bytes::from("counters");
+
+        // 并发修改不同的字段，所有修改都不会丢失
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let engine = engine.clone();
+                let key = key.clone();
+                thread::spawn(move || {
+                    for i in 0..50 {
+                        let path = format!("/t{t}");
+                        engine
+                            .json_set(key.clone(), &path, JsonValue::from(i))
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        for t in 0..4 {
+            assert_eq!(
+                engine.json_get(key.clone(), &format!("/t{t}")).unwrap(),
+                JsonValue::from(49)
+            );
+        }
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,242 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::{
+    data::log_record::{LogRecord, LogRecordType},
+    db::{is_internal_key, Engine, INTERNAL_KEY_PREFIX},
+    errors::{Errors, Result},
+    options::{IteratorOptions, WriteBatchOptions},
+};
+
+// key 的过期时间记录在内部前缀下，value 为过期时间（毫秒时间戳），与 key 在同一个事务中写入
+const KEY_TTL_PREFIX: &[u8] = b"key-ttl/";
+
+impl Engine {
+    /// key 剩余的有效时间，没有设置过期时间时返回 None，已经过期时返回 0
+    /// 设置了 Options::default_ttl 时，put 和事务中的 put 写入的 key 在 default_ttl 之后过期，
+    /// 没有设置时写入会清除 key 原有的过期时间；条件写入、租约等其他写入方式不会修改过期时间
+    pub fn ttl(&self, key: &[u8]) -> Option<Duration> {
+        let now = self.now_millis();
+        self.key_ttls
+            .read()
+            .get(key)
+            .map(|expire_at| Duration::from_millis(expire_at.saturating_sub(now)))
+    }
+
+    /// 删除所有已经过期的 key 以及它们的过期时间，返回删除的 key 的数量
+    /// 需要由调用方定期执行，不执行时过期的 key 也不可见，但是会一直占用空间
+    pub fn reap_expired_keys(&self) -> Result<usize> {
+        let now = self.now_millis();
+        let expired = self
+            .key_ttls
+            .read()
+            .iter()
+            .filter(|(_, expire_at)| **expire_at <= now)
+            .map(|(key, _)| Bytes::copy_from_slice(key))
+            .collect::<Vec<_>>();
+        // 逐个删除，分块存储的 value 同时删除分块
+        for key in expired.iter() {
+            self.delete(key.clone())?;
+        }
+        Ok(expired.len())
+    }
+
+    // key 是否已经过期，包括所在的前缀过期和 key 本身过期
+    pub(crate) fn is_expired(&self, key: &[u8]) -> bool {
+        if self.is_prefix_expired(key) {
+            return true;
+        }
+        let ttls = self.key_ttls.read();
+        if ttls.is_empty() {
+            return false;
+        }
+        ttls.get(key)
+            .is_some_and(|expire_at| *expire_at <= self.now_millis())
+    }
+
+    // 写入或者删除 key 时需要在同一个事务中写入的过期时间记录，不需要时返回 None
+    // 写入时按照 default_ttl 设置新的过期时间，没有设置 default_ttl 或者删除时清除原有的过期时间
+    pub(crate) fn key_ttl_record(&self, key: &[u8], is_put: bool) -> Option<LogRecord> {
+        if is_internal_key(key) {
+            return None;
+        }
+        match self.options.default_ttl {
+            Some(ttl) if is_put => self.key_ttl_record_with(key, Some(ttl)),
+            _ => self.key_ttl_record_with(key, None),
+        }
+    }
+
+    // 把 key 的过期时间设置为 ttl 之后的记录，ttl 为 None 时清除原有的过期时间，不需要时返回 None
+    pub(crate) fn key_ttl_record_with(
+        &self,
+        key: &[u8],
+        ttl: Option<Duration>,
+    ) -> Option<LogRecord> {
+        match ttl {
+            Some(ttl) => {
+                let expire_at = self.now_millis().saturating_add(ttl.as_millis() as u64);
+                Some(LogRecord {
+                    key: key_ttl_key(key).to_vec(),
+                    value: expire_at.to_be_bytes().to_vec(),
+                    rec_type: LogRecordType::NORMAL,
+                    meta: Default::default(),
+                })
+            }
+            _ if self.key_ttls.read().contains_key(key) => Some(LogRecord {
+                key: key_ttl_key(key).to_vec(),
+                value: Default::default(),
+                rec_type: LogRecordType::DELETED,
+                meta: Default::default(),
+            }),
+            _ => None,
+        }
+    }
+
+    // 分块存储的 value 和删除不经过事务写入，之后单独写入过期时间记录
+    pub(crate) fn write_key_ttl(&self, key: &[u8], is_put: bool) -> Result<()> {
+        self.commit_key_ttl_record(self.key_ttl_record(key, is_put))
+    }
+
+    // 单独写入 key 的过期时间，ttl 为 None 时清除原有的过期时间
+    pub(crate) fn write_key_ttl_with(&self, key: &[u8], ttl: Option<Duration>) -> Result<()> {
+        self.commit_key_ttl_record(self.key_ttl_record_with(key, ttl))
+    }
+
+    fn commit_key_ttl_record(&self, record: Option<LogRecord>) -> Result<()> {
+        let record = match record {
+            Some(record) => record,
+            None => return Ok(()),
+        };
+        let wb = self.new_write_batch(self.key_ttl_batch_options())?;
+        match record.rec_type {
+            LogRecordType::NORMAL => wb.put_unchecked(record.key.into(), record.value.into())?,
+            _ => wb.delete_unchecked(record.key.into())?,
+        }
+        wb.commit()
+    }
+
+    // 事务提交更新索引时调用，同步内存中的过期时间
+    pub(crate) fn apply_key_ttl_record(&self, record: &LogRecord) {
+        let prefix = key_ttl_key(&[]);
+        if !record.key.starts_with(&prefix) {
+            return;
+        }
+        let key = record.key[prefix.len()..].to_vec();
+        let mut ttls = self.key_ttls.write();
+        match decode_expire_at(&record.value) {
+            Some(expire_at) if record.rec_type == LogRecordType::NORMAL => {
+                ttls.insert(key, expire_at);
+            }
+            _ => {
+                ttls.remove(&key);
+            }
+        }
+    }
+
+    // 从索引中加载 key 的过期时间，打开数据库时调用
+    pub(crate) fn load_key_ttls(&self) -> Result<()> {
+        let prefix = key_ttl_key(&[]);
+        let mut ttls = self.key_ttls.write();
+        ttls.clear();
+        let mut index_iter = self.index.iterator(IteratorOptions {
+            prefix: prefix.to_vec(),
+            ..Default::default()
+        });
+        while let Some((key, pos)) = index_iter.next() {
+            let value = self.get_value_by_position(&pos)?;
+            let expire_at = decode_expire_at(&value).ok_or(Errors::DataFileCorrupted)?;
+            ttls.insert(key[prefix.len()..].to_vec(), expire_at);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn key_ttl_batch_options(&self) -> WriteBatchOptions {
+        WriteBatchOptions {
+            sync_writes: self.options.sync_write,
+            ..Default::default()
+        }
+    }
+}
+
+fn key_ttl_key(key: &[u8]) -> Bytes {
+    let mut ttl_key =
+        Vec::with_capacity(INTERNAL_KEY_PREFIX.len() + KEY_TTL_PREFIX.len() + key.len());
+    ttl_key.extend_from_slice(INTERNAL_KEY_PREFIX);
+    ttl_key.extend_from_slice(KEY_TTL_PREFIX);
+    ttl_key.extend_from_slice(key);
+    ttl_key.into()
+}
+
+fn decode_expire_at(value: &[u8]) -> Option<u64> {
+    <[u8; 8]>::try_from(value).ok().map(u64::from_be_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc};
+
+    use crate::{
+        clock::ManualClock,
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_default_ttl() {
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-default-ttl");
+        opts.default_ttl = Some(Duration::from_secs(60));
+        opts.clock = Some(clock.clone());
+        opts.value_chunk_size = Some(1024);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        engine.put(get_test_key(1), get_test_value(1)).unwrap();
+        let wb = engine.new_write_batch(Default::default()).unwrap();
+        wb.put(get_test_key(2), get_test_value(2)).unwrap();
+        wb.commit().unwrap();
+        let big = Bytes::from(vec![7u8; 5000]);
+        engine.put(get_test_key(3), big.clone()).unwrap();
+        assert_eq!(engine.ttl(&get_test_key(1)), Some(Duration::from_secs(60)));
+        assert_eq!(engine.ttl(&get_test_key(3)), Some(Duration::from_secs(60)));
+
+        // 过期之前重新写入会重新计时
+        clock.advance(Duration::from_secs(30));
+        engine.put(get_test_key(2), get_test_value(20)).unwrap();
+        clock.advance(Duration::from_secs(30));
+        assert!(engine.try_get(get_test_key(1)).unwrap().is_none());
+        assert!(engine.try_get(get_test_key(3)).unwrap().is_none());
+        assert_eq!(engine.get(get_test_key(2)).unwrap(), get_test_value(20));
+        assert_eq!(engine.ttl(&get_test_key(1)), Some(Duration::ZERO));
+        assert_eq!(engine.list_keys().unwrap(), vec![get_test_key(2)]);
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+
+        // 重启之后保留过期时间，不设置 default_ttl 时写入清除原有的过期时间
+        opts.default_ttl = None;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(engine.try_get(get_test_key(1)).unwrap().is_none());
+        assert_eq!(engine.ttl(&get_test_key(2)), Some(Duration::from_secs(30)));
+        engine.put(get_test_key(2), get_test_value(21)).unwrap();
+        assert_eq!(engine.ttl(&get_test_key(2)), None);
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(engine.get(get_test_key(2)).unwrap(), get_test_value(21));
+
+        // 删除过期的 key 以及它们的过期时间
+        assert_eq!(engine.reap_expired_keys().unwrap(), 2);
+        assert_eq!(engine.ttl(&get_test_key(1)), None);
+        assert_eq!(engine.ttl(&get_test_key(3)), None);
+        assert_eq!(engine.list_keys().unwrap().len(), 1);
+        assert_eq!(engine.reap_expired_keys().unwrap(), 0);
+
+        opts.default_ttl = Some(Duration::ZERO);
+        let res = Engine::open(opts.clone());
+        assert_eq!(res.err().unwrap(), Errors::InvalidDefaultTtl);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
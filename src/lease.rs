@@ -0,0 +1,246 @@
+use std::time::Duration;
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    data::log_record::LogRecordPos,
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+// 租约的 value：8 字节 fencing token + 8 字节过期时间（毫秒时间戳），均为大端序
+const LEASE_VALUE_SIZE: usize = 16;
+
+// 读取到的 key 在索引中的位置，以及保存的（token，过期时间）
+type StoredLease = (Option<LogRecordPos>, Option<(u64, u64)>);
+
+/// 通过 Engine::acquire_lease 获取的租约
+/// 每次获取租约 token 都会递增，持有者访问外部资源时带上 token，
+/// 资源方拒绝比见过的最大 token 更小的请求，过期之后仍在运行的旧持有者就不会造成破坏
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    key: Bytes,
+    token: u64,
+    expires_at: u64,
+}
+
+impl Lease {
+    pub fn key(&self) -> &Bytes {
+        &self.key
+    }
+
+    /// fencing token，同一个 key 上后获取的租约 token 更大
+    pub fn token(&self) -> u64 {
+        self.token
+    }
+
+    /// 过期时间，毫秒时间戳
+    pub fn expires_at(&self) -> u64 {
+        self.expires_at
+    }
+}
+
+impl Engine {
+    /// 获取 key 上的租约，有效期为 ttl，key 上已经有没有过期的租约时返回 None
+    /// 租约保存为 key 的 value，释放之后仍然保留 token，重启之后 token 也不会变小
+    pub fn acquire_lease(&self, key: Bytes, ttl: Duration) -> Result<Option<Lease>> {
+        self.check_key(&key)?;
+        loop {
+            let (pos, current) = match self.read_lease(&key)? {
+                Some(read) => read,
+                None => continue,
+            };
+            let now = self.now_millis();
+            let token = match current {
+                Some((_, expires_at)) if expires_at > now => return Ok(None),
+                Some((token, _)) => token + 1,
+                None => 1,
+            };
+            let lease = Lease {
+                key: key.clone(),
+                token,
+                expires_at: now.saturating_add(ttl.as_millis() as u64),
+            };
+            if self.put_if_unchanged(&key, pos, encode_lease(&lease))? {
+                return Ok(Some(lease));
+            }
+        }
+    }
+
+    /// 续约，新的过期时间为当前时间加上 ttl，token 不变
+    /// 已经过期但是还没有被其他持有者获取的租约也可以续约，
+    /// 租约已经被其他持有者获取或者已经释放时返回 Errors::LeaseLost
+    pub fn renew_lease(&self, lease: &Lease, ttl: Duration) -> Result<Lease> {
+        loop {
+            let pos = match self.read_lease(&lease.key)? {
+                Some((pos, Some((token, expires_at))))
+                    if token == lease.token && expires_at != 0 =>
+                {
+                    pos
+                }
+                Some(_) => return Err(Errors::LeaseLost),
+                None => continue,
+            };
+            let renewed = Lease {
+                expires_at: self.now_millis().saturating_add(ttl.as_millis() as u64),
+                ..lease.clone()
+            };
+            if self.put_if_unchanged(&lease.key, pos, encode_lease(&renewed))? {
+                return Ok(renewed);
+            }
+        }
+    }
+
+    /// 释放租约，其他调用者可以立即获取，返回 false 表示租约已经不属于调用者
+    pub fn release_lease(&self, lease: &Lease) -> Result<bool> {
+        loop {
+            let pos = match self.read_lease(&lease.key)? {
+                Some((pos, Some((token, expires_at))))
+                    if token == lease.token && expires_at != 0 =>
+                {
+                    pos
+                }
+                Some(_) => return Ok(false),
+                None => continue,
+            };
+            // 过期时间为 0 表示已经释放，保留 token 保证之后获取的 token 仍然递增
+            let released = Lease {
+                expires_at: 0,
+                ..lease.clone()
+            };
+            if self.put_if_unchanged(&lease.key, pos, encode_lease(&released))? {
+                return Ok(true);
+            }
+        }
+    }
+
+    // 读取 key 在索引中的位置和租约（token，过期时间）
+    // 读取期间 key 被并发修改时返回 None，调用方重新读取
+    fn read_lease(&self, key: &Bytes) -> Result<Option<StoredLease>> {
+        let pos = match self.index.get(key.to_vec()) {
+            Some(pos) => pos,
+            None => return Ok(Some((None, None))),
+        };
+        let record = match self.get_indexed_log_record(key) {
+            Ok(record) => record,
+            Err(Errors::KeyNotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let value = <[u8; LEASE_VALUE_SIZE]>::try_from(record.value.as_slice())
+            .map_err(|_| Errors::InvalidLease)?;
+        let token = u64::from_be_bytes(value[..8].try_into().unwrap());
+        let expires_at = u64::from_be_bytes(value[8..].try_into().unwrap());
+        Ok(Some((Some(pos), Some((token, expires_at)))))
+    }
+}
+
+fn encode_lease(lease: &Lease) -> Vec<u8> {
+    let mut value = BytesMut::with_capacity(LEASE_VALUE_SIZE);
+    value.put_u64(lease.token);
+    value.put_u64(lease.expires_at);
+    value.to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::options::Options;
+
+    use super::*;
+
+    #[test]
+    fn test_lease_acquire_renew_release() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-lease");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let key = Bytes::from("lock");
+        let ttl = Duration::from_millis(100);
+        let lease = engine.acquire_lease(key.clone(), ttl).unwrap().unwrap();
+        assert_eq!(lease.token(), 1);
+        // 没有过期之前其他调用者获取不到
+        assert_eq!(engine.acquire_lease(key.clone(), ttl).unwrap(), None);
+
+        // 续约之后 token 不变，过期时间延后
+        let renewed = engine.renew_lease(&lease, Duration::from_secs(10)).unwrap();
+        assert_eq!(renewed.token(), 1);
+        assert!(renewed.expires_at() > lease.expires_at());
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(engine.acquire_lease(key.clone(), ttl).unwrap(), None);
+
+        // 释放之后可以立即获取，token 递增
+        assert!(engine.release_lease(&renewed).unwrap());
+        assert!(!engine.release_lease(&renewed).unwrap());
+        assert_eq!(
+            engine.renew_lease(&renewed, ttl).err().unwrap(),
+            Errors::LeaseLost
+        );
+        let lease = engine.acquire_lease(key.clone(), ttl).unwrap().unwrap();
+        assert_eq!(lease.token(), 2);
+
+        // 过期之后被其他调用者获取，旧的租约不能续约
+        std::thread::sleep(Duration::from_millis(150));
+        let next = engine.acquire_lease(key.clone(), ttl).unwrap().unwrap();
+        assert_eq!(next.token(), 3);
+        assert_eq!(
+            engine.renew_lease(&lease, ttl).err().unwrap(),
+            Errors::LeaseLost
+        );
+        assert!(!engine.release_lease(&lease).unwrap());
+
+        // 重启之后 token 继续递增
+        assert!(engine.release_lease(&next).unwrap());
+        engine.close().expect("failed to close engine");
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let lease = engine.acquire_lease(key.clone(), ttl).unwrap().unwrap();
+        assert_eq!(lease.token(), 4);
+
+        // 不是租约的 value
+        engine
+            .put(Bytes::from("plain"), Bytes::from("value"))
+            .unwrap();
+        assert_eq!(
+            engine
+                .acquire_lease(Bytes::from("plain"), ttl)
+                .err()
+                .unwrap(),
+            Errors::InvalidLease
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_lease_concurrent_acquire() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-lease-concurrent");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 并发获取同一个租约只有一个成功
+        let acquired = AtomicUsize::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                let engine = &engine;
+                let acquired = &acquired;
+                s.spawn(move || {
+                    let lease = engine
+                        .acquire_lease(Bytes::from("lock"), Duration::from_secs(10))
+                        .unwrap();
+                    if lease.is_some() {
+                        acquired.fetch_add(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        assert_eq!(acquired.load(Ordering::SeqCst), 1);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
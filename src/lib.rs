@@ -1,13 +1,67 @@
+#![cfg_attr(test, allow(clippy::field_reassign_with_default))]
+
 pub mod data;
 mod errors;
 pub mod fio;
+pub mod follower;
+pub mod history;
+pub mod idempotent;
 pub mod index;
+pub mod index_snapshot;
 
+pub mod audit;
 pub mod batch;
+pub mod blob;
+mod buffer_pool;
+pub mod builder;
+#[cfg(feature = "json")]
+pub mod bulk;
+pub mod checksum;
+mod chunk;
+pub mod clock;
+pub mod compact;
+pub mod conditional;
+pub mod conflict;
+pub mod copy;
 pub mod db;
+pub mod deadline;
+pub mod debug;
+pub mod dump;
+pub mod fence;
+pub mod hash;
 pub mod iterator;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod key_ttl;
+pub mod lease;
+pub mod manifest;
+pub mod memtable;
+pub mod merge;
+pub mod merge_dir;
+pub mod metadata;
+pub mod metrics;
+pub mod mismatch;
+pub mod multi_get;
+pub mod open_cost;
 pub mod options;
+pub mod prefix_ttl;
+mod priority;
+pub mod punch;
+pub mod queue;
+pub mod quota;
+pub mod reader;
+pub mod reopen;
+pub mod sample;
+pub mod segment;
+pub mod seq;
+pub mod space;
+pub mod stream;
+pub mod trace;
 
+mod shutdown;
+#[cfg(test)]
 #[allow(unused)]
 mod tests;
 mod utils;
+
+pub use errors::{ErrorCategory, Errors, IoError, Result};
@@ -0,0 +1,463 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::mpsc::RecvTimeoutError,
+    time::Duration,
+};
+
+use log::error;
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    data::data_file::{get_data_file_name, DataFile},
+    db::{data_file_ids, Engine},
+    errors::{Errors, Result},
+    fio,
+    seq::SEQ_NO_FILE_NAME,
+    utils::{atomic_write, to_hex},
+};
+
+/// 保存已封存数据文件摘要的文件名
+pub const MANIFEST_FILE_NAME: &str = "MANIFEST";
+
+/// 已封存数据文件的大小和 SHA-256 摘要
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileDigest {
+    pub size: u64,
+    pub digest: [u8; 32],
+}
+
+// 数据文件摘要清单，每行保存一个文件：文件 id、大小、十六进制摘要
+pub(crate) struct Manifest {
+    path: PathBuf,
+    entries: Mutex<BTreeMap<u32, FileDigest>>,
+    // 新建的清单文件的权限
+    file_mode: u32,
+}
+
+impl Manifest {
+    // 空的清单，不对应任何文件
+    pub(crate) fn empty(dir_path: &Path) -> Self {
+        Self {
+            path: dir_path.join(MANIFEST_FILE_NAME),
+            entries: Mutex::new(BTreeMap::new()),
+            file_mode: 0o666,
+        }
+    }
+
+    // 读取数据目录中的清单，不存在时为空
+    pub(crate) fn load(dir_path: &Path) -> Result<Self> {
+        let path = dir_path.join(MANIFEST_FILE_NAME);
+        let entries = match path.is_file() {
+            true => read_manifest(&path)?,
+            false => BTreeMap::new(),
+        };
+        Ok(Self {
+            path,
+            entries: Mutex::new(entries),
+            file_mode: 0o666,
+        })
+    }
+
+    // 设置新建的清单文件的权限
+    pub(crate) fn with_file_mode(mut self, mode: u32) -> Self {
+        self.file_mode = mode;
+        self
+    }
+
+    pub(crate) fn get(&self, file_id: u32) -> Option<FileDigest> {
+        self.entries.lock().get(&file_id).copied()
+    }
+
+    // 清单中的所有记录，按文件 id 排列
+    pub(crate) fn entries(&self) -> Vec<(u32, FileDigest)> {
+        self.entries
+            .lock()
+            .iter()
+            .map(|(file_id, digest)| (*file_id, *digest))
+            .collect()
+    }
+
+    pub(crate) fn contains(&self, file_id: u32) -> bool {
+        self.entries.lock().contains_key(&file_id)
+    }
+
+    // 记录文件的摘要并写入清单
+    pub(crate) fn record(&self, file_id: u32, digest: FileDigest) -> Result<()> {
+        let mut entries = self.entries.lock();
+        entries.insert(file_id, digest);
+        write_manifest(&self.path, &entries, self.file_mode)
+    }
+
+    // 将清单写入到另一个数据目录中
+    pub(crate) fn persist_to(&self, dir_path: &Path) -> Result<()> {
+        let entries = self.entries.lock();
+        match entries.is_empty() {
+            true => Ok(()),
+            false => write_manifest(&dir_path.join(MANIFEST_FILE_NAME), &entries, self.file_mode),
+        }
+    }
+
+    // 移除已经删除的文件
+    pub(crate) fn remove(&self, file_ids: &[u32]) -> Result<()> {
+        let mut entries = self.entries.lock();
+        let len = entries.len();
+        for file_id in file_ids {
+            entries.remove(file_id);
+        }
+        match entries.len() == len {
+            true => Ok(()),
+            false => write_manifest(&self.path, &entries, self.file_mode),
+        }
+    }
+}
+
+fn read_manifest(path: &Path) -> Result<BTreeMap<u32, FileDigest>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            error!("Failed to read manifest: {e}");
+            return Err(Errors::FailedToReadFromDataFile(e.into()));
+        }
+    };
+    let mut entries = BTreeMap::new();
+    for line in content.lines().filter(|line| !line.is_empty()) {
+        let (file_id, digest) = match parse_manifest_line(line) {
+            Some(entry) => entry,
+            None => return Err(Errors::InvalidManifestFile),
+        };
+        entries.insert(file_id, digest);
+    }
+    Ok(entries)
+}
+
+fn parse_manifest_line(line: &str) -> Option<(u32, FileDigest)> {
+    let mut parts = line.split(' ');
+    let file_id = parts.next()?.parse().ok()?;
+    let size = parts.next()?.parse().ok()?;
+    let hex = parts.next()?;
+    if hex.len() != 64 || parts.next().is_some() {
+        return None;
+    }
+    let mut digest = [0; 32];
+    for (i, b) in digest.iter_mut().enumerate() {
+        *b = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some((file_id, FileDigest { size, digest }))
+}
+
+// 原子地写入，避免写到一半的清单
+fn write_manifest(path: &Path, entries: &BTreeMap<u32, FileDigest>, mode: u32) -> Result<()> {
+    let content = entries
+        .iter()
+        .map(|(file_id, d)| format!("{} {} {}\n", file_id, d.size, to_hex(&d.digest)))
+        .collect::<String>();
+    atomic_write(path, content.as_bytes(), mode)
+}
+
+// 计算文件前 size 字节的摘要，文件长度不足时返回 None
+pub(crate) fn digest_file(path: &Path, size: u64) -> Result<Option<FileDigest>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            error!("Failed to open data file {:?}: {e}", path);
+            return Err(Errors::FailedToOpenDataFile(e.into()));
+        }
+    };
+    let mut reader = file.take(size);
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    let mut read = 0;
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) => {
+                error!("Failed to read data file {:?}: {e}", path);
+                return Err(Errors::FailedToReadFromDataFile(e.into()));
+            }
+        };
+        hasher.update(&buf[..n]);
+        read += n as u64;
+    }
+    if read < size {
+        return Ok(None);
+    }
+    Ok(Some(FileDigest {
+        size,
+        digest: hasher.finalize().into(),
+    }))
+}
+
+// 逐条读取记录并校验 CRC，返回数据是否完整
+fn verify_records(data_file: &DataFile) -> Result<bool> {
+    let mut offset = 0;
+    loop {
+        match data_file.read_log_record(offset) {
+            Ok(res) => offset += res.size as u64,
+            Err(Errors::ReadDataFileEOF) => return Ok(true),
+            Err(Errors::InvalidLogRecordCrc) => return Ok(false),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// 校验数据文件，有摘要时比较整个文件的摘要，否则逐条校验记录
+fn verify_file(manifest: &Manifest, dir_path: &Path, file_id: u32) -> Result<VerifyMethod> {
+    let path = get_data_file_name(dir_path, file_id);
+    if let Some(expected) = manifest.get(file_id) {
+        let size = fs::metadata(&path).map_or(0, |m| m.len());
+        let ok = size == expected.size && digest_file(&path, size)? == Some(expected);
+        return Ok(VerifyMethod::Digest(ok));
+    }
+    let data_file = DataFile::open_read_only(dir_path.to_path_buf(), file_id)?;
+    Ok(VerifyMethod::Records(verify_records(&data_file)?))
+}
+
+enum VerifyMethod {
+    Digest(bool),
+    Records(bool),
+}
+
+/// 数据校验的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    // 通过清单中的摘要校验的文件数量
+    pub digest_verified: usize,
+    // 没有摘要，逐条校验记录 CRC 的文件数量
+    pub records_verified: usize,
+    // 损坏或者不完整的数据文件 id
+    pub corrupted: Vec<u32>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.corrupted.is_empty()
+    }
+
+    fn add(&mut self, file_id: u32, method: VerifyMethod) {
+        let ok = match method {
+            VerifyMethod::Digest(ok) => {
+                self.digest_verified += 1;
+                ok
+            }
+            VerifyMethod::Records(ok) => {
+                self.records_verified += 1;
+                ok
+            }
+        };
+        if !ok {
+            self.corrupted.push(file_id);
+        }
+    }
+}
+
+impl Engine {
+    /// 校验所有数据文件，已封存并且记录了摘要的文件比较整个文件的摘要，
+    /// 其他文件逐条校验记录的 CRC
+    pub fn verify(&self) -> Result<VerifyReport> {
+        let files = self.files.load();
+        let mut file_ids = files.older.keys().copied().collect::<Vec<_>>();
+        file_ids.sort();
+
+        let mut report = VerifyReport::default();
+        for file_id in file_ids {
+            let method = verify_file(&self.manifest, &self.options.dir_path, file_id)?;
+            report.add(file_id, method);
+        }
+        let ok = verify_records(&files.active)?;
+        report.add(files.active.get_file_id(), VerifyMethod::Records(ok));
+        Ok(report)
+    }
+
+    /// 从 fork_to 等方式得到的备份目录恢复数据库到空的目录中
+    /// 复制之后使用备份中的摘要校验每个文件，损坏或者没有复制完整时返回 Errors::DataFileCorrupted
+    pub fn restore(backup_dir: impl AsRef<Path>, dir_path: impl AsRef<Path>) -> Result<()> {
+        let (backup_dir, dir_path) = (backup_dir.as_ref(), dir_path.as_ref());
+        if dir_path.is_dir() {
+            match fs::read_dir(dir_path) {
+                Ok(mut entries) => {
+                    if entries.next().is_some() {
+                        return Err(Errors::TargetDirNotEmpty);
+                    }
+                }
+                Err(e) => return Err(Errors::FailedToReadDatabaseDir(e.into())),
+            }
+        } else if let Err(e) = fs::create_dir_all(dir_path) {
+            error!("Failed to create restore directory: {e}");
+            return Err(Errors::FailedToCreateDatabaseDir(e.into()));
+        } else {
+            fio::sync_parent_dir(dir_path)?;
+        }
+
+        let manifest = Manifest::load(backup_dir)?;
+        for file_id in data_file_ids(backup_dir)? {
+            let src = get_data_file_name(backup_dir, file_id);
+            if let Err(e) = fs::copy(&src, get_data_file_name(dir_path, file_id)) {
+                error!("Failed to copy data file {:?}: {e}", src);
+                return Err(Errors::FailedToCopyDataFile(e.into()));
+            }
+            let ok = match verify_file(&manifest, dir_path, file_id)? {
+                VerifyMethod::Digest(ok) | VerifyMethod::Records(ok) => ok,
+            };
+            if !ok {
+                error!("Data file {:?} is corrupted", src);
+                return Err(Errors::DataFileCorrupted);
+            }
+        }
+        for name in [MANIFEST_FILE_NAME, SEQ_NO_FILE_NAME] {
+            let src = backup_dir.join(name);
+            if src.is_file() {
+                if let Err(e) = fs::copy(&src, dir_path.join(name)) {
+                    error!("Failed to copy {:?}: {e}", src);
+                    return Err(Errors::FailedToCopyDataFile(e.into()));
+                }
+            }
+        }
+        fio::sync_dir(dir_path)
+    }
+
+    // 启动计算封存文件摘要的后台任务，启动时先补齐清单中缺少的文件
+    pub(crate) fn spawn_manifest_task(&self) -> Result<()> {
+        let receiver = self.subscribe_sealed_segments();
+        let manifest = self.manifest.clone();
+        let dir_path = self.options.dir_path.clone();
+        let maintenance_lock = self.maintenance_lock.clone();
+        let pending = self
+            .sealed_segments()
+            .into_iter()
+            .filter(|segment| !manifest.contains(segment.file_id))
+            .collect::<Vec<_>>();
+        let record = move |file_id: u32, size: u64| {
+            // 打洞会修改文件内容，计算摘要时不能同时进行
+            let _lock = maintenance_lock.lock();
+            let path = get_data_file_name(&dir_path, file_id);
+            let res = match digest_file(&path, size) {
+                Ok(Some(digest)) => manifest.record(file_id, digest),
+                Ok(None) => Ok(()),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                error!("Failed to record digest of data file {file_id}: {e}");
+            }
+        };
+        self.background.spawn("manifest", move |signal| {
+            for segment in pending {
+                if signal.is_shutdown() {
+                    return;
+                }
+                record(segment.file_id, segment.size);
+            }
+            while !signal.is_shutdown() {
+                match receiver.recv_timeout(Duration::from_millis(100)) {
+                    Ok(segment) => record(segment.file_id, segment.size),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, os::unix::fs::FileExt, time::Instant};
+
+    use crate::{
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    // 等待后台任务记录所有封存文件的摘要
+    fn wait_for_manifest(engine: &Engine) {
+        let start = Instant::now();
+        while engine
+            .sealed_segments()
+            .iter()
+            .any(|s| !engine.manifest.contains(s.file_id))
+        {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_digest_file() {
+        let dir = PathBuf::from("/tmp/bitcask-rs-manifest-digest");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data");
+        fs::write(&path, b"abcdef").unwrap();
+
+        // 只计算前 size 字节，清单中保存十六进制的 SHA-256 摘要
+        let digest = digest_file(&path, 3).unwrap().unwrap();
+        assert_eq!(digest.size, 3);
+        assert_eq!(
+            to_hex(&digest.digest),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(digest_file(&path, 7).unwrap().is_none());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(dir).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_manifest_verify_and_restore() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-manifest");
+        opts.data_file_size = 4 * 1024;
+        opts.seal_digest = true;
+        let backup_dir = PathBuf::from("/tmp/bitcask-rs-manifest-backup");
+        let restore_dir = PathBuf::from("/tmp/bitcask-rs-manifest-restore");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..300 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+        wait_for_manifest(&engine);
+        let sealed = engine.sealed_segments().len();
+        assert!(sealed > 1);
+
+        let report = engine.verify().unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.digest_verified, sealed);
+        assert_eq!(report.records_verified, 1);
+
+        // 备份之后恢复到新的目录
+        assert!(engine.fork_to(&backup_dir).is_ok());
+        assert!(Engine::restore(&backup_dir, &restore_dir).is_ok());
+        let mut restore_opts = opts.clone();
+        restore_opts.dir_path = restore_dir.clone();
+        restore_opts.seal_digest = false;
+        let restored = Engine::open(restore_opts).expect("failed to open engine");
+        assert_eq!(restored.list_keys().unwrap().len(), 300);
+        assert!(restored.verify().unwrap().is_ok());
+        assert_eq!(restored.verify().unwrap().digest_verified, sealed);
+        std::mem::drop(restored);
+
+        // 封存文件中的数据被修改
+        let path = get_data_file_name(&opts.dir_path, 0);
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.write_at(b"x", 20).unwrap();
+        let report = engine.verify().unwrap();
+        assert_eq!(report.corrupted, vec![0]);
+
+        // 备份没有复制完整
+        let path = get_data_file_name(&backup_dir, 1);
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(100).unwrap();
+        fs::remove_dir_all(&restore_dir).unwrap();
+        let res = Engine::restore(&backup_dir, &restore_dir);
+        assert_eq!(res.err().unwrap(), Errors::DataFileCorrupted);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(backup_dir).expect("failed to remove path");
+        std::fs::remove_dir_all(restore_dir).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,421 @@
+//! 内存写缓冲：设置 Options::memtable_bytes 时，put 和 delete 先写入内存，累计的数据量达到上限时一次顺序写入数据文件
+//! 读取先查内存再查索引；事务、条件写入、合并等其他写入以及遍历操作之前会先把内存中的数据刷新到数据文件，
+//! 保证写入顺序不变。刷新之前进程崩溃会丢失内存中的数据，sync 和 close 会先刷新
+
+use std::{collections::HashMap, sync::atomic::Ordering};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+use crate::{
+    batch::{log_record_key_with_seq, NON_TRANSACTION_SEQ_NO},
+    data::log_record::{LogRecord, LogRecordType},
+    db::Engine,
+    deadline,
+    errors::{Errors, Result},
+};
+
+// value 和元数据，None 表示删除
+type MemValue = Option<(Bytes, Bytes)>;
+
+// 内存中的一次写入
+struct MemEntry {
+    // 每次写入递增，刷新之后只移除写入期间没有被再次修改的 key
+    version: u64,
+    value: MemValue,
+}
+
+#[derive(Default)]
+struct MemtableInner {
+    entries: HashMap<Vec<u8>, MemEntry>,
+    // key、value 和元数据的总大小
+    bytes: usize,
+    next_version: u64,
+}
+
+pub(crate) struct Memtable {
+    limit: usize,
+    inner: Mutex<MemtableInner>,
+}
+
+impl Memtable {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            inner: Mutex::new(MemtableInner::default()),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.inner.lock().entries.is_empty()
+    }
+
+    // key 在内存中的最新写入，Some(None) 表示已经删除
+    fn get(&self, key: &[u8]) -> Option<MemValue> {
+        self.inner
+            .lock()
+            .entries
+            .get(key)
+            .map(|entry| entry.value.clone())
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        self.inner.lock().entries.contains_key(key)
+    }
+
+    // 写入之后是否达到刷新的上限
+    fn insert(&self, key: Vec<u8>, value: MemValue) -> bool {
+        let mut inner = self.inner.lock();
+        let size = key.len() + value.as_ref().map_or(0, |(v, m)| v.len() + m.len());
+        inner.next_version += 1;
+        let entry = MemEntry {
+            version: inner.next_version,
+            value,
+        };
+        inner.bytes += size;
+        if let Some(old) = inner.entries.insert(key.clone(), entry) {
+            inner.bytes -= key.len() + old.value.map_or(0, |(v, m)| v.len() + m.len());
+        }
+        inner.bytes >= self.limit
+    }
+
+    fn snapshot(&self) -> Vec<(Vec<u8>, u64, MemValue)> {
+        self.inner
+            .lock()
+            .entries
+            .iter()
+            .map(|(key, entry)| (key.clone(), entry.version, entry.value.clone()))
+            .collect()
+    }
+
+    // 移除已经刷新的写入，刷新期间再次写入的 key 保留新的值
+    fn remove_flushed(&self, flushed: &[(Vec<u8>, u64)]) {
+        let mut inner = self.inner.lock();
+        for (key, version) in flushed {
+            if inner.entries.get(key).map(|e| e.version) != Some(*version) {
+                continue;
+            }
+            if let Some(entry) = inner.entries.remove(key) {
+                inner.bytes -= key.len() + entry.value.map_or(0, |(v, m)| v.len() + m.len());
+            }
+        }
+    }
+}
+
+impl Engine {
+    /// 把内存写缓冲中的数据写入数据文件并更新索引，没有启用 memtable_bytes 时不做任何事
+    pub fn flush_memtable(&self) -> Result<()> {
+        if self.memtable.as_ref().is_none_or(|m| m.is_empty()) {
+            return Ok(());
+        }
+        let _lock = deadline::lock(&self.append_lock)?;
+        self.flush_memtable_locked()
+    }
+
+    // 调用方需要持有 append_lock，刷新之后索引中是所有 key 最新的位置
+    pub(crate) fn flush_memtable_locked(&self) -> Result<()> {
+        let memtable = match &self.memtable {
+            Some(memtable) if !memtable.is_empty() => memtable,
+            _ => return Ok(()),
+        };
+        // 已经写入数据文件的事务先更新完索引，避免之后覆盖刷新的位置
+        deadline::wait_idle(&self.inflight)?;
+        let snapshot = memtable.snapshot();
+        let records = snapshot
+            .iter()
+            .map(|(key, _, value)| {
+                let (value, meta, rec_type) = match value {
+                    Some((value, meta)) => (value.to_vec(), meta.to_vec(), LogRecordType::NORMAL),
+                    None => (Vec::new(), Vec::new(), LogRecordType::DELETED),
+                };
+                LogRecord {
+                    key: log_record_key_with_seq(key.clone(), NON_TRANSACTION_SEQ_NO),
+                    value,
+                    rec_type,
+                    meta,
+                }
+            })
+            .collect::<Vec<_>>();
+        let positions = self.write_log_records_locked(&records, &self.write_stats.data_bytes)?;
+        for ((key, _, value), pos) in snapshot.iter().zip(positions) {
+            self.mark_stale(key);
+            match value {
+                Some(_) => {
+                    self.index.put(key.clone(), pos);
+                }
+                None => {
+                    self.index.delete(key.clone());
+                }
+            }
+        }
+        if self.options.sync_write {
+            self.files.read().active.sync()?;
+        }
+        let flushed = snapshot
+            .into_iter()
+            .map(|(key, version, _)| (key, version))
+            .collect::<Vec<_>>();
+        memtable.remove_flushed(&flushed);
+        Ok(())
+    }
+
+    // 配置了前缀配额时配额需要根据索引计算用量，不使用内存写缓冲
+    fn memtable_enabled(&self) -> bool {
+        self.memtable.is_some() && !self.read_only && self.quotas.read().is_empty()
+    }
+
+    // 写入内存写缓冲，没有启用时返回 false，由调用方直接写入数据文件
+    pub(crate) fn memtable_put(&self, key: &Bytes, value: MemValue) -> Result<bool> {
+        if !self.memtable_enabled() {
+            return Ok(false);
+        }
+        let memtable = self.memtable.as_ref().unwrap();
+        let size = key.len() + value.as_ref().map_or(0, |(v, m)| v.len() + m.len());
+        // 已经写入内存，刷新超时时留给之后的写入
+        if memtable.insert(key.to_vec(), value) {
+            match self.flush_memtable() {
+                Err(Errors::Timeout) => {}
+                res => res?,
+            }
+        }
+        self.write_stats
+            .user_bytes
+            .fetch_add(size as u64, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    // key 在内存写缓冲中的记录，已经删除时返回 Some(None)
+    pub(crate) fn memtable_get(&self, key: &[u8]) -> Option<Option<LogRecord>> {
+        let value = self.memtable.as_ref()?.get(key)?;
+        Some(value.map(|(value, meta)| LogRecord {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            rec_type: LogRecordType::NORMAL,
+            meta: meta.to_vec(),
+        }))
+    }
+
+    // key 是否只在内存写缓冲中存在
+    pub(crate) fn memtable_contains(&self, key: &[u8]) -> bool {
+        self.memtable.as_ref().is_some_and(|m| m.contains(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::{atomic::AtomicBool, Arc},
+        thread,
+    };
+
+    use crate::{
+        errors::Errors,
+        options::{Options, WriteBatchOptions},
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_memtable() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-memtable");
+        opts.memtable_bytes = Some(64 * 1024);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 没有达到上限之前只写入内存，读取可以看到
+        engine.put(get_test_key(1), get_test_value(1)).unwrap();
+        engine
+            .put_with_meta(get_test_key(2), get_test_value(2), Bytes::from("m"))
+            .unwrap();
+        engine.delete(get_test_key(2)).unwrap();
+        assert!(engine.index.get(get_test_key(1).to_vec()).is_none());
+        assert!(engine.has_unsynced_data());
+        assert_eq!(engine.get(get_test_key(1)).unwrap(), get_test_value(1));
+        assert_eq!(
+            engine.get(get_test_key(2)).err().unwrap(),
+            Errors::KeyNotFound
+        );
+
+        // 事务写入之前先刷新，事务中的值覆盖内存中的值
+        engine.put(get_test_key(3), get_test_value(3)).unwrap();
+        let wb = engine
+            .new_write_batch(WriteBatchOptions::default())
+            .unwrap();
+        wb.put(get_test_key(3), get_test_value(30)).unwrap();
+        wb.commit().unwrap();
+        assert!(engine.index.get(get_test_key(1).to_vec()).is_some());
+        assert_eq!(engine.get(get_test_key(3)).unwrap(), get_test_value(30));
+        assert!(!engine
+            .put_if_absent(get_test_key(1), get_test_value(9))
+            .unwrap());
+
+        // 达到上限之后自动刷新
+        for i in 100..2000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        assert!(engine.index.get(get_test_key(100).to_vec()).is_some());
+        engine.delete(get_test_key(100)).unwrap();
+        assert_eq!(engine.list_keys().unwrap().len(), 1901);
+        assert!(engine.try_get(get_test_key(100)).unwrap().is_none());
+
+        engine.put(get_test_key(5), get_test_value(5)).unwrap();
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+
+        // 关闭时已经刷新
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(engine.get(get_test_key(5)).unwrap(), get_test_value(5));
+        assert_eq!(engine.get(get_test_key(3)).unwrap(), get_test_value(30));
+        assert!(engine.try_get(get_test_key(2)).unwrap().is_none());
+        assert!(engine.try_get(get_test_key(100)).unwrap().is_none());
+        assert_eq!(engine.list_keys().unwrap().len(), 1902);
+        engine.close().expect("failed to close");
+
+        opts.memtable_bytes = Some(0);
+        let res = Engine::open(opts.clone());
+        assert_eq!(res.err().unwrap(), Errors::InvalidMemtableBytes);
+        opts.memtable_bytes = Some(1024);
+        opts.value_chunk_size = Some(1024);
+        let res = Engine::open(opts.clone());
+        assert_eq!(res.err().unwrap(), Errors::InvalidMemtableBytes);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_memtable_index_readers() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-memtable-readers");
+        opts.memtable_bytes = Some(1 << 20);
+        let fork_path = PathBuf::from("/tmp/bitcask-rs-memtable-readers-fork");
+        let mut other_opts = opts.clone();
+        other_opts.dir_path = PathBuf::from("/tmp/bitcask-rs-memtable-readers-copy");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let other = Engine::open(other_opts.clone()).expect("failed to open engine");
+
+        // 只读取索引的操作也能看到内存写缓冲中的数据
+        engine.put(get_test_key(1), get_test_value(1)).unwrap();
+        let iter = engine.try_iter(Default::default()).unwrap();
+        assert_eq!(iter.next(), Some((get_test_key(1), get_test_value(1))));
+        std::mem::drop(iter);
+        engine.delete(get_test_key(1)).unwrap();
+        assert!(engine.sample_keys(10).is_empty());
+        engine.put(get_test_key(1), get_test_value(1)).unwrap();
+        assert_eq!(engine.sample_keys(10), vec![get_test_key(1)]);
+        engine.put(get_test_key(2), get_test_value(2)).unwrap();
+        assert_eq!(engine.explain_get(get_test_key(2)).unwrap().file_id, 0);
+        engine.put(get_test_key(3), get_test_value(3)).unwrap();
+        assert_eq!(engine.copy_range_to(&other, ..).unwrap(), 3);
+        let dead_bytes = engine.space_report().unwrap().dead_bytes;
+        engine.put(get_test_key(1), get_test_value(10)).unwrap();
+        assert!(engine.space_report().unwrap().dead_bytes > dead_bytes);
+        let dead_bytes = engine.file_stats()[0].dead_bytes;
+        engine.delete(get_test_key(2)).unwrap();
+        assert!(engine.file_stats()[0].dead_bytes > dead_bytes);
+        engine.delete(get_test_key(3)).unwrap();
+        engine.fork_to(&fork_path).unwrap();
+
+        let mut fork_opts = opts.clone();
+        fork_opts.dir_path = fork_path.clone();
+        let fork = Engine::open(fork_opts).expect("failed to open fork");
+        assert_eq!(fork.get(get_test_key(1)).unwrap(), get_test_value(10));
+        assert!(fork.try_get(get_test_key(3)).unwrap().is_none());
+        assert_eq!(other.get(get_test_key(3)).unwrap(), get_test_value(3));
+        std::mem::drop(fork);
+        std::mem::drop(other);
+        std::mem::drop(engine);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(&fork_path).expect("failed to remove path");
+        std::fs::remove_dir_all(other_opts.dir_path).expect("failed to remove path");
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_memtable_quota_and_merge_candidates() {
+        use crate::quota::{PrefixQuota, QuotaPolicy};
+
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-memtable-quota");
+        opts.data_file_size = 4 * 1024;
+        opts.memtable_bytes = Some(1 << 20);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 合并候选中的有效数据量不包含被内存写缓冲中的数据覆盖的记录
+        for i in 0..100 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine.flush_memtable().unwrap();
+        engine.put(get_test_key(100), get_test_value(100)).unwrap();
+        engine.flush_memtable().unwrap();
+        for i in 0..100 {
+            engine.put(get_test_key(i), get_test_value(i + 1)).unwrap();
+        }
+        let candidates = engine.merge_candidates().unwrap();
+        assert_eq!(candidates[0].file_id, 0);
+        assert!(candidates[0].live_bytes <= crate::fence::FILE_HEADER_SIZE);
+
+        // 注册配额时统计只在内存写缓冲中的 key
+        engine
+            .put(Bytes::from("user:1"), Bytes::from("value"))
+            .unwrap();
+        engine
+            .put(Bytes::from("user:2"), Bytes::from("value"))
+            .unwrap();
+        let quota = PrefixQuota {
+            max_keys: None,
+            max_bytes: None,
+            policy: QuotaPolicy::Reject,
+        };
+        engine
+            .set_prefix_quota(Bytes::from("user:"), quota)
+            .unwrap();
+        assert_eq!(engine.prefix_usage(b"user:").unwrap().keys, 2);
+        std::mem::drop(engine);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_memtable_concurrent_batch_commit() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-memtable-concurrent");
+        opts.memtable_bytes = Some(1 << 20);
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+
+        // 事务提交 fsync 期间其他线程写入内存写缓冲，提交不能等待刷新
+        let done = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let engine = engine.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut i = 0;
+                while !done.load(Ordering::Relaxed) || i < 2000 {
+                    engine
+                        .put(get_test_key(i % 2000), get_test_value(i))
+                        .unwrap();
+                    i += 1;
+                }
+            })
+        };
+        for i in 0..200 {
+            let wb = engine
+                .new_write_batch(WriteBatchOptions::default())
+                .unwrap();
+            wb.put(get_test_key(10000 + i), get_test_value(i)).unwrap();
+            wb.commit().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        writer.join().unwrap();
+        assert_eq!(engine.list_keys().unwrap().len(), 2200);
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
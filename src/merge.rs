@@ -0,0 +1,845 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    batch::{log_record_key_with_seq, parse_log_record_key, NON_TRANSACTION_SEQ_NO},
+    data::{
+        data_file::{get_data_file_name, DataFile},
+        log_record::{LogRecord, LogRecordPos, LogRecordType},
+    },
+    db::{DataFiles, Engine},
+    deadline,
+    errors::{Errors, Result},
+    fence::{read_file_header, FILE_HEADER_SIZE},
+};
+
+// 合并时每一批在写入锁中重写的数据量，批次越小对正常写入的阻塞越短
+pub(crate) const MERGE_BATCH_BYTES: u64 = 256 * 1024;
+
+/// 可以被合并的已封存数据文件
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeCandidate {
+    pub file_id: u32,
+    // 文件大小
+    pub size: u64,
+    // 仍然被索引引用的数据量
+    pub live_bytes: u64,
+    // 文件最后一次修改（封存）到现在的时间
+    pub age: Duration,
+}
+
+impl MergeCandidate {
+    /// 可以回收的数据量
+    pub fn garbage_bytes(&self) -> u64 {
+        self.size.saturating_sub(self.live_bytes)
+    }
+
+    /// 可以回收的数据量占文件大小的比例
+    pub fn garbage_ratio(&self) -> f64 {
+        match self.size {
+            0 => 0.0,
+            size => self.garbage_bytes() as f64 / size as f64,
+        }
+    }
+}
+
+/// 没有合并过时估算耗时使用的吞吐量，字节 / 秒
+pub const DEFAULT_MERGE_THROUGHPUT: u64 = 64 * 1024 * 1024;
+
+/// 合并计划，只根据当前的索引和数据文件估算，不会修改任何数据
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergePlan {
+    // 将要合并的文件 id，按升序排列
+    pub file_ids: Vec<u32>,
+    // 需要读取的数据量，即被合并文件的总大小
+    pub read_bytes: u64,
+    // 需要重写到活跃文件中的有效数据量
+    pub write_bytes: u64,
+    // 合并之后可以回收的磁盘空间
+    pub reclaimed_bytes: u64,
+    // 按照最近一次合并的吞吐量估算的耗时，没有合并过时按照 DEFAULT_MERGE_THROUGHPUT 估算
+    pub estimated_duration: Duration,
+}
+
+/// 选择下一次合并哪些数据文件，返回选中文件的 id
+pub trait MergePicker: Send + Sync {
+    fn pick(&self, candidates: &[MergeCandidate]) -> Vec<u32>;
+}
+
+/// 优先合并最旧的文件，只选择有可回收数据的文件
+pub struct OldestFirst {
+    pub max_files: usize,
+}
+
+impl Default for OldestFirst {
+    fn default() -> Self {
+        Self { max_files: 4 }
+    }
+}
+
+impl MergePicker for OldestFirst {
+    fn pick(&self, candidates: &[MergeCandidate]) -> Vec<u32> {
+        let mut candidates = candidates
+            .iter()
+            .filter(|c| c.garbage_bytes() > 0)
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|c| c.file_id);
+        candidates
+            .into_iter()
+            .take(self.max_files)
+            .map(|c| c.file_id)
+            .collect()
+    }
+}
+
+/// 优先合并可回收比例最高的文件，比例低于 min_garbage_ratio 的文件不会被选择
+pub struct HighestGarbageFirst {
+    pub min_garbage_ratio: f64,
+    pub max_files: usize,
+}
+
+impl Default for HighestGarbageFirst {
+    fn default() -> Self {
+        Self {
+            min_garbage_ratio: 0.5,
+            max_files: 4,
+        }
+    }
+}
+
+impl MergePicker for HighestGarbageFirst {
+    fn pick(&self, candidates: &[MergeCandidate]) -> Vec<u32> {
+        let mut candidates = candidates
+            .iter()
+            .filter(|c| c.garbage_bytes() > 0 && c.garbage_ratio() >= self.min_garbage_ratio)
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| {
+            b.garbage_ratio()
+                .total_cmp(&a.garbage_ratio())
+                .then(a.file_id.cmp(&b.file_id))
+        });
+        candidates
+            .into_iter()
+            .take(self.max_files)
+            .map(|c| c.file_id)
+            .collect()
+    }
+}
+
+/// 按照文件中有效数据的大小分层，大小相差不超过 size_ratio 倍的文件属于同一层，
+/// 从有效数据最小的一层开始，选择文件数量达到 min_files 的一层一起合并
+pub struct SizeTiered {
+    pub size_ratio: f64,
+    pub min_files: usize,
+    pub max_files: usize,
+}
+
+impl Default for SizeTiered {
+    fn default() -> Self {
+        Self {
+            size_ratio: 2.0,
+            min_files: 4,
+            max_files: 16,
+        }
+    }
+}
+
+impl MergePicker for SizeTiered {
+    fn pick(&self, candidates: &[MergeCandidate]) -> Vec<u32> {
+        let mut candidates = candidates.iter().collect::<Vec<_>>();
+        candidates.sort_by_key(|c| (c.live_bytes, c.file_id));
+
+        let mut start = 0;
+        while start < candidates.len() {
+            let base = std::cmp::max(candidates[start].live_bytes, 1) as f64;
+            let end = candidates[start..]
+                .iter()
+                .position(|c| c.live_bytes as f64 > base * self.size_ratio)
+                .map_or(candidates.len(), |n| start + n);
+            if end - start >= self.min_files {
+                return candidates[start..end]
+                    .iter()
+                    .take(self.max_files)
+                    .map(|c| c.file_id)
+                    .collect();
+            }
+            start = end;
+        }
+        Vec::new()
+    }
+}
+
+impl Engine {
+    /// 所有已封存的数据文件及其中有效数据的大小，按文件 id 排列
+    /// 需要读取索引中每条记录的长度，数据量大时代价较高
+    pub fn merge_candidates(&self) -> Result<Vec<MergeCandidate>> {
+        // 内存写缓冲中的数据覆盖的记录不再有效，先写入数据文件
+        self.flush_memtable()?;
+        let files = self.files.load();
+        let live_bytes = self.live_bytes_by_file(&files)?;
+
+        let mut candidates = files
+            .older
+            .values()
+            .map(|data_file| {
+                let file_id = data_file.get_file_id();
+                let path = get_data_file_name(&self.options.dir_path, file_id);
+                let age = fs::metadata(path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.elapsed().ok())
+                    .unwrap_or_default();
+                MergeCandidate {
+                    file_id,
+                    size: data_file.len(),
+                    live_bytes: live_bytes.get(&file_id).copied().unwrap_or(0),
+                    age,
+                }
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|c| c.file_id);
+        Ok(candidates)
+    }
+
+    // 每个数据文件中仍然被索引引用的数据量
+    pub(crate) fn live_bytes_by_file(&self, files: &DataFiles) -> Result<HashMap<u32, u64>> {
+        let mut live_bytes = HashMap::new();
+        let mut index_iter = self.index.iterator(Default::default());
+        while let Some((_, pos)) = index_iter.next() {
+            if files.get(pos.file_id).is_some() {
+                *live_bytes.entry(pos.file_id).or_insert(0) += pos.size;
+            }
+        }
+        // 文件头是每个数据文件都需要的，不算作失效数据
+        for data_file in files.older.values().chain(std::iter::once(&files.active)) {
+            if read_file_header(data_file)?.is_some() {
+                *live_bytes.entry(data_file.get_file_id()).or_insert(0) += FILE_HEADER_SIZE;
+            }
+        }
+        Ok(live_bytes)
+    }
+
+    /// 合并所有已封存的数据文件，返回重写的记录数
+    /// 有效数据重写到活跃文件中（写满时切换到新的文件），持久化之后一次性从文件集合中移除旧的文件，
+    /// 旧文件在没有迭代器等引用之后从磁盘上删除，被删除和覆盖的数据占用的空间随之回收
+    pub fn merge(&self) -> Result<usize> {
+        let file_ids = self.files.load().older.keys().copied().collect::<Vec<_>>();
+        self.compact_files(&file_ids)
+    }
+
+    /// 按照默认的策略（HighestGarbageFirst）计算合并计划，不执行合并
+    pub fn merge_plan(&self) -> Result<MergePlan> {
+        self.merge_plan_with(&HighestGarbageFirst::default())
+    }
+
+    /// 计算使用 picker 合并时会选择哪些文件，以及需要的 IO、可以回收的空间和预计的耗时，
+    /// 用于在执行 merge_with 之前安排合并的时间窗口
+    pub fn merge_plan_with(&self, picker: &dyn MergePicker) -> Result<MergePlan> {
+        let candidates = self.merge_candidates()?;
+        let mut file_ids = picker.pick(&candidates);
+        file_ids.sort();
+        file_ids.dedup();
+
+        let picked = candidates
+            .iter()
+            .filter(|c| file_ids.binary_search(&c.file_id).is_ok())
+            .collect::<Vec<_>>();
+        let read_bytes = picked.iter().map(|c| c.size).sum::<u64>();
+        // 文件头不会被重写
+        let write_bytes = picked
+            .iter()
+            .map(|c| c.live_bytes.saturating_sub(FILE_HEADER_SIZE))
+            .sum::<u64>();
+        let throughput = match self.write_stats.merge_throughput.load(Ordering::Relaxed) {
+            0 => DEFAULT_MERGE_THROUGHPUT,
+            n => n,
+        };
+        Ok(MergePlan {
+            file_ids: picked.iter().map(|c| c.file_id).collect(),
+            read_bytes,
+            write_bytes,
+            reclaimed_bytes: read_bytes.saturating_sub(write_bytes),
+            estimated_duration: Duration::from_secs_f64(
+                (read_bytes + write_bytes) as f64 / throughput as f64,
+            ),
+        })
+    }
+
+    /// 使用 picker 选择数据文件并合并，返回被合并的文件 id
+    pub fn merge_with(&self, picker: &dyn MergePicker) -> Result<Vec<u32>> {
+        let file_ids = self.merge_plan_with(picker)?.file_ids;
+        self.compact_files(&file_ids)?;
+        Ok(file_ids)
+    }
+
+    /// 将已封存数据文件中的有效数据重写到活跃文件中，再删除这些文件，返回重写的记录数
+    /// 活跃文件和不存在的文件会被忽略；合并期间读写不会被阻塞，只有每一批重写的数据追加到活跃文件时短暂持有写入锁
+    pub fn compact_files(&self, file_ids: &[u32]) -> Result<usize> {
+        if self.read_only {
+            return Err(Errors::ReadOnlyEngine);
+        }
+        let _lock = self.maintenance_lock.lock();
+        let files = self.files.load();
+        let mut file_ids = file_ids
+            .iter()
+            .copied()
+            .filter(|id| files.older.contains_key(id))
+            .collect::<Vec<_>>();
+        file_ids.sort();
+        file_ids.dedup();
+        if file_ids.is_empty() {
+            return Ok(0);
+        }
+
+        // 比被合并文件更旧、并且保留下来的文件
+        let selected = file_ids.iter().copied().collect::<HashSet<_>>();
+        let oldest_retained = files
+            .older
+            .keys()
+            .copied()
+            .filter(|id| !selected.contains(id))
+            .min();
+
+        let start = Instant::now();
+        let written_before = self.write_stats.merge_bytes.load(Ordering::Relaxed);
+        let mut read_bytes = 0;
+        let mut count = 0;
+        let mut throttle = MergeThrottle::new(self.options.merge_rate_limit);
+        for file_id in file_ids.iter() {
+            // 更旧的文件中可能还有被删除的 key 的数据，需要保留删除标记和跨文件事务的完成标识
+            let keep_markers = oldest_retained.is_some_and(|id| id < *file_id);
+            let data_file = files.older.get(file_id).unwrap();
+            count += self.compact_file(data_file, keep_markers, &mut throttle)?;
+            read_bytes += data_file.len();
+        }
+
+        // 重写的数据持久化之后再删除旧的文件
+        self.sync()?;
+        self.retire_data_files(&file_ids)?;
+
+        // 记录吞吐量，用于估算之后的合并计划的耗时
+        let written = self.write_stats.merge_bytes.load(Ordering::Relaxed) - written_before;
+        let elapsed = start.elapsed().as_secs_f64();
+        if elapsed > 0.0 && read_bytes + written > 0 {
+            let throughput = ((read_bytes + written) as f64 / elapsed) as u64;
+            self.write_stats
+                .merge_throughput
+                .store(throughput.max(1), Ordering::Relaxed);
+        }
+        Ok(count)
+    }
+
+    // 读取文件和判断记录是否有效都在锁外进行，有效的记录攒成一批之后在写入锁中重新检查并一次写入，
+    // 每个临界区只包含一次追加和索引更新，合并期间正常的读写可以继续进行
+    fn compact_file(
+        &self,
+        data_file: &DataFile,
+        keep_markers: bool,
+        throttle: &mut MergeThrottle,
+    ) -> Result<usize> {
+        let file_id = data_file.get_file_id();
+        let batch_bytes = MERGE_BATCH_BYTES
+            .min(self.options.data_file_size / 4)
+            .max(1);
+        // 文件中出现过的事务序列号
+        let mut seen_seqs = HashSet::new();
+        let mut batch = Vec::new();
+        let mut pending_bytes = 0;
+        let mut count = 0;
+        let mut offset = 0;
+        loop {
+            let (record, size) = match data_file.read_log_record(offset) {
+                Ok(res) => (res.record, res.size as u64),
+                Err(Errors::ReadDataFileEOF) => break,
+                Err(e) => return Err(e),
+            };
+            let pos = LogRecordPos {
+                file_id,
+                offset,
+                size,
+            };
+            offset += size;
+            throttle.consume(size)?;
+            if record.rec_type == LogRecordType::PADDING {
+                continue;
+            }
+
+            let (key, seq_no) = parse_log_record_key(record.key.clone());
+            let keep = match record.rec_type {
+                LogRecordType::NORMAL | LogRecordType::CHUNKED => {
+                    self.index.get(key.clone()) == Some(pos)
+                }
+                LogRecordType::DELETED => keep_markers && self.index.get(key.clone()).is_none(),
+                // 事务的数据在更旧的文件中，完成标识需要保留
+                LogRecordType::TXNFINISH => keep_markers && !seen_seqs.contains(&seq_no),
+                LogRecordType::PADDING => false,
+            };
+            if seq_no != NON_TRANSACTION_SEQ_NO {
+                seen_seqs.insert(seq_no);
+            }
+            if !keep {
+                continue;
+            }
+
+            let record = match record.rec_type {
+                LogRecordType::TXNFINISH => record,
+                _ => LogRecord {
+                    key: log_record_key_with_seq(key.clone(), NON_TRANSACTION_SEQ_NO),
+                    ..record
+                },
+            };
+            pending_bytes += record.encoded_length() as u64;
+            batch.push(MergeRewrite { key, pos, record });
+            if pending_bytes >= batch_bytes {
+                count += self.rewrite_batch(&mut batch)?;
+                throttle.consume(pending_bytes)?;
+                pending_bytes = 0;
+            }
+        }
+        count += self.rewrite_batch(&mut batch)?;
+        throttle.consume(pending_bytes)?;
+        Ok(count)
+    }
+
+    // 在写入锁中重新检查一批记录，锁外判断之后被覆盖、或者被重新写入的 key 的记录不再重写
+    fn rewrite_batch(&self, batch: &mut Vec<MergeRewrite>) -> Result<usize> {
+        if batch.is_empty() {
+            return Ok(0);
+        }
+        let _lock = self.append_lock.lock();
+        self.inflight.wait_idle();
+        batch.retain(|r| match r.record.rec_type {
+            LogRecordType::NORMAL | LogRecordType::CHUNKED => {
+                self.index.get(r.key.clone()) == Some(r.pos)
+            }
+            LogRecordType::DELETED => self.index.get(r.key.clone()).is_none(),
+            _ => true,
+        });
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        let (targets, records): (Vec<_>, Vec<_>) =
+            batch.drain(..).map(|r| ((r.key, r.pos), r.record)).unzip();
+        let positions = self.append_log_records_locked(&records, &self.write_stats.merge_bytes)?;
+        for (((key, pos), record), new_pos) in targets.into_iter().zip(&records).zip(positions) {
+            // 索引已经指向别处时，新写入的记录是失效的
+            if matches!(
+                record.rec_type,
+                LogRecordType::NORMAL | LogRecordType::CHUNKED
+            ) && !self.index.compare_and_put(key, pos, new_pos)
+            {
+                self.mark_stale_pos(&new_pos);
+            }
+        }
+        Ok(records.len())
+    }
+}
+
+// 按照 Options::merge_rate_limit 限制合并的速度，累计的读写量超过按照经过的时间允许的数据量时休眠
+// 只在写入锁之外调用，休眠时不会阻塞正常的写入
+struct MergeThrottle {
+    limit: Option<u64>,
+    start: Instant,
+    bytes: u64,
+}
+
+impl MergeThrottle {
+    fn new(limit: Option<u64>) -> Self {
+        Self {
+            limit,
+            start: Instant::now(),
+            bytes: 0,
+        }
+    }
+
+    fn consume(&mut self, bytes: u64) -> Result<()> {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        self.bytes += bytes;
+        let allowed = Duration::from_secs_f64(self.bytes as f64 / limit as f64);
+        let elapsed = self.start.elapsed();
+        if allowed > elapsed {
+            deadline::sleep(allowed - elapsed)?;
+        }
+        Ok(())
+    }
+}
+
+// 合并时需要重写的记录，pos 为在被合并文件中的位置
+struct MergeRewrite {
+    key: Vec<u8>,
+    pos: LogRecordPos,
+    record: LogRecord,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use crate::{
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    fn candidate(file_id: u32, size: u64, live_bytes: u64) -> MergeCandidate {
+        MergeCandidate {
+            file_id,
+            size,
+            live_bytes,
+            age: Duration::from_secs(100 - file_id as u64),
+        }
+    }
+
+    #[test]
+    fn test_oldest_first() {
+        let candidates = vec![
+            candidate(3, 100, 10),
+            candidate(1, 100, 100),
+            candidate(2, 100, 90),
+            candidate(0, 100, 50),
+        ];
+        let picker = OldestFirst { max_files: 2 };
+        // 没有可回收数据的文件不会被选择
+        assert_eq!(picker.pick(&candidates), vec![0, 2]);
+        assert!(picker.pick(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_highest_garbage_first() {
+        let candidates = vec![
+            candidate(0, 100, 50),
+            candidate(1, 100, 10),
+            candidate(2, 100, 90),
+            candidate(3, 100, 10),
+            candidate(4, 0, 0),
+        ];
+        let picker = HighestGarbageFirst::default();
+        assert_eq!(picker.pick(&candidates), vec![1, 3, 0]);
+
+        let picker = HighestGarbageFirst {
+            min_garbage_ratio: 0.0,
+            max_files: 10,
+        };
+        assert_eq!(picker.pick(&candidates), vec![1, 3, 0, 2]);
+    }
+
+    #[test]
+    fn test_size_tiered() {
+        let candidates = vec![
+            candidate(0, 1000, 1000),
+            candidate(1, 1000, 100),
+            candidate(2, 1000, 150),
+            candidate(3, 1000, 900),
+            candidate(4, 1000, 800),
+            candidate(5, 1000, 120),
+        ];
+        let picker = SizeTiered {
+            size_ratio: 2.0,
+            min_files: 3,
+            max_files: 16,
+        };
+        assert_eq!(picker.pick(&candidates), vec![1, 5, 2]);
+
+        // 最小的一层文件数量不够时选择下一层
+        let picker = SizeTiered {
+            size_ratio: 2.0,
+            min_files: 3,
+            max_files: 2,
+        };
+        assert_eq!(picker.pick(&candidates[..3]), Vec::<u32>::new());
+        assert_eq!(picker.pick(&candidates), vec![1, 5]);
+        let candidates = vec![
+            candidate(0, 1000, 10),
+            candidate(1, 1000, 1000),
+            candidate(2, 1000, 900),
+            candidate(3, 1000, 800),
+        ];
+        assert_eq!(picker.pick(&candidates), vec![3, 2]);
+    }
+
+    #[test]
+    fn test_merge_with() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-merge-with");
+        opts.data_file_size = 4 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        for i in 0..300 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+        // 覆盖和删除一部分数据，产生可回收的空间
+        for i in 0..100 {
+            let res = engine.put(get_test_key(i), Bytes::from("new-value"));
+            assert!(res.is_ok());
+        }
+        for i in 100..150 {
+            let res = engine.delete(get_test_key(i));
+            assert!(res.is_ok());
+        }
+        let wb = engine.new_write_batch(Default::default()).unwrap();
+        wb.put(get_test_key(1000), get_test_value(1000)).unwrap();
+        wb.commit().unwrap();
+
+        let candidates = engine.merge_candidates().unwrap();
+        let file_num = engine.stat().unwrap().data_file_num;
+        assert_eq!(candidates.len(), file_num - 1);
+        assert!(candidates.iter().all(|c| c.live_bytes <= c.size));
+        assert!(candidates[0].garbage_ratio() > 0.0);
+
+        // 合并最旧的文件时，更新的文件中的删除标记不需要保留
+        let merged = engine.merge_with(&OldestFirst { max_files: 2 }).unwrap();
+        assert_eq!(merged, vec![0, 1]);
+        let merged = engine
+            .merge_with(&HighestGarbageFirst {
+                min_garbage_ratio: 0.0,
+                max_files: 100,
+            })
+            .unwrap();
+        assert!(!merged.is_empty());
+        assert!(engine.stat().unwrap().data_file_num < file_num);
+        // 活跃文件和不存在的文件会被忽略
+        let active_id = engine.files.read().active.get_file_id();
+        assert_eq!(engine.compact_files(&[active_id, 10000]).unwrap(), 0);
+
+        let check = |engine: &Engine| {
+            for i in 0..100 {
+                assert_eq!(
+                    engine.get(get_test_key(i)).unwrap(),
+                    Bytes::from("new-value")
+                );
+            }
+            for i in 100..150 {
+                assert_eq!(
+                    engine.get(get_test_key(i)).err().unwrap(),
+                    Errors::KeyNotFound
+                );
+            }
+            for i in 150..300 {
+                assert_eq!(engine.get(get_test_key(i)).unwrap(), get_test_value(i));
+            }
+            assert!(engine.get(get_test_key(1000)).is_ok());
+            assert_eq!(engine.list_keys().unwrap().len(), 251);
+        };
+        check(&engine);
+
+        // 重启之后数据保持不变
+        engine.close().expect("failed to close");
+        let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+        check(&engine2);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_merge_rate_limit() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-merge-rate-limit");
+        opts.data_file_size = 16 * 1024;
+        opts.merge_rate_limit = Some(128 * 1024);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..1000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        for i in 0..500 {
+            engine.delete(get_test_key(i)).unwrap();
+        }
+
+        // 读取的数据量超过速度上限允许的数据量，合并至少需要对应的时间
+        let file_ids = engine
+            .files
+            .read()
+            .older
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        let read_bytes = engine
+            .merge_candidates()
+            .unwrap()
+            .iter()
+            .map(|c| c.size)
+            .sum::<u64>();
+        let start = Instant::now();
+        engine.compact_files(&file_ids).unwrap();
+        assert!(start.elapsed() >= Duration::from_secs_f64(read_bytes as f64 / (128.0 * 1024.0)));
+        for i in 500..1000 {
+            assert_eq!(engine.get(get_test_key(i)).unwrap(), get_test_value(i));
+        }
+        engine.close().expect("failed to close");
+
+        opts.merge_rate_limit = Some(0);
+        let res = Engine::open(opts.clone());
+        assert_eq!(res.err().unwrap(), Errors::InvalidMergeRateLimit);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_merge_plan() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-merge-plan");
+        opts.data_file_size = 4 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        for i in 0..300 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        for i in 0..100 {
+            engine
+                .put(get_test_key(i), Bytes::from("new-value"))
+                .unwrap();
+        }
+
+        // 计划不会修改数据，默认的策略选择可回收比例足够高的文件
+        let plan = engine.merge_plan().unwrap();
+        assert!(!plan.file_ids.is_empty());
+        let picker = OldestFirst { max_files: 2 };
+        let plan = engine.merge_plan_with(&picker).unwrap();
+        assert_eq!(plan.file_ids, vec![0, 1]);
+        assert_eq!(engine.merge_plan_with(&picker).unwrap(), plan);
+        let candidates = engine.merge_candidates().unwrap();
+        assert_eq!(plan.read_bytes, candidates[0].size + candidates[1].size);
+        assert!(plan.write_bytes < plan.read_bytes);
+        assert_eq!(plan.reclaimed_bytes, plan.read_bytes - plan.write_bytes);
+        assert!(plan.estimated_duration > Duration::ZERO);
+
+        // 执行的合并与计划一致，之后按照实际的吞吐量估算
+        let before = engine.stat().unwrap().disk_size;
+        assert_eq!(engine.merge_with(&picker).unwrap(), plan.file_ids);
+        assert!(engine.write_stats.merge_throughput.load(Ordering::Relaxed) > 0);
+        assert!(engine.stat().unwrap().disk_size < before);
+        let plan = engine
+            .merge_plan_with(&HighestGarbageFirst {
+                min_garbage_ratio: 0.99,
+                max_files: 4,
+            })
+            .unwrap();
+        assert!(plan.file_ids.is_empty());
+        assert_eq!(plan.estimated_duration, Duration::ZERO);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-merge");
+        opts.data_file_size = 4 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(engine.merge().unwrap(), 0);
+
+        for i in 0..300 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        for i in 0..200 {
+            engine
+                .put(get_test_key(i), Bytes::from("new-value"))
+                .unwrap();
+        }
+        for i in 200..250 {
+            engine.delete(get_test_key(i)).unwrap();
+        }
+        let old_files = engine
+            .files
+            .load()
+            .older
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        let before = engine.stat().unwrap().disk_size;
+
+        // 所有旧的数据文件被删除，只保留有效数据
+        assert!(engine.merge().unwrap() > 0);
+        for file_id in old_files {
+            assert!(!get_data_file_name(&opts.dir_path, file_id).exists());
+        }
+        assert!(engine.stat().unwrap().disk_size < before);
+
+        let check = |engine: &Engine| {
+            assert_eq!(engine.list_keys().unwrap().len(), 250);
+            assert_eq!(
+                engine.get(get_test_key(0)).unwrap(),
+                Bytes::from("new-value")
+            );
+            assert!(engine.get(get_test_key(200)).is_err());
+            assert_eq!(engine.get(get_test_key(299)).unwrap(), get_test_value(299));
+        };
+        check(&engine);
+        engine.close().expect("failed to close");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        check(&engine);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_merge_concurrent_writes() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-merge-concurrent");
+        opts.data_file_size = 16 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..1000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+
+        // 合并的同时写入、删除和读取，合并不会覆盖合并期间写入的数据，也不会恢复被删除的 key
+        std::thread::scope(|s| {
+            let engine = &engine;
+            s.spawn(move || {
+                for _ in 0..5 {
+                    engine.merge().unwrap();
+                }
+            });
+            s.spawn(move || {
+                for round in 0..3 {
+                    for i in 0..1000 {
+                        match i % 3 {
+                            0 => engine.delete(get_test_key(i)).unwrap(),
+                            _ => engine
+                                .put(get_test_key(i), get_test_value(i * 10 + round))
+                                .unwrap(),
+                        }
+                        assert!(engine.try_get(get_test_key(i + 1)).is_ok());
+                    }
+                }
+            });
+        });
+
+        let check = |engine: &Engine| {
+            for i in 0..1000 {
+                match i % 3 {
+                    0 => assert!(engine.try_get(get_test_key(i)).unwrap().is_none()),
+                    _ => assert_eq!(
+                        engine.get(get_test_key(i)).unwrap(),
+                        get_test_value(i * 10 + 2)
+                    ),
+                }
+            }
+        };
+        check(&engine);
+        engine.merge().unwrap();
+        check(&engine);
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        check(&engine);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
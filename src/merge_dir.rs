@@ -0,0 +1,280 @@
+//! 离线合并：把所有有效数据写入 `<dir>-merge-tmp`，持久化并写入完成标记之后再替换原来的目录
+//! 替换分两步：原目录重命名为 `<dir>-merge-old`，临时目录重命名为原目录；
+//! 任何一步崩溃之后，下次打开或者合并时根据完成标记继续替换或者丢弃临时目录，原目录中的数据不会被修改
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::{error, info, warn};
+
+use crate::{
+    batch::{log_record_key_with_seq, NON_TRANSACTION_SEQ_NO},
+    data::log_record::LogRecord,
+    db::Engine,
+    errors::{Errors, Result},
+    fence::write_db_id,
+    fio,
+    index::IndexIter,
+    merge::MERGE_BATCH_BYTES,
+    options::Options,
+    utils::atomic_write,
+};
+
+/// 临时目录中所有数据都已经持久化之后写入的完成标记
+pub const MERGE_FINISHED_FILE_NAME: &str = "merge-finished";
+
+const MERGE_TMP_SUFFIX: &str = "-merge-tmp";
+const MERGE_OLD_SUFFIX: &str = "-merge-old";
+
+impl Engine {
+    /// 离线合并 opts.dir_path 中的数据库，返回重写的记录数，调用时数据库不能处于打开状态
+    /// 有效数据写入临时目录并持久化之后才会替换原目录，合并过程中崩溃时原目录保持不变
+    pub fn merge_dir(opts: Options) -> Result<usize> {
+        let dir_path = opts.dir_path.clone();
+        let tmp_path = merge_tmp_dir(&dir_path)?;
+        let src = Engine::open(opts.clone())?;
+
+        // 丢弃之前留下的临时目录，打开时已经处理过带完成标记的临时目录
+        if tmp_path.exists() {
+            remove_dir(&tmp_path)?;
+        }
+        if let Err(e) = fio::create_dir(&tmp_path, opts.dir_mode, false) {
+            warn!("Failed to create merge directory: {e}");
+            return Err(Errors::FailedToCreateDatabaseDir(e.into()));
+        }
+        fio::sync_parent_dir(&tmp_path)?;
+        // 合并之后的数据库沿用原来的 id 和事务序列号
+        write_db_id(&tmp_path, &src.db_id, opts.file_mode)?;
+        src.seq.persist_to(&tmp_path)?;
+
+        let dst = Engine::open(Options {
+            dir_path: tmp_path.clone(),
+            sync_write: false,
+            rotate_interval: None,
+            merge_ratio: None,
+            sync_interval: None,
+            audit_log: None,
+            // 写入临时目录时不更新索引，关闭时不能写入索引快照
+            index_snapshot: false,
+            ..opts.clone()
+        })?;
+        let count = copy_live_records(&src, &dst)?;
+        dst.close()?;
+        src.close()?;
+        std::mem::drop(dst);
+        std::mem::drop(src);
+
+        // 完成标记写入之后临时目录才会被用来替换原目录
+        atomic_write(
+            &tmp_path.join(MERGE_FINISHED_FILE_NAME),
+            b"",
+            opts.file_mode,
+        )?;
+
+        finish_merge_dir(&dir_path)?;
+        info!("Merged {count} records into {:?}", dir_path);
+        Ok(count)
+    }
+}
+
+// 按照索引顺序分批写入所有有效的记录，包括内部前缀下的数据和分块
+fn copy_live_records(src: &Engine, dst: &Engine) -> Result<usize> {
+    let batch_bytes = MERGE_BATCH_BYTES.min(dst.options.data_file_size / 4);
+    let mut count = 0;
+    let mut records = Vec::new();
+    let mut size = 0;
+    for (key, pos) in IndexIter::from(src.index.iterator(Default::default())) {
+        let record = src.get_log_record_by_position(&pos)?;
+        size += pos.size;
+        records.push(LogRecord {
+            key: log_record_key_with_seq(key.to_vec(), NON_TRANSACTION_SEQ_NO),
+            value: record.value,
+            rec_type: record.rec_type,
+            meta: record.meta,
+        });
+        if size >= batch_bytes {
+            count += write_records(dst, &mut records)?;
+            size = 0;
+        }
+    }
+    count += write_records(dst, &mut records)?;
+    dst.sync()?;
+    Ok(count)
+}
+
+fn write_records(dst: &Engine, records: &mut Vec<LogRecord>) -> Result<usize> {
+    if records.is_empty() {
+        return Ok(0);
+    }
+    let _lock = dst.append_lock.lock();
+    dst.append_log_records_locked(records, &dst.write_stats.merge_bytes)?;
+    Ok(std::mem::take(records).len())
+}
+
+// 处理上次离线合并留下的目录，打开数据库之前调用
+// 带完成标记的临时目录继续替换原目录，没有完成标记的临时目录直接丢弃
+pub(crate) fn recover_merge_dir(dir_path: &Path) -> Result<()> {
+    let tmp_path = match merge_tmp_dir(dir_path) {
+        Ok(path) => path,
+        // 无法拼接出临时目录的路径时不可能进行过离线合并
+        Err(_) => return Ok(()),
+    };
+    if tmp_path.is_dir() {
+        match tmp_path.join(MERGE_FINISHED_FILE_NAME).is_file() {
+            true => return finish_merge_dir(dir_path),
+            false => {
+                warn!("Removing unfinished merge directory {:?}", tmp_path);
+                remove_dir(&tmp_path)?;
+            }
+        }
+    }
+    cleanup_merge_dir(dir_path)
+}
+
+// 用带完成标记的临时目录替换原目录，每一步都可以在崩溃之后重新执行
+fn finish_merge_dir(dir_path: &Path) -> Result<()> {
+    let tmp_path = merge_tmp_dir(dir_path)?;
+    let old_path = merge_old_dir(dir_path)?;
+    // 原目录已经重命名之后崩溃时，原目录不存在而旧目录存在
+    if dir_path.exists() {
+        if old_path.exists() {
+            remove_dir(&old_path)?;
+        }
+        rename_dir(dir_path, &old_path)?;
+    }
+    rename_dir(&tmp_path, dir_path)?;
+    fio::sync_parent_dir(dir_path)?;
+    cleanup_merge_dir(dir_path)
+}
+
+// 替换完成之后删除完成标记和旧目录
+fn cleanup_merge_dir(dir_path: &Path) -> Result<()> {
+    let marker = dir_path.join(MERGE_FINISHED_FILE_NAME);
+    if marker.is_file() {
+        if let Err(e) = fs::remove_file(&marker) {
+            error!("Failed to remove merge finished file: {e}");
+            return Err(Errors::FailedToWriteToDataFile(e.into()));
+        }
+        fio::sync_dir(dir_path)?;
+    }
+    let old_path = merge_old_dir(dir_path)?;
+    if old_path.exists() && dir_path.is_dir() {
+        remove_dir(&old_path)?;
+        fio::sync_parent_dir(dir_path)?;
+    }
+    Ok(())
+}
+
+fn merge_tmp_dir(dir_path: &Path) -> Result<PathBuf> {
+    sibling_dir(dir_path, MERGE_TMP_SUFFIX)
+}
+
+fn merge_old_dir(dir_path: &Path) -> Result<PathBuf> {
+    sibling_dir(dir_path, MERGE_OLD_SUFFIX)
+}
+
+// 与数据目录同级、名称带后缀的目录
+fn sibling_dir(dir_path: &Path, suffix: &str) -> Result<PathBuf> {
+    match dir_path.file_name() {
+        Some(name) => {
+            let mut name = name.to_os_string();
+            name.push(suffix);
+            Ok(dir_path.with_file_name(name))
+        }
+        None => Err(Errors::DirPathIsEmpty),
+    }
+}
+
+fn rename_dir(from: &Path, to: &Path) -> Result<()> {
+    if let Err(e) = fs::rename(from, to) {
+        error!("Failed to rename {:?} to {:?}: {e}", from, to);
+        return Err(Errors::FailedToCreateDatabaseDir(e.into()));
+    }
+    Ok(())
+}
+
+fn remove_dir(path: &Path) -> Result<()> {
+    if let Err(e) = fs::remove_dir_all(path) {
+        error!("Failed to remove directory {:?}: {e}", path);
+        return Err(Errors::FailedToReadDatabaseDir(e.into()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::rand_kv::{get_test_key, get_test_value};
+
+    use super::*;
+
+    #[test]
+    fn test_merge_dir() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-merge-dir");
+        opts.data_file_size = 64 * 1024;
+        opts.value_chunk_size = Some(1024);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..2000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        for i in 0..1000 {
+            engine.delete(get_test_key(i)).unwrap();
+        }
+        for i in 1000..1500 {
+            engine.put(get_test_key(i), get_test_value(i * 10)).unwrap();
+        }
+        let big = bytes::Bytes::from(vec![7u8; 5000]);
+        engine.put(get_test_key(0), big.clone()).unwrap();
+        let size_before = engine.stat().unwrap().disk_size;
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+
+        assert!(Engine::merge_dir(opts.clone()).unwrap() > 1001);
+        assert!(!merge_tmp_dir(&opts.dir_path).unwrap().exists());
+        assert!(!merge_old_dir(&opts.dir_path).unwrap().exists());
+        assert!(!opts.dir_path.join(MERGE_FINISHED_FILE_NAME).exists());
+
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(engine.stat().unwrap().disk_size < size_before);
+        assert_eq!(engine.list_keys().unwrap().len(), 1001);
+        assert_eq!(engine.get(get_test_key(0)).unwrap(), big);
+        assert!(engine.try_get(get_test_key(1)).unwrap().is_none());
+        assert_eq!(
+            engine.get(get_test_key(1200)).unwrap(),
+            get_test_value(12000)
+        );
+        assert_eq!(
+            engine.get(get_test_key(1800)).unwrap(),
+            get_test_value(1800)
+        );
+        engine.put(get_test_key(1), get_test_value(1)).unwrap();
+
+        // 模拟原目录已经移走、临时目录带有完成标记时崩溃
+        let tmp_path = merge_tmp_dir(&opts.dir_path).unwrap();
+        engine.fork_to(&tmp_path).unwrap();
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+        fs::write(tmp_path.join(MERGE_FINISHED_FILE_NAME), b"").unwrap();
+        let old_path = merge_old_dir(&opts.dir_path).unwrap();
+        fs::rename(&opts.dir_path, &old_path).unwrap();
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(!tmp_path.exists());
+        assert!(!old_path.exists());
+        assert_eq!(engine.get(get_test_key(1)).unwrap(), get_test_value(1));
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+
+        // 没有完成标记的临时目录被丢弃，原目录不变
+        fs::create_dir(&tmp_path).unwrap();
+        fs::write(tmp_path.join("000000001.data"), b"garbage").unwrap();
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(!tmp_path.exists());
+        assert_eq!(engine.list_keys().unwrap().len(), 1002);
+        engine.close().expect("failed to close");
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,227 @@
+use std::{fs, path::Path};
+
+use log::{error, warn};
+
+use crate::{
+    data::data_file::get_data_file_name,
+    db::{data_file_ids, Engine},
+    errors::{Errors, Result},
+    fio,
+    manifest::{digest_file, Manifest, MANIFEST_FILE_NAME},
+    seq::{read_seq_no, SEQ_NO_FILE_NAME},
+};
+
+/// 元数据文件（序列号文件、清单）与数据文件不一致的情况
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataMismatch {
+    // 序列号文件无法解析
+    InvalidSeqNoFile,
+
+    // 清单文件无法解析
+    InvalidManifestFile,
+
+    // 清单中记录的已封存文件不存在
+    MissingDataFile(u32),
+
+    // 清单中记录的文件不早于最新的数据文件，活跃文件不应该出现在清单中
+    ManifestAheadOfDisk {
+        file_id: u32,
+        latest_file_id: Option<u32>,
+    },
+
+    // 清单中记录的大小与数据文件的大小不一致
+    FileSizeMismatch {
+        file_id: u32,
+        manifest_size: u64,
+        disk_size: u64,
+    },
+}
+
+impl Engine {
+    /// 检查数据目录中的元数据文件与数据文件是否一致，返回所有不一致的地方
+    /// 没有元数据文件时不需要检查
+    pub fn check_metadata(dir_path: impl AsRef<Path>) -> Result<Vec<MetadataMismatch>> {
+        let dir_path = dir_path.as_ref();
+        let mut mismatches = Vec::new();
+        match read_seq_no(&dir_path.join(SEQ_NO_FILE_NAME)) {
+            Ok(_) => {}
+            Err(Errors::InvalidSeqNoFile) => mismatches.push(MetadataMismatch::InvalidSeqNoFile),
+            Err(e) => return Err(e),
+        }
+
+        let manifest = match Manifest::load(dir_path) {
+            Ok(manifest) => manifest,
+            Err(Errors::InvalidManifestFile) => {
+                mismatches.push(MetadataMismatch::InvalidManifestFile);
+                return Ok(mismatches);
+            }
+            Err(e) => return Err(e),
+        };
+        let file_ids = data_file_ids(dir_path)?;
+        let latest_file_id = file_ids.last().copied();
+        for (file_id, digest) in manifest.entries() {
+            if latest_file_id.is_none_or(|latest| file_id >= latest) {
+                mismatches.push(MetadataMismatch::ManifestAheadOfDisk {
+                    file_id,
+                    latest_file_id,
+                });
+                continue;
+            }
+            if file_ids.binary_search(&file_id).is_err() {
+                mismatches.push(MetadataMismatch::MissingDataFile(file_id));
+                continue;
+            }
+            let disk_size = match fs::metadata(get_data_file_name(dir_path, file_id)) {
+                Ok(metadata) => metadata.len(),
+                Err(e) => {
+                    error!("Failed to read metadata of data file {file_id}: {e}");
+                    return Err(Errors::FailedToReadDatabaseDir(e.into()));
+                }
+            };
+            if disk_size != digest.size {
+                mismatches.push(MetadataMismatch::FileSizeMismatch {
+                    file_id,
+                    manifest_size: digest.size,
+                    disk_size,
+                });
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+// 按照数据文件修复元数据
+// 无法解析的序列号文件被删除，之后从数据文件中恢复序列号；
+// 清单中不存在的文件被移除，大小不一致的文件按照当前的内容重新计算摘要
+pub(crate) fn heal_metadata(
+    dir_path: &Path,
+    mismatches: &[MetadataMismatch],
+    file_mode: u32,
+) -> Result<()> {
+    let mut heal_manifest = Vec::new();
+    for mismatch in mismatches {
+        warn!(
+            "Healing metadata mismatch in {:?}: {:?}",
+            dir_path, mismatch
+        );
+        match mismatch {
+            MetadataMismatch::InvalidSeqNoFile => remove_file(&dir_path.join(SEQ_NO_FILE_NAME))?,
+            MetadataMismatch::InvalidManifestFile => {
+                remove_file(&dir_path.join(MANIFEST_FILE_NAME))?
+            }
+            MetadataMismatch::MissingDataFile(file_id)
+            | MetadataMismatch::ManifestAheadOfDisk { file_id, .. } => {
+                heal_manifest.push((*file_id, None))
+            }
+            MetadataMismatch::FileSizeMismatch {
+                file_id, disk_size, ..
+            } => heal_manifest.push((*file_id, Some(*disk_size))),
+        }
+    }
+    if heal_manifest.is_empty() {
+        return Ok(());
+    }
+
+    let manifest = Manifest::load(dir_path)?.with_file_mode(file_mode);
+    for (file_id, disk_size) in heal_manifest {
+        manifest.remove(&[file_id])?;
+        if let Some(size) = disk_size {
+            if let Some(digest) = digest_file(&get_data_file_name(dir_path, file_id), size)? {
+                manifest.record(file_id, digest)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn remove_file(path: &Path) -> Result<()> {
+    if let Err(e) = fs::remove_file(path) {
+        error!("Failed to remove {:?}: {e}", path);
+        return Err(Errors::FailedToWriteToDataFile(e.into()));
+    }
+    fio::sync_parent_dir(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        options::{MetadataCheck, Options},
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_check_and_heal_metadata() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-check-metadata");
+        opts.data_file_size = 4 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..300 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+        let wb = engine.new_write_batch(Default::default()).unwrap();
+        wb.put(get_test_key(1000), get_test_value(1000)).unwrap();
+        wb.commit().unwrap();
+        // 合并掉最旧的文件，序列号被持久化
+        engine.compact_files(&[0]).unwrap();
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+        assert!(Engine::check_metadata(&opts.dir_path).unwrap().is_empty());
+
+        // 构造与数据文件不一致的清单和无法解析的序列号文件
+        let manifest = Manifest::load(&opts.dir_path).unwrap();
+        let path = get_data_file_name(&opts.dir_path, 1);
+        let digest = digest_file(&path, 10).unwrap().unwrap();
+        manifest.record(0, digest).unwrap();
+        manifest.record(1, digest).unwrap();
+        manifest.record(1000, digest).unwrap();
+        let size = fs::metadata(&path).unwrap().len();
+        fs::write(opts.dir_path.join(SEQ_NO_FILE_NAME), b"bad").unwrap();
+
+        let latest_file_id = data_file_ids(&opts.dir_path).unwrap().last().copied();
+        let expected = vec![
+            MetadataMismatch::InvalidSeqNoFile,
+            MetadataMismatch::MissingDataFile(0),
+            MetadataMismatch::FileSizeMismatch {
+                file_id: 1,
+                manifest_size: 10,
+                disk_size: size,
+            },
+            MetadataMismatch::ManifestAheadOfDisk {
+                file_id: 1000,
+                latest_file_id,
+            },
+        ];
+        assert_eq!(Engine::check_metadata(&opts.dir_path).unwrap(), expected);
+        let res = Engine::open(opts.clone());
+        assert_eq!(res.err().unwrap(), Errors::MetadataMismatch(expected));
+
+        // 自动修复之后元数据与数据文件一致
+        opts.metadata_check = MetadataCheck::Heal;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(engine.list_keys().unwrap().len(), 301);
+        assert_eq!(engine.current_seq(), 1);
+        assert!(Engine::check_metadata(&opts.dir_path).unwrap().is_empty());
+        let manifest = Manifest::load(&opts.dir_path).unwrap();
+        assert_eq!(manifest.entries().len(), 1);
+        assert_eq!(manifest.get(1).unwrap().size, size);
+        assert!(engine.verify().unwrap().is_ok());
+
+        // 清单无法解析
+        std::mem::drop(engine);
+        fs::write(opts.dir_path.join(MANIFEST_FILE_NAME), b"bad").unwrap();
+        assert_eq!(
+            Engine::check_metadata(&opts.dir_path).unwrap(),
+            vec![MetadataMismatch::InvalidManifestFile]
+        );
+        assert!(Engine::open(opts.clone()).is_ok());
+        assert!(!opts.dir_path.join(MANIFEST_FILE_NAME).exists());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
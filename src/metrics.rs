@@ -0,0 +1,342 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::db::Engine;
+
+// 桶的数量，第 i 个桶统计 [2^(i-1), 2^i) 范围内的值，第 0 个桶统计 0
+const BUCKET_NUM: usize = 65;
+
+/// 直方图，按 2 的幂划分区间，记录时只更新原子计数
+pub(crate) struct Histogram {
+    buckets: [AtomicU64; BUCKET_NUM],
+    count: AtomicU64,
+    sum: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub(crate) fn record(&self, value: u64) {
+        let idx = (u64::BITS - value.leading_zeros()) as usize;
+        self.buckets[idx].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+        self.max.fetch_max(value, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_duration(&self, d: Duration) {
+        self.record(d.as_micros() as u64);
+    }
+
+    pub(crate) fn snapshot(&self) -> HistogramSnapshot {
+        let buckets = self
+            .buckets
+            .iter()
+            .enumerate()
+            .filter_map(|(i, bucket)| match bucket.load(Ordering::Relaxed) {
+                0 => None,
+                n => Some((bucket_upper_bound(i), n)),
+            })
+            .collect();
+        HistogramSnapshot {
+            count: self.count.load(Ordering::Relaxed),
+            sum: self.sum.load(Ordering::Relaxed),
+            max: self.max.load(Ordering::Relaxed),
+            buckets,
+        }
+    }
+}
+
+// 桶中值的上界（包含）
+fn bucket_upper_bound(idx: usize) -> u64 {
+    match idx {
+        0 => 0,
+        64 => u64::MAX,
+        i => (1u64 << i) - 1,
+    }
+}
+
+/// 直方图在某一时刻的数据
+#[derive(Debug, Clone, Default)]
+pub struct HistogramSnapshot {
+    // 记录的次数
+    pub count: u64,
+    // 所有值的和
+    pub sum: u64,
+    // 最大值
+    pub max: u64,
+    // 非空的桶，(桶中值的上界, 数量)，按上界升序
+    pub buckets: Vec<(u64, u64)>,
+}
+
+impl HistogramSnapshot {
+    /// 平均值
+    pub fn mean(&self) -> f64 {
+        match self.count {
+            0 => 0.0,
+            n => self.sum as f64 / n as f64,
+        }
+    }
+
+    /// 估算分位数，q 的取值范围为 [0, 1]，返回所在桶的上界，不超过最大值
+    pub fn quantile(&self, q: f64) -> u64 {
+        let target = (self.count as f64 * q.clamp(0.0, 1.0)).ceil() as u64;
+        let mut seen = 0;
+        for (upper, n) in self.buckets.iter() {
+            seen += n;
+            if seen >= target.max(1) {
+                return std::cmp::min(*upper, self.max);
+            }
+        }
+        self.max
+    }
+}
+
+// 事务提交的统计
+#[derive(Default)]
+pub(crate) struct BatchMetrics {
+    pub(crate) records: Histogram,
+    pub(crate) bytes: Histogram,
+    pub(crate) append_latency: Histogram,
+    pub(crate) fsync_latency: Histogram,
+    pub(crate) index_latency: Histogram,
+}
+
+/// 事务提交的统计信息，从本次打开数据库开始计算，只统计提交成功的事务
+/// 耗时的单位为微秒
+#[derive(Debug, Clone, Default)]
+pub struct BatchMetricsSnapshot {
+    // 每个事务中的记录数
+    pub records: HistogramSnapshot,
+    // 每个事务中用户写入的数据量（key + value + 元数据）
+    pub bytes: HistogramSnapshot,
+    // 写入数据文件的耗时，包括等待提交锁的时间
+    pub append_latency: HistogramSnapshot,
+    // 持久化的耗时，只统计 sync_writes 的事务，包括等待合并的 fsync
+    pub fsync_latency: HistogramSnapshot,
+    // 按提交顺序更新索引的耗时
+    pub index_latency: HistogramSnapshot,
+}
+
+// 读写操作的次数统计，只统计用户数据
+#[derive(Default)]
+pub(crate) struct OpStats {
+    pub(crate) puts: AtomicU64,
+    pub(crate) deletes: AtomicU64,
+    pub(crate) gets: AtomicU64,
+    // 读取到的 value 的数据量
+    pub(crate) bytes_read: AtomicU64,
+}
+
+/// 某一时刻累计的统计数据，用于和之后的数据计算差值
+/// 计数从本次打开数据库开始累计
+#[derive(Debug, Clone, Copy)]
+pub struct StatsSnapshot {
+    // 获取快照的时间
+    pub at: Instant,
+    pub puts: u64,
+    pub deletes: u64,
+    pub gets: u64,
+    // 用户写入的数据量（key + value + 元数据）
+    pub user_bytes_written: u64,
+    // 实际追加到数据文件的数据量，包括 merge 等回收空间的写入
+    pub disk_bytes_written: u64,
+    // 读取到的 value 的数据量
+    pub bytes_read: u64,
+    // 布隆过滤器的查找次数和判断不存在的次数，没有启用时为 0
+    pub bloom_lookups: u64,
+    pub bloom_negatives: u64,
+}
+
+/// 两个快照之间的统计数据
+#[derive(Debug, Clone, Copy)]
+pub struct StatsDelta {
+    // 两个快照之间的时间
+    pub elapsed: Duration,
+    pub puts: u64,
+    pub deletes: u64,
+    pub gets: u64,
+    pub user_bytes_written: u64,
+    pub disk_bytes_written: u64,
+    pub bytes_read: u64,
+    pub bloom_lookups: u64,
+    pub bloom_negatives: u64,
+    // 当前的快照，作为下一次计算的起点
+    pub snapshot: StatsSnapshot,
+}
+
+impl StatsDelta {
+    fn per_sec(&self, n: u64) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => n as f64 / secs,
+            _ => 0.0,
+        }
+    }
+
+    /// 每秒的读写操作数
+    pub fn ops_per_sec(&self) -> f64 {
+        self.per_sec(self.puts + self.deletes + self.gets)
+    }
+
+    /// 每秒用户写入的数据量
+    pub fn write_bytes_per_sec(&self) -> f64 {
+        self.per_sec(self.user_bytes_written)
+    }
+
+    /// 每秒读取的数据量
+    pub fn read_bytes_per_sec(&self) -> f64 {
+        self.per_sec(self.bytes_read)
+    }
+
+    /// 布隆过滤器直接判断不存在、不需要查找索引的比例
+    pub fn bloom_hit_rate(&self) -> f64 {
+        match self.bloom_lookups {
+            0 => 0.0,
+            n => self.bloom_negatives as f64 / n as f64,
+        }
+    }
+}
+
+impl Engine {
+    /// 获取当前累计的统计数据
+    pub fn stats_snapshot(&self) -> StatsSnapshot {
+        let write_stats = &self.write_stats;
+        let bloom = self.bloom_stats().unwrap_or_default();
+        StatsSnapshot {
+            at: Instant::now(),
+            puts: self.op_stats.puts.load(Ordering::Relaxed),
+            deletes: self.op_stats.deletes.load(Ordering::Relaxed),
+            gets: self.op_stats.gets.load(Ordering::Relaxed),
+            user_bytes_written: write_stats.user_bytes.load(Ordering::Relaxed),
+            disk_bytes_written: write_stats.data_bytes.load(Ordering::Relaxed)
+                + write_stats.merge_bytes.load(Ordering::Relaxed),
+            bytes_read: self.op_stats.bytes_read.load(Ordering::Relaxed),
+            bloom_lookups: bloom.lookups,
+            bloom_negatives: bloom.negatives,
+        }
+    }
+
+    /// 计算从 since 到现在的统计数据，返回结果中的 snapshot 可以作为下一次的 since
+    pub fn stats_delta(&self, since: StatsSnapshot) -> StatsDelta {
+        let now = self.stats_snapshot();
+        StatsDelta {
+            elapsed: now.at.saturating_duration_since(since.at),
+            puts: now.puts.saturating_sub(since.puts),
+            deletes: now.deletes.saturating_sub(since.deletes),
+            gets: now.gets.saturating_sub(since.gets),
+            user_bytes_written: now
+                .user_bytes_written
+                .saturating_sub(since.user_bytes_written),
+            disk_bytes_written: now
+                .disk_bytes_written
+                .saturating_sub(since.disk_bytes_written),
+            bytes_read: now.bytes_read.saturating_sub(since.bytes_read),
+            bloom_lookups: now.bloom_lookups.saturating_sub(since.bloom_lookups),
+            bloom_negatives: now.bloom_negatives.saturating_sub(since.bloom_negatives),
+            snapshot: now,
+        }
+    }
+
+    /// 获取事务提交的统计信息，用于调整 max_batch_num 和持久化选项
+    pub fn batch_metrics(&self) -> BatchMetricsSnapshot {
+        let metrics = &self.batch_metrics;
+        BatchMetricsSnapshot {
+            records: metrics.records.snapshot(),
+            bytes: metrics.bytes.snapshot(),
+            append_latency: metrics.append_latency.snapshot(),
+            fsync_latency: metrics.fsync_latency.snapshot(),
+            index_latency: metrics.index_latency.snapshot(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use crate::{
+        options::{BloomFilterOptions, Options},
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_stats_delta() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-stats-delta");
+        opts.bloom_filter = Some(BloomFilterOptions::default());
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let start = engine.stats_snapshot();
+        for i in 0..10 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+        for i in 0..20 {
+            let _ = engine.get(get_test_key(i));
+        }
+        assert!(engine.delete(get_test_key(0)).is_ok());
+        // 幂等写入的请求 id 不计入
+        let res = engine.put_idempotent(Bytes::from("req"), get_test_key(1), get_test_value(1));
+        assert!(res.is_ok());
+        std::thread::sleep(Duration::from_millis(10));
+
+        let delta = engine.stats_delta(start);
+        assert_eq!(delta.puts, 11);
+        assert_eq!(delta.deletes, 1);
+        assert_eq!(delta.gets, 20);
+        assert_eq!(delta.bytes_read, 10 * get_test_value(0).len() as u64);
+        assert!(delta.user_bytes_written > 0);
+        assert!(delta.disk_bytes_written > delta.user_bytes_written);
+        assert!(delta.elapsed >= Duration::from_millis(10));
+        assert!(delta.ops_per_sec() > 0.0);
+        assert!(delta.bloom_lookups >= 20);
+        assert!(delta.bloom_hit_rate() > 0.0);
+
+        // 以上一次的快照为起点
+        let _ = engine.get(get_test_key(1));
+        let delta = engine.stats_delta(delta.snapshot);
+        assert_eq!(delta.puts, 0);
+        assert_eq!(delta.gets, 1);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_histogram() {
+        let hist = Histogram::default();
+        assert_eq!(hist.snapshot().quantile(0.5), 0);
+        for v in [0, 1, 2, 3, 100, 1000] {
+            hist.record(v);
+        }
+        hist.record(u64::MAX);
+
+        let snapshot = hist.snapshot();
+        assert_eq!(snapshot.count, 7);
+        assert_eq!(snapshot.max, u64::MAX);
+        assert_eq!(
+            snapshot.buckets,
+            vec![(0, 1), (1, 1), (3, 2), (127, 1), (1023, 1), (u64::MAX, 1)]
+        );
+        assert_eq!(snapshot.quantile(0.0), 0);
+        assert_eq!(snapshot.quantile(0.5), 3);
+        assert_eq!(snapshot.quantile(0.8), 1023);
+        assert_eq!(snapshot.quantile(1.0), u64::MAX);
+    }
+}
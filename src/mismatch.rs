@@ -0,0 +1,292 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use log::warn;
+
+use crate::{
+    data::log_record::{LogRecord, LogRecordPos, LogRecordType},
+    db::{is_internal_key, Engine},
+    deadline,
+    errors::{Errors, Result},
+    options::IndexMismatchPolicy,
+    trace::TraceOp,
+};
+
+// 索引与数据不一致的统计
+#[derive(Default)]
+pub(crate) struct MismatchStats {
+    deleted_records: AtomicU64,
+    missing_files: AtomicU64,
+    healed: AtomicU64,
+}
+
+/// 运行时索引与数据不一致的统计信息，从本次打开数据库开始计算
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexMismatchStats {
+    // 索引指向删除标记的次数
+    pub deleted_records: u64,
+    // 索引指向不存在的数据文件的次数
+    pub missing_files: u64,
+    // 自动从索引中删除的 key 的数量
+    pub healed: u64,
+}
+
+impl Engine {
+    /// 获取运行时索引与数据不一致的统计信息
+    pub fn index_mismatch_stats(&self) -> IndexMismatchStats {
+        let stats = &self.mismatch_stats;
+        IndexMismatchStats {
+            deleted_records: stats.deleted_records.load(Ordering::Relaxed),
+            missing_files: stats.missing_files.load(Ordering::Relaxed),
+            healed: stats.healed.load(Ordering::Relaxed),
+        }
+    }
+
+    // 根据索引读取 key 对应的有效 LogRecord
+    // 索引指向删除标记或者不存在的数据文件时，按照 Options::index_mismatch 处理
+    pub(crate) fn get_indexed_log_record(&self, key: &[u8]) -> Result<LogRecord> {
+        // 内存写缓冲中的数据比索引中的更新
+        if let Some(record) = self.memtable_get(key) {
+            return record.ok_or(Errors::KeyNotFound);
+        }
+        let internal = is_internal_key(key);
+        let mut trace = None;
+        if !internal {
+            self.op_stats.gets.fetch_add(1, Ordering::Relaxed);
+            trace = self.trace_start();
+        }
+        loop {
+            let pos = match self.index_get(key)? {
+                Some(pos) => pos,
+                None => {
+                    self.trace_finish(trace, TraceOp::Get, key, None);
+                    return Err(Errors::KeyNotFound);
+                }
+            };
+            let counter = match self.read_log_record_at(&pos) {
+                // 读取期间记录失效并且被打洞回收，使用新的位置重新读取
+                Ok(record)
+                    if record.rec_type == LogRecordType::PADDING
+                        && self.index.get(key.to_vec()) != Some(pos) =>
+                {
+                    continue
+                }
+                Err(Errors::InvalidLogRecordCrc) if self.index.get(key.to_vec()) != Some(pos) => {
+                    continue
+                }
+                Ok(record)
+                    if record.rec_type != LogRecordType::DELETED
+                        && record.rec_type != LogRecordType::PADDING =>
+                {
+                    let record = self.resolve_chunks(record)?;
+                    if !internal {
+                        self.op_stats
+                            .bytes_read
+                            .fetch_add(record.value.len() as u64, Ordering::Relaxed);
+                    }
+                    self.trace_finish(trace, TraceOp::Get, key, Some(pos.file_id));
+                    return Ok(record);
+                }
+                Ok(record) if record.rec_type == LogRecordType::PADDING => {
+                    return Err(Errors::IndexDataMismatch {
+                        file_id: pos.file_id,
+                        offset: pos.offset,
+                    })
+                }
+                Ok(_) => &self.mismatch_stats.deleted_records,
+                Err(Errors::FailedToOpenDataFile(_)) => &self.mismatch_stats.missing_files,
+                Err(e) => return Err(e),
+            };
+
+            // 读取期间索引被并发的写入或者 merge 更新，使用新的位置重新读取
+            if self.index.get(key.to_vec()) != Some(pos) {
+                continue;
+            }
+            counter.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "Index points to invalid data at file {} offset {}",
+                pos.file_id, pos.offset
+            );
+            return match self.options.index_mismatch {
+                IndexMismatchPolicy::Strict => Err(Errors::IndexDataMismatch {
+                    file_id: pos.file_id,
+                    offset: pos.offset,
+                }),
+                IndexMismatchPolicy::SelfHeal => {
+                    self.remove_mismatched_index(key, pos);
+                    Err(Errors::KeyNotFound)
+                }
+            };
+        }
+    }
+
+    // 只有索引仍然指向不一致的位置时才删除，避免删除并发写入的新数据
+    fn remove_mismatched_index(&self, key: &[u8], pos: LogRecordPos) {
+        // 设置了截止时间时等待超时不修复，下次读取时再修复
+        let _lock = match deadline::lock(&self.append_lock) {
+            Ok(lock) => lock,
+            Err(_) => return,
+        };
+        if deadline::wait_idle(&self.inflight).is_err() {
+            return;
+        }
+        if self.index.get(key.to_vec()) == Some(pos) && self.index.delete(key.to_vec()) {
+            self.mismatch_stats.healed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use crate::{
+        batch::{log_record_key_with_seq, NON_TRANSACTION_SEQ_NO},
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    // 追加一条删除标记，并让索引指向它
+    fn point_to_tombstone(engine: &Engine, key: &Bytes) {
+        let mut record = LogRecord {
+            key: log_record_key_with_seq(key.to_vec(), NON_TRANSACTION_SEQ_NO),
+            value: Default::default(),
+            rec_type: LogRecordType::DELETED,
+            meta: Default::default(),
+        };
+        let (pos, _inflight) = engine.append_log_record(&mut record).unwrap();
+        engine.index.put(key.to_vec(), pos);
+    }
+
+    #[test]
+    fn test_index_mismatch_strict() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-index-mismatch-strict");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..3 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+
+        point_to_tombstone(&engine, &get_test_key(0));
+        let pos = engine.index.get(get_test_key(0).to_vec()).unwrap();
+        assert_eq!(
+            engine.get(get_test_key(0)).err().unwrap(),
+            Errors::IndexDataMismatch {
+                file_id: pos.file_id,
+                offset: pos.offset,
+            }
+        );
+
+        let missing = LogRecordPos {
+            file_id: 100,
+            offset: 0,
+            size: 11,
+        };
+        engine.index.put(get_test_key(1).to_vec(), missing);
+        assert_eq!(
+            engine.get_with_meta(get_test_key(1)).err().unwrap(),
+            Errors::IndexDataMismatch {
+                file_id: 100,
+                offset: 0,
+            }
+        );
+        // 严格模式下不修改索引
+        assert_eq!(engine.index.get(get_test_key(1).to_vec()), Some(missing));
+        assert_eq!(engine.get(get_test_key(2)).unwrap(), get_test_value(2));
+        assert_eq!(
+            engine.index_mismatch_stats(),
+            IndexMismatchStats {
+                deleted_records: 1,
+                missing_files: 1,
+                healed: 0,
+            }
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_index_mismatch_self_heal() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-index-mismatch-self-heal");
+        opts.index_mismatch = IndexMismatchPolicy::SelfHeal;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..3 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+
+        point_to_tombstone(&engine, &get_test_key(0));
+        assert_eq!(
+            engine.get(get_test_key(0)).err().unwrap(),
+            Errors::KeyNotFound
+        );
+        assert!(engine.index.get(get_test_key(0).to_vec()).is_none());
+
+        engine.index.put(
+            get_test_key(1).to_vec(),
+            LogRecordPos {
+                file_id: 100,
+                offset: 0,
+                size: 11,
+            },
+        );
+        assert_eq!(
+            engine.get(get_test_key(1)).err().unwrap(),
+            Errors::KeyNotFound
+        );
+        assert!(engine.index.get(get_test_key(1).to_vec()).is_none());
+        assert_eq!(engine.list_keys().unwrap(), vec![get_test_key(2)]);
+        assert_eq!(
+            engine.index_mismatch_stats(),
+            IndexMismatchStats {
+                deleted_records: 1,
+                missing_files: 1,
+                healed: 2,
+            }
+        );
+
+        // 重新写入之后可以正常读取
+        assert!(engine.put(get_test_key(0), get_test_value(10)).is_ok());
+        assert_eq!(engine.get(get_test_key(0)).unwrap(), get_test_value(10));
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_index_mismatch_self_heal_concurrent_delete() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-index-mismatch-concurrent");
+        opts.index_mismatch = IndexMismatchPolicy::SelfHeal;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..100 {
+            point_to_tombstone(&engine, &get_test_key(i));
+        }
+
+        // 自动修复和删除同时移除索引中的 key，删除仍然返回成功
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..100 {
+                    let res = engine.delete(get_test_key(i));
+                    assert!(res.is_ok());
+                }
+            });
+            s.spawn(|| {
+                for i in 0..100 {
+                    let res = engine.get(get_test_key(i));
+                    assert_eq!(res.err().unwrap(), Errors::KeyNotFound);
+                }
+            });
+        });
+        assert!(engine.list_keys().unwrap().is_empty());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,168 @@
+use bytes::Bytes;
+
+use crate::{
+    data::log_record::LogRecordType,
+    db::Engine,
+    errors::{Errors, Result},
+    iterator::LiveIterator,
+    options::IteratorConsistency,
+};
+
+impl Engine {
+    /// 批量读取多个 key，返回的 value 与 keys 一一对应，key 不存在时为 None
+    /// consistency 为 Snapshot 时所有 key 的位置在同一时刻从索引中读取，返回的结果是某一时刻一致的视图，
+    /// 不会只读到并发事务的一部分写入，读取位置时会短暂阻塞写入；
+    /// ReadCommitted 时逐个读取，不阻塞写入，但不同 key 的 value 可能来自不同的时刻
+    pub fn multi_get(
+        &self,
+        keys: &[Bytes],
+        consistency: IteratorConsistency,
+    ) -> Result<Vec<Option<Bytes>>> {
+        if keys.iter().any(|key| key.is_empty()) {
+            return Err(Errors::KeyIsEmpty);
+        }
+        match consistency {
+            IteratorConsistency::ReadCommitted => {
+                keys.iter().map(|key| self.try_get(key.clone())).collect()
+            }
+            IteratorConsistency::Snapshot => loop {
+                if let Some(values) = self.snapshot_multi_get(keys)? {
+                    return Ok(values);
+                }
+            },
+        }
+    }
+
+    // 分块存储的 value 在读取分块之前被覆盖时返回 None，需要重新读取
+    fn snapshot_multi_get(&self, keys: &[Bytes]) -> Result<Option<Vec<Option<Bytes>>>> {
+        self.flush_memtable()?;
+        // 读取完成之前打洞不会回收这些位置
+        let _live = LiveIterator::new(&self.live_iterators);
+        let (files, positions) = {
+            let _lock = self.append_lock.lock();
+            // 已经写入的数据都更新完索引之后，索引中没有只写入了一部分的事务
+            self.inflight.wait_idle();
+            let positions = keys
+                .iter()
+                .map(|key| self.index.get(key.to_vec()))
+                .collect::<Vec<_>>();
+            (self.files.load(), positions)
+        };
+
+        // 按照位置排序之后读取，同一个文件中的读取是顺序的
+        let mut order = (0..keys.len())
+            .filter_map(|i| positions[i].map(|pos| (i, pos)))
+            .collect::<Vec<_>>();
+        order.sort_by_key(|(_, pos)| (pos.file_id, pos.offset));
+        let mut values = vec![None; keys.len()];
+        for (i, pos) in order {
+            if self.is_expired(&keys[i]) {
+                continue;
+            }
+            let record = files.read_log_record_at(&pos)?;
+            match record.rec_type {
+                LogRecordType::DELETED => continue,
+                LogRecordType::CHUNKED => match self.resolve_chunks(record) {
+                    Ok(record) => values[i] = Some(record.value.into()),
+                    // 分块已经随着 key 被覆盖一起删除
+                    Err(Errors::DataFileCorrupted)
+                        if self.index.get(keys[i].to_vec()) != Some(pos) =>
+                    {
+                        return Ok(None)
+                    }
+                    Err(e) => return Err(e),
+                },
+                _ => values[i] = Some(record.value.into()),
+            }
+        }
+        Ok(Some(values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        options::{Options, WriteBatchOptions},
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_multi_get() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-multi-get");
+        opts.value_chunk_size = Some(1024);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        for i in 0..10 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine.delete(get_test_key(3)).unwrap();
+        let big = Bytes::from(vec![7u8; 5000]);
+        engine.put(get_test_key(5), big.clone()).unwrap();
+
+        let keys = vec![
+            get_test_key(9),
+            get_test_key(3),
+            get_test_key(5),
+            get_test_key(100),
+            get_test_key(0),
+        ];
+        let expected = vec![
+            Some(get_test_value(9)),
+            None,
+            Some(big),
+            None,
+            Some(get_test_value(0)),
+        ];
+        for consistency in [
+            IteratorConsistency::Snapshot,
+            IteratorConsistency::ReadCommitted,
+        ] {
+            assert_eq!(engine.multi_get(&keys, consistency).unwrap(), expected);
+        }
+        assert!(engine
+            .multi_get(&[], IteratorConsistency::Snapshot)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            engine
+                .multi_get(&[Bytes::new()], IteratorConsistency::Snapshot)
+                .err()
+                .unwrap(),
+            Errors::KeyIsEmpty
+        );
+
+        // 事务同时修改两个 key，快照读取时两个 key 的 value 总是相同
+        let pair = vec![Bytes::from("a"), Bytes::from("b")];
+        std::thread::scope(|s| {
+            let engine = &engine;
+            let pair = &pair;
+            s.spawn(move || {
+                for i in 0..500 {
+                    let wb = engine
+                        .new_write_batch(WriteBatchOptions::default())
+                        .unwrap();
+                    for key in pair.iter() {
+                        wb.put(key.clone(), get_test_value(i)).unwrap();
+                    }
+                    wb.commit().unwrap();
+                }
+            });
+            s.spawn(move || {
+                for _ in 0..500 {
+                    let values = engine
+                        .multi_get(pair, IteratorConsistency::Snapshot)
+                        .unwrap();
+                    assert_eq!(values[0], values[1]);
+                }
+            });
+        });
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
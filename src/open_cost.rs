@@ -0,0 +1,215 @@
+use std::{fs, path::Path, time::Duration};
+
+use log::{error, warn};
+
+use crate::{
+    data::data_file::get_data_file_name,
+    db::{data_file_ids, Engine},
+    errors::{Errors, Result},
+    utils::atomic_write,
+};
+
+/// 关闭时写入的打开代价摘要的文件名
+pub const OPEN_SUMMARY_FILE_NAME: &str = "open-summary";
+
+// 没有摘要或者摘要中没有测量到加载速度时，按照这个速度估计（字节 / 秒）
+const DEFAULT_LOAD_THROUGHPUT: u64 = 100 * 1024 * 1024;
+
+// 加载的数据量太小时测量到的速度没有意义，不更新摘要中的速度
+const MIN_MEASURED_BYTES: u64 = 1024 * 1024;
+
+/// 打开数据库预计需要的时间
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenEstimate {
+    // 需要加载的数据文件的总大小
+    pub data_bytes: u64,
+    // 上次关闭时数据文件中的记录数，没有摘要时为 None
+    pub records: Option<u64>,
+    // 预计加载索引需要的时间
+    pub estimated: Duration,
+    // 是否使用了上次关闭时写入的摘要，为 false 时只根据数据文件的大小估计
+    pub from_summary: bool,
+}
+
+// 打开代价摘要，关闭时写入，下次打开之前读取
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct OpenSummary {
+    pub(crate) records: u64,
+    pub(crate) data_bytes: u64,
+    // 加载索引的速度（字节 / 秒），0 表示没有测量过
+    pub(crate) load_throughput: u64,
+}
+
+// 本次打开时加载索引的代价
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct OpenCost {
+    pub(crate) data_bytes: u64,
+    pub(crate) load_duration: Duration,
+}
+
+impl OpenCost {
+    // 测量到的加载速度，数据量太小时返回 None
+    fn throughput(&self) -> Option<u64> {
+        let secs = self.load_duration.as_secs_f64();
+        if self.data_bytes < MIN_MEASURED_BYTES || secs <= 0.0 {
+            return None;
+        }
+        Some(((self.data_bytes as f64 / secs) as u64).max(1))
+    }
+}
+
+impl Engine {
+    /// 估计打开数据目录中的数据库需要的时间，不会打开数据库
+    /// 按照上次关闭时记录的加载速度计算，没有摘要时按照数据文件的大小和默认的速度估计，
+    /// 可以用来在打开之前设置编排系统的超时时间
+    pub fn estimate_open_time<P: AsRef<Path>>(dir_path: P) -> Result<OpenEstimate> {
+        let dir_path = dir_path.as_ref();
+        let data_bytes = match dir_path.is_dir() {
+            true => data_files_size(dir_path)?,
+            false => 0,
+        };
+        let summary = read_open_summary(dir_path).unwrap_or_else(|e| {
+            warn!("Ignoring unreadable open summary: {e}");
+            None
+        });
+        let throughput = match summary {
+            Some(s) if s.load_throughput > 0 => s.load_throughput,
+            _ => DEFAULT_LOAD_THROUGHPUT,
+        };
+        Ok(OpenEstimate {
+            data_bytes,
+            records: summary.map(|s| s.records),
+            estimated: Duration::from_secs_f64(data_bytes as f64 / throughput as f64),
+            from_summary: summary.is_some(),
+        })
+    }
+
+    /// 本次打开时从数据文件加载索引花费的时间
+    pub fn index_load_duration(&self) -> Duration {
+        self.open_cost.load_duration
+    }
+
+    // 关闭时写入打开代价摘要，本次加载的数据量太小时沿用上次测量到的速度
+    pub(crate) fn write_open_summary(&self) -> Result<()> {
+        let dir_path = &self.options.dir_path;
+        let load_throughput = match self.open_cost.throughput() {
+            Some(throughput) => throughput,
+            None => read_open_summary(dir_path)
+                .ok()
+                .flatten()
+                .map_or(0, |s| s.load_throughput),
+        };
+        let stats = self.file_stats();
+        let summary = OpenSummary {
+            records: stats.iter().map(|s| s.record_count).sum(),
+            data_bytes: stats.iter().map(|s| s.total_bytes).sum(),
+            load_throughput,
+        };
+        write_open_summary(dir_path, &summary, self.options.file_mode)
+    }
+}
+
+// 数据目录中所有数据文件的总大小
+fn data_files_size(dir_path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for file_id in data_file_ids(dir_path)? {
+        if let Ok(metadata) = fs::metadata(get_data_file_name(dir_path, file_id)) {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+// 格式为三个小端序的 u64：记录数、数据量、加载速度
+pub(crate) fn read_open_summary(dir_path: &Path) -> Result<Option<OpenSummary>> {
+    let path = dir_path.join(OPEN_SUMMARY_FILE_NAME);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let buf = match fs::read(&path) {
+        Ok(buf) => buf,
+        Err(e) => {
+            error!("Failed to read open summary file: {e}");
+            return Err(Errors::FailedToReadFromDataFile(e.into()));
+        }
+    };
+    if buf.len() != 24 {
+        return Err(Errors::DataFileCorrupted);
+    }
+    let field = |i: usize| u64::from_le_bytes(buf[i * 8..i * 8 + 8].try_into().unwrap());
+    Ok(Some(OpenSummary {
+        records: field(0),
+        data_bytes: field(1),
+        load_throughput: field(2),
+    }))
+}
+
+// 原子地写入，崩溃时不会留下写到一半的摘要
+fn write_open_summary(dir_path: &Path, summary: &OpenSummary, mode: u32) -> Result<()> {
+    let mut buf = Vec::with_capacity(24);
+    for field in [summary.records, summary.data_bytes, summary.load_throughput] {
+        buf.extend_from_slice(&field.to_le_bytes());
+    }
+    atomic_write(&dir_path.join(OPEN_SUMMARY_FILE_NAME), &buf, mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_estimate_open_time() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-estimate-open-time");
+
+        // 不存在的目录
+        let estimate = Engine::estimate_open_time(&opts.dir_path).unwrap();
+        assert_eq!(estimate.data_bytes, 0);
+        assert_eq!(estimate.estimated, Duration::ZERO);
+        assert!(!estimate.from_summary);
+
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..1000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine.sync().unwrap();
+
+        // 没有摘要时按照数据文件的大小估计
+        let estimate = Engine::estimate_open_time(&opts.dir_path).unwrap();
+        assert!(estimate.data_bytes > 0);
+        assert_eq!(estimate.records, None);
+        assert!(!estimate.from_summary);
+
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+        let estimate = Engine::estimate_open_time(&opts.dir_path).unwrap();
+        assert!(estimate.from_summary);
+        assert_eq!(estimate.records, Some(1000));
+
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(engine.index_load_duration() > Duration::ZERO);
+        engine.close().expect("failed to close");
+
+        // 无法解析的摘要被忽略
+        fs::write(opts.dir_path.join(OPEN_SUMMARY_FILE_NAME), b"bad").unwrap();
+        let estimate = Engine::estimate_open_time(&opts.dir_path).unwrap();
+        assert!(!estimate.from_summary);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        engine.close().expect("failed to close");
+        assert!(
+            Engine::estimate_open_time(&opts.dir_path)
+                .unwrap()
+                .from_summary
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -1,4 +1,15 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use crate::{
+    clock::Clock, conflict::ConflictResolver, data::log_record::MIN_PADDING_SIZE, hash::KeyHasher,
+    index::Indexer,
+};
+
+/// key 校验函数，返回 false 时拒绝写入
+pub type KeyValidator = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+/// 创建自定义索引的函数，index_type 为 IndexType::Custom 时使用
+pub type IndexFactory = Arc<dyn Fn() -> Box<dyn Indexer> + Send + Sync>;
 
 #[derive(Clone)]
 pub struct Options {
@@ -9,6 +20,228 @@ pub struct Options {
     pub sync_write: bool,
 
     pub index_type: IndexType,
+
+    // 自定义索引，index_type 为 IndexType::Custom 时必须设置
+    pub custom_index: Option<IndexFactory>,
+
+    // 写入和删除时对 key 的额外校验
+    pub key_validator: Option<KeyValidator>,
+
+    // 关闭数据库时等待后台任务退出的超时时间
+    pub shutdown_timeout: Duration,
+
+    // 记录在数据文件中的对齐方式
+    pub record_alignment: RecordAlignment,
+
+    // 数据文件的 IO 类型
+    pub io_type: IOType,
+
+    // 活跃文件按时间切换的间隔，写入量很小时也能定期产生不再修改的数据文件，None 表示只按大小切换
+    pub rotate_interval: Option<Duration>,
+
+    // 活跃文件中失效数据的比例达到该值时提前切换，取值范围 (0, 1]，None 表示不启用
+    pub rotate_stale_ratio: Option<f64>,
+
+    // 已封存文件中失效数据的比例达到该值时自动合并，取值范围 (0, 1]，None 表示不启用
+    // 后台任务定期检查每个文件的失效数据量，并在后台线程中合并选出的文件，不阻塞正常的写入
+    pub merge_ratio: Option<f64>,
+
+    // 合并时读取和重写数据的总速度上限（字节 / 秒），避免合并占满磁盘带宽影响正常读写，None 表示不限制
+    pub merge_rate_limit: Option<u64>,
+
+    // sync_write 为 false 时由后台线程定期持久化活跃文件，None 表示完全交给操作系统
+    pub sync_interval: Option<SyncInterval>,
+
+    // 超过该长度的 value 拆分为多个分块存储，None 表示不拆分
+    // 事务中覆盖或者删除分块存储的 key 时不会清理旧的分块
+    pub value_chunk_size: Option<usize>,
+
+    // 将每个提交的写入追加到该文件中（NDJSON），用于审计，None 表示不记录
+    pub audit_log: Option<PathBuf>,
+
+    // 跟随者模式下检查新写入数据的间隔，None 表示只在调用 catch_up 时更新
+    pub follower_poll_interval: Option<Duration>,
+
+    // put_idempotent 记录的请求 id 的保留时间，超过之后相同的请求 id 可以再次写入
+    pub idempotency_ttl: Duration,
+
+    // 释放存储引擎时仍有没有持久化的数据的处理方式，只在启用 drop-check 特性时生效
+    pub unsynced_drop: UnsyncedDropAction,
+
+    // 数据文件封存之后在后台计算整个文件的摘要并记录在清单中，用于快速校验数据完整性
+    pub seal_digest: bool,
+
+    // 在索引之前使用布隆过滤器过滤不存在的 key，None 表示不启用
+    pub bloom_filter: Option<BloomFilterOptions>,
+
+    // 打开时元数据文件与数据文件不一致的处理方式
+    pub metadata_check: MetadataCheck,
+
+    // 运行时索引指向的数据与索引不一致时的处理方式
+    pub index_mismatch: IndexMismatchPolicy,
+
+    // 读写操作的采样率，采样的操作记录在环形缓冲区中，通过 Engine::recent_ops 获取，0 表示不采样
+    pub op_trace_sample_rate: f64,
+
+    // 是否允许打开属于其他数据库的数据文件，默认拒绝，用于确认需要合并其他数据库的文件时强制打开
+    pub allow_foreign_data_files: bool,
+
+    // 后台任务线程（文件切换、持久化、摘要计算等）的调度设置，默认与前台线程相同
+    pub background_priority: BackgroundPriority,
+
+    // 新建的数据文件和元数据文件（序列号、清单、db-id、审计日志）的权限，如 0o600，实际权限还受 umask 限制
+    pub file_mode: u32,
+
+    // 新建的数据目录的权限，如 0o700，已经存在的目录不会修改
+    pub dir_mode: u32,
+
+    // put 和事务中的 put 写入的 key 的默认有效时间，None 表示不过期
+    pub default_ttl: Option<Duration>,
+
+    // 过期时间、租约、队列可见时间和事件时间戳使用的时钟，None 表示使用系统时钟
+    // 需要不受系统时间调整影响时使用 MonotonicClock，测试中可以使用 ManualClock
+    pub clock: Option<Arc<dyn Clock>>,
+
+    // 布隆过滤器、操作追踪和 Engine::key_shard 使用的 key 哈希函数，None 表示使用 Xxh3
+    // 需要与外部的分片路由保持一致时指定相同的算法，也可以实现 KeyHasher 使用自定义的算法
+    pub key_hasher: Option<Arc<dyn KeyHasher>>,
+
+    // 跟随者回放时已经有同一个 key 的数据，决定收到的记录是否生效，None 表示使用 LastWriterWins
+    pub conflict_resolver: Option<Arc<dyn ConflictResolver>>,
+
+    // 非事务写入的记录不在 key 前写入序列号，每条记录节省一个字节
+    // 省略的记录通过 type 字节中的标志位区分，可以随时开启或者关闭，但是开启之后写入的数据文件不能被旧版本读取
+    pub omit_seq_prefix: bool,
+
+    // put 和 delete 先写入内存，累计的数据量达到该值时一次写入数据文件，None 表示直接写入数据文件
+    // 写入吞吐更高，但是刷新之前崩溃会丢失内存中的数据；不能与 value_chunk_size 一起使用，配置了前缀配额时不生效
+    pub memtable_bytes: Option<usize>,
+
+    // 打开时并行读取和解码数据文件的线程数，1 表示顺序加载，默认为 CPU 核数，最多 8 个
+    pub load_threads: usize,
+
+    // 关闭时把内存索引写入 INDEX.snapshot，下次打开时只回放之后写入的数据；数据文件被修改时自动退化为回放所有数据
+    pub index_snapshot: bool,
+}
+
+/// 打开时元数据文件（序列号文件、清单）与数据文件不一致的处理方式
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum MetadataCheck {
+    // 返回 Errors::MetadataMismatch，其中包含所有不一致的地方
+    Strict,
+
+    // 按照数据文件重建元数据之后继续打开
+    Heal,
+}
+
+/// 运行时索引指向删除标记或者不存在的数据文件时的处理方式
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum IndexMismatchPolicy {
+    // 返回 Errors::IndexDataMismatch
+    Strict,
+
+    // 从索引中删除这个 key，返回 Errors::KeyNotFound
+    SelfHeal,
+}
+
+/// 后台定期持久化的配置项
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct SyncInterval {
+    // 两次持久化之间的间隔
+    pub interval: Duration,
+
+    // 每次在间隔的基础上随机增加 [0, jitter] 的时间，避免多个实例同时持久化
+    pub jitter: Duration,
+}
+
+/// 后台任务线程的调度设置，用于降低后台任务的优先级，避免与延迟敏感的前台读写竞争
+/// 只在 Linux 上生效，设置失败（例如没有权限提高优先级）时只记录日志
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct BackgroundPriority {
+    // 线程的 nice 值，取值范围 [-20, 19]，越大优先级越低，None 表示不修改
+    pub nice: Option<i32>,
+
+    // 使用 idle IO 调度类，磁盘空闲时才执行后台任务的 IO
+    pub idle_io: bool,
+
+    // 后台线程可以运行的 CPU 编号，为空表示不限制
+    pub cpus: Vec<usize>,
+}
+
+/// 布隆过滤器配置项
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct BloomFilterOptions {
+    // 预期的 key 数量，超过之后误判率会升高
+    pub expected_keys: usize,
+
+    // 预期的误判率，取值范围 (0, 1)
+    pub false_positive_rate: f64,
+}
+
+impl Default for BloomFilterOptions {
+    fn default() -> Self {
+        Self {
+            expected_keys: 1_000_000,
+            false_positive_rate: 0.01,
+        }
+    }
+}
+
+/// 释放存储引擎时发现没有持久化的数据的处理方式
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum UnsyncedDropAction {
+    // 不处理
+    Ignore,
+
+    // 输出错误日志
+    Log,
+
+    // 直接 panic，用于在测试中发现遗漏的 sync 或 close 调用
+    Panic,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum IOType {
+    // 标准文件 IO
+    StandardFIO,
+
+    // O_DIRECT 直接 IO，绕过操作系统的页缓存，不支持时退化为标准文件 IO
+    DirectIO,
+}
+
+/// 记录对齐方式，对齐产生的空隙使用填充记录补齐
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum RecordAlignment {
+    // 不对齐，记录紧密排列
+    None,
+
+    // 每条记录都从块边界开始
+    Record(u64),
+
+    // 记录不跨越块边界，超过块大小的记录从块边界开始
+    Block(u64),
+}
+
+impl RecordAlignment {
+    // 计算在 offset 处写入长度为 len 的记录之前需要填充的字节数
+    pub(crate) fn padding(&self, offset: u64, len: u64) -> u64 {
+        let (block_size, pad) = match *self {
+            RecordAlignment::None => return 0,
+            RecordAlignment::Record(size) => (size, (size - offset % size) % size),
+            RecordAlignment::Block(size) => {
+                let in_block = offset % size;
+                match in_block != 0 && in_block + len > size {
+                    true => (size, size - in_block),
+                    false => (size, 0),
+                }
+            }
+        };
+        // 空隙太小放不下填充记录时，填充到下一个块边界
+        if pad > 0 && pad < MIN_PADDING_SIZE {
+            return pad + block_size;
+        }
+        pad
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
@@ -18,6 +251,12 @@ pub enum IndexType {
 
     // 跳表索引
     SkipList,
+
+    // 并发有序索引，读取不会被写入阻塞
+    ConcurrentBTree,
+
+    // 使用 Options::custom_index 创建的自定义索引
+    Custom,
 }
 
 impl Default for Options {
@@ -27,39 +266,87 @@ impl Default for Options {
             data_file_size: 256 * 1024 * 1024,
             sync_write: false,
             index_type: IndexType::BTree,
+            custom_index: None,
+            key_validator: None,
+            shutdown_timeout: Duration::from_secs(5),
+            record_alignment: RecordAlignment::None,
+            io_type: IOType::StandardFIO,
+            rotate_interval: None,
+            rotate_stale_ratio: None,
+            merge_ratio: None,
+            merge_rate_limit: None,
+            sync_interval: None,
+            value_chunk_size: None,
+            audit_log: None,
+            follower_poll_interval: Some(Duration::from_secs(1)),
+            idempotency_ttl: Duration::from_secs(24 * 60 * 60),
+            unsynced_drop: UnsyncedDropAction::Log,
+            seal_digest: false,
+            bloom_filter: None,
+            metadata_check: MetadataCheck::Strict,
+            index_mismatch: IndexMismatchPolicy::Strict,
+            op_trace_sample_rate: 0.0,
+            allow_foreign_data_files: false,
+            background_priority: BackgroundPriority::default(),
+            file_mode: 0o666,
+            dir_mode: 0o777,
+            default_ttl: None,
+            clock: None,
+            key_hasher: None,
+            conflict_resolver: None,
+            omit_seq_prefix: false,
+            memtable_bytes: None,
+            load_threads: std::thread::available_parallelism().map_or(1, |n| n.get().min(8)),
+            index_snapshot: true,
         }
     }
 }
 
 /// 索引迭代器配置项
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct IteratorOptions {
     pub prefix: Vec<u8>,
     pub reverse: bool,
+    // 是否同时遍历已经被删除的 key（从数据文件中扫描删除标记）
+    pub include_tombstones: bool,
+    // 迭代器的一致性，遍历删除标记时总是使用快照
+    pub consistency: IteratorConsistency,
 }
 
-impl Default for IteratorOptions {
-    fn default() -> Self {
-        Self {
-            prefix: Default::default(),
-            reverse: false,
-        }
-    }
+/// 迭代器的一致性
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum IteratorConsistency {
+    // 创建时复制一份索引，只能遍历到创建时已经存在的 key，
+    // 遍历结果是某一时刻一致的视图，但是需要额外的内存，索引很大时创建的代价较高
+    #[default]
+    Snapshot,
+
+    // 每次 next 都从索引中读取上一个 key 之后的第一个 key，返回读取时最新的 value，
+    // 可以遍历到创建之后新写入、并且还没有遍历到的 key，已经删除的 key 不会返回，
+    // 不需要复制索引，但是不同 key 的 value 可能来自不同的时刻，
+    // 自定义索引没有实现 Indexer::scan 时每次 next 都会复制整个索引
+    ReadCommitted,
 }
 
 /// 批量写入数据配置项
 pub struct WriteBatchOptions {
-    // 最大暂存数据量
+    // 最大暂存数据条数
     pub max_batch_num: usize,
+    // 最大暂存数据量（key + value + 元数据的长度）
+    pub max_batch_bytes: usize,
     // 持久化选项
     pub sync_writes: bool,
+    // 提交时等待内部锁的最长时间，超过时返回 Errors::Timeout，None 表示一直等待
+    pub deadline: Option<Duration>,
 }
 
 impl Default for WriteBatchOptions {
     fn default() -> Self {
         Self {
             max_batch_num: 10000,
+            max_batch_bytes: 256 * 1024 * 1024,
             sync_writes: true,
+            deadline: None,
         }
     }
 }
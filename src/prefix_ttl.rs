@@ -0,0 +1,326 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::{
+    db::{is_internal_key, Engine, INTERNAL_KEY_PREFIX},
+    errors::{Errors, Result},
+    options::{IteratorOptions, WriteBatchOptions},
+};
+
+// 前缀的过期时间记录在内部前缀下，value 为过期时间（毫秒时间戳）
+const PREFIX_TTL_PREFIX: &[u8] = b"prefix-ttl/";
+
+// 设置了过期时间的前缀
+pub(crate) struct PrefixTtl {
+    prefix: Vec<u8>,
+    expire_at: u64,
+}
+
+impl Engine {
+    /// 设置前缀的过期时间，前缀下所有的 key 在 ttl 之后一起过期，适用于会话之类的场景
+    /// 再次调用会重新设置过期时间；前缀已经过期时会先清理其中的 key，之后重新开始计时
+    /// 过期的 key 读取时不可见，由 reap_expired_prefixes 删除
+    pub fn expire_prefix(&self, prefix: Bytes, ttl: Duration) -> Result<()> {
+        self.check_key(&prefix)?;
+        let _lock = self.prefix_ttl_lock.lock();
+        if self.is_prefix_expired(&prefix) {
+            self.reap_prefix(&prefix)?;
+        }
+
+        let expire_at = self.now_millis().saturating_add(ttl.as_millis() as u64);
+        let wb = self.new_write_batch(self.prefix_ttl_batch_options())?;
+        wb.put_unchecked(
+            prefix_ttl_key(&prefix),
+            Bytes::copy_from_slice(&expire_at.to_be_bytes()),
+        )?;
+        wb.commit()?;
+        self.set_prefix_ttl_entry(&prefix, Some(expire_at));
+        Ok(())
+    }
+
+    /// 取消前缀的过期时间，返回这个前缀之前是否设置过过期时间
+    /// 已经过期的前缀会先清理其中的 key
+    pub fn persist_prefix(&self, prefix: &[u8]) -> Result<bool> {
+        let _lock = self.prefix_ttl_lock.lock();
+        if !self.prefix_ttls.read().iter().any(|e| e.prefix == prefix) {
+            return Ok(false);
+        }
+        if self.is_prefix_expired(prefix) {
+            self.reap_prefix(prefix)?;
+            return Ok(true);
+        }
+        let wb = self.new_write_batch(self.prefix_ttl_batch_options())?;
+        wb.delete_unchecked(prefix_ttl_key(prefix))?;
+        wb.commit()?;
+        self.set_prefix_ttl_entry(prefix, None);
+        Ok(true)
+    }
+
+    /// 前缀剩余的有效时间，没有设置过期时间时返回 None，已经过期时返回 0
+    pub fn prefix_ttl(&self, prefix: &[u8]) -> Option<Duration> {
+        let now = self.now_millis();
+        self.prefix_ttls
+            .read()
+            .iter()
+            .find(|e| e.prefix == prefix)
+            .map(|e| Duration::from_millis(e.expire_at.saturating_sub(now)))
+    }
+
+    /// 删除所有已经过期的前缀下的 key 以及前缀的过期时间，返回删除的 key 的数量
+    /// 需要由调用方定期执行，不执行时过期的 key 也不可见，但是会一直占用空间
+    pub fn reap_expired_prefixes(&self) -> Result<usize> {
+        let _lock = self.prefix_ttl_lock.lock();
+        let now = self.now_millis();
+        let expired: Vec<Vec<u8>> = self
+            .prefix_ttls
+            .read()
+            .iter()
+            .filter(|e| e.expire_at <= now)
+            .map(|e| e.prefix.clone())
+            .collect();
+        let mut reaped = 0;
+        for prefix in expired {
+            reaped += self.reap_prefix(&prefix)?;
+        }
+        Ok(reaped)
+    }
+
+    // key 是否在已经过期的前缀下
+    pub(crate) fn is_prefix_expired(&self, key: &[u8]) -> bool {
+        let ttls = self.prefix_ttls.read();
+        if ttls.is_empty() || is_internal_key(key) {
+            return false;
+        }
+        let now = self.now_millis();
+        ttls.iter()
+            .any(|e| e.expire_at <= now && key.starts_with(&e.prefix))
+    }
+
+    // 没有过期的前缀及其剩余的有效时间
+    pub(crate) fn active_prefix_ttls(&self) -> Vec<(Bytes, Duration)> {
+        let now = self.now_millis();
+        self.prefix_ttls
+            .read()
+            .iter()
+            .filter(|e| e.expire_at > now)
+            .map(|e| {
+                (
+                    Bytes::copy_from_slice(&e.prefix),
+                    Duration::from_millis(e.expire_at - now),
+                )
+            })
+            .collect()
+    }
+
+    // 从索引中加载前缀的过期时间，打开数据库时调用
+    pub(crate) fn load_prefix_ttls(&self) -> Result<()> {
+        let mut ttls = Vec::new();
+        let mut index_iter = self.index.iterator(IteratorOptions {
+            prefix: prefix_ttl_key(&[]).to_vec(),
+            ..Default::default()
+        });
+        while let Some((key, pos)) = index_iter.next() {
+            let value = self.get_value_by_position(&pos)?;
+            let expire_at = match <[u8; 8]>::try_from(value.as_ref()) {
+                Ok(bytes) => u64::from_be_bytes(bytes),
+                Err(_) => return Err(Errors::DataFileCorrupted),
+            };
+            ttls.push(PrefixTtl {
+                prefix: key[prefix_ttl_key(&[]).len()..].to_vec(),
+                expire_at,
+            });
+        }
+        *self.prefix_ttls.write() = ttls;
+        Ok(())
+    }
+
+    // 分批删除前缀下的 key 和前缀的过期时间，返回删除的 key 的数量
+    // 调用方需要持有 prefix_ttl_lock
+    fn reap_prefix(&self, prefix: &[u8]) -> Result<usize> {
+        // 只在内存写缓冲中的 key 也需要删除
+        self.flush_memtable()?;
+        let mut keys = self.index.list_keys(false, prefix)?;
+        keys.retain(|key| !is_internal_key(key));
+        let max_batch_num = WriteBatchOptions::default().max_batch_num;
+        for chunk in keys.chunks(max_batch_num) {
+            let wb = self.new_write_batch(self.prefix_ttl_batch_options())?;
+            for key in chunk {
+                wb.delete_unchecked(key.clone())?;
+            }
+            wb.commit()?;
+        }
+
+        let wb = self.new_write_batch(self.prefix_ttl_batch_options())?;
+        wb.delete_unchecked(prefix_ttl_key(prefix))?;
+        wb.commit()?;
+        self.set_prefix_ttl_entry(prefix, None);
+        Ok(keys.len())
+    }
+
+    fn set_prefix_ttl_entry(&self, prefix: &[u8], expire_at: Option<u64>) {
+        let mut ttls = self.prefix_ttls.write();
+        ttls.retain(|e| e.prefix != prefix);
+        if let Some(expire_at) = expire_at {
+            ttls.push(PrefixTtl {
+                prefix: prefix.to_vec(),
+                expire_at,
+            });
+        }
+    }
+
+    fn prefix_ttl_batch_options(&self) -> WriteBatchOptions {
+        WriteBatchOptions {
+            sync_writes: self.options.sync_write,
+            ..Default::default()
+        }
+    }
+}
+
+fn prefix_ttl_key(prefix: &[u8]) -> Bytes {
+    let mut key = Vec::with_capacity(INTERNAL_KEY_PREFIX.len() + PREFIX_TTL_PREFIX.len());
+    key.extend_from_slice(INTERNAL_KEY_PREFIX);
+    key.extend_from_slice(PREFIX_TTL_PREFIX);
+    key.extend_from_slice(prefix);
+    key.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc};
+
+    use crate::{clock::ManualClock, options::Options};
+
+    use super::*;
+
+    #[test]
+    fn test_expire_prefix() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-expire-prefix");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        for i in 0..10 {
+            let key = Bytes::from(format!("session/1/{}", i));
+            engine.put(key, Bytes::from("value")).unwrap();
+        }
+        engine
+            .put(Bytes::from("session/2/0"), Bytes::from("value"))
+            .unwrap();
+        engine
+            .expire_prefix(Bytes::from("session/1/"), Duration::from_millis(100))
+            .unwrap();
+        assert!(engine.prefix_ttl(b"session/1/").unwrap() <= Duration::from_millis(100));
+        assert_eq!(engine.prefix_ttl(b"session/2/"), None);
+        assert_eq!(
+            engine.get(Bytes::from("session/1/0")).unwrap(),
+            Bytes::from("value")
+        );
+
+        // 过期之后前缀下所有的 key 都不可见，其他的 key 不受影响
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(
+            engine.get(Bytes::from("session/1/0")).err().unwrap(),
+            Errors::KeyNotFound
+        );
+        assert_eq!(engine.try_get(Bytes::from("session/1/1")).unwrap(), None);
+        assert_eq!(
+            engine.list_keys().unwrap(),
+            vec![Bytes::from("session/2/0")]
+        );
+        let iter = engine.iter(Default::default());
+        assert_eq!(iter.next().unwrap().0, Bytes::from("session/2/0"));
+        assert!(iter.next().is_none());
+
+        // 重启之后过期时间仍然有效
+        engine.close().expect("failed to close engine");
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(engine.prefix_ttl(b"session/1/"), Some(Duration::ZERO));
+        assert_eq!(engine.try_get(Bytes::from("session/1/0")).unwrap(), None);
+
+        // 清理过期的 key
+        assert_eq!(engine.reap_expired_prefixes().unwrap(), 10);
+        assert_eq!(engine.reap_expired_prefixes().unwrap(), 0);
+        assert_eq!(engine.prefix_ttl(b"session/1/"), None);
+        assert_eq!(
+            engine.index.list_keys(false, b"session/1/").unwrap().len(),
+            0
+        );
+        assert_eq!(engine.list_keys().unwrap().len(), 1);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_expire_prefix_renew_and_persist() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-expire-prefix-renew");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let prefix = Bytes::from("session/");
+        engine
+            .put(Bytes::from("session/a"), Bytes::from("value"))
+            .unwrap();
+        engine
+            .expire_prefix(prefix.clone(), Duration::from_millis(100))
+            .unwrap();
+        // 续期之后不会过期
+        engine
+            .expire_prefix(prefix.clone(), Duration::from_secs(60))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(engine.try_get(Bytes::from("session/a")).unwrap().is_some());
+
+        // 取消过期时间
+        assert!(engine.persist_prefix(&prefix).unwrap());
+        assert!(!engine.persist_prefix(&prefix).unwrap());
+        assert_eq!(engine.prefix_ttl(&prefix), None);
+
+        // 过期的前缀重新设置过期时间时，之前的 key 被清理，不会重新可见
+        engine
+            .expire_prefix(prefix.clone(), Duration::from_millis(50))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        engine
+            .expire_prefix(prefix.clone(), Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(engine.try_get(Bytes::from("session/a")).unwrap(), None);
+        engine
+            .put(Bytes::from("session/b"), Bytes::from("value"))
+            .unwrap();
+        assert!(engine.try_get(Bytes::from("session/b")).unwrap().is_some());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_reap_prefix_memtable() {
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-reap-prefix-memtable");
+        opts.memtable_bytes = Some(1024 * 1024);
+        opts.clock = Some(clock.clone());
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        engine
+            .expire_prefix(Bytes::from("session/"), Duration::from_secs(60))
+            .unwrap();
+        // 设置过期时间之后写入的 key 只在内存写缓冲中
+        for i in 0..5 {
+            let key = Bytes::from(format!("session/{}", i));
+            engine.put(key, Bytes::from("value")).unwrap();
+        }
+
+        // 清理时删除内存写缓冲中的 key，移除前缀的过期时间之后不会重新可见
+        clock.advance(Duration::from_secs(61));
+        assert_eq!(engine.reap_expired_prefixes().unwrap(), 5);
+        assert_eq!(engine.prefix_ttl(b"session/"), None);
+        assert_eq!(engine.try_get(Bytes::from("session/0")).unwrap(), None);
+        assert!(engine.list_keys().unwrap().is_empty());
+        engine.close().expect("failed to close engine");
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,111 @@
+use std::io;
+
+use crate::{errors::Errors, options::BackgroundPriority};
+
+// sched_setaffinity 支持的最大 CPU 编号
+const MAX_CPUS: usize = 1024;
+
+// 校验后台任务线程的调度设置
+pub(crate) fn check_priority(priority: &BackgroundPriority) -> Option<Errors> {
+    if priority.nice.is_some_and(|n| !(-20..=19).contains(&n))
+        || priority.cpus.iter().any(|&cpu| cpu >= MAX_CPUS)
+    {
+        return Some(Errors::InvalidBackgroundPriority);
+    }
+    None
+}
+
+// 将调度设置应用到当前线程，在后台任务线程启动时调用
+#[cfg(target_os = "linux")]
+pub(crate) fn apply_priority(priority: &BackgroundPriority) -> io::Result<()> {
+    // 调整优先级需要线程 id，传入 0 时会修改整个进程
+    let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+    if let Some(nice) = priority.nice {
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, nice) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    if priority.idle_io {
+        // ioprio_set(IOPRIO_WHO_PROCESS, tid, IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT)
+        const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+        const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+        const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+        let prio = IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT;
+        if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, tid, prio) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    if !priority.cpus.is_empty() {
+        let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+        for &cpu in priority.cpus.iter() {
+            unsafe { libc::CPU_SET(cpu, &mut set) };
+        }
+        let size = std::mem::size_of::<libc::cpu_set_t>();
+        if unsafe { libc::sched_setaffinity(0, size, &set) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn apply_priority(priority: &BackgroundPriority) -> io::Result<()> {
+    if *priority == BackgroundPriority::default() {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "background priority is only supported on linux",
+    ))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use crate::shutdown::ShutdownHandle;
+
+    use super::*;
+
+    #[test]
+    fn test_apply_priority() {
+        assert!(check_priority(&BackgroundPriority::default()).is_none());
+        let invalid = BackgroundPriority {
+            nice: Some(20),
+            ..Default::default()
+        };
+        assert_eq!(
+            check_priority(&invalid),
+            Some(Errors::InvalidBackgroundPriority)
+        );
+
+        // 后台任务线程降低优先级，不影响当前线程
+        let before = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+        let handle = ShutdownHandle::new(BackgroundPriority {
+            nice: Some(19),
+            idle_io: false,
+            cpus: vec![0],
+        });
+        let (sender, receiver) = mpsc::channel();
+        handle
+            .spawn("priority", move |_| {
+                let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+                let nice = unsafe { libc::getpriority(libc::PRIO_PROCESS, tid) };
+                let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+                let size = std::mem::size_of::<libc::cpu_set_t>();
+                unsafe { libc::sched_getaffinity(0, size, &mut set) };
+                let cpus = (0..MAX_CPUS)
+                    .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+                    .count();
+                sender.send((nice, cpus)).unwrap();
+            })
+            .unwrap();
+        let (nice, cpus) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(nice, 19);
+        assert_eq!(cpus, 1);
+        assert_eq!(unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) }, before);
+        handle.shutdown(Duration::from_secs(5)).unwrap();
+    }
+}
@@ -0,0 +1,414 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    os::unix::fs::{FileExt, MetadataExt},
+    path::Path,
+    sync::atomic::Ordering,
+};
+
+use log::{error, warn};
+use prost::length_delimiter_len;
+
+use crate::{
+    batch::parse_log_record_key,
+    data::{
+        data_file::{get_data_file_name, DataFile},
+        log_record::{padding_record, LogRecordPos, LogRecordType},
+    },
+    db::Engine,
+    errors::{Errors, Result},
+    fio,
+    manifest::{digest_file, Manifest},
+    utils::atomic_write,
+};
+
+/// 记录正在打洞的区间的文件名，打洞中途崩溃时在下次打开时重做
+pub const PUNCH_FILE_NAME: &str = "PUNCH";
+
+// 每条填充记录覆盖的最大长度，避免读取时一次分配过大的内存
+const MAX_PUNCH_RECORD_SIZE: u64 = 4 * 1024 * 1024;
+
+impl Engine {
+    /// 对已封存数据文件中连续的失效记录打洞（FALLOC_FL_PUNCH_HOLE），不重写有效数据，返回释放的磁盘空间
+    /// 连续的失效记录被替换为一条填充记录，中间的数据块从文件中释放，文件的长度和其他记录的位置不变，
+    /// 适用于单个文件很大、只有部分区域失效，全量 merge 代价太大的情况
+    /// 删除标记和事务完成标识总是保留；存在未释放的迭代器时返回 Errors::IteratorsInUse
+    /// 存在其他硬链接的文件（例如 fork_to 共享的文件）会被跳过，打洞会同时修改其他数据库中的数据
+    pub fn punch_holes(&self, file_ids: &[u32]) -> Result<u64> {
+        if self.read_only {
+            return Err(Errors::ReadOnlyEngine);
+        }
+        let _lock = self.maintenance_lock.lock();
+        let files = self.files.load();
+        let mut file_ids = file_ids
+            .iter()
+            .copied()
+            .filter(|id| files.older.contains_key(id))
+            .collect::<Vec<_>>();
+        file_ids.sort();
+        file_ids.dedup();
+
+        let mut freed = 0;
+        for file_id in file_ids {
+            let path = get_data_file_name(&self.options.dir_path, file_id);
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    error!("Failed to read metadata of data file {:?}: {e}", path);
+                    return Err(Errors::FailedToReadFromDataFile(e.into()));
+                }
+            };
+            if metadata.nlink() > 1 {
+                warn!("Skip punching holes in data file {file_id} shared by hard links");
+                continue;
+            }
+            let block_size = metadata.blksize().max(512);
+            let runs = self.stale_runs(files.older.get(&file_id).unwrap(), block_size)?;
+            if runs.is_empty() {
+                continue;
+            }
+            // 失效的记录不会再被索引引用，扫描之后创建的迭代器不会读取到这些记录，
+            // 之前创建的迭代器可能仍然持有它们的位置
+            if self.live_iterators.load(Ordering::SeqCst) > 0 {
+                return Err(Errors::IteratorsInUse);
+            }
+
+            let entries = runs
+                .iter()
+                .map(|(start, end)| (file_id, *start, *end))
+                .collect::<Vec<_>>();
+            write_punch_file(&self.options.dir_path, &entries, self.options.file_mode)?;
+            punch_runs(&path, &runs)?;
+            if let Some(expected) = self.manifest.get(file_id) {
+                if let Some(digest) = digest_file(&path, expected.size)? {
+                    self.manifest.record(file_id, digest)?;
+                }
+            }
+            remove_punch_file(&self.options.dir_path)?;
+            freed += runs
+                .iter()
+                .map(|(start, end)| freed_bytes(*start, *end, block_size))
+                .sum::<u64>();
+        }
+        Ok(freed)
+    }
+
+    // 文件中连续的失效记录组成的区间，只保留至少能释放一个数据块的区间
+    fn stale_runs(&self, data_file: &DataFile, block_size: u64) -> Result<Vec<(u64, u64)>> {
+        let file_id = data_file.get_file_id();
+        let mut runs = Vec::new();
+        let mut run: Option<(u64, u64)> = None;
+        let mut offset = 0;
+        loop {
+            let (record, size) = match data_file.read_log_record(offset) {
+                Ok(res) => (res.record, res.size as u64),
+                Err(Errors::ReadDataFileEOF) => break,
+                Err(e) => return Err(e),
+            };
+            let pos = LogRecordPos {
+                file_id,
+                offset,
+                size,
+            };
+            // 文件头需要保留
+            let stale = offset > 0
+                && match record.rec_type {
+                    LogRecordType::PADDING => true,
+                    LogRecordType::NORMAL | LogRecordType::CHUNKED => {
+                        let (key, _) = parse_log_record_key(record.key);
+                        self.index.get(key) != Some(pos)
+                    }
+                    LogRecordType::DELETED | LogRecordType::TXNFINISH => false,
+                };
+            run = match (stale, run) {
+                (true, Some((start, _))) => Some((start, offset + size)),
+                (true, None) => Some((offset, offset + size)),
+                (false, Some(r)) => {
+                    runs.push(r);
+                    None
+                }
+                (false, None) => None,
+            };
+            offset += size;
+        }
+        runs.extend(run);
+
+        Ok(runs
+            .into_iter()
+            .flat_map(split_run)
+            .filter(|(start, end)| freed_bytes(*start, *end, block_size) > 0)
+            .collect())
+    }
+}
+
+// 将过长的区间拆分为长度相近的多个区间
+fn split_run((start, end): (u64, u64)) -> Vec<(u64, u64)> {
+    let n = (end - start).div_ceil(MAX_PUNCH_RECORD_SIZE);
+    let len = (end - start) / n;
+    (0..n)
+        .map(|i| {
+            let piece_end = if i == n - 1 {
+                end
+            } else {
+                start + (i + 1) * len
+            };
+            (start + i * len, piece_end)
+        })
+        .collect()
+}
+
+// 区间替换为填充记录之后，完全位于填充内容中的数据块的大小
+fn freed_bytes(start: u64, end: u64, block_size: u64) -> u64 {
+    let (hole_start, hole_end) = hole_range(start, end);
+    let first = hole_start.div_ceil(block_size) * block_size;
+    let last = hole_end / block_size * block_size;
+    last.saturating_sub(first)
+}
+
+// 填充记录中值为 0 的部分，之前是记录头，之后是 4 字节的 crc
+fn hole_range(start: u64, end: u64) -> (u64, u64) {
+    // type + key size + value size
+    let value_size = padding_record(end - start).value.len();
+    let header_size = 2 + length_delimiter_len(value_size) as u64;
+    (start + header_size, end - 4)
+}
+
+// 先打洞再写入填充记录的记录头和 crc，打洞的部分读取时为 0，正好是填充记录的内容
+// 重复执行的结果相同，崩溃之后可以直接重做
+fn punch_runs(path: &Path, runs: &[(u64, u64)]) -> Result<()> {
+    let res = OpenOptions::new().write(true).open(path).and_then(|file| {
+        for (start, end) in runs {
+            let encoded = padding_record(end - start).encode();
+            let (hole_start, hole_end) = hole_range(*start, *end);
+            punch_hole(&file, hole_start, hole_end - hole_start)?;
+            file.write_all_at(encoded.header(), *start)?;
+            file.write_all_at(&encoded.crc().to_be_bytes(), hole_end)?;
+        }
+        file.sync_all()
+    });
+    if let Err(e) = res {
+        error!("Failed to punch holes in data file {:?}: {e}", path);
+        return Err(Errors::FailedToPunchHole(e.into()));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn punch_hole(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+    let res = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            mode,
+            offset as libc::off_t,
+            len as libc::off_t,
+        )
+    };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn punch_hole(_file: &File, _offset: u64, _len: u64) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "hole punching is only supported on linux",
+    ))
+}
+
+// 每行记录一个区间：文件 id、起始位置、结束位置
+fn write_punch_file(dir_path: &Path, entries: &[(u32, u64, u64)], mode: u32) -> Result<()> {
+    let path = dir_path.join(PUNCH_FILE_NAME);
+    let content = entries
+        .iter()
+        .map(|(file_id, start, end)| format!("{} {} {}\n", file_id, start, end))
+        .collect::<String>();
+    atomic_write(&path, content.as_bytes(), mode)
+}
+
+fn remove_punch_file(dir_path: &Path) -> Result<()> {
+    let path = dir_path.join(PUNCH_FILE_NAME);
+    if let Err(e) = fs::remove_file(&path) {
+        error!("Failed to remove punch file: {e}");
+        return Err(Errors::FailedToWriteToDataFile(e.into()));
+    }
+    fio::sync_parent_dir(&path)
+}
+
+// 重做上次没有完成的打洞，在加载数据文件之前调用
+pub(crate) fn redo_punch_holes(dir_path: &Path, file_mode: u32) -> Result<()> {
+    let path = dir_path.join(PUNCH_FILE_NAME);
+    if !path.is_file() {
+        return Ok(());
+    }
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) => {
+            error!("Failed to read punch file: {e}");
+            return Err(Errors::FailedToReadFromDataFile(e.into()));
+        }
+    };
+    let mut entries = Vec::new();
+    for line in content.lines() {
+        let parts = line
+            .split(' ')
+            .map(|part| part.parse::<u64>())
+            .collect::<std::result::Result<Vec<_>, _>>();
+        match parts.as_deref() {
+            Ok([file_id, start, end]) if start < end => {
+                entries.push((*file_id as u32, *start, *end))
+            }
+            // 写入到一半的文件不会被重命名，无法解析时说明文件损坏
+            _ => return Err(Errors::DataDirectoryCorrupted),
+        }
+    }
+
+    let manifest = Manifest::load(dir_path)?.with_file_mode(file_mode);
+    let mut file_ids = entries.iter().map(|(id, _, _)| *id).collect::<Vec<_>>();
+    file_ids.sort();
+    file_ids.dedup();
+    for file_id in file_ids {
+        let data_path = get_data_file_name(dir_path, file_id);
+        // 打洞之后文件已经被 merge 删除
+        if !data_path.is_file() {
+            continue;
+        }
+        warn!("Redoing interrupted hole punching in data file {file_id}");
+        let runs = entries
+            .iter()
+            .filter(|(id, _, _)| *id == file_id)
+            .map(|(_, start, end)| (*start, *end))
+            .collect::<Vec<_>>();
+        punch_runs(&data_path, &runs)?;
+        if let Some(expected) = manifest.get(file_id) {
+            if let Some(digest) = digest_file(&data_path, expected.size)? {
+                manifest.record(file_id, digest)?;
+            }
+        }
+    }
+    remove_punch_file(dir_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use crate::{
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    fn allocated_bytes(path: &Path) -> u64 {
+        fs::metadata(path).unwrap().blocks() * 512
+    }
+
+    #[test]
+    fn test_punch_holes() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-punch-holes");
+        opts.data_file_size = 1024 * 1024;
+        opts.seal_digest = true;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 第一个文件中的大部分 key 被覆盖，被删除的 key 保留删除标记
+        let value = Bytes::from(vec![b'v'; 1024]);
+        for i in 0..2000 {
+            engine.put(get_test_key(i), value.clone()).unwrap();
+        }
+        engine.delete(get_test_key(1)).unwrap();
+        for i in 2..1000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine.sync().unwrap();
+
+        {
+            let _iter = engine.iter(Default::default());
+            assert_eq!(engine.punch_holes(&[0]), Err(Errors::IteratorsInUse));
+        }
+
+        // 打洞中途崩溃，打开时重做
+        let path = get_data_file_name(&opts.dir_path, 0);
+        let before = allocated_bytes(&path);
+        let size = fs::metadata(&path).unwrap().len();
+        let files = engine.files.load();
+        let runs = engine
+            .stale_runs(files.older.get(&0).unwrap(), 4096)
+            .unwrap();
+        let entries = runs.iter().map(|(s, e)| (0, *s, *e)).collect::<Vec<_>>();
+        write_punch_file(&opts.dir_path, &entries, 0o666).unwrap();
+        std::mem::drop(files);
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(!opts.dir_path.join(PUNCH_FILE_NAME).exists());
+        let redone = allocated_bytes(&path);
+        assert!(before - redone > 900 * 1024, "{before} {redone}");
+
+        // 已经打过洞的区域不会再占用空间
+        let freed = engine.punch_holes(&[0, 1, 100]).unwrap();
+        assert!(freed > 900 * 1024, "{freed}");
+        assert_eq!(allocated_bytes(&path), redone);
+        assert_eq!(fs::metadata(&path).unwrap().len(), size);
+        assert!(!opts.dir_path.join(PUNCH_FILE_NAME).exists());
+
+        assert_eq!(engine.get(get_test_key(10)).unwrap(), get_test_value(10));
+        assert_eq!(engine.get(get_test_key(1500)).unwrap(), value);
+        assert!(engine.verify().unwrap().is_ok());
+        std::mem::drop(engine);
+
+        // 重新打开之后数据不变，删除的 key 不会恢复
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(engine.list_keys().unwrap().len(), 1999);
+        assert_eq!(engine.get(get_test_key(999)).unwrap(), get_test_value(999));
+        assert_eq!(engine.get(get_test_key(0)).unwrap(), value);
+        assert!(engine.get(get_test_key(1)).is_err());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_punch_holes_skip_forked_files() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-punch-fork");
+        opts.data_file_size = 32 * 1024;
+        let fork_path = PathBuf::from("/tmp/bitcask-rs-punch-fork-copy");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 每个 key 写入一个文件，封存的文件与副本共享，源数据库覆盖之后不能打洞
+        let value = Bytes::from(vec![b'v'; 20 * 1024]);
+        for i in 0..3 {
+            engine.put(get_test_key(i), value.clone()).unwrap();
+        }
+        engine.fork_to(&fork_path).unwrap();
+        for i in 0..3 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        assert_eq!(engine.punch_holes(&[0, 1]).unwrap(), 0);
+        std::mem::drop(engine);
+
+        let mut fork_opts = opts.clone();
+        fork_opts.dir_path = fork_path.clone();
+        let fork = Engine::open(fork_opts).expect("failed to open fork");
+        for i in 0..3 {
+            assert_eq!(fork.get(get_test_key(i)).unwrap(), value);
+        }
+        std::mem::drop(fork);
+
+        // 副本删除之后可以打洞
+        std::fs::remove_dir_all(&fork_path).expect("failed to remove path");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(engine.punch_holes(&[0, 1]).unwrap() > 0);
+        assert_eq!(engine.get(get_test_key(1)).unwrap(), get_test_value(1));
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
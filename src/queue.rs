@@ -0,0 +1,321 @@
+use std::{ops::Bound, time::Duration};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    batch::WriteBatch,
+    db::{Engine, INTERNAL_KEY_PREFIX},
+    errors::{Errors, Result},
+    options::{IteratorOptions, WriteBatchOptions},
+};
+
+// 队列的数据记录在内部前缀下：前缀 + 队列名称长度 + 队列名称 + 类型 + 后缀
+const QUEUE_PREFIX: &[u8] = b"queue/";
+
+// 下一个元素的 id，value 为 8 字节大端序整数
+const KIND_NEXT_ID: u8 = b'n';
+// 可见的元素，后缀为元素 id
+const KIND_READY: u8 = b'r';
+// 取出之后还没有确认的元素，后缀为可见时间（毫秒时间戳）+ 元素 id
+const KIND_INFLIGHT: u8 = b'i';
+
+/// 持久化的先进先出队列，通过 Engine::queue 获取
+/// 同一个引擎上所有队列的操作串行执行
+pub struct Queue<'a> {
+    engine: &'a Engine,
+    name: Bytes,
+}
+
+/// 带可见性超时取出的元素，需要在超时之前调用 Queue::ack 确认，否则会被再次取出
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueMessage {
+    pub id: u64,
+    pub value: Bytes,
+    // 重新可见的时间，和 id 一起定位未确认的元素
+    visible_at: u64,
+}
+
+// 下一个可以取出的元素
+struct Visible {
+    key: Bytes,
+    id: u64,
+    value: Bytes,
+}
+
+impl Engine {
+    /// 获取指定名称的队列，队列不需要提前创建
+    pub fn queue(&self, name: Bytes) -> Result<Queue<'_>> {
+        if name.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+        Ok(Queue { engine: self, name })
+    }
+}
+
+impl Queue<'_> {
+    /// 向队尾添加一个元素，返回元素的 id，同一个队列中的 id 严格递增
+    pub fn push(&self, value: Bytes) -> Result<u64> {
+        let _lock = self.engine.queue_lock.lock();
+        let meta = self.key(KIND_NEXT_ID, &[]);
+        let id = match self.engine.get(meta.clone()) {
+            Ok(value) => match <[u8; 8]>::try_from(value.as_ref()) {
+                Ok(bytes) => u64::from_be_bytes(bytes),
+                Err(_) => return Err(Errors::DataFileCorrupted),
+            },
+            Err(Errors::KeyNotFound) => 0,
+            Err(e) => return Err(e),
+        };
+
+        let wb = self.new_write_batch()?;
+        wb.put_unchecked(self.key(KIND_READY, &id.to_be_bytes()), value)?;
+        wb.put_unchecked(meta, Bytes::copy_from_slice(&(id + 1).to_be_bytes()))?;
+        wb.commit()?;
+        Ok(id)
+    }
+
+    /// 查看队首的元素，不会取出
+    pub fn peek(&self) -> Result<Option<(u64, Bytes)>> {
+        let _lock = self.engine.queue_lock.lock();
+        Ok(self.next_visible()?.map(|item| (item.id, item.value)))
+    }
+
+    /// 取出并删除队首的元素，队列为空时返回 None
+    pub fn pop(&self) -> Result<Option<(u64, Bytes)>> {
+        let _lock = self.engine.queue_lock.lock();
+        let item = match self.next_visible()? {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        let wb = self.new_write_batch()?;
+        wb.delete_unchecked(item.key)?;
+        wb.commit()?;
+        Ok(Some((item.id, item.value)))
+    }
+
+    /// 取出队首的元素，在 visibility_timeout 之内其他调用不会再取到这个元素
+    /// 超时之前没有调用 ack 确认的元素会重新可见，并且排在未取出的元素之前
+    pub fn pop_with_visibility(
+        &self,
+        visibility_timeout: Duration,
+    ) -> Result<Option<QueueMessage>> {
+        let _lock = self.engine.queue_lock.lock();
+        let item = match self.next_visible()? {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        let visible_at = self
+            .engine
+            .now_millis()
+            .saturating_add(visibility_timeout.as_millis() as u64);
+        let wb = self.new_write_batch()?;
+        wb.delete_unchecked(item.key)?;
+        wb.put_unchecked(self.inflight_key(visible_at, item.id), item.value.clone())?;
+        wb.commit()?;
+        Ok(Some(QueueMessage {
+            id: item.id,
+            value: item.value,
+            visible_at,
+        }))
+    }
+
+    /// 确认已经处理完的元素，将其从队列中删除
+    /// 返回 false 表示元素已经被确认过，或者超时之后被再次取出
+    pub fn ack(&self, message: &QueueMessage) -> Result<bool> {
+        let _lock = self.engine.queue_lock.lock();
+        let key = self.inflight_key(message.visible_at, message.id);
+        if self.engine.index.get(key.to_vec()).is_none() {
+            return Ok(false);
+        }
+        let wb = self.new_write_batch()?;
+        wb.delete_unchecked(key)?;
+        wb.commit()?;
+        Ok(true)
+    }
+
+    // 下一个可以取出的元素，先取已经超时的未确认元素，再取可见的元素
+    fn next_visible(&self) -> Result<Option<Visible>> {
+        // 未确认的元素按照可见时间排序，只需要检查第一个
+        if let Some(key) = self.first(KIND_INFLIGHT) {
+            let suffix = &key[key.len() - 16..];
+            let visible_at = u64::from_be_bytes(suffix[..8].try_into().unwrap());
+            if visible_at <= self.engine.now_millis() {
+                let id = u64::from_be_bytes(suffix[8..].try_into().unwrap());
+                let value = self.engine.get(key.clone())?;
+                return Ok(Some(Visible { key, id, value }));
+            }
+        }
+        if let Some(key) = self.first(KIND_READY) {
+            let id = u64::from_be_bytes(key[key.len() - 8..].try_into().unwrap());
+            let value = self.engine.get(key.clone())?;
+            return Ok(Some(Visible { key, id, value }));
+        }
+        Ok(None)
+    }
+
+    // 指定类型的第一个 key
+    fn first(&self, kind: u8) -> Option<Bytes> {
+        let options = IteratorOptions {
+            prefix: self.key(kind, &[]).to_vec(),
+            ..Default::default()
+        };
+        self.engine
+            .index
+            .scan(&options, Bound::Unbounded, 1)
+            .pop()
+            .map(|(key, _)| key)
+    }
+
+    fn new_write_batch(&self) -> Result<WriteBatch<'_>> {
+        self.engine.new_write_batch(WriteBatchOptions {
+            sync_writes: self.engine.options.sync_write,
+            ..Default::default()
+        })
+    }
+
+    fn inflight_key(&self, visible_at: u64, id: u64) -> Bytes {
+        let mut suffix = [0; 16];
+        suffix[..8].copy_from_slice(&visible_at.to_be_bytes());
+        suffix[8..].copy_from_slice(&id.to_be_bytes());
+        self.key(KIND_INFLIGHT, &suffix)
+    }
+
+    // 队列名称带上长度，一个队列的名称是另一个队列名称的前缀时也不会混在一起
+    fn key(&self, kind: u8, suffix: &[u8]) -> Bytes {
+        let mut key = BytesMut::with_capacity(
+            INTERNAL_KEY_PREFIX.len() + QUEUE_PREFIX.len() + 4 + self.name.len() + 1 + suffix.len(),
+        );
+        key.put_slice(INTERNAL_KEY_PREFIX);
+        key.put_slice(QUEUE_PREFIX);
+        key.put_u32(self.name.len() as u32);
+        key.put_slice(&self.name);
+        key.put_u8(kind);
+        key.put_slice(suffix);
+        key.freeze()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, path::PathBuf, sync::Arc};
+
+    use crate::options::Options;
+
+    use super::*;
+
+    #[test]
+    fn test_queue_push_pop() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-queue-push-pop");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let queue = engine.queue(Bytes::from("jobs")).unwrap();
+        assert_eq!(queue.pop().unwrap(), None);
+        for i in 0..10 {
+            assert_eq!(queue.push(Bytes::from(format!("job-{}", i))).unwrap(), i);
+        }
+        // 名称是前缀的其他队列互不影响
+        let other = engine.queue(Bytes::from("job")).unwrap();
+        other.push(Bytes::from("other")).unwrap();
+
+        assert_eq!(queue.peek().unwrap(), Some((0, Bytes::from("job-0"))));
+        assert_eq!(queue.pop().unwrap(), Some((0, Bytes::from("job-0"))));
+        assert_eq!(queue.pop().unwrap(), Some((1, Bytes::from("job-1"))));
+        // 队列的数据对用户不可见
+        assert!(engine.list_keys().unwrap().is_empty());
+
+        // 重启之后队列中的元素和 id 仍然有效
+        engine.close().expect("failed to close engine");
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let queue = engine.queue(Bytes::from("jobs")).unwrap();
+        assert_eq!(queue.peek().unwrap(), Some((2, Bytes::from("job-2"))));
+        for i in 2..10 {
+            assert_eq!(queue.pop().unwrap().unwrap().0, i);
+        }
+        assert_eq!(queue.pop().unwrap(), None);
+        // 队列清空之后 id 不会重复使用
+        assert_eq!(queue.push(Bytes::from("job-10")).unwrap(), 10);
+        let other = engine.queue(Bytes::from("job")).unwrap();
+        assert_eq!(other.pop().unwrap(), Some((0, Bytes::from("other"))));
+
+        assert_eq!(
+            engine.queue(Bytes::new()).err().unwrap(),
+            Errors::KeyIsEmpty
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_queue_visibility_timeout() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-queue-visibility");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let queue = engine.queue(Bytes::from("jobs")).unwrap();
+        queue.push(Bytes::from("job-0")).unwrap();
+        queue.push(Bytes::from("job-1")).unwrap();
+
+        let timeout = Duration::from_millis(100);
+        let msg0 = queue.pop_with_visibility(timeout).unwrap().unwrap();
+        assert_eq!(msg0.id, 0);
+        // 取出的元素在超时之前不可见
+        let msg1 = queue.pop_with_visibility(timeout).unwrap().unwrap();
+        assert_eq!(msg1.id, 1);
+        assert_eq!(queue.peek().unwrap(), None);
+        assert!(queue.ack(&msg1).unwrap());
+        assert!(!queue.ack(&msg1).unwrap());
+
+        // 超时之后没有确认的元素重新可见
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(queue.peek().unwrap(), Some((0, Bytes::from("job-0"))));
+        let again = queue.pop_with_visibility(timeout).unwrap().unwrap();
+        assert_eq!(again.id, 0);
+        assert_eq!(again.value, msg0.value);
+        // 之前的取出已经失效
+        assert!(!queue.ack(&msg0).unwrap());
+        assert!(queue.ack(&again).unwrap());
+        assert_eq!(queue.pop().unwrap(), None);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_queue_concurrent_pop() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-queue-concurrent");
+        let engine = Arc::new(Engine::open(opts.clone()).expect("failed to open engine"));
+
+        let queue = engine.queue(Bytes::from("jobs")).unwrap();
+        for i in 0..200 {
+            queue.push(Bytes::from(format!("job-{}", i))).unwrap();
+        }
+
+        // 每个元素只会被取出一次
+        let handles = (0..4)
+            .map(|_| {
+                let engine = engine.clone();
+                std::thread::spawn(move || {
+                    let queue = engine.queue(Bytes::from("jobs")).unwrap();
+                    let mut ids = Vec::new();
+                    while let Some((id, _)) = queue.pop().unwrap() {
+                        ids.push(id);
+                    }
+                    ids
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut ids = HashSet::new();
+        for handle in handles {
+            for id in handle.join().unwrap() {
+                assert!(ids.insert(id));
+            }
+        }
+        assert_eq!(ids.len(), 200);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,368 @@
+use std::{collections::HashMap, sync::Arc};
+
+use bytes::Bytes;
+
+use crate::{
+    chunk::ChunkHead,
+    data::log_record::{LogRecordPos, LogRecordType},
+    db::Engine,
+    errors::{Errors, Result},
+    options::IteratorOptions,
+};
+
+/// 前缀配额的用量，bytes 统计的是 key 和 value 的长度之和
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaUsage {
+    pub keys: u64,
+    pub bytes: u64,
+}
+
+/// 超出配额时调用的回调，参数为前缀和写入后的用量
+pub type QuotaCallback = Arc<dyn Fn(&[u8], QuotaUsage) + Send + Sync>;
+
+/// 超出配额时的处理策略
+#[derive(Clone)]
+pub enum QuotaPolicy {
+    // 拒绝写入，返回 Errors::QuotaExceeded
+    Reject,
+
+    // 调用用户回调，写入照常进行
+    Callback(QuotaCallback),
+}
+
+/// 前缀配额配置项，限制为 None 表示不限制
+#[derive(Clone)]
+pub struct PrefixQuota {
+    pub max_keys: Option<u64>,
+    pub max_bytes: Option<u64>,
+    pub policy: QuotaPolicy,
+}
+
+pub(crate) struct QuotaEntry {
+    prefix: Vec<u8>,
+    quota: PrefixQuota,
+    usage: QuotaUsage,
+}
+
+/// 一次写入对某个前缀用量的影响
+pub(crate) struct QuotaDelta {
+    prefix: Vec<u8>,
+    keys: i64,
+    bytes: i64,
+}
+
+impl Engine {
+    /// 为前缀注册配额，已存在的同名前缀配额会被替换
+    /// 注册时会遍历该前缀下的已有数据计算初始用量
+    pub fn set_prefix_quota(&self, prefix: Bytes, quota: PrefixQuota) -> Result<()> {
+        // 注册配额之后不再使用内存写缓冲，其中的 key 需要先写入数据文件才能统计
+        self.flush_memtable()?;
+        let mut usage = QuotaUsage::default();
+        let mut iter = self.index.iterator(IteratorOptions {
+            prefix: prefix.to_vec(),
+            ..Default::default()
+        });
+        while let Some((key, pos)) = iter.next() {
+            if let Some(value_size) = self.value_size_at(&pos)? {
+                usage.keys += 1;
+                usage.bytes += (key.len() + value_size) as u64;
+            }
+        }
+
+        let mut quotas = self.quotas.write();
+        quotas.retain(|e| e.prefix != prefix);
+        quotas.push(QuotaEntry {
+            prefix: prefix.to_vec(),
+            quota,
+            usage,
+        });
+        Ok(())
+    }
+
+    /// 移除前缀配额，返回该前缀之前是否注册过配额
+    pub fn remove_prefix_quota(&self, prefix: &[u8]) -> bool {
+        let mut quotas = self.quotas.write();
+        let len = quotas.len();
+        quotas.retain(|e| e.prefix != prefix);
+        quotas.len() != len
+    }
+
+    /// 获取前缀当前的用量
+    pub fn prefix_usage(&self, prefix: &[u8]) -> Option<QuotaUsage> {
+        let quotas = self.quotas.read();
+        quotas.iter().find(|e| e.prefix == prefix).map(|e| e.usage)
+    }
+
+    // 计算一组写入（value 长度为 None 表示删除）对各前缀用量的影响
+    // 超出配额时按照策略拒绝写入或者调用回调
+    // 检查和更新用量不在同一个临界区内，并发写入时配额只是软限制
+    pub(crate) fn check_quota(&self, writes: &[(&[u8], Option<usize>)]) -> Result<Vec<QuotaDelta>> {
+        let mut deltas = Vec::new();
+        let mut callbacks = Vec::new();
+        {
+            let quotas = self.quotas.read();
+            if quotas.is_empty() {
+                return Ok(deltas);
+            }
+
+            // 相同的 key 以最后一次写入为准，每个 key 只查找一次旧数据的大小
+            let mut latest = HashMap::new();
+            for (key, value_size) in writes.iter() {
+                latest.insert(*key, *value_size);
+            }
+            let mut sized = Vec::with_capacity(latest.len());
+            for (key, value_size) in latest {
+                if !quotas.iter().any(|e| key.starts_with(&e.prefix)) {
+                    continue;
+                }
+                let old_size = match self.index.get(key.to_vec()) {
+                    Some(pos) => self.value_size_at(&pos)?,
+                    None => None,
+                };
+                sized.push((key, old_size, value_size));
+            }
+
+            for entry in quotas.iter() {
+                let mut delta = QuotaDelta {
+                    prefix: entry.prefix.clone(),
+                    keys: 0,
+                    bytes: 0,
+                };
+                for (key, old_size, value_size) in sized.iter() {
+                    if !key.starts_with(&entry.prefix) {
+                        continue;
+                    }
+                    // 旧数据占用的大小
+                    if let Some(size) = old_size {
+                        delta.keys -= 1;
+                        delta.bytes -= (key.len() + size) as i64;
+                    }
+                    if let Some(size) = value_size {
+                        delta.keys += 1;
+                        delta.bytes += (key.len() + size) as i64;
+                    }
+                }
+                if delta.keys == 0 && delta.bytes == 0 {
+                    continue;
+                }
+
+                let usage = QuotaUsage {
+                    keys: entry.usage.keys.saturating_add_signed(delta.keys),
+                    bytes: entry.usage.bytes.saturating_add_signed(delta.bytes),
+                };
+                let exceeded = (delta.keys > 0
+                    && entry.quota.max_keys.is_some_and(|m| usage.keys > m))
+                    || (delta.bytes > 0 && entry.quota.max_bytes.is_some_and(|m| usage.bytes > m));
+                if exceeded {
+                    match &entry.quota.policy {
+                        QuotaPolicy::Reject => return Err(Errors::QuotaExceeded),
+                        QuotaPolicy::Callback(f) => {
+                            callbacks.push((f.clone(), entry.prefix.clone(), usage))
+                        }
+                    }
+                }
+                deltas.push(delta);
+            }
+        }
+
+        // 释放锁之后再调用回调，避免回调中访问配额接口时死锁
+        for (f, prefix, usage) in callbacks {
+            f(&prefix, usage);
+        }
+        Ok(deltas)
+    }
+
+    // 位置上记录的 value 长度，只读取记录头，分块存储的 value 读取分块头中的总长度
+    fn value_size_at(&self, pos: &LogRecordPos) -> Result<Option<usize>> {
+        let data_file = self.shared_data_file(pos.file_id)?;
+        let header = data_file.read_record_header(pos.offset)?;
+        match header.rec_type {
+            LogRecordType::NORMAL => Ok(Some(header.value_size)),
+            LogRecordType::CHUNKED => {
+                let record = data_file.read_log_record(pos.offset)?.record;
+                Ok(Some(ChunkHead::decode(&record.value)?.len as usize))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // 写入成功之后更新前缀用量
+    pub(crate) fn apply_quota(&self, deltas: Vec<QuotaDelta>) {
+        if deltas.is_empty() {
+            return;
+        }
+        let mut quotas = self.quotas.write();
+        for delta in deltas {
+            if let Some(entry) = quotas.iter_mut().find(|e| e.prefix == delta.prefix) {
+                entry.usage.keys = entry.usage.keys.saturating_add_signed(delta.keys);
+                entry.usage.bytes = entry.usage.bytes.saturating_add_signed(delta.bytes);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::options::Options;
+
+    use super::*;
+
+    #[test]
+    fn test_prefix_quota_reject() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-quota-reject");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let put_res1 = engine.put(Bytes::from("tenant-a:1"), Bytes::from("value"));
+        assert!(put_res1.is_ok());
+
+        // 注册时统计已有数据
+        let quota = PrefixQuota {
+            max_keys: Some(2),
+            max_bytes: None,
+            policy: QuotaPolicy::Reject,
+        };
+        engine
+            .set_prefix_quota(Bytes::from("tenant-a:"), quota)
+            .unwrap();
+        let usage = engine.prefix_usage(b"tenant-a:").unwrap();
+        assert_eq!(usage.keys, 1);
+        assert_eq!(usage.bytes, 15);
+
+        let put_res2 = engine.put(Bytes::from("tenant-a:2"), Bytes::from("value"));
+        assert!(put_res2.is_ok());
+        let put_res3 = engine.put(Bytes::from("tenant-a:3"), Bytes::from("value"));
+        assert_eq!(put_res3.err().unwrap(), Errors::QuotaExceeded);
+        assert!(engine.get(Bytes::from("tenant-a:3")).is_err());
+
+        // 覆盖已有的 key 不会增加 key 数量
+        let put_res4 = engine.put(Bytes::from("tenant-a:2"), Bytes::from("new-value"));
+        assert!(put_res4.is_ok());
+        // 其他前缀不受影响
+        let put_res5 = engine.put(Bytes::from("tenant-b:1"), Bytes::from("value"));
+        assert!(put_res5.is_ok());
+
+        // 删除之后释放配额
+        let del_res = engine.delete(Bytes::from("tenant-a:1"));
+        assert!(del_res.is_ok());
+        let put_res6 = engine.put(Bytes::from("tenant-a:3"), Bytes::from("value"));
+        assert!(put_res6.is_ok());
+
+        // 批量写入同样受到配额限制
+        let wb = engine.new_write_batch(Default::default()).unwrap();
+        wb.put(Bytes::from("tenant-a:4"), Bytes::from("value"))
+            .unwrap();
+        assert_eq!(wb.commit().err().unwrap(), Errors::QuotaExceeded);
+
+        assert!(engine.remove_prefix_quota(b"tenant-a:"));
+        assert!(wb.commit().is_ok());
+        assert!(engine.prefix_usage(b"tenant-a:").is_none());
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_prefix_quota_callback() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-quota-callback");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let exceeded = Arc::new(AtomicUsize::new(0));
+        let counter = exceeded.clone();
+        let quota = PrefixQuota {
+            max_keys: None,
+            max_bytes: Some(20),
+            policy: QuotaPolicy::Callback(Arc::new(move |prefix, usage| {
+                assert_eq!(prefix, b"user:");
+                assert!(usage.bytes > 20);
+                counter.fetch_add(1, Ordering::SeqCst);
+            })),
+        };
+        engine
+            .set_prefix_quota(Bytes::from("user:"), quota)
+            .unwrap();
+
+        let put_res1 = engine.put(Bytes::from("user:1"), Bytes::from("value"));
+        assert!(put_res1.is_ok());
+        assert_eq!(exceeded.load(Ordering::SeqCst), 0);
+
+        // 超出配额时调用回调，但写入照常进行
+        let put_res2 = engine.put(Bytes::from("user:2"), Bytes::from("a-long-value"));
+        assert!(put_res2.is_ok());
+        assert_eq!(exceeded.load(Ordering::SeqCst), 1);
+        assert!(engine.get(Bytes::from("user:2")).is_ok());
+        assert_eq!(engine.prefix_usage(b"user:").unwrap().bytes, 29);
+
+        // 删除数据不会触发回调
+        let del_res = engine.delete(Bytes::from("user:2"));
+        assert!(del_res.is_ok());
+        assert_eq!(exceeded.load(Ordering::SeqCst), 1);
+        assert_eq!(engine.prefix_usage(b"user:").unwrap().bytes, 11);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_prefix_quota_usage() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-quota-usage");
+        opts.value_chunk_size = Some(16);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let quota = PrefixQuota {
+            max_keys: None,
+            max_bytes: None,
+            policy: QuotaPolicy::Reject,
+        };
+        engine
+            .set_prefix_quota(Bytes::from("user:"), quota.clone())
+            .unwrap();
+
+        // 同一次检查中重复的 key 只计算一次，以最后一次写入为准
+        let deltas = engine
+            .check_quota(&[(b"user:1", Some(5)), (b"user:1", Some(3))])
+            .unwrap();
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].keys, 1);
+        assert_eq!(deltas[0].bytes, 9);
+
+        // 元数据不计入用量，分块存储的 value 按照总长度计算
+        engine
+            .put_with_meta(
+                Bytes::from("user:1"),
+                Bytes::from("value"),
+                Bytes::from("meta"),
+            )
+            .unwrap();
+        engine
+            .put(Bytes::from("user:2"), Bytes::from(vec![b'a'; 100]))
+            .unwrap();
+        assert_eq!(engine.prefix_usage(b"user:").unwrap().bytes, 11 + 106);
+
+        // 覆盖时减去的旧数据大小与写入时相同
+        engine.put(Bytes::from("user:1"), Bytes::from("a")).unwrap();
+        engine.put(Bytes::from("user:2"), Bytes::from("b")).unwrap();
+        let usage = engine.prefix_usage(b"user:").unwrap();
+        assert_eq!(usage.keys, 2);
+        assert_eq!(usage.bytes, 14);
+
+        // 重新注册时统计的用量与增量更新的结果相同
+        engine
+            .put(Bytes::from("user:3"), Bytes::from(vec![b'c'; 50]))
+            .unwrap();
+        let usage = engine.prefix_usage(b"user:").unwrap();
+        engine
+            .set_prefix_quota(Bytes::from("user:"), quota)
+            .unwrap();
+        assert_eq!(engine.prefix_usage(b"user:").unwrap(), usage);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,289 @@
+use std::{collections::VecDeque, io, sync::Arc};
+
+use bytes::{Buf, Bytes};
+
+use crate::{
+    chunk::ChunkHead,
+    data::{data_file::DataFile, log_record::LogRecordType},
+    db::Engine,
+    errors::{Errors, Result},
+};
+
+// 每次从数据文件中读取的最大长度
+const MAX_READ_CHUNK: usize = 64 * 1024;
+
+/// 按块从数据文件中读取 value，不需要把整个 value 读入内存
+/// 读取到末尾时校验整条记录的 crc，校验失败返回 io::ErrorKind::InvalidData
+/// 分块存储的 value 每次读取一个分块，读取时校验分块的 crc
+pub struct ValueReader {
+    source: Source,
+    len: u64,
+    // 已经读取的长度
+    read: u64,
+}
+
+enum Source {
+    // value 在一条记录中
+    Record(RecordReader),
+    // 分块存储的 value，剩余分块所在的数据文件和位置
+    Chunks {
+        chunks: VecDeque<(Arc<DataFile>, u64)>,
+        current: Bytes,
+    },
+}
+
+struct RecordReader {
+    data_file: Arc<DataFile>,
+    // 下一次读取的位置
+    offset: u64,
+    // value 结束的位置，之后是 crc
+    end: u64,
+    hasher: crc32fast::Hasher,
+    crc: u32,
+    verified: bool,
+}
+
+impl ValueReader {
+    /// value 的总长度
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 还没有读取的长度
+    pub fn remaining(&self) -> u64 {
+        self.len - self.read
+    }
+}
+
+impl io::Read for ValueReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = match &mut self.source {
+            Source::Record(reader) => reader.read(buf)?,
+            Source::Chunks { chunks, current } => {
+                while current.is_empty() {
+                    let (data_file, offset) = match chunks.pop_front() {
+                        Some(chunk) => chunk,
+                        None => return Ok(0),
+                    };
+                    let record = data_file.read_log_record(offset).map_err(io_error)?.record;
+                    *current = record.value.into();
+                }
+                let n = buf.len().min(current.len());
+                buf[..n].copy_from_slice(&current[..n]);
+                current.advance(n);
+                n
+            }
+        };
+        self.read += n as u64;
+        Ok(n)
+    }
+}
+
+impl RecordReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.offset == self.end {
+            if !self.verified {
+                self.verified = true;
+                if self.hasher.clone().finalize() != self.crc {
+                    return Err(io_error(Errors::InvalidLogRecordCrc));
+                }
+            }
+            return Ok(0);
+        }
+
+        let n = buf
+            .len()
+            .min(MAX_READ_CHUNK)
+            .min((self.end - self.offset) as usize);
+        read_exact_at(&self.data_file, &mut buf[..n], self.offset).map_err(io_error)?;
+        self.hasher.update(&buf[..n]);
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+fn read_exact_at(data_file: &DataFile, buf: &mut [u8], offset: u64) -> Result<()> {
+    let n = data_file.read_at(buf, offset)?;
+    if n < buf.len() {
+        return Err(Errors::ReadDataFileEOF);
+    }
+    Ok(())
+}
+
+fn io_error(e: Errors) -> io::Error {
+    let kind = match e {
+        Errors::ReadDataFileEOF => io::ErrorKind::UnexpectedEof,
+        Errors::InvalidLogRecordCrc => io::ErrorKind::InvalidData,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, e)
+}
+
+impl Engine {
+    /// 获取按块读取 value 的 reader，适用于很大的 value
+    /// reader 持有数据文件的引用，之后的写入和 merge 不影响已经打开的 reader
+    pub fn get_reader(&self, key: Bytes) -> Result<ValueReader> {
+        if key.is_empty() {
+            return Err(Errors::KeyIsEmpty);
+        }
+
+        if self.is_expired(&key) {
+            return Err(Errors::KeyNotFound);
+        }
+        self.flush_memtable()?;
+        let pos = match self.index.get(key.to_vec()) {
+            Some(pos) => pos,
+            None => return Err(Errors::KeyNotFound),
+        };
+        let data_file = self.shared_data_file(pos.file_id)?;
+        data_file.read_stats().record();
+
+        let header = data_file.read_record_header(pos.offset)?;
+        match header.rec_type {
+            LogRecordType::DELETED => return Err(Errors::KeyNotFound),
+            LogRecordType::CHUNKED => {
+                let record = data_file.read_log_record(pos.offset)?.record;
+                let head = ChunkHead::decode(&record.value)?;
+                let chunks = self
+                    .chunk_positions(&head)?
+                    .into_iter()
+                    .map(|pos| Ok((self.shared_data_file(pos.file_id)?, pos.offset)))
+                    .collect::<Result<VecDeque<_>>>()?;
+                return Ok(ValueReader {
+                    source: Source::Chunks {
+                        chunks,
+                        current: Bytes::new(),
+                    },
+                    len: head.len,
+                    read: 0,
+                });
+            }
+            _ => {}
+        }
+
+        // 记录头、元数据和 key 计入校验值
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&header.raw);
+        let prefix_len = header.meta_size + header.key_size;
+        if prefix_len > 0 {
+            // 元数据最长 255 字节，连同 key 一起读取
+            let mut prefix = vec![0; prefix_len];
+            read_exact_at(
+                &data_file,
+                &mut prefix,
+                pos.offset + header.header_size as u64,
+            )?;
+            hasher.update(&prefix);
+        }
+
+        let start = pos.offset + (header.header_size + prefix_len) as u64;
+        let end = start + header.value_size as u64;
+        let mut crc = [0; 4];
+        read_exact_at(&data_file, &mut crc, end)?;
+
+        Ok(ValueReader {
+            source: Source::Record(RecordReader {
+                data_file,
+                offset: start,
+                end,
+                hasher,
+                crc: u32::from_be_bytes(crc),
+                verified: false,
+            }),
+            len: header.value_size as u64,
+            read: 0,
+        })
+    }
+
+    pub(crate) fn shared_data_file(&self, file_id: u32) -> Result<Arc<DataFile>> {
+        self.files
+            .read()
+            .get_shared(file_id)
+            .ok_or_else(|| Errors::FailedToOpenDataFile(io::ErrorKind::NotFound.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, path::PathBuf};
+
+    use crate::options::Options;
+
+    use super::*;
+
+    #[test]
+    fn test_get_reader() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-get-reader");
+        opts.data_file_size = 1024 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let value = (0..300 * 1024).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let res = engine.put_with_meta(
+            Bytes::from("big"),
+            Bytes::from(value.clone()),
+            Bytes::from("meta"),
+        );
+        assert!(res.is_ok());
+        assert!(engine.put(Bytes::from("small"), Bytes::from("v")).is_ok());
+
+        let mut reader = engine.get_reader(Bytes::from("big")).unwrap();
+        assert_eq!(reader.len(), value.len() as u64);
+        let mut buf = vec![0; 1000];
+        assert_eq!(reader.read(&mut buf).unwrap(), 1000);
+        assert_eq!(&buf[..], &value[..1000]);
+        assert_eq!(reader.remaining(), value.len() as u64 - 1000);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(&rest[..], &value[1000..]);
+
+        // 写入新的数据不影响已经打开的 reader
+        let mut reader = engine.get_reader(Bytes::from("small")).unwrap();
+        assert!(engine.put(Bytes::from("small"), Bytes::from("v2")).is_ok());
+        let mut small = String::new();
+        reader.read_to_string(&mut small).unwrap();
+        assert_eq!(small, "v");
+
+        assert!(engine.delete(Bytes::from("small")).is_ok());
+        assert_eq!(
+            engine.get_reader(Bytes::from("small")).err().unwrap(),
+            Errors::KeyNotFound
+        );
+        assert_eq!(
+            engine.get_reader(Bytes::new()).err().unwrap(),
+            Errors::KeyIsEmpty
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_get_reader_crc() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-get-reader-crc");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(engine.put(Bytes::from("key"), Bytes::from("value")).is_ok());
+        assert!(engine.sync().is_ok());
+
+        // 修改 value 的最后一个字节
+        let path = crate::data::data_file::get_data_file_name(&opts.dir_path, 0);
+        let mut data = std::fs::read(&path).unwrap();
+        let pos = data.windows(5).position(|w| w == b"value").unwrap();
+        data[pos + 4] = b'x';
+        std::fs::write(&path, data).unwrap();
+
+        let mut reader = engine.get_reader(Bytes::from("key")).unwrap();
+        let mut buf = Vec::new();
+        let err = reader.read_to_end(&mut buf).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(buf, b"valux");
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,182 @@
+use std::sync::Arc;
+
+use crate::{
+    audit::AuditLog,
+    db::{check_options, Engine},
+    errors::{Errors, Result},
+    options::Options,
+    shutdown::ShutdownHandle,
+    trace::OpTracer,
+};
+
+impl Engine {
+    /// 不关闭数据库直接应用新的配置项，数据文件和索引保持不变
+    /// 持久化策略（sync_write、sync_interval）、文件切换、审计日志、采样率等可以修改，
+    /// 后台任务会按照新的配置项重新启动；修改数据目录、索引类型等只能在打开时确定的配置项
+    /// 会返回 Errors::ImmutableOption，此时数据库仍然使用原来的配置项
+    pub fn reopen(&mut self, opts: Options) -> Result<()> {
+        if self.read_only {
+            return Err(Errors::ReadOnlyEngine);
+        }
+        if let Some(e) = check_options(&opts) {
+            return Err(e);
+        }
+        if let Some(name) = changed_immutable_option(&self.options, &opts) {
+            return Err(Errors::ImmutableOption(name));
+        }
+
+        // 先打开新的审计日志，失败时不影响正在运行的数据库
+        let audit = match &opts.audit_log {
+            Some(path) if opts.audit_log != self.options.audit_log => {
+                Some(AuditLog::open(path, opts.file_mode)?)
+            }
+            _ => None,
+        };
+
+        // 停止按照旧的配置项运行的后台任务，并持久化已经写入的数据
+        self.background.shutdown(self.options.shutdown_timeout)?;
+        self.background = ShutdownHandle::new(opts.background_priority.clone());
+        self.sync()?;
+
+        if opts.audit_log != self.options.audit_log {
+            self.audit = audit;
+        }
+        if opts.op_trace_sample_rate != self.options.op_trace_sample_rate {
+            self.op_tracer = OpTracer::new(opts.op_trace_sample_rate);
+        }
+        self.options = Arc::new(opts);
+        self.spawn_background_tasks()
+    }
+}
+
+// 只能在打开数据库时确定的配置项，返回第一个被修改的配置项的名称
+fn changed_immutable_option(old: &Options, new: &Options) -> Option<&'static str> {
+    if old.dir_path != new.dir_path {
+        return Some("dir_path");
+    }
+    if old.index_type != new.index_type {
+        return Some("index_type");
+    }
+    let same_custom_index = match (&old.custom_index, &new.custom_index) {
+        (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+        (None, None) => true,
+        _ => false,
+    };
+    if !same_custom_index {
+        return Some("custom_index");
+    }
+    if old.bloom_filter != new.bloom_filter {
+        return Some("bloom_filter");
+    }
+    if old.io_type != new.io_type {
+        return Some("io_type");
+    }
+    // 已经写入的记录的对齐方式不能改变
+    if old.record_alignment != new.record_alignment {
+        return Some("record_alignment");
+    }
+    // 元数据文件按照打开时的权限写入
+    if old.file_mode != new.file_mode {
+        return Some("file_mode");
+    }
+    if old.dir_mode != new.dir_mode {
+        return Some("dir_mode");
+    }
+    // 已经写入的过期时间按照打开时的时钟计算
+    let same_clock = match (&old.clock, &new.clock) {
+        (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+        (None, None) => true,
+        _ => false,
+    };
+    if !same_clock {
+        return Some("clock");
+    }
+    // 布隆过滤器按照打开时的哈希函数构建
+    let same_hasher = match (&old.key_hasher, &new.key_hasher) {
+        (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+        (None, None) => true,
+        _ => false,
+    };
+    if !same_hasher {
+        return Some("key_hasher");
+    }
+    // 内存写缓冲在打开时创建
+    if old.memtable_bytes != new.memtable_bytes {
+        return Some("memtable_bytes");
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use bytes::Bytes;
+
+    use crate::options::{IndexType, SyncInterval};
+
+    use super::*;
+
+    #[test]
+    fn test_reopen() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-reopen");
+        let mut engine = Engine::open(opts.clone()).expect("failed to open engine");
+        engine.put(Bytes::from("a"), Bytes::from("1")).unwrap();
+        assert!(engine.background_tasks().is_empty());
+
+        // 修改持久化策略，后台任务按照新的配置项启动
+        let mut new_opts = opts.clone();
+        new_opts.sync_interval = Some(SyncInterval {
+            interval: Duration::from_millis(20),
+            jitter: Duration::from_millis(5),
+        });
+        new_opts.rotate_interval = Some(Duration::from_secs(60));
+        new_opts.op_trace_sample_rate = 1.0;
+        engine.reopen(new_opts.clone()).unwrap();
+        let mut tasks = engine.background_tasks();
+        tasks.sort();
+        assert_eq!(tasks, vec!["rotate".to_string(), "sync".to_string()]);
+        assert_eq!(engine.get(Bytes::from("a")).unwrap(), Bytes::from("1"));
+        assert_eq!(engine.recent_ops().len(), 1);
+
+        engine.put(Bytes::from("b"), Bytes::from("2")).unwrap();
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(!engine.has_unsynced_data());
+
+        // 恢复原来的配置项，后台任务停止
+        engine.reopen(opts.clone()).unwrap();
+        assert!(engine.background_tasks().is_empty());
+        assert!(engine.recent_ops().is_empty());
+
+        // 只能在打开时确定的配置项
+        let mut bad = opts.clone();
+        bad.dir_path = PathBuf::from("/tmp/bitcask-rs-reopen-other");
+        assert_eq!(
+            engine.reopen(bad).err().unwrap(),
+            Errors::ImmutableOption("dir_path")
+        );
+        let mut bad = opts.clone();
+        bad.index_type = IndexType::ConcurrentBTree;
+        assert_eq!(
+            engine.reopen(bad).err().unwrap(),
+            Errors::ImmutableOption("index_type")
+        );
+        let mut bad = opts.clone();
+        bad.memtable_bytes = Some(1024);
+        assert_eq!(
+            engine.reopen(bad).err().unwrap(),
+            Errors::ImmutableOption("memtable_bytes")
+        );
+        let mut bad = opts.clone();
+        bad.data_file_size = 1;
+        assert_eq!(
+            engine.reopen(bad).err().unwrap(),
+            Errors::DataFileSizeTooSmall
+        );
+        assert_eq!(engine.get(Bytes::from("b")).unwrap(), Bytes::from("2"));
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    ops::Bound,
+};
+
+use bytes::Bytes;
+use log::warn;
+
+use crate::{
+    db::{is_internal_key, Engine},
+    options::IteratorOptions,
+};
+
+// 每次从索引中读取的 key 的数量
+const SAMPLE_SCAN_BATCH: usize = 1024;
+
+impl Engine {
+    /// 随机返回一个 key，每个 key 被选中的概率相同，数据库为空时返回 None
+    pub fn random_key(&self) -> Option<Bytes> {
+        self.sample_keys(1).pop()
+    }
+
+    /// 随机选择 n 个不同的 key，按 key 的字节序升序返回，key 的数量不足 n 时返回所有的 key
+    /// 使用蓄水池抽样分批遍历索引，额外的内存只与 n 有关，用于缓存淘汰、数据抽查等场景
+    /// 内存写缓冲中的数据先写入数据文件，写入失败时只从索引中的 key 抽样
+    pub fn sample_keys(&self, n: usize) -> Vec<Bytes> {
+        if n == 0 {
+            return Vec::new();
+        }
+        if let Err(e) = self.flush_memtable() {
+            warn!("Failed to flush memtable before sampling keys: {e}");
+        }
+        let mut rng = Rng::new();
+        let mut reservoir = Vec::with_capacity(n.min(SAMPLE_SCAN_BATCH));
+        let mut seen = 0u64;
+        let options = IteratorOptions::default();
+        let mut cursor: Option<Bytes> = None;
+        loop {
+            let start = match &cursor {
+                Some(key) => Bound::Excluded(key.as_ref()),
+                None => Bound::Unbounded,
+            };
+            let batch = self.index.scan(&options, start, SAMPLE_SCAN_BATCH);
+            let exhausted = batch.len() < SAMPLE_SCAN_BATCH;
+            cursor = batch.last().map(|(key, _)| key.clone());
+            for (key, _) in batch {
+                if is_internal_key(&key) || self.is_expired(&key) {
+                    continue;
+                }
+                seen += 1;
+                if reservoir.len() < n {
+                    reservoir.push(key);
+                } else {
+                    // 第 seen 个 key 以 n / seen 的概率替换蓄水池中的一个 key
+                    let j = rng.next() % seen;
+                    if j < n as u64 {
+                        reservoir[j as usize] = key;
+                    }
+                }
+            }
+            if exhausted {
+                break;
+            }
+        }
+        reservoir.sort();
+        reservoir
+    }
+}
+
+// 抽样使用的随机数生成器（xorshift64*），不需要密码学安全
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Self {
+        // 种子不能为 0
+        Self(RandomState::new().build_hasher().finish() | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use crate::{
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_sample_keys() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-sample-keys");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(engine.random_key(), None);
+        assert!(engine.sample_keys(10).is_empty());
+
+        for i in 0..3000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        engine.put_cas_blob(Bytes::from("internal")).unwrap();
+
+        // 返回不同的 key，按字节序排列
+        let keys = engine.sample_keys(100);
+        assert_eq!(keys.len(), 100);
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert!(keys.iter().all(|k| engine.get(k.clone()).is_ok()));
+        assert_eq!(engine.sample_keys(5000).len(), 3000);
+        assert_eq!(engine.sample_keys(0).len(), 0);
+
+        // 每个 key 被选中的概率大致相同，key 跨越多个扫描批次
+        let mut counts: HashMap<Bytes, usize> = HashMap::new();
+        for _ in 0..1000 {
+            *counts.entry(engine.random_key().unwrap()).or_default() += 1;
+        }
+        let first_half = counts
+            .iter()
+            .filter(|(k, _)| **k < get_test_key(1500))
+            .map(|(_, c)| c)
+            .sum::<usize>();
+        assert!(first_half > 400 && first_half < 600, "{first_half}");
+        assert!(counts.len() > 700);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    data::data_file::{get_data_file_name, DataFile},
+    db::Engine,
+};
+
+/// 已经封存的数据文件，封存之后文件内容不会再被修改，只可能被 merge 整体删除
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SealedSegment {
+    pub file_id: u32,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// 数据文件封存事件的订阅者
+#[derive(Default)]
+pub(crate) struct SegmentSubscribers {
+    senders: Mutex<Vec<Sender<SealedSegment>>>,
+}
+
+impl SegmentSubscribers {
+    // 通知所有订阅者，已经关闭的接收端会被移除
+    pub(crate) fn notify(&self, segment: SealedSegment) {
+        let mut senders = self.senders.lock();
+        senders.retain(|sender| sender.send(segment.clone()).is_ok());
+    }
+}
+
+impl SealedSegment {
+    pub(crate) fn new(dir_path: &std::path::Path, data_file: &DataFile) -> Self {
+        Self {
+            file_id: data_file.get_file_id(),
+            path: get_data_file_name(dir_path, data_file.get_file_id()),
+            size: data_file.len(),
+        }
+    }
+}
+
+impl Engine {
+    /// 当前所有已封存的数据文件，按照文件 id 从小到大排列
+    pub fn sealed_segments(&self) -> Vec<SealedSegment> {
+        let files = self.files.load();
+        let mut segments = files
+            .older
+            .values()
+            .map(|f| SealedSegment::new(&self.options.dir_path, f))
+            .collect::<Vec<_>>();
+        segments.sort_by_key(|s| s.file_id);
+        segments
+    }
+
+    /// 订阅数据文件封存事件，活跃文件切换时，被封存的文件在持久化之后发送到返回的通道中
+    /// 事件在写入路径上发送，接收端不需要及时处理，丢弃接收端即可取消订阅
+    pub fn subscribe_sealed_segments(&self) -> Receiver<SealedSegment> {
+        let (sender, receiver) = channel();
+        self.segment_subscribers.senders.lock().push(sender);
+        receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::{
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_sealed_segments() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-sealed-segments");
+        opts.data_file_size = 4 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert!(engine.sealed_segments().is_empty());
+
+        let receiver = engine.subscribe_sealed_segments();
+        // 取消订阅不影响写入
+        std::mem::drop(engine.subscribe_sealed_segments());
+        for i in 0..500 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+
+        let events = receiver.try_iter().collect::<Vec<_>>();
+        assert!(!events.is_empty());
+        assert_eq!(events, engine.sealed_segments());
+        for segment in events.iter() {
+            // 封存的文件已经持久化，大小不会再变化
+            assert_eq!(fs::metadata(&segment.path).unwrap().len(), segment.size);
+            assert!(segment.size <= opts.data_file_size);
+        }
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
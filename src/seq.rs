@@ -0,0 +1,169 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use log::error;
+use parking_lot::Mutex;
+
+use crate::{
+    db::Engine,
+    errors::{Errors, Result},
+    utils::atomic_write,
+};
+
+/// 持久化事务序列号的文件名
+pub const SEQ_NO_FILE_NAME: &str = "seq-no";
+
+/// 事务序列号分配器
+/// 序列号从 1 开始单调递增，0 保留给非事务写入。重启之后从数据文件中读取到的最大序列号
+/// 和持久化的序列号中较大的一个继续分配，删除数据文件之前会先持久化当前的序列号，
+/// 保证即使带有最大序列号的记录被清理之后，已经分配过的序列号也不会重复
+pub(crate) struct SeqAllocator {
+    // 最近一次分配的序列号
+    current: AtomicU64,
+    // 持久化序列号的文件，只读模式下为 None
+    path: Option<PathBuf>,
+    // 新建的序列号文件的权限
+    file_mode: u32,
+    // 串行化持久化
+    persist_lock: Mutex<()>,
+}
+
+impl SeqAllocator {
+    // 打开数据目录中的序列号分配器，recovered 为从数据文件中读取到的最大序列号
+    pub(crate) fn open(dir_path: &Path, recovered: u64) -> Result<Self> {
+        let path = dir_path.join(SEQ_NO_FILE_NAME);
+        let persisted = read_seq_no(&path)?;
+        Ok(Self {
+            current: AtomicU64::new(std::cmp::max(recovered, persisted)),
+            path: Some(path),
+            file_mode: 0o666,
+            persist_lock: Mutex::new(()),
+        })
+    }
+
+    // 设置新建的序列号文件的权限
+    pub(crate) fn with_file_mode(mut self, mode: u32) -> Self {
+        self.file_mode = mode;
+        self
+    }
+
+    // 只读模式下的分配器，不会分配新的序列号，也不会写入文件
+    pub(crate) fn read_only() -> Self {
+        Self {
+            current: AtomicU64::new(0),
+            path: None,
+            file_mode: 0o666,
+            persist_lock: Mutex::new(()),
+        }
+    }
+
+    // 分配一个新的序列号
+    pub(crate) fn allocate(&self) -> Result<u64> {
+        if self.path.is_none() {
+            return Err(Errors::ReadOnlyEngine);
+        }
+        match self
+            .current
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |seq| seq.checked_add(1))
+        {
+            Ok(seq) => Ok(seq + 1),
+            Err(_) => Err(Errors::SeqNoOverflow),
+        }
+    }
+
+    // 最近一次分配的序列号，没有分配过时为 0
+    pub(crate) fn current(&self) -> u64 {
+        self.current.load(Ordering::SeqCst)
+    }
+
+    // 读取到其他进程分配的序列号，只会增大
+    pub(crate) fn observe(&self, seq: u64) {
+        self.current.fetch_max(seq, Ordering::SeqCst);
+    }
+
+    // 将当前的序列号持久化到数据目录中
+    pub(crate) fn persist(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => {
+                let _lock = self.persist_lock.lock();
+                write_seq_no(path, self.current(), self.file_mode)
+            }
+            None => Ok(()),
+        }
+    }
+
+    // 将当前的序列号持久化到另一个数据目录中
+    pub(crate) fn persist_to(&self, dir_path: &Path) -> Result<()> {
+        write_seq_no(
+            &dir_path.join(SEQ_NO_FILE_NAME),
+            self.current(),
+            self.file_mode,
+        )
+    }
+}
+
+pub(crate) fn read_seq_no(path: &Path) -> Result<u64> {
+    if !path.is_file() {
+        return Ok(0);
+    }
+    let buf = match fs::read(path) {
+        Ok(buf) => buf,
+        Err(e) => {
+            error!("Failed to read seq no file: {e}");
+            return Err(Errors::FailedToReadFromDataFile(e.into()));
+        }
+    };
+    match <[u8; 8]>::try_from(buf.as_slice()) {
+        Ok(bytes) => Ok(u64::from_le_bytes(bytes)),
+        Err(_) => Err(Errors::InvalidSeqNoFile),
+    }
+}
+
+// 原子地写入，崩溃时不会留下写到一半的序列号文件
+fn write_seq_no(path: &Path, seq: u64, mode: u32) -> Result<()> {
+    atomic_write(path, &seq.to_le_bytes(), mode)
+}
+
+impl Engine {
+    /// 最近一次分配的事务序列号，没有提交过事务时为 0
+    /// 序列号单调递增，重启之后也不会重复
+    pub fn current_seq(&self) -> u64 {
+        self.seq.current()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seq_allocator() {
+        let dir_path = PathBuf::from("/tmp/bitcask-rs-seq-allocator");
+        fs::create_dir_all(&dir_path).unwrap();
+
+        let seq = SeqAllocator::open(&dir_path, 5).unwrap();
+        assert_eq!(seq.current(), 5);
+        assert_eq!(seq.allocate().unwrap(), 6);
+        assert_eq!(seq.allocate().unwrap(), 7);
+        assert!(seq.persist().is_ok());
+
+        // 持久化的序列号大于数据文件中的序列号
+        let seq = SeqAllocator::open(&dir_path, 3).unwrap();
+        assert_eq!(seq.allocate().unwrap(), 8);
+
+        // 溢出
+        seq.observe(u64::MAX);
+        assert_eq!(seq.allocate().err().unwrap(), Errors::SeqNoOverflow);
+        assert_eq!(seq.current(), u64::MAX);
+
+        // 只读模式不分配
+        let seq = SeqAllocator::read_only();
+        assert_eq!(seq.allocate().err().unwrap(), Errors::ReadOnlyEngine);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(dir_path).expect("failed to remove path");
+    }
+}
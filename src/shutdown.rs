@@ -0,0 +1,192 @@
+use std::{
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use log::{error, warn};
+use parking_lot::{Condvar, Mutex};
+
+use crate::{
+    errors::{Errors, Result},
+    options::BackgroundPriority,
+    priority::apply_priority,
+};
+
+/// 后台任务的停止信号，任务应当在循环中检查或者等待该信号
+#[derive(Clone, Default)]
+pub(crate) struct ShutdownSignal {
+    inner: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl ShutdownSignal {
+    // 是否已经收到停止信号
+    pub(crate) fn is_shutdown(&self) -> bool {
+        *self.inner.0.lock()
+    }
+
+    // 等待一段时间，期间收到停止信号会提前返回，返回值表示是否已经收到停止信号
+    pub(crate) fn wait_timeout(&self, timeout: Duration) -> bool {
+        let (lock, cvar) = &*self.inner;
+        let mut stopped = lock.lock();
+        if !*stopped {
+            cvar.wait_for(&mut stopped, timeout);
+        }
+        *stopped
+    }
+
+    fn shutdown(&self) {
+        let (lock, cvar) = &*self.inner;
+        *lock.lock() = true;
+        cvar.notify_all();
+    }
+}
+
+struct BackgroundTask {
+    name: String,
+    handle: JoinHandle<()>,
+}
+
+/// 管理存储引擎的所有后台任务（merge、flush、reaper等）
+/// 关闭时统一发送停止信号，并在超时时间内等待所有任务退出
+#[derive(Default)]
+pub(crate) struct ShutdownHandle {
+    signal: ShutdownSignal,
+    tasks: Mutex<Vec<BackgroundTask>>,
+    // 后台任务线程的调度设置
+    priority: BackgroundPriority,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(priority: BackgroundPriority) -> Self {
+        Self {
+            signal: ShutdownSignal::default(),
+            tasks: Mutex::new(Vec::new()),
+            priority,
+        }
+    }
+
+    // 启动一个后台任务
+    pub(crate) fn spawn<F>(&self, name: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(ShutdownSignal) + Send + 'static,
+    {
+        let signal = self.signal.clone();
+        let priority = self.priority.clone();
+        let task_name = name.to_string();
+        let handle = match thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                if let Err(e) = apply_priority(&priority) {
+                    warn!("Failed to set priority of background task {task_name}: {e}");
+                }
+                f(signal)
+            }) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error!("Failed to spawn background task {name}: {e}");
+                return Err(Errors::FailedToSpawnBackgroundTask(e.into()));
+            }
+        };
+        self.tasks.lock().push(BackgroundTask {
+            name: name.to_string(),
+            handle,
+        });
+        Ok(())
+    }
+
+    // 当前仍在运行的后台任务名称
+    pub(crate) fn running_tasks(&self) -> Vec<String> {
+        let tasks = self.tasks.lock();
+        tasks
+            .iter()
+            .filter(|t| !t.handle.is_finished())
+            .map(|t| t.name.clone())
+            .collect()
+    }
+
+    // 发送停止信号并等待所有任务退出，超时未退出的任务会被返回
+    pub(crate) fn shutdown(&self, timeout: Duration) -> Result<()> {
+        self.signal.shutdown();
+
+        let deadline = Instant::now() + timeout;
+        let mut tasks = std::mem::take(&mut *self.tasks.lock());
+        loop {
+            let (finished, running): (Vec<_>, Vec<_>) =
+                tasks.into_iter().partition(|t| t.handle.is_finished());
+            for task in finished {
+                if task.handle.join().is_err() {
+                    error!("Background task {} panicked", task.name);
+                }
+            }
+            tasks = running;
+            if tasks.is_empty() || Instant::now() >= deadline {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        if tasks.is_empty() {
+            return Ok(());
+        }
+        let names = tasks.iter().map(|t| t.name.clone()).collect::<Vec<_>>();
+        warn!("Background tasks did not stop in time: {:?}", names);
+        // 未退出的任务不再等待，放回去以便后续查看
+        self.tasks.lock().extend(tasks);
+        Err(Errors::BackgroundTasksStuck(names))
+    }
+}
+
+impl Drop for ShutdownHandle {
+    fn drop(&mut self) {
+        // 未调用 close 时也通知后台任务退出，但不等待
+        self.signal.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_shutdown_join_tasks() {
+        let handle = ShutdownHandle::default();
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        for i in 0..3 {
+            let counter = counter.clone();
+            let res = handle.spawn(&format!("task-{i}"), move |signal| {
+                while !signal.wait_timeout(Duration::from_millis(10)) {}
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+            assert!(res.is_ok());
+        }
+        assert_eq!(handle.running_tasks().len(), 3);
+
+        let res = handle.shutdown(Duration::from_secs(5));
+        assert!(res.is_ok());
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+        assert!(handle.running_tasks().is_empty());
+    }
+
+    #[test]
+    fn test_shutdown_report_stuck_tasks() {
+        let handle = ShutdownHandle::default();
+        let res1 = handle.spawn("quick", |signal| {
+            signal.wait_timeout(Duration::from_secs(10));
+        });
+        assert!(res1.is_ok());
+        // 不响应停止信号的任务
+        let res2 = handle.spawn("stuck", |_| thread::sleep(Duration::from_millis(500)));
+        assert!(res2.is_ok());
+
+        let res = handle.shutdown(Duration::from_millis(50));
+        assert_eq!(
+            res.err().unwrap(),
+            Errors::BackgroundTasksStuck(vec!["stuck".to_string()])
+        );
+        assert_eq!(handle.running_tasks(), vec!["stuck".to_string()]);
+    }
+}
@@ -0,0 +1,356 @@
+use log::warn;
+
+use crate::{
+    data::{data_file::DataFile, log_record::LogRecordType},
+    db::Engine,
+    errors::{Errors, Result},
+    index::IndexStats,
+    merge::{HighestGarbageFirst, MergeCandidate, MergePicker},
+};
+
+/// 单个数据文件的空间占用
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSpace {
+    pub file_id: u32,
+    pub size: u64,
+    // 仍然被索引引用的数据量
+    pub live_bytes: u64,
+    // 被覆盖、删除的数据以及删除标记、事务标识、填充等可回收的数据量
+    pub dead_bytes: u64,
+    // 删除标记的数量和占用的数据量
+    pub tombstones: u64,
+    pub tombstone_bytes: u64,
+    pub is_active: bool,
+}
+
+/// 单个数据文件的垃圾统计，由写入时维护的计数器得到，不需要读取数据文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStats {
+    pub file_id: u32,
+    pub total_bytes: u64,
+    // 仍然被索引引用的数据量，包括文件头
+    pub live_bytes: u64,
+    // 被覆盖、删除的数据以及删除标记、事务标识、填充等可回收的数据量
+    pub dead_bytes: u64,
+    // 文件中的记录数，包括失效的记录、删除标记和事务标识，不包括对齐填充
+    pub record_count: u64,
+}
+
+/// 数据目录的空间放大报告
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpaceReport {
+    pub live_bytes: u64,
+    pub dead_bytes: u64,
+    pub tombstones: u64,
+    // 按文件 id 排列
+    pub files: Vec<FileSpace>,
+    // 按照 picker 进行一次合并预计可以回收的数据量
+    pub reclaimable_bytes: u64,
+    // 预计被合并的文件
+    pub merge_file_ids: Vec<u32>,
+}
+
+impl SpaceReport {
+    /// 数据文件总大小
+    pub fn total_bytes(&self) -> u64 {
+        self.live_bytes + self.dead_bytes
+    }
+
+    /// 空间放大，数据文件总大小与有效数据量的比值
+    pub fn space_amplification(&self) -> f64 {
+        match self.live_bytes {
+            0 => 0.0,
+            n => self.total_bytes() as f64 / n as f64,
+        }
+    }
+}
+
+impl Engine {
+    /// 内存索引的 key 数量和峰值，索引不支持统计时返回 None
+    pub fn index_stats(&self) -> Option<IndexStats> {
+        self.index.stats()
+    }
+
+    /// 使用当前有效的 key 重建内存索引，大量删除之后把不再使用的内存归还给操作系统，返回重建之后的统计信息
+    /// 重建期间持有索引的写锁，读写都会被阻塞，适合在清理完成之后的低峰期调用
+    pub fn shrink_index(&self) -> Option<IndexStats> {
+        self.index.shrink();
+        self.index.stats()
+    }
+
+    /// 每个数据文件的总大小、有效数据量、可回收数据量和记录数，按文件 id 排列
+    /// 只读取内存中的计数器，代价很低，可以频繁调用来决定合并的时机；
+    /// 需要删除标记的统计和合并的预估时使用 space_report；启用内存写缓冲时先把其中的数据写入数据文件
+    pub fn file_stats(&self) -> Vec<FileStats> {
+        if let Err(e) = self.flush_memtable() {
+            warn!("Failed to flush memtable before collecting file stats: {e}");
+        }
+        let files = self.files.load();
+        let mut stats = files
+            .older
+            .values()
+            .chain(std::iter::once(&files.active))
+            .map(|data_file| {
+                let total_bytes = data_file.len();
+                let dead_bytes = data_file.get_stale_bytes().min(total_bytes);
+                FileStats {
+                    file_id: data_file.get_file_id(),
+                    total_bytes,
+                    live_bytes: total_bytes - dead_bytes,
+                    dead_bytes,
+                    record_count: data_file.get_record_count(),
+                }
+            })
+            .collect::<Vec<_>>();
+        stats.sort_by_key(|s| s.file_id);
+        stats
+    }
+
+    /// 统计每个数据文件中的有效数据、可回收数据和删除标记，
+    /// 并按照默认的 HighestGarbageFirst 策略估算一次合并可以回收的空间
+    /// 需要扫描所有的数据文件，数据量大时代价较高
+    pub fn space_report(&self) -> Result<SpaceReport> {
+        self.space_report_with(&HighestGarbageFirst::default())
+    }
+
+    /// 同 space_report，使用指定的 picker 估算合并可以回收的空间
+    pub fn space_report_with(&self, picker: &dyn MergePicker) -> Result<SpaceReport> {
+        // 内存写缓冲中的数据覆盖的记录还没有计入失效数据，先写入数据文件
+        self.flush_memtable()?;
+        let files = self.files.load();
+        let live_bytes = self.live_bytes_by_file(&files)?;
+
+        let mut data_files = files.older.values().collect::<Vec<_>>();
+        data_files.push(&files.active);
+        data_files.sort_by_key(|f| f.get_file_id());
+
+        let mut file_spaces = Vec::with_capacity(data_files.len());
+        for data_file in data_files {
+            let file_id = data_file.get_file_id();
+            let (tombstones, tombstone_bytes) = count_tombstones(data_file)?;
+            let size = data_file.len();
+            let live = live_bytes.get(&file_id).copied().unwrap_or(0);
+            file_spaces.push(FileSpace {
+                file_id,
+                size,
+                live_bytes: live,
+                dead_bytes: size.saturating_sub(live),
+                tombstones,
+                tombstone_bytes,
+                is_active: file_id == files.active.get_file_id(),
+            });
+        }
+
+        let candidates = file_spaces
+            .iter()
+            .filter(|f| !f.is_active)
+            .map(|f| MergeCandidate {
+                file_id: f.file_id,
+                size: f.size,
+                live_bytes: f.live_bytes,
+                age: Default::default(),
+            })
+            .collect::<Vec<_>>();
+        let mut merge_file_ids = picker.pick(&candidates);
+        merge_file_ids.sort();
+        merge_file_ids.dedup();
+
+        // 存在更旧并且保留下来的文件时，合并需要保留删除标记
+        let oldest_retained = candidates
+            .iter()
+            .map(|c| c.file_id)
+            .find(|id| !merge_file_ids.contains(id));
+        let reclaimable_bytes = file_spaces
+            .iter()
+            .filter(|f| merge_file_ids.contains(&f.file_id))
+            .map(|f| match oldest_retained {
+                Some(id) if id < f.file_id => f.dead_bytes.saturating_sub(f.tombstone_bytes),
+                _ => f.dead_bytes,
+            })
+            .sum();
+
+        Ok(SpaceReport {
+            live_bytes: file_spaces.iter().map(|f| f.live_bytes).sum(),
+            dead_bytes: file_spaces.iter().map(|f| f.dead_bytes).sum(),
+            tombstones: file_spaces.iter().map(|f| f.tombstones).sum(),
+            files: file_spaces,
+            reclaimable_bytes,
+            merge_file_ids,
+        })
+    }
+}
+
+// 统计数据文件中删除标记的数量和数据量
+fn count_tombstones(data_file: &DataFile) -> Result<(u64, u64)> {
+    let (mut count, mut bytes) = (0, 0);
+    let mut offset = 0;
+    while offset < data_file.len() {
+        let res = match data_file.read_log_record(offset) {
+            Ok(res) => res,
+            Err(Errors::ReadDataFileEOF) => break,
+            Err(e) => return Err(e),
+        };
+        if res.record.rec_type == LogRecordType::DELETED {
+            count += 1;
+            bytes += res.size as u64;
+        }
+        offset += res.size as u64;
+    }
+    Ok((count, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use crate::{
+        fence::FILE_HEADER_SIZE,
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_space_report() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-space-report");
+        opts.data_file_size = 4 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 空数据库
+        let report = engine.space_report().unwrap();
+        assert_eq!(report.total_bytes(), FILE_HEADER_SIZE);
+        assert_eq!(report.dead_bytes, 0);
+        assert_eq!(report.files.len(), 1);
+
+        for i in 0..200 {
+            let res = engine.put(get_test_key(i), get_test_value(i));
+            assert!(res.is_ok());
+        }
+        let report = engine.space_report().unwrap();
+        assert_eq!(report.dead_bytes, 0);
+        assert_eq!(report.reclaimable_bytes, 0);
+        assert!(report.merge_file_ids.is_empty());
+        assert_eq!(report.space_amplification(), 1.0);
+
+        // 覆盖和删除
+        for i in 0..100 {
+            let res = engine.put(get_test_key(i), Bytes::from("new-value"));
+            assert!(res.is_ok());
+        }
+        for i in 100..120 {
+            let res = engine.delete(get_test_key(i));
+            assert!(res.is_ok());
+        }
+        let report = engine.space_report().unwrap();
+        assert_eq!(report.tombstones, 20);
+        assert!(report.dead_bytes > 0);
+        assert!(report.space_amplification() > 1.0);
+        assert_eq!(
+            report.total_bytes(),
+            report.files.iter().map(|f| f.size).sum::<u64>()
+        );
+        assert!(report.files.last().unwrap().is_active);
+        assert!(!report.merge_file_ids.is_empty());
+        assert!(report.reclaimable_bytes > 0);
+        assert!(report.reclaimable_bytes <= report.dead_bytes);
+
+        // 合并的文件与报告中的一致，合并之后有效数据不变
+        let before = report.total_bytes();
+        let merged = engine.merge_with(&HighestGarbageFirst::default()).unwrap();
+        assert_eq!(merged, report.merge_file_ids);
+        let after = engine.space_report().unwrap();
+        assert!(after.total_bytes() < before);
+        // 被合并的文件的文件头一起被删除
+        assert_eq!(
+            after.live_bytes + merged.len() as u64 * FILE_HEADER_SIZE,
+            report.live_bytes
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_file_stats() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-file-stats");
+        opts.data_file_size = 4 * 1024;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let stats = engine.file_stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].live_bytes, FILE_HEADER_SIZE);
+        assert_eq!(stats[0].record_count, 0);
+
+        for i in 0..200 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        for i in 0..100 {
+            engine
+                .put(get_test_key(i), Bytes::from("new-value"))
+                .unwrap();
+        }
+        for i in 100..120 {
+            engine.delete(get_test_key(i)).unwrap();
+        }
+
+        // 与扫描数据文件得到的结果一致，重启之后保持不变
+        let check = |engine: &Engine| {
+            let stats = engine.file_stats();
+            let report = engine.space_report().unwrap();
+            assert_eq!(stats.len(), report.files.len());
+            for (stat, space) in stats.iter().zip(report.files.iter()) {
+                assert_eq!(stat.file_id, space.file_id);
+                assert_eq!(stat.total_bytes, space.size);
+                assert_eq!(stat.live_bytes, space.live_bytes);
+                assert_eq!(stat.dead_bytes, space.dead_bytes);
+            }
+            stats.iter().map(|s| s.record_count).sum::<u64>()
+        };
+        assert_eq!(check(&engine), 320);
+        engine.close().expect("failed to close");
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        assert_eq!(check(&engine), 320);
+
+        // 合并之后旧文件中的垃圾被回收
+        let dead_bytes = |stats: &[FileStats]| stats.iter().map(|s| s.dead_bytes).sum::<u64>();
+        let before = engine.file_stats();
+        engine.merge().unwrap();
+        assert!(check(&engine) < 320);
+        assert!(dead_bytes(&engine.file_stats()) < dead_bytes(&before));
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_shrink_index() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-shrink-index");
+        opts.bloom_filter = Some(Default::default());
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for i in 0..1000 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        for i in 0..800 {
+            engine.delete(get_test_key(i)).unwrap();
+        }
+        let stats = engine.index_stats().unwrap();
+        assert_eq!((stats.keys, stats.peak_keys), (200, 1000));
+        assert!(stats.fragmentation() > 0.79);
+
+        let stats = engine.shrink_index().unwrap();
+        assert_eq!((stats.keys, stats.peak_keys), (200, 200));
+        assert_eq!(engine.list_keys().unwrap().len(), 200);
+        assert_eq!(engine.get(get_test_key(900)).unwrap(), get_test_value(900));
+        engine.close().expect("failed to close");
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,270 @@
+use std::ops::Bound;
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::{
+    db::{is_internal_key, Engine},
+    errors::Result,
+    options::{IteratorConsistency, IteratorOptions},
+};
+
+// 事件 key 的后缀长度：8 字节毫秒时间戳 + 4 字节序号，均为大端序
+const EVENT_ID_SIZE: usize = 12;
+
+// 查找流中最后一个事件时每次从索引中读取的个数
+const SCAN_BATCH: usize = 16;
+
+/// 事件 id，先按时间戳、再按序号排序，与事件 key 的字节序一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct EventId {
+    // 毫秒时间戳
+    pub timestamp: u64,
+    // 同一毫秒内的序号
+    pub seq: u32,
+}
+
+impl EventId {
+    // 大于当前 id 的最小 id
+    fn successor(self) -> Self {
+        match self.seq.checked_add(1) {
+            Some(seq) => EventId { seq, ..self },
+            None => EventId {
+                timestamp: self.timestamp + 1,
+                seq: 0,
+            },
+        }
+    }
+}
+
+impl Engine {
+    /// 向事件流中追加一个事件，返回事件 id
+    /// 事件的 key 为 stream_key 加上编码后的事件 id，同一个引擎分配的事件 id 严格递增，
+    /// 时钟回拨或者同一毫秒内有多个事件时，沿用上一个时间戳并递增序号
+    pub fn append_event(&self, stream_key: Bytes, payload: Bytes) -> Result<EventId> {
+        self.check_key(&stream_key)?;
+
+        let mut last = self.last_event_id.lock();
+        // 重启之后从流中已有的最后一个事件继续分配
+        let prev = match self.last_stream_event(&stream_key) {
+            Some(id) => id.max(*last),
+            None => *last,
+        };
+        let now = EventId {
+            timestamp: self.now_millis(),
+            seq: 0,
+        };
+        let id = now.max(prev.successor());
+        self.put(event_key(&stream_key, id), payload)?;
+        *last = id;
+        Ok(id)
+    }
+
+    /// 读取事件流中时间戳在 [from, to) 之间的事件，按事件 id 升序排列
+    pub fn read_range(
+        &self,
+        stream_key: Bytes,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<(EventId, Bytes)>> {
+        self.check_key(&stream_key)?;
+        let mut events = Vec::new();
+        if from >= to {
+            return Ok(events);
+        }
+
+        let end = event_key(
+            &stream_key,
+            EventId {
+                timestamp: to,
+                seq: 0,
+            },
+        );
+        let iter = self.try_iter(IteratorOptions {
+            prefix: stream_key.to_vec(),
+            consistency: IteratorConsistency::ReadCommitted,
+            ..Default::default()
+        })?;
+        iter.seek(
+            event_key(
+                &stream_key,
+                EventId {
+                    timestamp: from,
+                    seq: 0,
+                },
+            )
+            .to_vec(),
+        );
+        while let Some((key, value)) = iter.next() {
+            if key >= end {
+                break;
+            }
+            // 跳过以 stream_key 为前缀的其他 key
+            if let Some(id) = parse_event_key(&stream_key, &key) {
+                events.push((id, value));
+            }
+        }
+        Ok(events)
+    }
+
+    // 流中 id 最大的事件，从最大的事件 key 开始倒序查找
+    fn last_stream_event(&self, stream_key: &[u8]) -> Option<EventId> {
+        let options = IteratorOptions {
+            prefix: stream_key.to_vec(),
+            reverse: true,
+            ..Default::default()
+        };
+        let max_key = event_key(
+            stream_key,
+            EventId {
+                timestamp: u64::MAX,
+                seq: u32::MAX,
+            },
+        );
+        let mut start = Bound::Included(max_key.to_vec());
+        loop {
+            let items = self
+                .index
+                .scan(&options, start.as_ref().map(Vec::as_slice), SCAN_BATCH);
+            for (key, _) in items.iter() {
+                if let Some(id) = parse_event_key(stream_key, key) {
+                    return Some(id);
+                }
+            }
+            match items.last() {
+                Some((key, _)) if items.len() == SCAN_BATCH => {
+                    start = Bound::Excluded(key.to_vec());
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+// 事件 key：stream_key + 时间戳 + 序号
+pub(crate) fn event_key(stream_key: &[u8], id: EventId) -> Bytes {
+    let mut key = BytesMut::with_capacity(stream_key.len() + EVENT_ID_SIZE);
+    key.put_slice(stream_key);
+    key.put_u64(id.timestamp);
+    key.put_u32(id.seq);
+    key.freeze()
+}
+
+// 解析事件 key，key 不属于这个流时返回 None
+// 后缀是定长的，以 stream_key 为前缀的其他流的 key 长度不同
+fn parse_event_key(stream_key: &[u8], key: &[u8]) -> Option<EventId> {
+    if key.len() != stream_key.len() + EVENT_ID_SIZE
+        || !key.starts_with(stream_key)
+        || is_internal_key(key)
+    {
+        return None;
+    }
+    let suffix = &key[stream_key.len()..];
+    Some(EventId {
+        timestamp: u64::from_be_bytes(suffix[..8].try_into().unwrap()),
+        seq: u32::from_be_bytes(suffix[8..].try_into().unwrap()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{errors::Errors, options::Options};
+
+    use super::*;
+
+    #[test]
+    fn test_append_event_read_range() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-append-event");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        let stream = Bytes::from("events");
+        let mut ids = Vec::new();
+        for i in 0..100 {
+            let payload = Bytes::from(format!("event-{}", i));
+            ids.push(engine.append_event(stream.clone(), payload).unwrap());
+        }
+        // 事件 id 严格递增，同一毫秒内的事件序号递增
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+
+        // 以同样的前缀开头的其他流和普通的 key 不会被读取到
+        engine
+            .append_event(Bytes::from("events-other"), Bytes::from("other"))
+            .unwrap();
+        engine
+            .put(Bytes::from("events-plain"), Bytes::from("plain"))
+            .unwrap();
+
+        let events = engine.read_range(stream.clone(), 0, u64::MAX).unwrap();
+        assert_eq!(events.len(), 100);
+        for (i, (id, payload)) in events.iter().enumerate() {
+            assert_eq!(*id, ids[i]);
+            assert_eq!(*payload, Bytes::from(format!("event-{}", i)));
+        }
+
+        // 时间范围是左闭右开的
+        let first = ids[0].timestamp;
+        let last = ids[99].timestamp;
+        let events = engine.read_range(stream.clone(), first, last).unwrap();
+        assert_eq!(
+            events.len(),
+            ids.iter().filter(|id| id.timestamp < last).count()
+        );
+        let events = engine.read_range(stream.clone(), last, last + 1).unwrap();
+        assert!(events.iter().all(|(id, _)| id.timestamp == last));
+        assert!(!events.is_empty());
+        assert!(engine
+            .read_range(stream.clone(), last, first)
+            .unwrap()
+            .is_empty());
+
+        // 重启之后继续分配更大的事件 id
+        engine.close().expect("failed to close engine");
+        std::mem::drop(engine);
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        let id = engine
+            .append_event(stream.clone(), Bytes::from("event-100"))
+            .unwrap();
+        assert!(id > ids[99]);
+        let events = engine.read_range(stream.clone(), 0, u64::MAX).unwrap();
+        assert_eq!(events.len(), 101);
+        assert_eq!(events[100].0, id);
+
+        let res = engine.append_event(Bytes::new(), Bytes::from("event"));
+        assert_eq!(res.err().unwrap(), Errors::KeyIsEmpty);
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_append_event_future_timestamp() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-append-event-future");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        // 流中已有时间戳更大的事件（例如时钟回拨），新事件沿用这个时间戳并递增序号
+        let stream = Bytes::from("events");
+        let future = EventId {
+            timestamp: engine.now_millis() + 60_000,
+            seq: u32::MAX,
+        };
+        engine
+            .put(event_key(&stream, future), Bytes::from("future"))
+            .unwrap();
+        let id = engine
+            .append_event(stream.clone(), Bytes::from("event"))
+            .unwrap();
+        assert_eq!(
+            id,
+            EventId {
+                timestamp: future.timestamp + 1,
+                seq: 0
+            }
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -1,10 +1,20 @@
 use bytes::Bytes;
-use std::{fs, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use crate::{
+    batch::{log_record_key_with_seq, NON_TRANSACTION_SEQ_NO},
+    data::log_record::{LogRecord, LogRecordType},
     db::Engine,
     errors::Errors,
-    options::Options,
+    fio::torn::CrashSimulator,
+    options::{BloomFilterOptions, IOType, IndexType, Options, RecordAlignment, SyncInterval},
+    quota::{PrefixQuota, QuotaPolicy, QuotaUsage},
     utils::rand_kv::{get_test_key, get_test_value},
 };
 
@@ -20,7 +30,7 @@ fn test_engine_put() {
     assert!(res1.is_ok());
     let res2 = engine.get(get_test_key(11));
     assert!(res2.is_ok());
-    assert!(res2.unwrap().len() > 0);
+    assert!(!res2.unwrap().is_empty());
 
     // 2.重复 Put key 相同的数据
     let res3 = engine.put(get_test_key(22), get_test_value(22));
@@ -73,7 +83,7 @@ fn test_engine_get() {
     assert!(res1.is_ok());
     let res2 = engine.get(get_test_key(111));
     assert!(res2.is_ok());
-    assert!(res2.unwrap().len() > 0);
+    assert!(!res2.unwrap().is_empty());
 
     // 2.读取一个不存在的 key
     let res3 = engine.get(Bytes::from("not existed key"));
@@ -198,6 +208,608 @@ fn test_engine_sync() {
     std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
 }
 
+#[test]
+fn test_engine_put_with_meta() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-put-meta");
+    opts.data_file_size = 64 * 1024 * 1024;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.写入带有元数据的数据
+    let res1 = engine.put_with_meta(
+        get_test_key(1),
+        get_test_value(1),
+        Bytes::from("content-type:json"),
+    );
+    assert!(res1.is_ok());
+    let (value, meta) = engine.get_with_meta(get_test_key(1)).unwrap();
+    assert_eq!(value, get_test_value(1));
+    assert_eq!(meta, Bytes::from("content-type:json"));
+    assert_eq!(engine.get(get_test_key(1)).unwrap(), get_test_value(1));
+
+    // 2.普通写入的数据元数据为空
+    let res2 = engine.put(get_test_key(2), get_test_value(2));
+    assert!(res2.is_ok());
+    let (_, meta) = engine.get_with_meta(get_test_key(2)).unwrap();
+    assert!(meta.is_empty());
+
+    // 3.元数据过长
+    let res3 = engine.put_with_meta(get_test_key(3), get_test_value(3), vec![0; 256].into());
+    assert_eq!(Errors::MetaTooLarge, res3.err().unwrap());
+
+    // 4.重启后元数据依然存在
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    let (value, meta) = engine2.get_with_meta(get_test_key(1)).unwrap();
+    assert_eq!(value, get_test_value(1));
+    assert_eq!(meta, Bytes::from("content-type:json"));
+    let (value, meta) = engine2.get_with_meta(get_test_key(2)).unwrap();
+    assert_eq!(value, get_test_value(2));
+    assert!(meta.is_empty());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_try_get() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-try-get");
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.不存在的 key 返回 None
+    assert_eq!(engine.try_get(get_test_key(1)).unwrap(), None);
+    assert_eq!(engine.try_get_with_meta(get_test_key(1)).unwrap(), None);
+
+    // 2.存在的 key
+    let res = engine.put_with_meta(get_test_key(1), get_test_value(1), Bytes::from("meta"));
+    assert!(res.is_ok());
+    assert_eq!(
+        engine.try_get(get_test_key(1)).unwrap(),
+        Some(get_test_value(1))
+    );
+    assert_eq!(
+        engine.try_get_with_meta(get_test_key(1)).unwrap(),
+        Some((get_test_value(1), Bytes::from("meta")))
+    );
+
+    // 3.删除之后返回 None
+    assert!(engine.delete(get_test_key(1)).is_ok());
+    assert_eq!(engine.try_get(get_test_key(1)).unwrap(), None);
+
+    // 4.key 为空仍然返回错误
+    assert_eq!(
+        engine.try_get(Bytes::new()).err().unwrap(),
+        Errors::KeyIsEmpty
+    );
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_key_validator() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-key-validator");
+    opts.data_file_size = 64 * 1024 * 1024;
+    opts.key_validator = Some(Arc::new(|key| std::str::from_utf8(key).is_ok()));
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.合法的 key
+    let res1 = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res1.is_ok());
+
+    // 2.不合法的 key
+    let invalid_key = Bytes::from(vec![0xff, 0xfe]);
+    let res2 = engine.put(invalid_key.clone(), get_test_value(2));
+    assert_eq!(Errors::InvalidKey, res2.err().unwrap());
+    let res3 = engine.delete(invalid_key.clone());
+    assert_eq!(Errors::InvalidKey, res3.err().unwrap());
+
+    // 3.批量写入
+    let wb = engine.new_write_batch(Default::default()).unwrap();
+    let res4 = wb.put(invalid_key.clone(), get_test_value(3));
+    assert_eq!(Errors::InvalidKey, res4.err().unwrap());
+    let res5 = wb.delete(invalid_key);
+    assert_eq!(Errors::InvalidKey, res5.err().unwrap());
+
+    // 4.空 key 依然返回 KeyIsEmpty
+    let res6 = engine.put(Bytes::new(), get_test_value(4));
+    assert_eq!(Errors::KeyIsEmpty, res6.err().unwrap());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_record_alignment() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-record-alignment");
+    opts.data_file_size = 64 * 1024;
+    opts.record_alignment = RecordAlignment::Record(4096);
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.每条记录都从块边界开始
+    for i in 0..=100 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let res1 = engine.delete(get_test_key(5));
+    assert!(res1.is_ok());
+    for i in 0..=100 {
+        if let Some(pos) = engine.index.get(get_test_key(i).to_vec()) {
+            assert_eq!(pos.offset % 4096, 0);
+        }
+    }
+    assert_eq!(engine.get(get_test_key(50)).unwrap(), get_test_value(50));
+
+    // 2.重启后跳过填充记录加载索引
+    std::mem::drop(engine);
+    let mut opts2 = opts.clone();
+    opts2.record_alignment = RecordAlignment::Block(512);
+    let engine2 = Engine::open(opts2.clone()).expect("failed to open engine");
+    assert_eq!(engine2.list_keys().unwrap().len(), 100);
+    assert_eq!(engine2.get(get_test_key(100)).unwrap(), get_test_value(100));
+    assert_eq!(
+        Errors::KeyNotFound,
+        engine2.get(get_test_key(5)).err().unwrap()
+    );
+
+    // 3.记录不跨越块边界
+    for i in 200..=300 {
+        let res = engine2.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    for i in 200..=300 {
+        let pos = engine2.index.get(get_test_key(i).to_vec()).unwrap();
+        let len = engine2.get(get_test_key(i)).unwrap().len() as u64;
+        assert!(pos.offset % 512 + len <= 512);
+    }
+
+    // 4.不合法的对齐配置
+    let mut opts3 = opts.clone();
+    opts3.record_alignment = RecordAlignment::Record(1000);
+    let res2 = Engine::open(opts3);
+    assert_eq!(Errors::InvalidRecordAlignment, res2.err().unwrap());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_direct_io() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-direct-io");
+    opts.data_file_size = 64 * 1024;
+    opts.io_type = IOType::DirectIO;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 1.写入数据并发生文件切换
+    for i in 0..=2000 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let res1 = engine.delete(get_test_key(10));
+    assert!(res1.is_ok());
+    assert_eq!(engine.get(get_test_key(100)).unwrap(), get_test_value(100));
+    assert_eq!(
+        engine.get(get_test_key(2000)).unwrap(),
+        get_test_value(2000)
+    );
+
+    // 2.重启后继续写入
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(engine2.list_keys().unwrap().len(), 2000);
+    let res2 = engine2.put(get_test_key(3000), get_test_value(3000));
+    assert!(res2.is_ok());
+
+    // 3.切换回标准文件 IO 之后数据依然完整
+    std::mem::drop(engine2);
+    let mut opts2 = opts.clone();
+    opts2.io_type = IOType::StandardFIO;
+    let engine3 = Engine::open(opts2.clone()).expect("failed to open engine");
+    let res3 = engine3.put(get_test_key(4000), get_test_value(4000));
+    assert!(res3.is_ok());
+    std::mem::drop(engine3);
+
+    let engine4 = Engine::open(opts2.clone()).expect("failed to open engine");
+    assert_eq!(engine4.list_keys().unwrap().len(), 2002);
+    assert_eq!(engine4.get(get_test_key(1)).unwrap(), get_test_value(1));
+    assert_eq!(
+        engine4.get(get_test_key(3000)).unwrap(),
+        get_test_value(3000)
+    );
+    assert_eq!(
+        engine4.get(get_test_key(4000)).unwrap(),
+        get_test_value(4000)
+    );
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_concurrent_get_during_rotation() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-concurrent-get");
+    opts.data_file_size = 4 * 1024;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+
+    // 写入过程中不断切换活跃文件，读取已经写入的数据不受影响
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for i in 100..1000 {
+                let res = engine.put(get_test_key(i), get_test_value(i));
+                assert!(res.is_ok());
+            }
+        });
+        for _ in 0..4 {
+            s.spawn(|| {
+                for i in 0..1000 {
+                    let res = engine.get(get_test_key(i % 100));
+                    assert!(res.is_ok());
+                }
+            });
+        }
+    });
+
+    for i in 0..1000 {
+        let res = engine.get(get_test_key(i));
+        assert!(res.is_ok());
+    }
+    assert!(engine.stat().unwrap().data_file_num > 1);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_concurrent_btree_index() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-concurrent-btree");
+    opts.data_file_size = 64 * 1024;
+    opts.index_type = IndexType::ConcurrentBTree;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..1000 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    for i in 0..500 {
+        let res = engine.delete(get_test_key(i));
+        assert!(res.is_ok());
+    }
+
+    // 重启之后重新加载索引
+    engine.close().expect("failed to close");
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(engine2.list_keys().unwrap().len(), 500);
+    assert_eq!(
+        engine2.get(get_test_key(0)).err().unwrap(),
+        Errors::KeyNotFound
+    );
+    assert!(engine2.get(get_test_key(999)).is_ok());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_concurrent_delete() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-concurrent-delete");
+    opts.data_file_size = 64 * 1024;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    let quota = PrefixQuota {
+        max_keys: None,
+        max_bytes: None,
+        policy: QuotaPolicy::Reject,
+    };
+    assert!(engine
+        .set_prefix_quota(Bytes::from("bitcask"), quota)
+        .is_ok());
+
+    for i in 0..200 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+
+    // 多个线程同时删除相同的 key，都应该返回成功
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for i in 0..200 {
+                    let res = engine.delete(get_test_key(i));
+                    assert!(res.is_ok());
+                }
+            });
+        }
+    });
+
+    assert!(engine.list_keys().unwrap().is_empty());
+    // 每个 key 的用量只扣除一次
+    assert_eq!(
+        engine.prefix_usage(b"bitcask").unwrap(),
+        QuotaUsage::default()
+    );
+    // 删除已经不存在的 key
+    assert!(engine.delete(get_test_key(0)).is_ok());
+
+    // 重启之后 key 仍然是被删除的
+    engine.close().expect("failed to close");
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert!(engine2.list_keys().unwrap().is_empty());
+    assert_eq!(
+        engine2.get(get_test_key(100)).err().unwrap(),
+        Errors::KeyNotFound
+    );
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_rotate_interval() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-rotate-interval");
+    opts.rotate_interval = Some(Duration::from_millis(50));
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 空的活跃文件不会被切换
+    std::thread::sleep(Duration::from_millis(150));
+    assert_eq!(engine.stat().unwrap().data_file_num, 1);
+
+    let res = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res.is_ok());
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(engine.stat().unwrap().data_file_num, 2);
+    assert!(engine.get(get_test_key(1)).is_ok());
+    assert_eq!(engine.background_tasks(), vec!["rotate".to_string()]);
+
+    assert!(engine.close().is_ok());
+    assert!(engine.background_tasks().is_empty());
+
+    // 无效的配置
+    opts.rotate_interval = Some(Duration::ZERO);
+    let res = Engine::open(opts.clone());
+    assert_eq!(res.err().unwrap(), Errors::InvalidRotateOptions);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_sync_interval() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-sync-interval");
+    opts.sync_interval = Some(SyncInterval {
+        interval: Duration::from_millis(20),
+        jitter: Duration::from_millis(10),
+    });
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 没有写入时不会持久化
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(engine.interval_syncs(), 0);
+
+    let res = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res.is_ok());
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(!engine.has_unsynced_data());
+    // 多次间隔内只写入了一次，只需要持久化一次
+    assert_eq!(engine.interval_syncs(), 1);
+    assert_eq!(engine.background_tasks(), vec!["sync".to_string()]);
+
+    assert!(engine.close().is_ok());
+    assert!(engine.background_tasks().is_empty());
+
+    // 无效的配置
+    opts.sync_interval = Some(SyncInterval {
+        interval: Duration::ZERO,
+        jitter: Duration::ZERO,
+    });
+    let res = Engine::open(opts.clone());
+    assert_eq!(res.err().unwrap(), Errors::InvalidSyncInterval);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_rotate_stale_ratio() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-rotate-stale-ratio");
+    opts.data_file_size = 64 * 1024;
+    opts.rotate_stale_ratio = Some(0.5);
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 反复覆盖同一个 key，活跃文件中大部分数据很快失效
+    for i in 0..200 {
+        let res = engine.put(get_test_key(1), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    let total = 200 * (get_test_value(0).len() as u64);
+    assert!(total < opts.data_file_size);
+    assert!(engine.stat().unwrap().data_file_num > 1);
+    assert_eq!(engine.get(get_test_key(1)).unwrap(), get_test_value(199));
+
+    // 不覆盖数据时不会提前切换
+    let files = engine.stat().unwrap().data_file_num;
+    for i in 0..200 {
+        let res = engine.put(get_test_key(i + 1000), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    assert!(engine.stat().unwrap().data_file_num <= files + 1);
+
+    opts.rotate_stale_ratio = Some(1.5);
+    let res = Engine::open(opts.clone());
+    assert_eq!(res.err().unwrap(), Errors::InvalidRotateOptions);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_merge_ratio() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-merge-ratio");
+    opts.data_file_size = 32 * 1024;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 0..1000 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    engine.close().expect("failed to close");
+    std::mem::drop(engine);
+
+    // 重新打开时根据索引计算已有文件的失效数据量
+    opts.merge_ratio = Some(0.5);
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    assert!(engine.background_tasks().contains(&"merge".to_string()));
+    for i in 0..900 {
+        let res = engine.put(get_test_key(i), get_test_value(i + 1));
+        assert!(res.is_ok());
+    }
+    let files = engine.stat().unwrap().data_file_num;
+    assert_eq!(engine.stat().unwrap().merge_bytes_written, 0);
+
+    // 后台任务选出文件之后自动合并，不需要等待下一次写入
+    let start = Instant::now();
+    while engine.stat().unwrap().data_file_num >= files && start.elapsed() < Duration::from_secs(10)
+    {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert!(engine.stat().unwrap().merge_bytes_written > 0);
+    assert!(engine.stat().unwrap().data_file_num < files);
+    for i in 0..1000 {
+        let expected = match i < 900 {
+            true => get_test_value(i + 1),
+            false => get_test_value(i),
+        };
+        assert_eq!(engine.get(get_test_key(i)).unwrap(), expected);
+    }
+    engine.close().expect("failed to close");
+    std::mem::drop(engine);
+
+    opts.merge_ratio = Some(0.0);
+    let res = Engine::open(opts.clone());
+    assert_eq!(res.err().unwrap(), Errors::InvalidMergeRatio);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_merge_ratio_not_block_writes() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-merge-ratio-background");
+    opts.data_file_size = 32 * 1024;
+    opts.merge_ratio = Some(0.5);
+    // 限速让合并持续足够长的时间
+    opts.merge_rate_limit = Some(64 * 1024);
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 0..1000 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    for i in 0..900 {
+        let res = engine.put(get_test_key(i), get_test_value(i + 1));
+        assert!(res.is_ok());
+    }
+
+    // 等待后台任务开始合并
+    let start = Instant::now();
+    while engine.maintenance_lock.try_lock().is_some() {
+        assert!(start.elapsed() < Duration::from_secs(10));
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    // 合并进行期间写入不会被阻塞
+    let put_start = Instant::now();
+    for i in 0..10 {
+        let res = engine.put(get_test_key(2000 + i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    assert!(put_start.elapsed() < Duration::from_millis(200));
+    assert!(engine.maintenance_lock.try_lock().is_none());
+
+    // 合并完成之后数据不变
+    let lock = engine.maintenance_lock.lock();
+    assert!(engine.stat().unwrap().merge_bytes_written > 0);
+    for i in 0..1000 {
+        let expected = match i < 900 {
+            true => get_test_value(i + 1),
+            false => get_test_value(i),
+        };
+        assert_eq!(engine.get(get_test_key(i)).unwrap(), expected);
+    }
+    drop(lock);
+    engine.close().expect("failed to close");
+    std::mem::drop(engine);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_fork_to() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-fork");
+    opts.data_file_size = 32 * 1024;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 写入足够多的数据，产生多个数据文件
+    for i in 0..=1000 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+
+    let fork_dir = PathBuf::from("/tmp/bitcask-rs-fork-target");
+    let fork_res = engine.fork_to(&fork_dir);
+    assert!(fork_res.is_ok());
+
+    // 目标目录不为空时不能克隆
+    let fork_res2 = engine.fork_to(&fork_dir);
+    assert_eq!(Errors::TargetDirNotEmpty, fork_res2.err().unwrap());
+
+    let mut fork_opts = opts.clone();
+    fork_opts.dir_path = fork_dir.clone();
+    let fork = Engine::open(fork_opts).expect("failed to open forked engine");
+    assert_eq!(fork.list_keys().unwrap().len(), 1001);
+    assert_eq!(fork.get(get_test_key(500)).unwrap(), get_test_value(500));
+
+    // 克隆之后两边的写入互不影响
+    let res1 = fork.put(get_test_key(1), Bytes::from("fork value"));
+    assert!(res1.is_ok());
+    let res2 = fork.delete(get_test_key(2));
+    assert!(res2.is_ok());
+    let res3 = engine.put(get_test_key(3), Bytes::from("origin value"));
+    assert!(res3.is_ok());
+
+    assert_eq!(engine.get(get_test_key(1)).unwrap(), get_test_value(1));
+    assert_eq!(engine.get(get_test_key(2)).unwrap(), get_test_value(2));
+    assert_eq!(
+        fork.get(get_test_key(1)).unwrap(),
+        Bytes::from("fork value")
+    );
+    assert_eq!(
+        Errors::KeyNotFound,
+        fork.get(get_test_key(2)).err().unwrap()
+    );
+    assert_eq!(fork.get(get_test_key(3)).unwrap(), get_test_value(3));
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    std::fs::remove_dir_all(fork_dir).expect("failed to remove path");
+}
+
 // #[test]
 // fn test_engine_filelock() {
 //     let mut opts = Options::default();
@@ -217,31 +829,244 @@ fn test_engine_sync() {
 //     std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
 // }
 
-// #[test]
-// fn test_engine_stat() {
-//     let mut opts = Options::default();
-//     opts.dir_path = PathBuf::from("/tmp/bitcask-rs-stat");
-//     let engine = Engine::open(opts.clone()).expect("failed to open engine");
+#[test]
+fn test_engine_stat() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-stat");
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
 
-//     for i in 0..=10000 {
-//         let res = engine.put(get_test_key(i), get_test_value(i));
-//         assert!(res.is_ok());
-//     }
-//     for i in 0..=1000 {
-//         let res = engine.put(get_test_key(i), get_test_value(i));
-//         assert!(res.is_ok());
-//     }
-//     for i in 2000..=5000 {
-//         let res = engine.delete(get_test_key(i));
-//         assert!(res.is_ok());
-//     }
+    for i in 0..=10000 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    for i in 0..=1000 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    for i in 2000..=5000 {
+        let res = engine.delete(get_test_key(i));
+        assert!(res.is_ok());
+    }
 
-//     let stat = engine.stat().unwrap();
-//     assert!(stat.reclaim_size > 0);
+    let stat = engine.stat().unwrap();
+    assert_eq!(stat.key_num, 7000);
+    assert_eq!(stat.data_file_num, 1);
+    assert!(stat.disk_size > 0);
+    assert!(stat.user_bytes_written > 0);
+    // 记录头、事务序列号和 crc 带来额外的写入
+    assert!(stat.data_bytes_written > stat.user_bytes_written);
+    assert!(stat.write_amplification > 1.0);
+    assert_eq!(stat.file_reads.len(), 1);
+    assert_eq!(stat.file_reads[0].reads, 0);
+    assert!(stat.file_reads[0].last_read_at.is_none());
 
-//     // 删除测试的文件夹
-//     std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
-// }
+    // 读取统计
+    for i in 0..100 {
+        assert!(engine.get(get_test_key(i)).is_ok());
+    }
+    let stat = engine.stat().unwrap();
+    assert_eq!(stat.file_reads[0].file_id, 0);
+    assert_eq!(stat.file_reads[0].reads, 100);
+    assert!(stat.file_reads[0].last_read_at.is_some());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_has_unsynced_data() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-unsynced");
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    assert!(!engine.has_unsynced_data());
+
+    let res = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res.is_ok());
+    assert!(engine.has_unsynced_data());
+    assert!(engine.sync().is_ok());
+    assert!(!engine.has_unsynced_data());
+
+    // 重新打开之后文件中的数据视为已经持久化
+    let res = engine.put(get_test_key(2), get_test_value(2));
+    assert!(res.is_ok());
+    assert!(engine.close().is_ok());
+    std::mem::drop(engine);
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    assert!(!engine.has_unsynced_data());
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[cfg(feature = "drop-check")]
+#[test]
+fn test_engine_drop_check_panic() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-drop-check");
+    opts.unsynced_drop = crate::options::UnsyncedDropAction::Panic;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    let res = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res.is_ok());
+
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| std::mem::drop(engine)));
+    assert!(res.is_err());
+
+    // 持久化之后释放不会 panic
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    let res = engine.put(get_test_key(2), get_test_value(2));
+    assert!(res.is_ok());
+    assert!(engine.sync().is_ok());
+    std::mem::drop(engine);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_bloom_filter() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-bloom-filter");
+    opts.bloom_filter = Some(BloomFilterOptions {
+        expected_keys: 1000,
+        false_positive_rate: 0.01,
+    });
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    assert!(engine.bloom_stats().is_some());
+
+    for i in 0..100 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    for i in 100..1100 {
+        assert_eq!(
+            engine.get(get_test_key(i)).err().unwrap(),
+            Errors::KeyNotFound
+        );
+    }
+    let stats = engine.bloom_stats().unwrap();
+    assert_eq!(stats.lookups, 1000);
+    assert!(stats.false_positive_rate() < 0.05);
+
+    // 重启之后加载索引时重建过滤器
+    engine.close().expect("failed to close");
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(engine2.get(get_test_key(99)).unwrap(), get_test_value(99));
+    assert_eq!(engine2.bloom_stats().unwrap().negatives, 0);
+
+    // 没有启用时返回 None
+    let mut opts2 = opts.clone();
+    opts2.bloom_filter = None;
+    std::mem::drop(engine2);
+    let engine3 = Engine::open(opts2).expect("failed to open engine");
+    assert!(engine3.bloom_stats().is_none());
+
+    let mut opts3 = opts.clone();
+    opts3.bloom_filter = Some(BloomFilterOptions {
+        expected_keys: 1000,
+        false_positive_rate: 1.0,
+    });
+    let res = Engine::open(opts3);
+    assert_eq!(res.err().unwrap(), Errors::InvalidBloomFilterOptions);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_custom_index() {
+    use crate::{
+        data::log_record::LogRecordPos,
+        index::{btree::BTree, Indexer, IndexerIterator},
+        options::IteratorOptions,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // 统计写入次数的自定义索引
+    struct CountingIndex {
+        inner: BTree,
+        puts: Arc<AtomicUsize>,
+    }
+
+    impl Indexer for CountingIndex {
+        fn put(&self, key: Vec<u8>, pos: LogRecordPos) -> bool {
+            self.puts.fetch_add(1, Ordering::SeqCst);
+            self.inner.put(key, pos)
+        }
+        fn get(&self, key: Vec<u8>) -> Option<LogRecordPos> {
+            self.inner.get(key)
+        }
+        fn delete(&self, key: Vec<u8>) -> bool {
+            self.inner.delete(key)
+        }
+        fn compare_and_put(&self, key: Vec<u8>, old: LogRecordPos, new: LogRecordPos) -> bool {
+            self.inner.compare_and_put(key, old, new)
+        }
+        fn iterator(&self, option: IteratorOptions) -> Box<dyn IndexerIterator> {
+            self.inner.iterator(option)
+        }
+        fn list_keys(&self, reverse: bool, prefix: &[u8]) -> crate::errors::Result<Vec<Bytes>> {
+            self.inner.list_keys(reverse, prefix)
+        }
+    }
+
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-custom-index");
+    opts.index_type = IndexType::Custom;
+    let res = Engine::open(opts.clone());
+    assert_eq!(res.err().unwrap(), Errors::CustomIndexNotSet);
+
+    let puts = Arc::new(AtomicUsize::new(0));
+    let factory_puts = puts.clone();
+    opts.custom_index = Some(Arc::new(move || {
+        Box::new(CountingIndex {
+            inner: BTree::new(),
+            puts: factory_puts.clone(),
+        })
+    }));
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 0..10 {
+        let res = engine.put(get_test_key(i), get_test_value(i));
+        assert!(res.is_ok());
+    }
+    assert_eq!(puts.load(Ordering::SeqCst), 10);
+    assert_eq!(engine.get(get_test_key(3)).unwrap(), get_test_value(3));
+
+    // 重启之后使用自定义索引加载数据
+    engine.close().expect("failed to close");
+    std::mem::drop(engine);
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert_eq!(puts.load(Ordering::SeqCst), 20);
+    assert_eq!(engine2.list_keys().unwrap().len(), 10);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_remove_temp_data_files() {
+    use crate::data::data_file::{get_data_file_name, get_temp_data_file_name};
+
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-temp-data-files");
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    let res = engine.put(get_test_key(1), get_test_value(1));
+    assert!(res.is_ok());
+    engine.close().expect("failed to close");
+    std::mem::drop(engine);
+
+    // 模拟切换文件时崩溃，残留只创建了一半的临时文件
+    let temp_path = get_temp_data_file_name(&opts.dir_path, 1);
+    fs::write(&temp_path, b"half").unwrap();
+
+    let engine2 = Engine::open(opts.clone()).expect("failed to open engine");
+    assert!(!temp_path.exists());
+    assert!(!get_data_file_name(&opts.dir_path, 1).exists());
+    assert_eq!(engine2.get(get_test_key(1)).unwrap(), get_test_value(1));
+    assert_eq!(engine2.stat().unwrap().data_file_num, 1);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
 
 // #[test]
 // fn test_engine_backup() {
@@ -267,3 +1092,344 @@ fn test_engine_sync() {
 //     std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
 //     std::fs::remove_dir_all(backup_dir).expect("failed to remove path");
 // }
+
+#[test]
+#[cfg(not(feature = "strict-invariants"))]
+fn test_engine_open_invalid_record_type() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-invalid-record-type");
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    engine
+        .put(get_test_key(1), get_test_value(1))
+        .expect("failed to put");
+    std::mem::drop(engine);
+
+    // 把第一条记录的类型改成不存在的值，打开时应该返回错误而不是 panic
+    let path = crate::data::data_file::get_data_file_name(&opts.dir_path, 0);
+    let mut content = fs::read(&path).unwrap();
+    content[0] = 0x7f;
+    fs::write(&path, content).unwrap();
+
+    let res = Engine::open(opts.clone());
+    assert_eq!(res.err(), Some(Errors::DataFileCorrupted));
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_file_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-file-mode");
+    opts.data_file_size = 64 * 1024;
+    opts.seal_digest = true;
+    opts.audit_log = Some(opts.dir_path.join("audit.log"));
+    opts.file_mode = 0o600;
+    opts.dir_mode = 0o700;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    for i in 0..2000 {
+        engine.put(get_test_key(i), get_test_value(i)).unwrap();
+    }
+    engine.close().unwrap();
+
+    // 数据目录和其中的所有文件都只有所有者可以访问
+    let mode = |path: &std::path::Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode(&opts.dir_path), 0o700);
+    let mut files = 0;
+    for entry in fs::read_dir(&opts.dir_path).unwrap() {
+        let path = entry.unwrap().path();
+        assert_eq!(mode(&path), 0o600, "{:?}", path);
+        files += 1;
+    }
+    assert!(files > 4);
+
+    // 副本使用相同的权限
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    let fork_dir = PathBuf::from("/tmp/bitcask-rs-file-mode-fork");
+    engine.fork_to(&fork_dir).unwrap();
+    assert_eq!(mode(&fork_dir), 0o700);
+    assert_eq!(mode(&fork_dir.join(crate::fence::DB_ID_FILE_NAME)), 0o600);
+
+    let mut bad = opts.clone();
+    bad.file_mode = 0o400;
+    assert_eq!(Engine::open(bad).err(), Some(Errors::InvalidFileMode));
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    std::fs::remove_dir_all(fork_dir).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_dead_bytes() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-dead-bytes");
+    opts.data_file_size = 16 * 1024;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 写入时累加的失效数据量与根据索引计算的结果一致
+    let check = |engine: &Engine| {
+        let report = engine.space_report().unwrap();
+        let files = engine.files.load();
+        for file in report.files.iter() {
+            let data_file = files.get(file.file_id).unwrap();
+            assert_eq!(
+                data_file.get_stale_bytes(),
+                file.dead_bytes,
+                "{}",
+                file.file_id
+            );
+        }
+        assert!(report.dead_bytes > 0);
+    };
+
+    for i in 0..1000 {
+        engine
+            .put(get_test_key(i % 300), get_test_value(i))
+            .unwrap();
+    }
+    for i in 0..100 {
+        engine.delete(get_test_key(i)).unwrap();
+    }
+    let wb = engine.new_write_batch(Default::default()).unwrap();
+    wb.put(get_test_key(200), get_test_value(1)).unwrap();
+    wb.delete(get_test_key(201)).unwrap();
+    wb.commit().unwrap();
+    assert!(engine.files.load().older.len() > 1);
+    check(&engine);
+
+    engine.close().expect("failed to close");
+    std::mem::drop(engine);
+
+    // 重启之后重新计算
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    check(&engine);
+    engine.put(get_test_key(250), get_test_value(1)).unwrap();
+    check(&engine);
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_omit_seq_prefix() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-omit-seq-prefix");
+    opts.data_file_size = 16 * 1024;
+    opts.omit_seq_prefix = true;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    for i in 0..500 {
+        engine.put(get_test_key(i), get_test_value(i)).unwrap();
+    }
+    for i in 0..100 {
+        engine.delete(get_test_key(i)).unwrap();
+    }
+    let wb = engine.new_write_batch(Default::default()).unwrap();
+    wb.put(get_test_key(1000), get_test_value(1000)).unwrap();
+    wb.delete(get_test_key(200)).unwrap();
+    wb.commit().unwrap();
+
+    // 每条非事务写入的记录少一个字节，位置中记录的长度与实际写入的长度一致
+    let report = engine.space_report().unwrap();
+    let files = engine.files.load();
+    for file in report.files.iter() {
+        let data_file = files.get(file.file_id).unwrap();
+        assert_eq!(data_file.get_stale_bytes(), file.dead_bytes);
+    }
+    let pos = engine.index.get(get_test_key(300).to_vec()).unwrap();
+    let record = LogRecord {
+        key: log_record_key_with_seq(get_test_key(300).to_vec(), NON_TRANSACTION_SEQ_NO),
+        value: get_test_value(300).to_vec(),
+        rec_type: LogRecordType::NORMAL,
+        meta: Default::default(),
+    };
+    assert_eq!(pos.size, record.encoded_length() as u64 - 1);
+
+    let check = |engine: &Engine| {
+        for i in 0..100 {
+            assert!(engine.try_get(get_test_key(i)).unwrap().is_none());
+        }
+        assert!(engine.try_get(get_test_key(200)).unwrap().is_none());
+        assert_eq!(engine.get(get_test_key(300)).unwrap(), get_test_value(300));
+        assert_eq!(
+            engine.get(get_test_key(1000)).unwrap(),
+            get_test_value(1000)
+        );
+        assert_eq!(engine.list_keys().unwrap().len(), 400);
+    };
+    check(&engine);
+    engine.close().expect("failed to close");
+    std::mem::drop(engine);
+
+    // 关闭选项之后仍然可以读取已经写入的数据，合并之后数据不变
+    opts.omit_seq_prefix = false;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    check(&engine);
+    engine.merge().unwrap();
+    check(&engine);
+    engine.close().expect("failed to close");
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+#[test]
+fn test_engine_parallel_load() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-parallel-load");
+    opts.data_file_size = 4 * 1024;
+    opts.load_threads = 1;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+    // 同一个 key 的多次写入和删除分布在不同的数据文件中
+    for i in 0..1000 {
+        engine.put(get_test_key(i), get_test_value(i)).unwrap();
+    }
+    for i in 0..300 {
+        engine.delete(get_test_key(i)).unwrap();
+    }
+    for i in 0..100 {
+        engine.put(get_test_key(i), get_test_value(i * 10)).unwrap();
+    }
+    let wb = engine.new_write_batch(Default::default()).unwrap();
+    wb.put(get_test_key(2000), get_test_value(2000)).unwrap();
+    wb.delete(get_test_key(500)).unwrap();
+    wb.commit().unwrap();
+    assert!(engine.stat().unwrap().data_file_num > 20);
+    engine.close().expect("failed to close");
+    std::mem::drop(engine);
+
+    let check = |engine: &Engine| {
+        assert_eq!(engine.list_keys().unwrap().len(), 800);
+        assert_eq!(engine.get(get_test_key(50)).unwrap(), get_test_value(500));
+        assert!(engine.try_get(get_test_key(200)).unwrap().is_none());
+        assert!(engine.try_get(get_test_key(500)).unwrap().is_none());
+        assert_eq!(
+            engine.get(get_test_key(2000)).unwrap(),
+            get_test_value(2000)
+        );
+    };
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    check(&engine);
+    let sequential = engine.file_stats();
+    engine.close().expect("failed to close");
+    std::mem::drop(engine);
+
+    // 并行加载的结果与顺序加载一致，之后可以继续写入
+    opts.load_threads = 4;
+    let engine = Engine::open(opts.clone()).expect("failed to open engine");
+    check(&engine);
+    assert_eq!(engine.file_stats(), sequential);
+    engine
+        .put(get_test_key(3000), get_test_value(3000))
+        .unwrap();
+    engine.close().expect("failed to close");
+    std::mem::drop(engine);
+
+    opts.load_threads = 0;
+    assert_eq!(
+        Engine::open(opts.clone()).err().unwrap(),
+        Errors::InvalidLoadThreads
+    );
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
+
+// 读取所有的 key 和 value
+fn read_all(engine: &Engine) -> BTreeMap<Bytes, Bytes> {
+    let keys = engine.list_keys().unwrap();
+    keys.into_iter()
+        .map(|key| (key.clone(), engine.get(key).unwrap()))
+        .collect()
+}
+
+// 随机写入之后模拟断电并重新打开，恢复的数据必须是最近一次持久化之后某一次写入完成时的数据
+// 事务要么完整生效要么完全丢失，切换数据文件和合并不能丢失已经持久化的数据
+// 不完整的记录会触发 strict-invariants 的检查，只在没有启用该特性时运行
+#[test]
+#[cfg(not(feature = "strict-invariants"))]
+fn test_engine_crash_recovery() {
+    let mut opts = Options::default();
+    opts.dir_path = PathBuf::from("/tmp/bitcask-rs-crash-recovery");
+    opts.data_file_size = 8 * 1024;
+    opts.unsynced_drop = crate::options::UnsyncedDropAction::Ignore;
+    let sim = CrashSimulator::install(&opts.dir_path, 0x2763);
+    let mut rng = 0x9E37_79B9_7F4A_7C15u64;
+    let mut next = move |n: u64| {
+        rng ^= rng << 13;
+        rng ^= rng >> 7;
+        rng ^= rng << 17;
+        rng % n
+    };
+
+    // 崩溃之后可能恢复出的数据
+    let mut states = vec![BTreeMap::new()];
+    let mut version = 0;
+    for round in 0..40 {
+        let engine = Engine::open(opts.clone()).expect("failed to open engine after crash");
+        let mut current = read_all(&engine);
+        assert!(
+            states.contains(&current),
+            "round {round}: recovered data is not a prefix of the writes"
+        );
+        states = vec![current.clone()];
+
+        for _ in 0..next(300) {
+            version += 1;
+            let key = get_test_key(next(50) as usize);
+            match next(100) {
+                0..=49 => {
+                    let value = get_test_value(version);
+                    engine.put(key.clone(), value.clone()).unwrap();
+                    current.insert(key, value);
+                }
+                50..=64 => {
+                    engine.delete(key.clone()).unwrap();
+                    current.remove(&key);
+                }
+                65..=84 => {
+                    let wb = engine.new_write_batch(Default::default()).unwrap();
+                    for i in 0..next(8) + 1 {
+                        let key = get_test_key(next(50) as usize);
+                        if next(4) == 0 {
+                            wb.delete(key.clone()).unwrap();
+                            current.remove(&key);
+                        } else {
+                            let value = get_test_value(version * 10 + i as usize);
+                            wb.put(key.clone(), value.clone()).unwrap();
+                            current.insert(key, value);
+                        }
+                    }
+                    wb.commit().unwrap();
+                }
+                85..=94 => {
+                    engine.sync().unwrap();
+                    states.clear();
+                }
+                _ => {
+                    engine.merge().unwrap();
+                }
+            }
+            states.push(current.clone());
+        }
+        // 偶尔正常关闭，之后的打开使用索引快照
+        if next(4) == 0 {
+            engine.close().expect("failed to close");
+            states = vec![current];
+        } else {
+            sim.crash();
+        }
+        std::mem::drop(engine);
+    }
+
+    let engine = Engine::open(opts.clone()).expect("failed to open engine after crash");
+    assert!(states.contains(&read_all(&engine)));
+    engine.close().expect("failed to close");
+    sim.uninstall();
+
+    // 删除测试的文件夹
+    std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+}
@@ -0,0 +1,219 @@
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant, SystemTime},
+};
+
+use parking_lot::Mutex;
+
+use crate::{db::Engine, errors::Errors};
+
+// 最多保留的采样记录数，超过之后丢弃最旧的记录
+pub(crate) const OP_TRACE_CAPACITY: usize = 1024;
+
+/// 采样记录的操作类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceOp {
+    Get,
+    Put,
+    Delete,
+}
+
+/// 一次被采样的操作
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpTrace {
+    pub op: TraceOp,
+    // key 的哈希值，不记录 key 本身，相同的 key 哈希值相同
+    pub key_hash: u64,
+    // 操作的耗时
+    pub latency: Duration,
+    // 读取或者写入的数据文件，读取的 key 不存在时为 None
+    pub file_id: Option<u32>,
+    // 操作开始的时间
+    pub at: SystemTime,
+}
+
+// 按照采样率记录操作，保存在环形缓冲区中
+pub(crate) struct OpTracer {
+    // 采样阈值，随机数小于等于阈值时采样
+    threshold: u64,
+    counter: AtomicU64,
+    traces: Mutex<VecDeque<OpTrace>>,
+}
+
+// 正在进行的被采样的操作
+pub(crate) struct TraceStart {
+    start: Instant,
+    at: SystemTime,
+}
+
+impl OpTracer {
+    // 采样率为 0 时不创建
+    pub(crate) fn new(sample_rate: f64) -> Option<Self> {
+        if sample_rate <= 0.0 {
+            return None;
+        }
+        let threshold = match sample_rate >= 1.0 {
+            true => u64::MAX,
+            false => (sample_rate * u64::MAX as f64) as u64,
+        };
+        Some(Self {
+            threshold,
+            counter: AtomicU64::new(0),
+            traces: Mutex::new(VecDeque::with_capacity(OP_TRACE_CAPACITY)),
+        })
+    }
+
+    // 决定是否采样这次操作，只原子递增一个计数器，不采样时开销很小
+    fn start(&self) -> Option<TraceStart> {
+        let n = self.counter.fetch_add(1, Ordering::Relaxed);
+        if splitmix64(n) > self.threshold {
+            return None;
+        }
+        Some(TraceStart {
+            start: Instant::now(),
+            at: SystemTime::now(),
+        })
+    }
+
+    fn record(&self, trace: OpTrace) {
+        let mut traces = self.traces.lock();
+        if traces.len() == OP_TRACE_CAPACITY {
+            traces.pop_front();
+        }
+        traces.push_back(trace);
+    }
+}
+
+impl Engine {
+    /// 最近被采样的操作，按时间从旧到新排列，最多返回最近的 1024 条
+    /// Options::op_trace_sample_rate 为 0 时返回空
+    pub fn recent_ops(&self) -> Vec<OpTrace> {
+        match &self.op_tracer {
+            Some(tracer) => tracer.traces.lock().iter().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // 开始一次操作，返回 None 表示这次操作不采样
+    pub(crate) fn trace_start(&self) -> Option<TraceStart> {
+        self.op_tracer.as_ref().and_then(OpTracer::start)
+    }
+
+    // 结束一次被采样的操作
+    pub(crate) fn trace_finish(
+        &self,
+        start: Option<TraceStart>,
+        op: TraceOp,
+        key: &[u8],
+        file_id: Option<u32>,
+    ) {
+        let (Some(tracer), Some(start)) = (&self.op_tracer, start) else {
+            return;
+        };
+        tracer.record(OpTrace {
+            op,
+            key_hash: self.hash_key(key),
+            latency: start.start.elapsed(),
+            file_id,
+            at: start.at,
+        });
+    }
+}
+
+// 校验采样率，必须在 [0, 1] 之间
+pub(crate) fn check_sample_rate(rate: f64) -> Option<Errors> {
+    match (0.0..=1.0).contains(&rate) {
+        true => None,
+        false => Some(Errors::InvalidOpTraceSampleRate),
+    }
+}
+
+// 将计数器打散成均匀分布的随机数
+fn splitmix64(n: u64) -> u64 {
+    let mut z = n.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use crate::{
+        options::Options,
+        utils::rand_kv::{get_test_key, get_test_value},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_recent_ops() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-recent-ops");
+        opts.op_trace_sample_rate = 1.0;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+
+        engine.put(get_test_key(1), get_test_value(1)).unwrap();
+        engine.get(get_test_key(1)).unwrap();
+        assert!(engine.get(get_test_key(2)).is_err());
+        engine.delete(get_test_key(1)).unwrap();
+
+        let ops = engine.recent_ops();
+        let kinds: Vec<_> = ops.iter().map(|t| t.op).collect();
+        assert_eq!(
+            kinds,
+            vec![TraceOp::Put, TraceOp::Get, TraceOp::Get, TraceOp::Delete]
+        );
+        assert_eq!(ops[0].key_hash, ops[1].key_hash);
+        assert_ne!(ops[1].key_hash, ops[2].key_hash);
+        assert_eq!(ops[0].file_id, Some(0));
+        assert_eq!(ops[1].file_id, Some(0));
+        assert_eq!(ops[2].file_id, None);
+        assert!(ops.windows(2).all(|w| w[0].at <= w[1].at));
+
+        // 只保留最近的记录
+        for i in 0..OP_TRACE_CAPACITY * 2 {
+            engine.put(get_test_key(i), get_test_value(i)).unwrap();
+        }
+        let ops = engine.recent_ops();
+        assert_eq!(ops.len(), OP_TRACE_CAPACITY);
+        assert!(ops.iter().all(|t| t.op == TraceOp::Put));
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+
+    #[test]
+    fn test_recent_ops_sampling() {
+        let mut opts = Options::default();
+        opts.dir_path = PathBuf::from("/tmp/bitcask-rs-recent-ops-sampling");
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        // 默认不采样
+        engine
+            .put(Bytes::from("key"), Bytes::from("value"))
+            .unwrap();
+        assert!(engine.recent_ops().is_empty());
+        std::mem::drop(engine);
+
+        opts.op_trace_sample_rate = 0.1;
+        let engine = Engine::open(opts.clone()).expect("failed to open engine");
+        for _ in 0..1000 {
+            engine.get(Bytes::from("key")).unwrap();
+        }
+        let sampled = engine.recent_ops().len();
+        assert!(sampled > 50 && sampled < 150, "sampled {sampled}");
+
+        opts.op_trace_sample_rate = 1.5;
+        assert_eq!(
+            Engine::open(opts.clone()).err().unwrap(),
+            Errors::InvalidOpTraceSampleRate
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(opts.clone().dir_path).expect("failed to remove path");
+    }
+}
@@ -0,0 +1,77 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use log::error;
+
+use crate::{
+    errors::{Errors, Result},
+    fio,
+};
+
+/// 原子地写入整个文件：先写入同一目录下的临时文件并持久化，再重命名为目标文件并持久化所在的目录
+/// 崩溃之后目标文件要么是旧的内容，要么是完整的新内容，不会留下写到一半的文件
+pub(crate) fn atomic_write(path: &Path, bytes: &[u8], mode: u32) -> Result<()> {
+    atomic_write_with(path, mode, |w| w.write_all(bytes))
+}
+
+// 与 atomic_write 相同，内容较大时由 write 分多次写入，不需要先在内存中拼接
+pub(crate) fn atomic_write_with<F>(path: &Path, mode: u32, write: F) -> Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let res = fio::create_file(&tmp_path, mode)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            write(&mut writer)?;
+            writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if let Err(e) = res {
+        error!("Failed to write {:?}: {e}", path);
+        let _ = fs::remove_file(&tmp_path);
+        return Err(Errors::FailedToWriteToDataFile(e.into()));
+    }
+    fio::sync_parent_dir(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::fs::PermissionsExt, path::PathBuf};
+
+    use super::*;
+
+    #[test]
+    fn test_atomic_write() {
+        let dir_path = PathBuf::from("/tmp/bitcask-rs-atomic-write");
+        fio::create_dir(&dir_path, 0o777, true).unwrap();
+        let path = dir_path.join("meta");
+
+        atomic_write(&path, b"first", 0o600).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        // 覆盖已有的文件，不留下临时文件
+        atomic_write(&path, b"second", 0o600).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(fs::read_dir(&dir_path).unwrap().count(), 1);
+
+        // 目录不存在时返回错误
+        let res = atomic_write(&dir_path.join("missing").join("meta"), b"x", 0o600);
+        assert_eq!(
+            res.err().unwrap(),
+            Errors::FailedToWriteToDataFile(std::io::ErrorKind::NotFound.into())
+        );
+
+        // 删除测试的文件夹
+        std::fs::remove_dir_all(&dir_path).expect("failed to remove path");
+    }
+}
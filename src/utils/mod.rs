@@ -1 +1,10 @@
+pub(crate) mod atomic;
 pub mod rand_kv;
+pub(crate) mod sharded_lock;
+
+pub(crate) use atomic::{atomic_write, atomic_write_with};
+
+// 十六进制编码
+pub(crate) fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -17,11 +17,11 @@ pub fn get_test_value(i: usize) -> Bytes {
 fn test_get_test_key_value() {
     for i in 0..=100 {
         println!("key: {:?}", get_test_key(i));
-        assert!(get_test_key(i).len() > 0)
+        assert!(!get_test_key(i).is_empty())
     }
 
     for i in 0..=100 {
         println!("value: {:?}", get_test_value(i));
-        assert!(get_test_value(i).len() > 0)
+        assert!(!get_test_value(i).is_empty())
     }
 }
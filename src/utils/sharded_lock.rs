@@ -0,0 +1,105 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Instant,
+};
+
+use parking_lot::{Mutex, RwLock, RwLockReadGuard};
+
+// 为每个线程分配的分片编号
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+// 按缓存行对齐，避免不同分片的锁落在同一个缓存行上
+#[repr(align(128))]
+struct CachePadded<T>(T);
+
+/// 读多写少的共享数据，数据本身不可变，修改时整体替换
+/// 每个分片各自保存一份当前数据的引用，读取时只获取当前线程所属分片的读锁，
+/// 不同线程的读取不会竞争同一个锁，写入时依次替换所有分片中的引用
+pub(crate) struct ShardedLock<T> {
+    shards: Vec<CachePadded<RwLock<Arc<T>>>>,
+    // 串行化写入
+    update_lock: Mutex<()>,
+}
+
+impl<T> ShardedLock<T> {
+    pub(crate) fn new(value: T) -> Self {
+        let num = thread::available_parallelism().map_or(1, |n| n.get());
+        let value = Arc::new(value);
+        Self {
+            shards: (0..num)
+                .map(|_| CachePadded(RwLock::new(value.clone())))
+                .collect(),
+            update_lock: Mutex::new(()),
+        }
+    }
+
+    fn shard(&self) -> &RwLock<Arc<T>> {
+        let idx = THREAD_SHARD.with(|shard| *shard) % self.shards.len();
+        &self.shards[idx].0
+    }
+
+    // 读取当前数据，持有返回值期间不能调用 update
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, Arc<T>> {
+        self.shard().read()
+    }
+
+    // 同 read，最多等待到 deadline，超时返回 None
+    pub(crate) fn try_read_until(&self, deadline: Instant) -> Option<RwLockReadGuard<'_, Arc<T>>> {
+        self.shard().try_read_until(deadline)
+    }
+
+    // 获取当前数据的引用，适合需要长时间持有的场景
+    pub(crate) fn load(&self) -> Arc<T> {
+        self.read().clone()
+    }
+
+    // 基于当前数据生成新的数据并替换
+    pub(crate) fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&T) -> T,
+    {
+        let _lock = self.update_lock.lock();
+        let value = Arc::new(f(&self.load()));
+        for shard in self.shards.iter() {
+            *shard.0.write() = value.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sharded_lock_update() {
+        let lock = Arc::new(ShardedLock::new(vec![1]));
+        let old = lock.load();
+        lock.update(|v| {
+            let mut v = v.clone();
+            v.push(2);
+            v
+        });
+        // 之前拿到的数据不受影响
+        assert_eq!(*old, vec![1]);
+        assert_eq!(**lock.read(), vec![1, 2]);
+
+        // 其他线程读取到的是最新的数据
+        let handles = (0..4)
+            .map(|_| {
+                let lock = lock.clone();
+                thread::spawn(move || lock.read().len())
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), 2);
+        }
+    }
+}